@@ -1,10 +1,13 @@
+use wazuh_cert_oauth2_model::models::claim_mapping::{ClaimMapping, parse_claim_list};
 use wazuh_cert_oauth2_model::models::document::DiscoveryDocument;
+use wazuh_cert_oauth2_model::models::enrollment_request::RegisterAgentResponse;
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
 use wazuh_cert_oauth2_model::services::jwks::validate_token;
 
-use crate::services::agent_name::generate_agent_name;
-use crate::services::generate_csr::generate_key_and_csr;
+use crate::services::agent_name::{current_agent_name, generate_agent_name};
+use crate::services::await_approval::await_approval;
+use crate::services::generate_csr::{KeyType, generate_key_and_csr};
 use crate::services::get_token::{GetTokenParams, get_token};
 use crate::services::restart_agent::restart_agent;
 use crate::services::save_to_file::save_cert_and_key;
@@ -24,9 +27,16 @@ pub struct FlowParams {
     cert_path: String,
     ca_cert_path: String,
     key_path: String,
+    key_type: KeyType,
     agent_control: bool,
     timeout_secs: u64,
     overwrite: bool,
+    device_id: Option<String>,
+    /// Where the agent name is read from in the token.
+    claim_mapping: ClaimMapping,
+    /// Re-enrollment of an installed agent: keep it running and keep its
+    /// configured name instead of deriving one from the token.
+    renewal: bool,
 }
 
 impl From<Opt> for FlowParams {
@@ -42,9 +52,12 @@ impl From<Opt> for FlowParams {
                 cert_path,
                 ca_cert_path,
                 key_path,
+                key_type,
                 agent_control,
                 timeout_secs,
                 overwrite,
+                device_id,
+                claim_name,
             } => Self {
                 issuer,
                 audience_csv: audience,
@@ -54,15 +67,60 @@ impl From<Opt> for FlowParams {
                 is_service_account,
                 cert_path,
                 key_path,
+                key_type,
                 agent_control,
                 ca_cert_path,
                 timeout_secs,
                 overwrite,
+                device_id,
+                claim_mapping: ClaimMapping {
+                    name: parse_claim_list(&claim_name),
+                    ..Default::default()
+                },
+                renewal: false,
+            },
+            Opt::Renew {
+                issuer,
+                audience,
+                client_id,
+                client_secret,
+                endpoint,
+                cert_path,
+                ca_cert_path,
+                key_path,
+                key_type,
+                agent_control,
+                timeout_secs,
+                device_id,
+                ..
+            } => Self {
+                issuer,
+                audience_csv: audience,
+                client_id,
+                client_secret: Some(client_secret),
+                endpoint,
+                is_service_account: true,
+                cert_path,
+                key_path,
+                key_type,
+                agent_control,
+                ca_cert_path,
+                timeout_secs,
+                overwrite: true,
+                device_id,
+                claim_mapping: ClaimMapping::default(),
+                renewal: true,
             },
         }
     }
 }
 
+impl FlowParams {
+    pub fn cert_path(&self) -> &str {
+        &self.cert_path
+    }
+}
+
 pub async fn run_oauth2_flow(params: &FlowParams) -> AppResult<()> {
     let kc_audiences = params
         .audience_csv
@@ -76,7 +134,7 @@ pub async fn run_oauth2_flow(params: &FlowParams) -> AppResult<()> {
             params.issuer
         ))
         .await?;
-    if params.agent_control {
+    if params.agent_control && !params.renewal {
         info!("Stopping agent");
         stop_agent().await?;
     }
@@ -100,32 +158,49 @@ pub async fn run_oauth2_flow(params: &FlowParams) -> AppResult<()> {
     let claims = validate_token(&token, &jwks, &Some(kc_audiences)).await?;
     let sub = claims.sub.clone();
 
-    let agent_name = if params.agent_control {
-        let name = claims.get_name().ok_or(AppError::JwtMissingName)?;
-        Some(generate_agent_name(&name))
-    } else {
+    let agent_name = if !params.agent_control {
         None
+    } else if params.renewal {
+        current_agent_name().await?
+    } else {
+        let name = params
+            .claim_mapping
+            .name(&claims)
+            .ok_or(AppError::JwtMissingName)?;
+        Some(generate_agent_name(&name))
     };
 
     debug!("Generating keypair and CSR");
-    let (csr_pem, private_key_pem) = generate_key_and_csr(&sub)?;
+    let (csr_pem, private_key_pem) = generate_key_and_csr(&sub, params.key_type)?;
 
     debug!("Submitting CSR for signing, overwrite={}", params.overwrite);
-    let signed = submit_csr(
+    let signed = match submit_csr(
         &http,
         &params.endpoint,
         &token,
         &csr_pem,
         params.overwrite,
         agent_name.as_deref(),
+        params.device_id.as_deref(),
     )
-    .await?;
+    .await?
+    {
+        RegisterAgentResponse::Issued(signed) => signed,
+        RegisterAgentResponse::Pending(request) => {
+            await_approval(&http, &params.endpoint, &token, *request, claims.exp as u64).await?
+        }
+    };
 
     debug!("Saving certificate and private key");
+    // Servers issuing from an intermediate return the leaf plus intermediates
+    // so the agent can present a complete chain.
     save_cert_and_key(
         &params.cert_path,
         &params.key_path,
-        &signed.certificate_pem,
+        signed
+            .full_chain_pem
+            .as_deref()
+            .unwrap_or(&signed.certificate_pem),
         &private_key_pem,
         &params.ca_cert_path,
         Some(&signed.ca_cert_pem),
@@ -133,13 +208,14 @@ pub async fn run_oauth2_flow(params: &FlowParams) -> AppResult<()> {
     .await?;
 
     if params.agent_control {
-        if let Some(ref name) = agent_name {
+        if !params.renewal
+            && let Some(ref name) = agent_name
+        {
             debug!("Setting agent name");
             set_name(name).await?;
+            info!("Name set successfully!");
         }
 
-        info!("Name set successfully!");
-
         debug!("Restarting agent");
         restart_agent().await?;
     }
@@ -150,6 +226,7 @@ pub async fn run_oauth2_flow(params: &FlowParams) -> AppResult<()> {
 #[cfg(test)]
 mod tests {
     use super::FlowParams;
+    use crate::services::generate_csr::KeyType;
     use crate::shared::cli::Opt;
 
     #[test]
@@ -164,9 +241,12 @@ mod tests {
             cert_path: "/tmp/client.cert".to_string(),
             ca_cert_path: "/tmp/ca.pem".to_string(),
             key_path: "/tmp/client.key".to_string(),
+            key_type: KeyType::EcP256,
             agent_control: false,
             timeout_secs: 120,
             overwrite: true,
+            device_id: Some("laptop-01".to_string()),
+            claim_name: "upn, name".to_string(),
         };
 
         let params = FlowParams::from(opt);
@@ -180,7 +260,42 @@ mod tests {
         assert_eq!(params.cert_path, "/tmp/client.cert");
         assert_eq!(params.ca_cert_path, "/tmp/ca.pem");
         assert_eq!(params.key_path, "/tmp/client.key");
+        assert_eq!(params.key_type, KeyType::EcP256);
         assert!(!params.agent_control);
         assert!(params.overwrite);
+        assert_eq!(params.device_id.as_deref(), Some("laptop-01"));
+        assert_eq!(params.claim_mapping.name, ["upn", "name"]);
+        assert!(!params.renewal);
+    }
+
+    #[test]
+    fn renew_opt_maps_to_service_account_overwrite() {
+        let opt = Opt::Renew {
+            issuer: "https://issuer.example/realms/demo".to_string(),
+            audience: "account".to_string(),
+            client_id: "client-id".to_string(),
+            client_secret: "client-secret".to_string(),
+            endpoint: "https://cert.example/api/register-agent".to_string(),
+            cert_path: "/tmp/client.cert".to_string(),
+            ca_cert_path: "/tmp/ca.pem".to_string(),
+            key_path: "/tmp/client.key".to_string(),
+            key_type: KeyType::Ed25519,
+            agent_control: true,
+            timeout_secs: 120,
+            device_id: None,
+            renew_fraction: 0.5,
+            check_interval_secs: 60,
+            min_backoff_secs: 1,
+            max_backoff_secs: 10,
+            once: true,
+        };
+
+        let params = FlowParams::from(opt);
+
+        assert!(params.is_service_account);
+        assert!(params.overwrite);
+        assert!(params.renewal);
+        assert_eq!(params.client_secret.as_deref(), Some("client-secret"));
+        assert_eq!(params.cert_path(), "/tmp/client.cert");
     }
 }
@@ -1,13 +1,17 @@
 #[macro_use]
 extern crate log;
 
+use std::time::Duration;
+
 use crate::flow::{FlowParams, run_oauth2_flow};
+use crate::renew::{RenewSchedule, run_renew};
 use crate::shared::cli::Opt;
 use clap::Parser;
 use env_logger::{Builder, Env};
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
 mod flow;
+mod renew;
 mod services;
 pub mod shared;
 
@@ -30,11 +34,31 @@ async fn main() {
 
 /// Orchestrates the CSR flow: stop agent, obtain token, validate claims,
 /// generate CSR and key, submit CSR, save cert+key, set agent name, restart agent.
+/// The `renew` subcommand repeats it unattended before the certificate expires.
 async fn app() -> AppResult<()> {
     match Opt::try_parse() {
         Ok(opt) => {
-            let params = FlowParams::from(opt);
-            run_oauth2_flow(&params).await?;
+            let params = FlowParams::from(opt.clone());
+            match opt {
+                Opt::OAuth2 { .. } => run_oauth2_flow(&params).await?,
+                Opt::Renew {
+                    renew_fraction,
+                    check_interval_secs,
+                    min_backoff_secs,
+                    max_backoff_secs,
+                    once,
+                    ..
+                } => {
+                    let schedule = RenewSchedule {
+                        fraction: renew_fraction,
+                        check_interval: Duration::from_secs(check_interval_secs),
+                        min_backoff: Duration::from_secs(min_backoff_secs),
+                        max_backoff: Duration::from_secs(max_backoff_secs),
+                        once,
+                    };
+                    run_renew(&params, &schedule).await?
+                }
+            }
 
             Ok(())
         }
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use openssl::x509::X509;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::services::time::{asn1_to_unix, unix_now};
+
+use crate::flow::{FlowParams, run_oauth2_flow};
+
+/// Shortest retry delay accepted, so a failing server is never retried in a
+/// tight loop.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// When and how often the installed certificate is renewed.
+#[derive(Debug, Clone)]
+pub struct RenewSchedule {
+    /// Fraction of the lifetime (0, 1] after which the certificate is renewed.
+    pub fraction: f64,
+    pub check_interval: Duration,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Check once and exit instead of looping.
+    pub once: bool,
+}
+
+/// Watch the installed certificate and re-enroll once it is due.
+///
+/// Failed renewals are retried with exponential backoff and jitter until one
+/// succeeds; with `once` set the first failure is returned instead.
+pub async fn run_renew(params: &FlowParams, schedule: &RenewSchedule) -> AppResult<()> {
+    if !(schedule.fraction > 0.0 && schedule.fraction <= 1.0) {
+        return Err(AppError::ValidationError(format!(
+            "renew fraction must be in (0, 1], got {}",
+            schedule.fraction
+        )));
+    }
+    if schedule.min_backoff < MIN_BACKOFF {
+        return Err(AppError::ValidationError(format!(
+            "min backoff must be at least {MIN_BACKOFF:?}, got {:?}",
+            schedule.min_backoff
+        )));
+    }
+
+    let mut just_renewed = false;
+    loop {
+        let (not_before, not_after) = read_cert_validity(params.cert_path()).await?;
+        let due = renewal_due_at(not_before, not_after, schedule.fraction);
+        let now = unix_now();
+
+        if now >= due && just_renewed {
+            // A replacement that is already due (e.g. clamped to the CA's own
+            // expiry) must not turn into a tight renewal loop.
+            warn!(
+                "Renewed certificate is already due; next attempt in {:?}",
+                schedule.check_interval
+            );
+            just_renewed = false;
+            tokio::time::sleep(schedule.check_interval).await;
+            continue;
+        }
+        if now >= due {
+            info!("Certificate is due for renewal (notAfter={})", not_after);
+            let mut attempt = 0;
+            loop {
+                match run_oauth2_flow(params).await {
+                    Ok(()) => {
+                        info!("Certificate renewed");
+                        break;
+                    }
+                    Err(e) if schedule.once => return Err(e),
+                    Err(e) => {
+                        let delay = backoff_delay(
+                            attempt,
+                            schedule.min_backoff,
+                            schedule.max_backoff,
+                            rand::random::<f64>(),
+                        );
+                        warn!("Renewal failed: {}; retrying in {:?}", e, delay);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                }
+            }
+            if schedule.once {
+                return Ok(());
+            }
+            // Re-read the new certificate to schedule the next renewal.
+            just_renewed = true;
+            continue;
+        }
+
+        if schedule.once {
+            info!("Certificate not due for renewal until {}", due);
+            return Ok(());
+        }
+        just_renewed = false;
+        let wait = Duration::from_secs(due - now).min(schedule.check_interval);
+        debug!("Next renewal check in {:?}", wait);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// `(notBefore, notAfter)` of the PEM certificate at `path`, in unix seconds.
+async fn read_cert_validity(path: &str) -> AppResult<(u64, u64)> {
+    let pem = tokio::fs::read(path).await?;
+    let cert = X509::from_pem(&pem)?;
+    Ok((
+        asn1_to_unix(cert.not_before())?,
+        asn1_to_unix(cert.not_after())?,
+    ))
+}
+
+/// Unix time at which `fraction` of the certificate lifetime has elapsed.
+fn renewal_due_at(not_before: u64, not_after: u64, fraction: f64) -> u64 {
+    let lifetime = not_after.saturating_sub(not_before);
+    not_before + (lifetime as f64 * fraction) as u64
+}
+
+/// Exponential backoff capped at `max`, with the upper half jittered by
+/// `jitter` in `[0, 1)` so agents that lost the server together spread out.
+fn backoff_delay(attempt: u32, min: Duration, max: Duration, jitter: f64) -> Duration {
+    let base = min.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+    base / 2 + base.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff_delay, read_cert_validity, renewal_due_at};
+    use crate::shared::test_support::temp_dir;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509, X509NameBuilder};
+    use std::time::Duration;
+
+    #[test]
+    fn renewal_is_due_after_fraction_of_lifetime() {
+        assert_eq!(renewal_due_at(1_000, 2_000, 0.5), 1_500);
+        assert_eq!(renewal_due_at(1_000, 2_000, 1.0), 2_000);
+        assert_eq!(renewal_due_at(2_000, 1_000, 0.5), 2_000);
+    }
+
+    #[test]
+    fn backoff_doubles_and_is_capped() {
+        let min = Duration::from_secs(30);
+        let max = Duration::from_secs(3600);
+        assert_eq!(backoff_delay(0, min, max, 0.0), Duration::from_secs(15));
+        assert_eq!(
+            backoff_delay(0, min, max, 0.999),
+            Duration::from_millis(29_985)
+        );
+        assert_eq!(backoff_delay(2, min, max, 0.0), Duration::from_secs(60));
+        assert_eq!(backoff_delay(40, min, max, 0.0), Duration::from_secs(1800));
+        assert!(backoff_delay(40, min, max, 0.999) <= max);
+    }
+
+    #[tokio::test]
+    async fn reads_validity_from_installed_certificate() {
+        let key = PKey::from_rsa(Rsa::generate(2048).expect("rsa")).expect("pkey");
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", "agent").expect("cn");
+        let name = name.build();
+        let mut builder = X509::builder().expect("builder");
+        builder.set_subject_name(&name).expect("subject");
+        builder.set_issuer_name(&name).expect("issuer");
+        builder.set_pubkey(&key).expect("pubkey");
+        let not_before = Asn1Time::from_unix(1_700_000_000).expect("time");
+        let not_after = Asn1Time::from_unix(1_702_592_000).expect("time");
+        builder.set_not_before(&not_before).expect("not before");
+        builder.set_not_after(&not_after).expect("not after");
+        builder.sign(&key, MessageDigest::sha256()).expect("sign");
+        let pem = builder.build().to_pem().expect("pem");
+
+        let dir = temp_dir("client-renew-test");
+        tokio::fs::create_dir_all(&dir).await.expect("temp dir");
+        let path = dir.join("cert.pem");
+        tokio::fs::write(&path, pem).await.expect("write cert");
+
+        let validity = read_cert_validity(&path.to_string_lossy())
+            .await
+            .expect("cert should parse");
+        assert_eq!(validity, (1_700_000_000, 1_702_592_000));
+
+        let _ = tokio::fs::remove_dir_all(dir).await;
+    }
+}
@@ -1,3 +1,19 @@
+use crate::shared::path::default_path_to_ossec_conf;
+use wazuh_cert_oauth2_model::models::errors::AppResult;
+
+/// Read the agent name currently configured in `ossec.conf`, if any.
+pub async fn current_agent_name() -> AppResult<Option<String>> {
+    let conf = tokio::fs::read_to_string(default_path_to_ossec_conf()).await?;
+    Ok(parse_agent_name(&conf))
+}
+
+fn parse_agent_name(conf: &str) -> Option<String> {
+    let start = conf.find("<agent_name>")? + "<agent_name>".len();
+    let end = start + conf[start..].find("</agent_name>")?;
+    let name = conf[start..end].trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
 /// Generate the Wazuh agent name from the subject claim.
 pub fn generate_agent_name(claim_name: &str) -> String {
     let name = diacritics::remove_diacritics(claim_name);
@@ -21,7 +37,7 @@ pub fn generate_agent_name(claim_name: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::generate_agent_name;
+    use super::{generate_agent_name, parse_agent_name};
 
     #[test]
     fn name_is_ascii_alphanumeric_with_dashes() {
@@ -45,4 +61,12 @@ mod tests {
             agent_name
         );
     }
+
+    #[test]
+    fn agent_name_is_read_from_ossec_conf() {
+        let conf = "<client>\n  <enrollment>\n    <agent_name> Alice-Bob-123abc </agent_name>\n  </enrollment>\n</client>";
+        assert_eq!(parse_agent_name(conf).as_deref(), Some("Alice-Bob-123abc"));
+        assert_eq!(parse_agent_name("<agent_name></agent_name>"), None);
+        assert_eq!(parse_agent_name("<client/>"), None);
+    }
 }
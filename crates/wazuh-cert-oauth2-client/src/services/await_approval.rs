@@ -0,0 +1,84 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where the server publishes enrollment `id`, next to the register endpoint.
+fn enrollment_url(endpoint: &str, id: &str) -> String {
+    let base = endpoint
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .map_or(endpoint, |(base, _)| base);
+    format!("{base}/enrollments/{id}")
+}
+
+/// Poll a held enrollment until an admin decides on it, giving up at
+/// `deadline_unix` (the expiry of `token`, which the polls authenticate with).
+pub async fn await_approval(
+    http: &HttpClient,
+    endpoint: &str,
+    token: &str,
+    request: EnrollmentRequest,
+    deadline_unix: u64,
+) -> AppResult<SignedCertResponse> {
+    let url = enrollment_url(endpoint, &request.id);
+    info!(
+        "Enrollment request {} is awaiting admin approval; waiting for a decision",
+        request.id
+    );
+    loop {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now + POLL_INTERVAL.as_secs() > deadline_unix {
+            return Err(AppError::UpstreamError(format!(
+                "enrollment request {} is still awaiting approval; run again once an admin approved it",
+                request.id
+            )));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let current: EnrollmentRequest = http.fetch_json_auth(&url, token).await?;
+        match current.status {
+            EnrollmentStatus::Pending => debug!("Enrollment request {} still pending", current.id),
+            EnrollmentStatus::Approved => {
+                info!("Enrollment request {} approved", current.id);
+                return current.certificate.ok_or_else(|| {
+                    AppError::UpstreamError(format!(
+                        "enrollment request {} approved without a certificate",
+                        current.id
+                    ))
+                });
+            }
+            EnrollmentStatus::Rejected => {
+                return Err(AppError::Forbidden(format!(
+                    "enrollment request {} was rejected: {}",
+                    current.id,
+                    current.reason.as_deref().unwrap_or("no reason given")
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::enrollment_url;
+
+    #[test]
+    fn enrollment_url_is_a_sibling_of_the_register_endpoint() {
+        assert_eq!(
+            enrollment_url("https://cert.example/api/register-agent", "ab12"),
+            "https://cert.example/api/enrollments/ab12"
+        );
+        assert_eq!(
+            enrollment_url("https://cert.example/api/register-agent/", "ab12"),
+            "https://cert.example/api/enrollments/ab12"
+        );
+    }
+}
@@ -1,16 +1,55 @@
+use clap::ValueEnum;
+use openssl::ec::{EcGroup, EcKey};
 use openssl::hash::MessageDigest;
 use openssl::nid::Nid;
-use openssl::pkey::PKey;
+use openssl::pkey::{PKey, Private};
 use openssl::rsa::Rsa;
 use openssl::x509::X509NameBuilder;
 use openssl::x509::X509ReqBuilder;
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
-/// Generate an RSA-2048 keypair and a PKCS#10 CSR with CN set to `sub`.
+/// Algorithm of the agent keypair; the server's key policy must allow it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyType {
+    /// RSA-2048.
+    Rsa,
+    /// ECDSA on P-256.
+    #[value(name = "p256")]
+    EcP256,
+    /// ECDSA on P-384.
+    #[value(name = "p384")]
+    EcP384,
+    Ed25519,
+}
+
+impl KeyType {
+    fn generate(self) -> AppResult<PKey<Private>> {
+        let ec = |nid| -> AppResult<PKey<Private>> {
+            let group = EcGroup::from_curve_name(nid)?;
+            Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+        };
+        match self {
+            Self::Rsa => Ok(PKey::from_rsa(Rsa::generate(2048)?)?),
+            Self::EcP256 => ec(Nid::X9_62_PRIME256V1),
+            Self::EcP384 => ec(Nid::SECP384R1),
+            Self::Ed25519 => Ok(PKey::generate_ed25519()?),
+        }
+    }
+
+    /// Ed25519 signs the message itself, without a separate digest.
+    fn digest(self) -> MessageDigest {
+        match self {
+            Self::Ed25519 => MessageDigest::null(),
+            Self::EcP384 => MessageDigest::sha384(),
+            Self::Rsa | Self::EcP256 => MessageDigest::sha256(),
+        }
+    }
+}
+
+/// Generate a keypair of `key_type` and a PKCS#10 CSR with CN set to `sub`.
 /// Returns (csr_pem, private_key_pem)
-pub fn generate_key_and_csr(sub: &str) -> AppResult<(String, String)> {
-    let rsa = Rsa::generate(2048)?;
-    let pkey = PKey::from_rsa(rsa)?;
+pub fn generate_key_and_csr(sub: &str, key_type: KeyType) -> AppResult<(String, String)> {
+    let pkey = key_type.generate()?;
 
     let mut name_builder = X509NameBuilder::new()?;
     name_builder.append_entry_by_nid(Nid::COMMONNAME, sub)?;
@@ -19,7 +58,7 @@ pub fn generate_key_and_csr(sub: &str) -> AppResult<(String, String)> {
     let mut req_builder = X509ReqBuilder::new()?;
     req_builder.set_pubkey(&pkey)?;
     req_builder.set_subject_name(&name)?;
-    req_builder.sign(&pkey, MessageDigest::sha256())?;
+    req_builder.sign(&pkey, key_type.digest())?;
     let csr = req_builder.build();
 
     let csr_pem = String::from_utf8(csr.to_pem()?)?;
@@ -30,15 +69,16 @@ pub fn generate_key_and_csr(sub: &str) -> AppResult<(String, String)> {
 
 #[cfg(test)]
 mod tests {
-    use super::generate_key_and_csr;
+    use super::{KeyType, generate_key_and_csr};
     use openssl::nid::Nid;
-    use openssl::pkey::PKey;
+    use openssl::pkey::{Id, PKey};
     use openssl::x509::X509Req;
 
     #[test]
     fn generated_csr_has_subject_cn_and_matches_private_key() {
         let subject = "agent-subject-123";
-        let (csr_pem, key_pem) = generate_key_and_csr(subject).expect("csr generation should work");
+        let (csr_pem, key_pem) =
+            generate_key_and_csr(subject, KeyType::Rsa).expect("csr generation should work");
 
         let csr = X509Req::from_pem(csr_pem.as_bytes()).expect("csr pem should parse");
         let key = PKey::private_key_from_pem(key_pem.as_bytes()).expect("key pem should parse");
@@ -57,4 +97,26 @@ mod tests {
             .to_string();
         assert_eq!(cn, subject);
     }
+
+    #[test]
+    fn generates_ec_and_ed25519_csrs() {
+        for (key_type, curve) in [
+            (KeyType::EcP256, Some(Nid::X9_62_PRIME256V1)),
+            (KeyType::EcP384, Some(Nid::SECP384R1)),
+            (KeyType::Ed25519, None),
+        ] {
+            let (csr_pem, key_pem) =
+                generate_key_and_csr("agent", key_type).expect("csr generation should work");
+            let csr = X509Req::from_pem(csr_pem.as_bytes()).expect("csr pem should parse");
+            let key = PKey::private_key_from_pem(key_pem.as_bytes()).expect("key pem should parse");
+            assert!(csr.verify(&key).expect("csr verification should run"));
+            match curve {
+                Some(nid) => assert_eq!(
+                    key.ec_key().expect("ec key").group().curve_name(),
+                    Some(nid)
+                ),
+                None => assert_eq!(key.id(), Id::ED25519),
+            }
+        }
+    }
 }
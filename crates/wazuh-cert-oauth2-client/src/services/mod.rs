@@ -1,4 +1,5 @@
 pub mod agent_name;
+pub mod await_approval;
 pub mod generate_csr;
 pub mod get_token;
 pub mod restart_agent;
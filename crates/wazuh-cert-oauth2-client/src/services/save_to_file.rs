@@ -3,7 +3,11 @@ use tokio::fs::{OpenOptions, create_dir_all};
 use tokio::io::AsyncWriteExt;
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
-/// Save the certificate (and optional chain) and the private key to files.
+/// Save the certificate and the private key, plus the CA bundle when given.
+///
+/// `certificate_pem` may carry the leaf followed by intermediates; it is
+/// written as-is so the agent presents the whole chain. `ca_chain_pem` is the
+/// CA bundle (issuing CA up to the root) written to `ca_cert_path`.
 pub async fn save_cert_and_key(
     cert_file: &str,
     key_file: &str,
@@ -20,7 +24,7 @@ pub async fn save_cert_and_key(
     write_with_permissions(key_file, private_key_pem).await?;
 
     if let Some(chain) = ca_chain_pem {
-        log::info!("Writing private key to file: {:?}", ca_cert_path);
+        log::info!("Writing CA bundle to file: {:?}", ca_cert_path);
         write_with_permissions(ca_cert_path, chain).await?;
     }
 
@@ -57,21 +61,12 @@ async fn create_parent_dir_if_not_exists(file_path: impl AsRef<Path>) -> AppResu
 #[cfg(test)]
 mod tests {
     use super::save_cert_and_key;
-    use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use crate::shared::test_support::temp_dir;
     use tokio::fs;
 
-    fn unique_temp_dir() -> PathBuf {
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("time should be monotonic")
-            .as_nanos();
-        std::env::temp_dir().join(format!("wazuh-client-save-test-{}", nanos))
-    }
-
     #[tokio::test]
     async fn save_cert_and_key_writes_all_files_when_chain_is_present() {
-        let dir = unique_temp_dir();
+        let dir = temp_dir("client-save-test");
         let cert_file = dir.join("nested").join("sslagent.cert");
         let key_file = dir.join("nested").join("sslagent.key");
         let ca_file = dir.join("nested").join("ca.pem");
@@ -109,7 +104,7 @@ mod tests {
 
     #[tokio::test]
     async fn save_cert_and_key_does_not_create_ca_file_when_chain_absent() {
-        let dir = unique_temp_dir();
+        let dir = temp_dir("client-save-test");
         let cert_file = dir.join("sslagent.cert");
         let key_file = dir.join("sslagent.key");
         let ca_file = dir.join("ca.pem");
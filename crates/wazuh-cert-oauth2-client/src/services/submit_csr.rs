@@ -1,9 +1,10 @@
+use wazuh_cert_oauth2_model::models::enrollment_request::RegisterAgentResponse;
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
-use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
 
-/// Submit a CSR to the server for signing.
+/// Submit a CSR to the server for signing; the server may instead hold it for
+/// an admin's approval.
 pub async fn submit_csr(
     http: &HttpClient,
     endpoint: &str,
@@ -11,11 +12,14 @@ pub async fn submit_csr(
     csr_pem: &str,
     overwrite: bool,
     agent_name: Option<&str>,
-) -> AppResult<SignedCertResponse> {
+    device_id: Option<&str>,
+) -> AppResult<RegisterAgentResponse> {
     let dto = SignCsrRequest {
         csr_pem: csr_pem.to_string(),
         overwrite: Some(overwrite),
         wazuh_agent_name: agent_name.map(|s| s.to_string()),
+        profile: None,
+        device_id: device_id.map(|s| s.to_string()),
     };
     http.post_json_auth(endpoint, token, &dto).await
 }
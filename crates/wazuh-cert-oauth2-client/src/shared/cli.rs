@@ -1,8 +1,9 @@
+use crate::services::generate_csr::KeyType;
 use crate::shared::path::{default_cert_path, default_key_path, default_server_ca_cert_path};
 use clap::ArgAction;
 use clap::Parser;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     version,
     name = "Wazuh Cert Auth CLI",
@@ -48,6 +49,10 @@ pub enum Opt {
         #[arg(env, long, default_value_t = default_key_path(), short = 'k')]
         key_path: String,
 
+        /// Algorithm of the generated agent key.
+        #[arg(env, long, value_enum, default_value_t = KeyType::Rsa)]
+        key_type: KeyType,
+
         #[arg(env, long, default_value_t = true, action = ArgAction::Set, default_missing_value = "true", num_args = 0..=1)]
         agent_control: bool,
 
@@ -55,12 +60,95 @@ pub enum Opt {
         timeout_secs: u64,
         #[arg(env, long, default_value_t = false, action = ArgAction::Set, default_missing_value = "true", num_args = 0..=1)]
         overwrite: bool,
+
+        /// Identifies this machine so enrolling it replaces only its own
+        /// certificate, leaving the user's other devices active.
+        #[arg(env, long)]
+        device_id: Option<String>,
+
+        /// Comma-separated JSON paths of the token claim the agent name is
+        /// derived from; the first present wins.
+        #[arg(env, long, default_value = "name,preferred_username")]
+        claim_name: String,
+    },
+    #[command(
+        about = "Re-enroll with service-account credentials before the certificate expires",
+        alias = "daemon"
+    )]
+    Renew {
+        #[arg(
+            env,
+            long,
+            default_value = "https://login.wazuh.adorsys.team/realms/adorsys"
+        )]
+        issuer: String,
+
+        #[arg(env, long, short = 'a', default_value = "account")]
+        audience: String,
+
+        #[arg(env, long, short = 'i', default_value = "adorsys-machine-client")]
+        client_id: String,
+
+        #[arg(env, long, short = 's', required = true)]
+        client_secret: String,
+
+        #[arg(
+            env,
+            long,
+            short = 'e',
+            default_value = "https://cert.wazuh.adorsys.team/api/register-agent"
+        )]
+        endpoint: String,
+
+        #[arg(env, long, default_value_t = default_server_ca_cert_path(), short = 'r')]
+        ca_cert_path: String,
+
+        #[arg(env, long, default_value_t = default_cert_path(), short = 'c')]
+        cert_path: String,
+
+        #[arg(env, long, default_value_t = default_key_path(), short = 'k')]
+        key_path: String,
+
+        /// Algorithm of the generated agent key.
+        #[arg(env, long, value_enum, default_value_t = KeyType::Rsa)]
+        key_type: KeyType,
+
+        #[arg(env, long, default_value_t = true, action = ArgAction::Set, default_missing_value = "true", num_args = 0..=1)]
+        agent_control: bool,
+
+        #[arg(env, long, default_value_t = 120, short = 't')]
+        timeout_secs: u64,
+
+        /// Device id sent with each renewal; use the one given at enrollment.
+        #[arg(env, long)]
+        device_id: Option<String>,
+
+        /// Fraction of the certificate lifetime after which it is renewed.
+        #[arg(env, long, default_value_t = 0.66)]
+        renew_fraction: f64,
+
+        /// Maximum seconds between checks of the installed certificate.
+        #[arg(env, long, default_value_t = 3600)]
+        check_interval_secs: u64,
+
+        /// First retry delay when the server is unreachable; doubles per attempt.
+        /// At least 1.
+        #[arg(env, long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+        min_backoff_secs: u64,
+
+        #[arg(env, long, default_value_t = 3600)]
+        max_backoff_secs: u64,
+
+        /// Check once, renew if due, and exit instead of running as a daemon.
+        #[arg(env, long, default_value_t = false, action = ArgAction::Set, default_missing_value = "true", num_args = 0..=1)]
+        once: bool,
     },
 }
 
 #[cfg(test)]
 mod tests {
     use super::Opt;
+    use crate::services::generate_csr::KeyType;
     use crate::shared::path::{default_cert_path, default_key_path, default_server_ca_cert_path};
     use clap::Parser;
 
@@ -77,7 +165,9 @@ mod tests {
                 ca_cert_path,
                 key_path,
                 agent_control,
+                key_type,
                 overwrite, // ignore
+                claim_name,
                 ..
             } => {
                 assert_eq!(issuer, "https://login.wazuh.adorsys.team/realms/adorsys");
@@ -91,8 +181,11 @@ mod tests {
                 assert_eq!(ca_cert_path, default_server_ca_cert_path());
                 assert_eq!(key_path, default_key_path());
                 assert!(agent_control);
+                assert_eq!(key_type, KeyType::Rsa);
                 assert!(!overwrite);
+                assert_eq!(claim_name, "name,preferred_username");
             }
+            other => panic!("unexpected subcommand: {other:?}"),
         }
     }
 
@@ -101,6 +194,7 @@ mod tests {
         let parsed = Opt::parse_from(["client", "o-auth2", "--agent-control=false"]);
         match parsed {
             Opt::OAuth2 { agent_control, .. } => assert!(!agent_control),
+            other => panic!("unexpected subcommand: {other:?}"),
         }
     }
 
@@ -109,6 +203,7 @@ mod tests {
         let parsed = Opt::parse_from(["client", "o-auth2", "--overwrite", "true"]);
         match parsed {
             Opt::OAuth2 { overwrite, .. } => assert!(overwrite),
+            other => panic!("unexpected subcommand: {other:?}"),
         }
     }
 
@@ -117,6 +212,64 @@ mod tests {
         let parsed = Opt::parse_from(["client", "o-auth2", "--overwrite=false"]);
         match parsed {
             Opt::OAuth2 { overwrite, .. } => assert!(!overwrite),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn device_id_is_optional() {
+        match Opt::parse_from(["client", "o-auth2"]) {
+            Opt::OAuth2 { device_id, .. } => assert_eq!(device_id, None),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+        let parsed = Opt::parse_from(["client", "o-auth2", "--device-id", "laptop-01"]);
+        match parsed {
+            Opt::OAuth2 { device_id, .. } => assert_eq!(device_id.as_deref(), Some("laptop-01")),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn key_type_is_selectable() {
+        let parsed = Opt::parse_from(["client", "o-auth2", "--key-type", "ed25519"]);
+        match parsed {
+            Opt::OAuth2 { key_type, .. } => assert_eq!(key_type, KeyType::Ed25519),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+        assert!(Opt::try_parse_from(["client", "o-auth2", "--key-type", "dsa"]).is_err());
+    }
+
+    #[test]
+    fn renew_cli_requires_secret_and_uses_schedule_defaults() {
+        assert!(Opt::try_parse_from(["client", "renew"]).is_err());
+        assert!(
+            Opt::try_parse_from([
+                "client",
+                "renew",
+                "--client-secret",
+                "s3cret",
+                "--min-backoff-secs",
+                "0"
+            ])
+            .is_err(),
+            "a zero backoff would retry in a tight loop"
+        );
+
+        let parsed = Opt::parse_from(["client", "daemon", "--client-secret", "s3cret"]);
+        match parsed {
+            Opt::Renew {
+                client_secret,
+                renew_fraction,
+                check_interval_secs,
+                once,
+                ..
+            } => {
+                assert_eq!(client_secret, "s3cret");
+                assert_eq!(renew_fraction, 0.66);
+                assert_eq!(check_interval_secs, 3600);
+                assert!(!once);
+            }
+            other => panic!("unexpected subcommand: {other:?}"),
         }
     }
 }
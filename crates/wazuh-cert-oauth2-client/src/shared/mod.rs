@@ -1,3 +1,5 @@
 pub mod cli;
 pub mod path;
 pub mod sed_command;
+#[cfg(test)]
+pub(crate) mod test_support;
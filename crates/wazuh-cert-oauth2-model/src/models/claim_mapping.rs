@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::claims::{Claims, RealmAccess};
+
+/// Where roles, groups, the display name and the email are found in a token.
+///
+/// Each entry is a list of dot-separated JSON paths into the token's claims,
+/// e.g. `resource_access.wazuh.roles`. Roles and groups are the union of
+/// every path holding a string or an array of strings; the name and email
+/// come from the first path holding a non-empty string. Paths missing from a
+/// token are skipped, so one mapping can serve several identity providers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClaimMapping {
+    pub roles: Vec<String>,
+    pub groups: Vec<String>,
+    pub name: Vec<String>,
+    pub email: Vec<String>,
+    /// Roles or groups that make their holder an admin.
+    pub admin_roles: Vec<String>,
+}
+
+impl Default for ClaimMapping {
+    /// Keycloak's layout: realm roles, `name` then `preferred_username`,
+    /// `email`, and `wazuh_admin` as the admin role.
+    fn default() -> Self {
+        Self {
+            roles: vec!["realm_access.roles".to_string()],
+            groups: Vec::new(),
+            name: vec!["name".to_string(), "preferred_username".to_string()],
+            email: vec!["email".to_string()],
+            admin_roles: vec!["wazuh_admin".to_string()],
+        }
+    }
+}
+
+/// Split a comma-separated list of paths or role names, dropping blanks.
+pub fn parse_claim_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn lookup<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(claims, |value, key| value.as_object()?.get(key))
+}
+
+impl ClaimMapping {
+    /// Roles and groups of `claims`, in path order and without duplicates.
+    pub fn roles(&self, claims: &Claims) -> Vec<String> {
+        let raw = claims.to_value();
+        let mut found: Vec<String> = Vec::new();
+        for path in self.roles.iter().chain(&self.groups) {
+            let values = match lookup(&raw, path) {
+                Some(Value::String(s)) => vec![s.as_str()],
+                Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+                _ => continue,
+            };
+            for value in values {
+                if !found.iter().any(|f| f == value) {
+                    found.push(value.to_string());
+                }
+            }
+        }
+        found
+    }
+
+    /// Display name of the holder of `claims`.
+    pub fn name(&self, claims: &Claims) -> Option<String> {
+        Self::first_string(&self.name, claims)
+    }
+
+    /// Email address of the holder of `claims`.
+    pub fn email(&self, claims: &Claims) -> Option<String> {
+        Self::first_string(&self.email, claims)
+    }
+
+    /// Whether any mapped role or group of `claims` is an admin role.
+    pub fn is_admin(&self, claims: &Claims) -> bool {
+        let roles = self.roles(claims);
+        self.admin_roles.iter().any(|admin| roles.contains(admin))
+    }
+
+    /// `claims` with `name`, `email` and `realm_access.roles` replaced by
+    /// the mapped values, so role and name checks see what the mapping
+    /// found. The original claims stay readable in [`Claims::extra`].
+    pub fn apply(&self, claims: Claims) -> Claims {
+        let roles = self.roles(&claims);
+        Claims {
+            name: self.name(&claims),
+            email: self.email(&claims),
+            realm_access: Some(RealmAccess { roles }),
+            ..claims
+        }
+    }
+
+    fn first_string(paths: &[String], claims: &Claims) -> Option<String> {
+        let raw = claims.to_value();
+        paths.iter().find_map(|path| {
+            lookup(&raw, path)
+                .and_then(Value::as_str)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClaimMapping, parse_claim_list};
+    use crate::models::claims::Claims;
+
+    fn claims(json: serde_json::Value) -> Claims {
+        serde_json::from_value(json).expect("claims should parse")
+    }
+
+    #[test]
+    fn default_mapping_reads_keycloak_claims() {
+        let claims = claims(serde_json::json!({
+            "sub": "s", "iss": "i", "exp": 1,
+            "preferred_username": "jdoe",
+            "realm_access": {"roles": ["wazuh_admin"]},
+        }));
+        let mapping = ClaimMapping::default();
+        assert_eq!(mapping.name(&claims).as_deref(), Some("jdoe"));
+        assert!(mapping.is_admin(&claims));
+        assert_eq!(mapping.email(&claims), None);
+    }
+
+    #[test]
+    fn mapping_reads_groups_and_client_roles() {
+        let claims = claims(serde_json::json!({
+            "sub": "s", "iss": "i", "exp": 1,
+            "name": "",
+            "upn": "jdoe@example.com",
+            "display": "Jane Doe",
+            "groups": ["pki-admins", "staff"],
+            "resource_access": {"wazuh": {"roles": ["staff", "operator"]}},
+        }));
+        let mapping = ClaimMapping {
+            roles: parse_claim_list("realm_access.roles, resource_access.wazuh.roles"),
+            groups: parse_claim_list("groups"),
+            name: parse_claim_list("name,display"),
+            email: parse_claim_list("upn"),
+            admin_roles: parse_claim_list("pki-admins"),
+        };
+        assert_eq!(mapping.roles(&claims), ["staff", "operator", "pki-admins"]);
+        assert_eq!(mapping.name(&claims).as_deref(), Some("Jane Doe"));
+        assert!(mapping.is_admin(&claims));
+
+        let applied = mapping.apply(claims);
+        assert_eq!(applied.email.as_deref(), Some("jdoe@example.com"));
+        assert!(applied.has_any_role(&["operator"]));
+        assert!(applied.extra.contains_key("groups"));
+    }
+}
@@ -1,6 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-#[derive(Deserialize, Clone, Debug)]
+use super::claim_mapping::ClaimMapping;
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct Claims {
     pub sub: String,
     #[serde(default)]
@@ -10,11 +13,29 @@ pub struct Claims {
     #[serde(default)]
     pub preferred_username: Option<String>,
     #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
     pub realm_access: Option<RealmAccess>,
-    // Add other claims as needed
+    /// Authentication context class, e.g. a level of assurance.
+    #[serde(default)]
+    pub acr: Option<String>,
+    /// Authentication methods used, e.g. `pwd`, `otp`, `mfa` (RFC 8176).
+    #[serde(default)]
+    pub amr: Option<Vec<String>>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    /// When the user last authenticated (unix seconds).
+    #[serde(default)]
+    pub auth_time: Option<u64>,
+    /// Client the token was issued to.
+    #[serde(default)]
+    pub azp: Option<String>,
+    /// Every other claim of the token, read through a [`ClaimMapping`].
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Deserialize, Clone, Debug, Default)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct RealmAccess {
     #[serde(default)]
     pub roles: Vec<String>,
@@ -27,10 +48,25 @@ impl Claims {
             .or_else(|| self.preferred_username.clone())
     }
 
+    /// Whether the default [`ClaimMapping`] makes the holder an admin.
     pub fn is_admin(&self) -> bool {
+        ClaimMapping::default().is_admin(self)
+    }
+
+    /// The claims as the JSON object they were read from.
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// Whether any of `roles` is present in `realm_access.roles`.
+    pub fn has_any_role<S: AsRef<str>>(&self, roles: &[S]) -> bool {
         self.realm_access
             .as_ref()
-            .map(|ra| ra.roles.iter().any(|r| r == "wazuh_admin"))
+            .map(|ra| {
+                ra.roles
+                    .iter()
+                    .any(|r| roles.iter().any(|wanted| wanted.as_ref() == r))
+            })
             .unwrap_or(false)
     }
 }
@@ -46,7 +82,9 @@ mod tests {
             iss: "https://issuer.example/realms/main".to_string(),
             exp: 9_999_999_999,
             preferred_username: None,
+            email: None,
             realm_access: None,
+            ..Default::default()
         }
     }
 
@@ -93,6 +131,18 @@ mod tests {
         assert!(!claims.is_admin());
     }
 
+    #[test]
+    fn has_any_role_matches_configured_role_names() {
+        use super::RealmAccess;
+        let mut claims = base_claims();
+        claims.realm_access = Some(RealmAccess {
+            roles: vec!["pki-operator".to_string()],
+        });
+        assert!(claims.has_any_role(&["cert-admin", "pki-operator"]));
+        assert!(!claims.has_any_role(&["wazuh_admin"]));
+        assert!(!claims.has_any_role::<&str>(&[]));
+    }
+
     #[test]
     fn is_admin_returns_false_when_realm_access_missing() {
         let claims = base_claims();
@@ -12,6 +12,13 @@ pub struct DiscoveryDocument {
     pub extra: Value, // to capture additional fields
 }
 
+impl DiscoveryDocument {
+    /// Token introspection endpoint (RFC 7662), if the issuer has one.
+    pub fn introspection_endpoint(&self) -> Option<&str> {
+        self.extra.get("introspection_endpoint")?.as_str()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::DiscoveryDocument;
@@ -35,5 +42,6 @@ mod tests {
             doc.extra["userinfo_endpoint"],
             "https://issuer.example/userinfo"
         );
+        assert_eq!(doc.introspection_endpoint(), None);
     }
 }
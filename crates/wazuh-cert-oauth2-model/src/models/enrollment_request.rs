@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::signed_cert_response::SignedCertResponse;
+
+/// Where an enrollment held for admin approval stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnrollmentStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl EnrollmentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+
+    /// Parse the lowercase form used in the API and the ledger.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(Self::Pending),
+            "approved" => Some(Self::Approved),
+            "rejected" => Some(Self::Rejected),
+            _ => None,
+        }
+    }
+}
+
+/// An enrollment that needs admin approval before its certificate is signed.
+///
+/// `register-agent` answers `202 Accepted` with this document; the requester
+/// polls `/api/enrollments/<id>` until it is approved (and carries the
+/// certificate) or rejected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollmentRequest {
+    pub id: String,
+    pub subject: String,
+    pub status: EnrollmentStatus,
+    pub created_at_unix: u64,
+    #[serde(default)]
+    pub realm: Option<String>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub wazuh_agent_name: Option<String>,
+    #[serde(default)]
+    pub decided_at_unix: Option<u64>,
+    /// Subject of the admin who approved or rejected the request.
+    #[serde(default)]
+    pub decided_by: Option<String>,
+    /// Reason given for a rejection.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Serial of the certificate issued on approval.
+    #[serde(default)]
+    pub serial_hex: Option<String>,
+    /// The issued certificate, once approved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<SignedCertResponse>,
+}
+
+/// Answer of `register-agent`: the certificate, or the request held for
+/// approval (`202 Accepted`).
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RegisterAgentResponse {
+    Issued(SignedCertResponse),
+    Pending(Box<EnrollmentRequest>),
+}
+
+/// Body of `POST /api/enrollments/<id>/reject`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RejectEnrollmentRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnrollmentRequest, EnrollmentStatus, RegisterAgentResponse};
+
+    #[test]
+    fn pending_request_round_trip() {
+        let json =
+            r#"{"id":"ab12","subject":"user-a","status":"pending","created_at_unix":1700000000}"#;
+        let parsed: EnrollmentRequest = serde_json::from_str(json).expect("parse should work");
+        assert_eq!(parsed.status, EnrollmentStatus::Pending);
+        assert!(parsed.certificate.is_none());
+
+        let out = serde_json::to_string(&parsed).expect("serialize should work");
+        assert!(out.contains(r#""status":"pending""#));
+        assert!(!out.contains("certificate"));
+        for status in [
+            EnrollmentStatus::Pending,
+            EnrollmentStatus::Approved,
+            EnrollmentStatus::Rejected,
+        ] {
+            assert_eq!(EnrollmentStatus::parse(status.as_str()), Some(status));
+        }
+    }
+
+    #[test]
+    fn register_response_distinguishes_issued_from_pending() {
+        let issued: RegisterAgentResponse =
+            serde_json::from_str(r#"{"certificate_pem":"CERT","ca_cert_pem":"CA"}"#)
+                .expect("issued should parse");
+        assert!(matches!(issued, RegisterAgentResponse::Issued(s) if s.certificate_pem == "CERT"));
+
+        let pending: RegisterAgentResponse = serde_json::from_str(
+            r#"{"id":"ab12","subject":"user-a","status":"pending","created_at_unix":1}"#,
+        )
+        .expect("pending should parse");
+        assert!(matches!(pending, RegisterAgentResponse::Pending(r) if r.id == "ab12"));
+    }
+}
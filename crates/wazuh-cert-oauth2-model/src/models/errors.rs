@@ -26,6 +26,12 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     ValidationError(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
     #[error("Serialization error: {0}")]
     Serialization(String),
 
@@ -48,6 +54,12 @@ pub enum AppError {
     #[error("No matching JWK found for kid: {0}")]
     JwtKeyNotFound(String),
 
+    #[error("JWT issuer is not trusted: {0}")]
+    JwtUntrustedIssuer(String),
+
+    #[error("Token is not active")]
+    TokenInactive,
+
     // CSR / X509 policy
     #[error("CSR missing public key")]
     CsrMissingPublicKey,
@@ -55,10 +67,43 @@ pub enum AppError {
     #[error("CSR verification failed")]
     CsrVerificationFailed,
 
-    #[error("RSA key too small: {bits} bits (min 2048)")]
-    KeyPolicyRsaTooSmall { bits: usize },
+    #[error("CSR PEM too large: {bytes} bytes (max {max})")]
+    CsrTooLarge { bytes: usize, max: usize },
+
+    #[error("CSR is not a valid PEM certificate request")]
+    CsrInvalidPem,
+
+    #[error("CSR subject has no CN")]
+    CsrMissingCommonName,
+
+    #[error("CSR subject has more than one CN")]
+    CsrMultipleCommonNames,
+
+    #[error("CSR CN '{cn}' does not match the authenticated subject")]
+    CsrCommonNameMismatch { cn: String },
+
+    #[error("CSR extension request is malformed")]
+    CsrMalformedExtensions,
+
+    #[error("CSR requests a CA certificate")]
+    CsrRequestsCa,
+
+    #[error("CSR requests disallowed extension: {extension}")]
+    CsrExtensionNotAllowed { extension: String },
+
+    #[error("CSR requests a SAN the certificate would not carry: {name}")]
+    CsrSanNotAllowed { name: String },
+
+    #[error("Unknown signing profile: {name}")]
+    UnknownCertProfile { name: String },
+
+    #[error("RSA key too small: {bits} bits (min {min})")]
+    KeyPolicyRsaTooSmall { bits: usize, min: usize },
+
+    #[error("RSA key too large: {bits} bits (max {max})")]
+    KeyPolicyRsaTooLarge { bits: usize, max: usize },
 
-    #[error("Unsupported EC curve: {nid} (only P-256 allowed)")]
+    #[error("Unsupported EC curve: {nid}")]
     KeyPolicyUnsupportedEcCurve { nid: String },
 
     #[error("Unknown EC curve")]
@@ -136,11 +181,24 @@ impl<'r> Responder<'r, 'static> for AppError {
             #[cfg(feature = "postgres")]
             AppError::DatabaseError(_) => Status::BadGateway,
             AppError::Conflict(_) => Status::Conflict,
+            AppError::Forbidden(_) => Status::Forbidden,
+            AppError::NotFound(_) => Status::NotFound,
             AppError::RequestTokenError(_) => Status::ServiceUnavailable,
             AppError::CsrMissingPublicKey
             | AppError::SerdeError(_)
             | AppError::CsrVerificationFailed
+            | AppError::CsrTooLarge { .. }
+            | AppError::CsrInvalidPem
+            | AppError::CsrMissingCommonName
+            | AppError::CsrMultipleCommonNames
+            | AppError::CsrCommonNameMismatch { .. }
+            | AppError::CsrMalformedExtensions
+            | AppError::CsrRequestsCa
+            | AppError::CsrExtensionNotAllowed { .. }
+            | AppError::CsrSanNotAllowed { .. }
+            | AppError::UnknownCertProfile { .. }
             | AppError::KeyPolicyRsaTooSmall { .. }
+            | AppError::KeyPolicyRsaTooLarge { .. }
             | AppError::KeyPolicyUnsupportedEcCurve { .. }
             | AppError::KeyPolicyUnknownEcCurve
             | AppError::KeyPolicyUnsupportedKeyType { .. }
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Signing profile of certificates requested without one, and of ledger rows
+/// recorded before profiles existed.
+pub const DEFAULT_CERT_PROFILE: &str = "agent";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LedgerEntry {
     pub subject: String,
     pub serial_hex: String,
@@ -16,4 +20,38 @@ pub struct LedgerEntry {
     pub realm: Option<String>,
     #[serde(default)]
     pub wazuh_agent_name: Option<String>,
+    /// Certificate `notAfter` (unix seconds); absent for legacy rows.
+    #[serde(default)]
+    pub not_after_unix: Option<u64>,
+    /// Key identifier of the CA that issued the certificate; absent for
+    /// legacy rows and revoke-stubs, which belong to every issuer's CRL.
+    #[serde(default)]
+    pub issuer_key_id: Option<String>,
+    /// Signing profile the certificate was issued under; absent for legacy
+    /// rows, which count as [`DEFAULT_CERT_PROFILE`].
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Device the certificate was requested for; absent when the client sent
+    /// none and for legacy rows.
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// When the key was known or suspected to be compromised, for revoked
+    /// entries whose revoker gave one.
+    #[serde(default)]
+    pub invalidity_date_unix: Option<u64>,
+}
+
+impl LedgerEntry {
+    /// Signing profile of the entry, defaulting legacy rows to
+    /// [`DEFAULT_CERT_PROFILE`].
+    pub fn profile_name(&self) -> &str {
+        self.profile.as_deref().unwrap_or(DEFAULT_CERT_PROFILE)
+    }
+
+    /// Whether the certificate was issued to `subject` of the token issuer
+    /// `issuer`. Subjects are only unique per issuer, and rows recorded
+    /// without an issuer belong to nobody.
+    pub fn issued_to(&self, issuer: &str, subject: &str) -> bool {
+        self.subject == subject && self.issuer.as_deref() == Some(issuer)
+    }
 }
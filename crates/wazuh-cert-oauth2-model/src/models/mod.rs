@@ -1,7 +1,10 @@
+pub mod claim_mapping;
 pub mod claims;
 pub mod document;
+pub mod enrollment_request;
 pub mod errors;
 pub mod ledger_entry;
+pub mod revocation_reason;
 pub mod revoke_request;
 pub mod sign_csr_request;
 pub mod signed_cert_response;
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+/// RFC 5280 §5.3.1 `CRLReason`, serialized with the RFC's ASN.1 names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RevocationReason {
+    #[serde(rename = "unspecified")]
+    Unspecified,
+    #[serde(rename = "keyCompromise")]
+    KeyCompromise,
+    #[serde(rename = "cACompromise")]
+    CaCompromise,
+    #[serde(rename = "affiliationChanged")]
+    AffiliationChanged,
+    #[serde(rename = "superseded")]
+    Superseded,
+    #[serde(rename = "cessationOfOperation")]
+    CessationOfOperation,
+    #[serde(rename = "certificateHold")]
+    CertificateHold,
+    #[serde(rename = "removeFromCRL")]
+    RemoveFromCrl,
+    #[serde(rename = "privilegeWithdrawn")]
+    PrivilegeWithdrawn,
+    #[serde(rename = "aACompromise")]
+    AaCompromise,
+}
+
+impl RevocationReason {
+    const ALL: [RevocationReason; 10] = [
+        Self::Unspecified,
+        Self::KeyCompromise,
+        Self::CaCompromise,
+        Self::AffiliationChanged,
+        Self::Superseded,
+        Self::CessationOfOperation,
+        Self::CertificateHold,
+        Self::RemoveFromCrl,
+        Self::PrivilegeWithdrawn,
+        Self::AaCompromise,
+    ];
+
+    /// The ENUMERATED value carried in a CRL entry's reasonCode extension.
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Unspecified => 0,
+            Self::KeyCompromise => 1,
+            Self::CaCompromise => 2,
+            Self::AffiliationChanged => 3,
+            Self::Superseded => 4,
+            Self::CessationOfOperation => 5,
+            Self::CertificateHold => 6,
+            // 7 is unused.
+            Self::RemoveFromCrl => 8,
+            Self::PrivilegeWithdrawn => 9,
+            Self::AaCompromise => 10,
+        }
+    }
+
+    /// The reason with CRL reasonCode `code`, as sent in ACME revokeCert.
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.code() == code)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unspecified => "unspecified",
+            Self::KeyCompromise => "keyCompromise",
+            Self::CaCompromise => "cACompromise",
+            Self::AffiliationChanged => "affiliationChanged",
+            Self::Superseded => "superseded",
+            Self::CessationOfOperation => "cessationOfOperation",
+            Self::CertificateHold => "certificateHold",
+            Self::RemoveFromCrl => "removeFromCRL",
+            Self::PrivilegeWithdrawn => "privilegeWithdrawn",
+            Self::AaCompromise => "aACompromise",
+        }
+    }
+
+    /// Reason code of a ledger reason, read from its `<reasonName>` or
+    /// `<reasonName>: <text>` form as written for structured revocations.
+    ///
+    /// Free text is not guessed at: a wrong keyCompromise or certificateHold
+    /// misleads relying parties, so the reason is left out instead.
+    pub fn from_ledger(text: &str) -> Option<Self> {
+        let head = match text.split_once(':') {
+            Some((head, _)) => head,
+            None => text,
+        };
+        Self::from_name(head.trim())
+    }
+
+    /// Parse an RFC name, ignoring case and `-`/`_`/space separators
+    /// (`keyCompromise`, `key_compromise`, `Key Compromise`).
+    fn from_name(name: &str) -> Option<Self> {
+        let norm = |s: &str| {
+            s.chars()
+                .filter(|c| !matches!(c, '-' | '_' | ' '))
+                .collect::<String>()
+                .to_ascii_lowercase()
+        };
+        let name = norm(name);
+        Self::ALL.into_iter().find(|r| norm(r.as_str()) == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RevocationReason;
+
+    #[test]
+    fn only_structured_ledger_reasons_map_to_reason_codes() {
+        let cases = [
+            (
+                "keyCompromise: laptop stolen",
+                Some(RevocationReason::KeyCompromise),
+            ),
+            (
+                "cessation_of_operation",
+                Some(RevocationReason::CessationOfOperation),
+            ),
+            (
+                "superseded: auto-rotate (one cert per user)",
+                Some(RevocationReason::Superseded),
+            ),
+            ("not compromised, rotating", None),
+            ("household move", None),
+            ("almost expired", None),
+            ("auto-rotate (one cert per user)", None),
+            ("test", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(RevocationReason::from_ledger(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn serializes_with_rfc_names() {
+        let json = serde_json::to_string(&RevocationReason::CaCompromise).expect("serialize");
+        assert_eq!(json, r#""cACompromise""#);
+        let parsed: RevocationReason =
+            serde_json::from_str(r#""removeFromCRL""#).expect("deserialize");
+        assert_eq!(parsed, RevocationReason::RemoveFromCrl);
+        assert_eq!(parsed.code(), 8);
+    }
+}
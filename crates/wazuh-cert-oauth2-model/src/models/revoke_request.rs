@@ -1,15 +1,42 @@
 use serde::{Deserialize, Serialize};
 
+use super::revocation_reason::RevocationReason;
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct RevokeRequest {
     pub serial_hex: Option<String>,
     pub subject: Option<String>,
     pub reason: Option<String>,
+    /// Structured CRL reason; `reason` alone is kept as a comment only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason_code: Option<RevocationReason>,
+    /// When the key is known or suspected to have been compromised, in unix
+    /// seconds; published as the RFC 5280 invalidityDate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalidity_date_unix: Option<u64>,
+}
+
+impl RevokeRequest {
+    /// Reason as stored in the ledger: `<reasonName>: <text>` when a
+    /// structured code is given, so it survives as free text.
+    pub fn ledger_reason(&self) -> Option<String> {
+        let text = self
+            .reason
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+        match (self.reason_code, text) {
+            (Some(code), Some(text)) => Some(format!("{}: {}", code.as_str(), text)),
+            (Some(code), None) => Some(code.as_str().to_string()),
+            (None, text) => text.map(str::to_string),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::RevokeRequest;
+    use crate::models::revocation_reason::RevocationReason;
 
     #[test]
     fn revoke_request_serializes_and_deserializes() {
@@ -17,6 +44,8 @@ mod tests {
             serial_hex: Some("ABCD".to_string()),
             subject: Some("user-1".to_string()),
             reason: Some("test".to_string()),
+            reason_code: None,
+            invalidity_date_unix: None,
         };
 
         let json = serde_json::to_string(&req).expect("serialization should work");
@@ -26,4 +55,19 @@ mod tests {
         assert_eq!(parsed.subject.as_deref(), Some("user-1"));
         assert_eq!(parsed.reason.as_deref(), Some("test"));
     }
+
+    #[test]
+    fn structured_reason_codes_prefix_the_ledger_reason() {
+        let parsed: RevokeRequest = serde_json::from_str(
+            r#"{"serial_hex":"AB","reason":"laptop stolen","reason_code":"keyCompromise"}"#,
+        )
+        .expect("deserialization should work");
+        assert_eq!(parsed.reason_code, Some(RevocationReason::KeyCompromise));
+        let stored = parsed.ledger_reason().expect("reason");
+        assert_eq!(stored, "keyCompromise: laptop stolen");
+        assert_eq!(
+            RevocationReason::from_ledger(&stored),
+            Some(RevocationReason::KeyCompromise)
+        );
+    }
 }
@@ -7,6 +7,14 @@ pub struct SignCsrRequest {
     pub overwrite: Option<bool>,
     #[serde(default)]
     pub wazuh_agent_name: Option<String>,
+    /// Named signing profile to issue under; the default agent profile when
+    /// absent.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Stable identifier of the requesting device. Re-enrolling replaces
+    /// only the certificate of the same device.
+    #[serde(default)]
+    pub device_id: Option<String>,
 }
 
 #[cfg(test)]
@@ -19,11 +27,15 @@ mod tests {
             csr_pem: "-----BEGIN CERTIFICATE REQUEST-----...".to_string(),
             overwrite: Some(true),
             wazuh_agent_name: Some("DevOps-SRE-b7301d".to_string()),
+            profile: Some("manager".to_string()),
+            device_id: Some("laptop-01".to_string()),
         };
 
         let json = serde_json::to_string(&req).expect("serialize should work");
         let parsed: SignCsrRequest = serde_json::from_str(&json).expect("parse should work");
         assert_eq!(parsed.csr_pem, req.csr_pem);
+        assert_eq!(parsed.profile.as_deref(), Some("manager"));
+        assert_eq!(parsed.device_id.as_deref(), Some("laptop-01"));
     }
 
     #[test]
@@ -32,5 +44,7 @@ mod tests {
         let json = r#"{"csr_pem":"-----BEGIN CERTIFICATE REQUEST-----..."}"#;
         let parsed: SignCsrRequest = serde_json::from_str(json).expect("parse should work");
         assert_eq!(parsed.overwrite, None);
+        assert_eq!(parsed.profile, None);
+        assert_eq!(parsed.device_id, None);
     }
 }
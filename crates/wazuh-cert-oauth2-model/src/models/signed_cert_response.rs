@@ -1,9 +1,18 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SignedCertResponse {
+    /// Issued leaf certificate.
     pub certificate_pem: String,
+    /// CA bundle: the issuing CA followed by its issuers up to the root.
     pub ca_cert_pem: String,
+    /// Leaf followed by the intermediates needed to reach the root; absent
+    /// from older servers.
+    #[serde(default)]
+    pub full_chain_pem: Option<String>,
+    /// Certificate `notAfter` (unix seconds), so clients can plan renewal.
+    #[serde(default)]
+    pub not_after_unix: Option<u64>,
 }
 
 #[cfg(test)]
@@ -15,10 +24,22 @@ mod tests {
         let response = SignedCertResponse {
             certificate_pem: "CERT".to_string(),
             ca_cert_pem: "CA".to_string(),
+            full_chain_pem: Some("CERT\nINTERMEDIATE".to_string()),
+            not_after_unix: Some(1_700_000_000),
         };
         let json = serde_json::to_string(&response).expect("serialize should work");
         let parsed: SignedCertResponse = serde_json::from_str(&json).expect("parse should work");
         assert_eq!(parsed.certificate_pem, "CERT");
         assert_eq!(parsed.ca_cert_pem, "CA");
+        assert_eq!(parsed.not_after_unix, Some(1_700_000_000));
+        assert_eq!(parsed.full_chain_pem.as_deref(), Some("CERT\nINTERMEDIATE"));
+    }
+
+    #[test]
+    fn optional_fields_default_to_none_for_older_servers() {
+        let json = r#"{"certificate_pem":"CERT","ca_cert_pem":"CA"}"#;
+        let parsed: SignedCertResponse = serde_json::from_str(json).expect("parse should work");
+        assert_eq!(parsed.not_after_unix, None);
+        assert_eq!(parsed.full_chain_pem, None);
     }
 }
@@ -1,13 +1,56 @@
 use crate::models::claims::Claims;
 use crate::models::errors::{AppError, AppResult};
 use jsonwebtoken::{DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use serde::Deserialize;
 use tracing::debug;
 
+/// What a token must carry besides a valid signature.
+#[derive(Debug, Clone, Default)]
+pub struct TokenRequirements {
+    /// Accepted `aud` values; `None` skips the audience check.
+    pub audiences: Option<Vec<String>>,
+    /// Exact `iss` the token must carry; `None` skips the issuer check.
+    pub issuer: Option<String>,
+    /// Clock skew tolerated on `exp` and `nbf`, in seconds; `None` keeps the
+    /// default of 60.
+    pub leeway_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct UnverifiedIssuer {
+    iss: String,
+}
+
+/// The `iss` claim of `token`, read WITHOUT verifying the signature.
+///
+/// Only fit for choosing which issuer's keys to verify the token with; the
+/// value must be checked again by [`validate_token_with`].
+pub fn unverified_issuer(token: &str) -> AppResult<String> {
+    Ok(
+        jsonwebtoken::dangerous::insecure_decode::<UnverifiedIssuer>(token)?
+            .claims
+            .iss,
+    )
+}
+
 /// Validate the token using the provided JWKS.
 pub async fn validate_token(
     token: &str,
     jwks: &JwkSet,
     audiences: &Option<Vec<String>>,
+) -> AppResult<Claims> {
+    let requirements = TokenRequirements {
+        audiences: audiences.clone(),
+        ..Default::default()
+    };
+    validate_token_with(token, jwks, &requirements).await
+}
+
+/// Validate the token using the provided JWKS and `requirements`.
+pub async fn validate_token_with(
+    token: &str,
+    jwks: &JwkSet,
+    requirements: &TokenRequirements,
 ) -> AppResult<Claims> {
     let header = decode_header(token)?;
     debug!("decoded header: {:?}", header);
@@ -31,11 +74,17 @@ pub async fn validate_token(
 
     debug!("validating token");
     let mut validation = Validation::new(header.alg);
-    if let Some(audiences) = &audiences {
+    if let Some(audiences) = &requirements.audiences {
         validation.set_audience(audiences);
     } else {
         validation.validate_aud = false;
     }
+    if let Some(issuer) = &requirements.issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    if let Some(leeway) = requirements.leeway_secs {
+        validation.leeway = leeway;
+    }
 
     debug!("decoding token");
     match decode::<Claims>(token, &key, &validation) {
@@ -52,7 +101,7 @@ pub async fn validate_token(
 
 #[cfg(test)]
 mod tests {
-    use super::validate_token;
+    use super::{TokenRequirements, unverified_issuer, validate_token, validate_token_with};
     use crate::models::errors::AppError;
     use jsonwebtoken::jwk::JwkSet;
     use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
@@ -107,4 +156,39 @@ mod tests {
         let err = validate_token(&token, &jwks, &None).await.unwrap_err();
         assert!(matches!(err, AppError::JwtKeyNotFound(k) if k == "missing-kid"));
     }
+
+    #[tokio::test]
+    async fn validate_token_with_requires_the_exact_issuer() {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("kid-1".to_string());
+        let token = encode(
+            &header,
+            &sample_claims(),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .expect("token should encode");
+        let jwks: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [{"kty": "oct", "kid": "kid-1", "alg": "HS256", "k": "c2VjcmV0"}]
+        }))
+        .expect("jwks should parse");
+        assert_eq!(
+            unverified_issuer(&token).expect("iss"),
+            "https://issuer.example/realms/test"
+        );
+
+        let mut requirements = TokenRequirements {
+            issuer: Some("https://issuer.example/realms/test".to_string()),
+            ..Default::default()
+        };
+        let claims = validate_token_with(&token, &jwks, &requirements)
+            .await
+            .expect("token should validate");
+        assert_eq!(claims.sub, "subject-1");
+
+        requirements.issuer = Some("https://issuer.example/realms/test/".to_string());
+        let err = validate_token_with(&token, &jwks, &requirements)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::JwtError(_)));
+    }
 }
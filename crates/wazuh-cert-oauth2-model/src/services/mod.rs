@@ -1,5 +1,7 @@
 pub mod http_client;
 pub mod jwks;
+pub mod refresh_cache;
+pub mod time;
 pub mod wazuh;
 
 #[cfg(feature = "rocket")]
@@ -0,0 +1,359 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::models::errors::{AppError, AppResult};
+use crate::services::http_client::HttpClient;
+
+/// How long a fetched document is used and how often it may be fetched.
+#[derive(Debug, Clone, Copy)]
+pub struct RefreshPolicy {
+    /// Age until which the document is used without fetching it again.
+    pub ttl: Duration,
+    /// Shortest time between two fetches, also when a refresh is forced.
+    pub min_refresh_interval: Duration,
+    /// How long past `ttl` the last good document is still served while
+    /// fetching it again fails.
+    pub max_stale: Duration,
+}
+
+impl RefreshPolicy {
+    /// `ttl` with at most one fetch per 10 seconds and an hour of staleness.
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            min_refresh_interval: Duration::from_secs(10),
+            max_stale: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// A JSON document fetched over HTTP and kept for reuse, such as an issuer's
+/// discovery document or JWKS.
+///
+/// Once older than its TTL, the cached copy is still returned while a
+/// background task fetches a new one. If that fails the copy keeps being
+/// served until `max_stale` has passed as well, so a briefly unreachable
+/// issuer does not fail every request.
+///
+/// One fetch runs at a time: callers that need the document while it is
+/// being fetched wait for that fetch instead of starting their own, and
+/// nothing is locked while it runs.
+pub struct RefreshingCache<T> {
+    policy: RefreshPolicy,
+    http: HttpClient,
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+struct Slot<T> {
+    value: Option<(Arc<T>, Instant)>,
+    last_attempt: Option<Instant>,
+    /// Closed when the fetch in flight, if any, finishes.
+    fetching: Option<watch::Receiver<()>>,
+}
+
+/// The fetch in flight. Dropping it, also when the fetching task is
+/// cancelled, wakes the callers waiting for it.
+struct Fetch<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+    _done: watch::Sender<()>,
+}
+
+impl<T> Drop for Fetch<T> {
+    fn drop(&mut self) {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner()).fetching = None;
+    }
+}
+
+impl<T> Clone for RefreshingCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy,
+            http: self.http.clone(),
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> RefreshingCache<T> {
+    pub fn new(policy: RefreshPolicy, http: HttpClient) -> Self {
+        Self {
+            policy,
+            http,
+            slot: Arc::new(Mutex::new(Slot {
+                value: None,
+                last_attempt: None,
+                fetching: None,
+            })),
+        }
+    }
+
+    /// The document at `url`, fetched only when nothing usable is cached.
+    pub async fn get(&self, url: &str) -> AppResult<Arc<T>> {
+        let mut waited = false;
+        loop {
+            let turn = {
+                let mut slot = self.slot();
+                if let Some((value, fetched)) = &slot.value {
+                    let age = fetched.elapsed();
+                    if age < self.policy.ttl {
+                        return Ok(value.clone());
+                    }
+                    if age < self.policy.ttl + self.policy.max_stale {
+                        let value = value.clone();
+                        if slot.fetching.is_none() && self.may_fetch(&slot) {
+                            let fetch = self.begin_fetch(&mut slot);
+                            self.spawn_refresh(url.to_string(), fetch);
+                        }
+                        return Ok(value);
+                    }
+                }
+                if waited {
+                    return Err(AppError::UpstreamError(format!("could not fetch {url}")));
+                }
+                slot.fetching
+                    .clone()
+                    .ok_or_else(|| self.begin_fetch(&mut slot))
+            };
+            match turn {
+                // Errs once the fetch in flight is over.
+                Ok(mut done) => {
+                    let _ = done.changed().await;
+                }
+                Err(fetch) => return self.fetch(url, fetch).await,
+            }
+            waited = true;
+        }
+    }
+
+    /// Fetch the document at `url` now, e.g. because it lacks a key a token
+    /// names. Within `min_refresh_interval` of the last fetch the cached
+    /// copy is returned instead, and it is also returned when fetching
+    /// fails and it is not too stale yet.
+    pub async fn refresh(&self, url: &str) -> AppResult<Arc<T>> {
+        let fetch = loop {
+            let mut done = {
+                let mut slot = self.slot();
+                if !self.may_fetch(&slot)
+                    && let Some((value, _)) = &slot.value
+                {
+                    debug!("not refetching {url}: fetched too recently");
+                    return Ok(value.clone());
+                }
+                match slot.fetching.clone() {
+                    Some(done) => done,
+                    None => break self.begin_fetch(&mut slot),
+                }
+            };
+            let _ = done.changed().await;
+        };
+        match self.fetch(url, fetch).await {
+            Ok(value) => Ok(value),
+            Err(e) => match &self.slot().value {
+                Some((value, fetched))
+                    if fetched.elapsed() < self.policy.ttl + self.policy.max_stale =>
+                {
+                    warn!("could not refetch {url}, serving the cached copy: {e}");
+                    Ok(value.clone())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Store `value` as if it had just been fetched.
+    pub async fn insert(&self, value: T) {
+        self.slot().value = Some((Arc::new(value), Instant::now()));
+    }
+
+    fn slot(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn may_fetch(&self, slot: &Slot<T>) -> bool {
+        slot.last_attempt
+            .is_none_or(|at| at.elapsed() >= self.policy.min_refresh_interval)
+    }
+
+    fn begin_fetch(&self, slot: &mut Slot<T>) -> Fetch<T> {
+        let (done, waiting) = watch::channel(());
+        slot.fetching = Some(waiting);
+        slot.last_attempt = Some(Instant::now());
+        Fetch {
+            slot: self.slot.clone(),
+            _done: done,
+        }
+    }
+
+    /// Run `fetch` and store what it got, then wake its waiters.
+    async fn fetch(&self, url: &str, fetch: Fetch<T>) -> AppResult<Arc<T>> {
+        let value = Arc::new(self.http.fetch_json::<T>(url).await?);
+        self.slot().value = Some((value.clone(), Instant::now()));
+        drop(fetch);
+        Ok(value)
+    }
+
+    fn spawn_refresh(&self, url: String, fetch: Fetch<T>) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = cache.fetch(&url, fetch).await {
+                warn!("could not refetch {url}, serving the cached copy: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    use super::{RefreshPolicy, RefreshingCache};
+    use crate::services::http_client::HttpClient;
+
+    /// Serves `body` as JSON, or `503` while it is `None`, counting requests.
+    struct Issuer {
+        url: String,
+        body: Arc<Mutex<Option<Value>>>,
+        hits: Arc<AtomicUsize>,
+    }
+
+    async fn issuer(body: Value) -> Issuer {
+        slow_issuer(body, Duration::ZERO).await
+    }
+
+    /// An issuer that waits `delay` before answering.
+    async fn slow_issuer(body: Value, delay: Duration) -> Issuer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let url = format!("http://{}/jwks", listener.local_addr().expect("addr"));
+        let body = Arc::new(Mutex::new(Some(body)));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let (served, counted) = (body.clone(), hits.clone());
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                counted.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+                let response = match served.lock().await.as_ref() {
+                    Some(body) => {
+                        let body = body.to_string();
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        )
+                    }
+                    None => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        Issuer { url, body, hits }
+    }
+
+    fn cache_with(ttl_ms: u64, min_interval_ms: u64) -> RefreshingCache<Value> {
+        RefreshingCache::new(
+            RefreshPolicy {
+                ttl: Duration::from_millis(ttl_ms),
+                min_refresh_interval: Duration::from_millis(min_interval_ms),
+                max_stale: Duration::from_secs(3600),
+            },
+            HttpClient::new_with_defaults().expect("client"),
+        )
+    }
+
+    #[tokio::test]
+    async fn forced_refresh_is_rate_limited_and_keeps_the_last_good_copy() {
+        let issuer = issuer(json!({"keys": 1})).await;
+        let cache = cache_with(3_600_000, 100);
+        assert_eq!(
+            *cache.get(&issuer.url).await.expect("get"),
+            json!({"keys": 1})
+        );
+
+        *issuer.body.lock().await = Some(json!({"keys": 2}));
+        let again = cache.refresh(&issuer.url).await.expect("refresh");
+        assert_eq!(*again, json!({"keys": 1}), "refetched too soon");
+        assert_eq!(issuer.hits.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let rotated = cache.refresh(&issuer.url).await.expect("refresh");
+        assert_eq!(*rotated, json!({"keys": 2}));
+
+        *issuer.body.lock().await = None;
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let kept = cache.refresh(&issuer.url).await.expect("last good copy");
+        assert_eq!(*kept, json!({"keys": 2}));
+        assert_eq!(issuer.hits.load(Ordering::SeqCst), 3);
+
+        // With nothing cached an unreachable issuer is an error.
+        assert!(cache_with(3_600_000, 0).get(&issuer.url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stale_copy_is_served_while_refetching_in_the_background() {
+        let issuer = issuer(json!({"keys": 1})).await;
+        let cache = cache_with(50, 0);
+        cache.get(&issuer.url).await.expect("get");
+
+        *issuer.body.lock().await = Some(json!({"keys": 2}));
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let stale = cache.get(&issuer.url).await.expect("stale copy");
+        assert_eq!(*stale, json!({"keys": 1}));
+        for _ in 0..100 {
+            if *cache.get(&issuer.url).await.expect("get") == json!({"keys": 2}) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            *cache.get(&issuer.url).await.expect("get"),
+            json!({"keys": 2})
+        );
+
+        // A failing issuer leaves the last good copy in place.
+        *issuer.body.lock().await = None;
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        for _ in 0..5 {
+            let kept = cache.get(&issuer.url).await.expect("last good copy");
+            assert_eq!(*kept, json!({"keys": 2}));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(issuer.hits.load(Ordering::SeqCst) >= 3);
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_fetch_without_blocking_the_cache() {
+        let issuer = slow_issuer(json!({"keys": 1}), Duration::from_millis(300)).await;
+        let cache = cache_with(3_600_000, 0);
+        let callers: Vec<_> = (0..5)
+            .map(|_| {
+                let (cache, url) = (cache.clone(), issuer.url.clone());
+                tokio::spawn(async move { cache.get(&url).await })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The cache stays usable while the fetch is in flight.
+        tokio::time::timeout(Duration::from_millis(100), cache.insert(json!({"keys": 0})))
+            .await
+            .expect("insert while fetching");
+        for caller in callers {
+            let value = caller.await.expect("join").expect("get");
+            assert_eq!(*value, json!({"keys": 1}));
+        }
+        assert_eq!(issuer.hits.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,23 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "openssl")]
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+
+#[cfg(feature = "openssl")]
+use crate::models::errors::AppResult;
+
+/// Current time in unix seconds; `0` if the clock is before the epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
+
+/// Convert an ASN.1 time to unix seconds.
+#[cfg(feature = "openssl")]
+pub fn asn1_to_unix(time: &Asn1TimeRef) -> AppResult<u64> {
+    let epoch = Asn1Time::from_unix(0)?;
+    let diff = epoch.diff(time)?;
+    Ok((diff.days as i64 * 86_400 + diff.secs as i64).max(0) as u64)
+}
@@ -0,0 +1,4 @@
+-- Certificate expiry rollback
+
+ALTER TABLE ledger_entry DROP COLUMN IF EXISTS not_after_unix;
+ALTER TABLE ledger_event DROP COLUMN IF EXISTS not_after_unix;
@@ -0,0 +1,8 @@
+-- Certificate expiry on ledger rows
+--
+-- Records each certificate's notAfter so renewal windows and expiry reports
+-- can be answered from the ledger. Nullable: rows issued before this
+-- migration have no recorded expiry.
+
+ALTER TABLE ledger_event ADD COLUMN not_after_unix BIGINT;
+ALTER TABLE ledger_entry ADD COLUMN not_after_unix BIGINT;
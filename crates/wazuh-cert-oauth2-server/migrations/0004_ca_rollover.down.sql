@@ -0,0 +1,14 @@
+-- Multiple issuing CAs rollback
+
+DROP TABLE IF EXISTS crl_cache;
+CREATE TABLE crl_cache (
+    id          SMALLINT PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    der         BYTEA       NOT NULL,
+    etag        TEXT        NOT NULL,
+    generation  BIGINT      NOT NULL,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+DROP INDEX IF EXISTS idx_entry_revoked_issuer;
+ALTER TABLE ledger_entry DROP COLUMN IF EXISTS issuer_key_id;
+ALTER TABLE ledger_event DROP COLUMN IF EXISTS issuer_key_id;
@@ -0,0 +1,21 @@
+-- Multiple issuing CAs (CA rollover)
+--
+-- Ledger rows record the key identifier of the CA that signed them so each
+-- CA's CRL only lists its own certificates. Nullable: untagged rows (issued
+-- before this migration, revoke-stubs) are listed in every issuer's CRL.
+--
+-- The CRL cache holds one row per issuer. It is a derived artifact and is
+-- rebuilt on demand, so the single-row table is replaced outright.
+
+ALTER TABLE ledger_event ADD COLUMN issuer_key_id TEXT;
+ALTER TABLE ledger_entry ADD COLUMN issuer_key_id TEXT;
+CREATE INDEX idx_entry_revoked_issuer ON ledger_entry (issuer_key_id) WHERE revoked;
+
+DROP TABLE crl_cache;
+CREATE TABLE crl_cache (
+    issuer_key_id TEXT PRIMARY KEY,
+    der           BYTEA       NOT NULL,
+    etag          TEXT        NOT NULL,
+    generation    BIGINT      NOT NULL,
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
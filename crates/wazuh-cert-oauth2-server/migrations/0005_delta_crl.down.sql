@@ -0,0 +1,6 @@
+-- Delta CRLs rollback
+
+ALTER TABLE crl_cache DROP COLUMN IF EXISTS delta_base_unix;
+ALTER TABLE crl_cache DROP COLUMN IF EXISTS delta_base_number;
+ALTER TABLE crl_cache DROP COLUMN IF EXISTS delta_etag;
+ALTER TABLE crl_cache DROP COLUMN IF EXISTS delta_der;
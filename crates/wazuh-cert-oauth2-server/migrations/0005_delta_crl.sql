@@ -0,0 +1,10 @@
+-- Delta CRLs
+--
+-- Each issuer's row also holds its latest delta CRL and the base it is
+-- relative to: the cRLNumber (generation) of the base CRL and when that base
+-- was chosen. All nullable: deltas are only produced when enabled.
+
+ALTER TABLE crl_cache ADD COLUMN delta_der BYTEA;
+ALTER TABLE crl_cache ADD COLUMN delta_etag TEXT;
+ALTER TABLE crl_cache ADD COLUMN delta_base_number BIGINT;
+ALTER TABLE crl_cache ADD COLUMN delta_base_unix BIGINT;
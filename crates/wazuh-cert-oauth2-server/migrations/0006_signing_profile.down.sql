@@ -0,0 +1,4 @@
+-- Named signing profiles rollback
+
+ALTER TABLE ledger_entry DROP COLUMN IF EXISTS profile;
+ALTER TABLE ledger_event DROP COLUMN IF EXISTS profile;
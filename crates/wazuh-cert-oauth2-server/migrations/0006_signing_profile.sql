@@ -0,0 +1,9 @@
+-- Named signing profiles
+--
+-- Ledger rows record the signing profile (agent, manager, ...) they were
+-- issued under, and the one-active-certificate rule applies per profile.
+-- Nullable: rows issued before this migration belong to the default `agent`
+-- profile.
+
+ALTER TABLE ledger_event ADD COLUMN profile TEXT;
+ALTER TABLE ledger_entry ADD COLUMN profile TEXT;
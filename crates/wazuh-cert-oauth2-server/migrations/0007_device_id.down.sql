@@ -0,0 +1,4 @@
+-- Per-device certificate quotas rollback
+
+ALTER TABLE ledger_entry DROP COLUMN IF EXISTS device_id;
+ALTER TABLE ledger_event DROP COLUMN IF EXISTS device_id;
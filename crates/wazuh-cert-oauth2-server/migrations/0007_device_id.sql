@@ -0,0 +1,9 @@
+-- Per-device certificate quotas
+--
+-- Ledger rows record the device a certificate was requested for, so
+-- re-enrolling one device only rotates that device's certificate. Nullable:
+-- rows without a device (legacy rows, clients that send none) are replaced by
+-- any enrollment of the same subject and profile.
+
+ALTER TABLE ledger_event ADD COLUMN device_id TEXT;
+ALTER TABLE ledger_entry ADD COLUMN device_id TEXT;
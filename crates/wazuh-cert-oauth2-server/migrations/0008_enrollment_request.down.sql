@@ -0,0 +1,3 @@
+-- Enrollments held for admin approval rollback
+
+DROP TABLE IF EXISTS enrollment_request;
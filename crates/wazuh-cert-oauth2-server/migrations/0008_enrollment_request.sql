@@ -0,0 +1,28 @@
+-- Enrollments held for admin approval
+--
+-- A request whose realm or roles require approval is stored here instead of
+-- being signed. Approving it signs the stored CSR as the stored token claims
+-- and records the certificate in the ledger as usual; the issued certificate
+-- is kept on the row so the requester can collect it.
+
+CREATE TABLE enrollment_request (
+    id               TEXT PRIMARY KEY,
+    subject          TEXT    NOT NULL,
+    status           TEXT    NOT NULL,  -- 'pending' | 'approved' | 'rejected'
+    created_at_unix  BIGINT  NOT NULL,
+    realm            TEXT,
+    profile          TEXT,
+    device_id        TEXT,
+    wazuh_agent_name TEXT,
+    decided_at_unix  BIGINT,
+    decided_by       TEXT,
+    reason           TEXT,
+    serial_hex       TEXT,
+    certificate      TEXT,              -- JSON SignedCertResponse once approved
+    csr_pem          TEXT    NOT NULL,
+    claims           TEXT    NOT NULL,  -- JSON claims of the requesting token
+    overwrite        BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at       TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX idx_enrollment_status ON enrollment_request (status, created_at_unix);
+CREATE INDEX idx_enrollment_subject ON enrollment_request (subject);
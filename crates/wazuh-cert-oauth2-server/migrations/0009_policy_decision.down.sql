@@ -0,0 +1,3 @@
+-- Decisions of the external policy hook rollback
+
+DROP TABLE IF EXISTS policy_decision;
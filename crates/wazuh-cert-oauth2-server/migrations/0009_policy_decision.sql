@@ -0,0 +1,19 @@
+-- Decisions of the external policy hook
+--
+-- Append-only: one row per consulted request, whether the endpoint allowed
+-- it, denied it or failed ('allow' | 'deny' | 'error_allow' | 'error_deny').
+
+CREATE TABLE policy_decision (
+    id               BIGSERIAL PRIMARY KEY,
+    subject          TEXT    NOT NULL,
+    decided_at_unix  BIGINT  NOT NULL,
+    outcome          TEXT    NOT NULL,
+    reason           TEXT,
+    profile          TEXT,
+    device_id        TEXT,
+    wazuh_agent_name TEXT,
+    client_ip        TEXT,
+    validity_days    INTEGER,
+    extra_sans       TEXT[]  NOT NULL DEFAULT '{}'
+);
+CREATE INDEX idx_policy_decision_subject ON policy_decision (subject, decided_at_unix);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_entry_revoked_at;
+DROP INDEX IF EXISTS idx_entry_agent_name;
+DROP INDEX IF EXISTS idx_entry_issuer_issued;
+DROP INDEX IF EXISTS idx_entry_realm_issued;
+DROP INDEX IF EXISTS idx_entry_issued;
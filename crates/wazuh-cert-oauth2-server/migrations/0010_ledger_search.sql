@@ -0,0 +1,12 @@
+-- Indexes for the ledger search API
+--
+-- Pages are ordered by (issued_at_unix, serial_hex) and continue after a
+-- cursor, so the filters usually given alone lead indexes that end in that
+-- order. Agent-name prefixes are matched as a byte-order range, hence
+-- COLLATE "C".
+
+CREATE INDEX idx_entry_issued ON ledger_entry (issued_at_unix, serial_hex);
+CREATE INDEX idx_entry_realm_issued ON ledger_entry (realm, issued_at_unix, serial_hex);
+CREATE INDEX idx_entry_issuer_issued ON ledger_entry (issuer, issued_at_unix, serial_hex);
+CREATE INDEX idx_entry_agent_name ON ledger_entry (wazuh_agent_name COLLATE "C");
+CREATE INDEX idx_entry_revoked_at ON ledger_entry (revoked_at_unix) WHERE revoked_at_unix IS NOT NULL;
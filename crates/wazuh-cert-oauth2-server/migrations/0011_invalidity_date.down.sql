@@ -0,0 +1,4 @@
+-- Invalidity dates of revoked certificates rollback
+
+ALTER TABLE ledger_entry DROP COLUMN IF EXISTS invalidity_date_unix;
+ALTER TABLE ledger_event DROP COLUMN IF EXISTS invalidity_date_unix;
@@ -0,0 +1,8 @@
+-- Invalidity dates of revoked certificates
+--
+-- When a revoker knows since when a key was compromised, the date is kept and
+-- published as the RFC 5280 invalidityDate of the CRL entry and OCSP
+-- response. Nullable: most revocations do not give one.
+
+ALTER TABLE ledger_event ADD COLUMN invalidity_date_unix BIGINT;
+ALTER TABLE ledger_entry ADD COLUMN invalidity_date_unix BIGINT;
@@ -0,0 +1,7 @@
+-- ACME state shared by every replica rollback
+
+DROP TABLE IF EXISTS acme_authz;
+DROP TABLE IF EXISTS acme_order;
+DROP TABLE IF EXISTS acme_account;
+DROP TABLE IF EXISTS acme_eab_key;
+DROP TABLE IF EXISTS acme_nonce;
@@ -0,0 +1,51 @@
+-- ACME state shared by every replica
+--
+-- Nonces, EAB keys awaiting their account, accounts, and orders with their
+-- authorizations. Bindings hold the JSON of the OIDC claims the account
+-- issues certificates as; identifiers and authorization ids are JSON arrays.
+
+CREATE TABLE acme_nonce (
+    nonce        TEXT PRIMARY KEY,
+    expires_unix BIGINT NOT NULL
+);
+CREATE INDEX idx_acme_nonce_expires ON acme_nonce (expires_unix);
+
+CREATE TABLE acme_eab_key (
+    kid              TEXT PRIMARY KEY,
+    hmac_key         BYTEA  NOT NULL,
+    binding          TEXT   NOT NULL,
+    expires_unix     BIGINT NOT NULL,
+    bound_until_unix BIGINT NOT NULL
+);
+
+CREATE TABLE acme_account (
+    id               TEXT PRIMARY KEY,
+    thumbprint       TEXT    NOT NULL UNIQUE,
+    jwk              TEXT    NOT NULL,
+    deactivated      BOOLEAN NOT NULL DEFAULT FALSE,
+    contact          TEXT[]  NOT NULL DEFAULT '{}',
+    binding          TEXT    NOT NULL,
+    bound_until_unix BIGINT  NOT NULL,
+    updated_at       TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE acme_order (
+    id           TEXT PRIMARY KEY,
+    account_id   TEXT   NOT NULL REFERENCES acme_account (id) ON DELETE CASCADE,
+    status       TEXT   NOT NULL,  -- 'ready' | 'processing' | 'valid' | 'invalid'
+    expires_unix BIGINT NOT NULL,
+    identifiers  TEXT   NOT NULL,
+    authz_ids    TEXT   NOT NULL,
+    certificate  TEXT,             -- PEM chain once valid
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX idx_acme_order_account ON acme_order (account_id, expires_unix);
+CREATE INDEX idx_acme_order_expires ON acme_order (expires_unix);
+
+CREATE TABLE acme_authz (
+    id           TEXT PRIMARY KEY,
+    account_id   TEXT   NOT NULL REFERENCES acme_account (id) ON DELETE CASCADE,
+    identifier   TEXT   NOT NULL,
+    expires_unix BIGINT NOT NULL
+);
+CREATE INDEX idx_acme_authz_expires ON acme_authz (expires_unix);
@@ -0,0 +1,646 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::http::uri::Origin;
+use rocket::serde::json::Json;
+use tracing::info;
+use wazuh_cert_oauth2_model::models::errors::AppError;
+
+use crate::handlers::middle::{ClientInfo, Principal};
+use crate::models::access_policy::AccessPolicy;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::oidc_state::OidcState;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::acme::{
+    AcmeReply, AcmeResult, AcmeState, Binding, EabCredentials, EabRequest, Jws,
+};
+use crate::shared::certs::{unix_now, validate_agent_name, validate_device_id};
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+
+/// Mint an External Account Binding key for the caller. The ACME account
+/// registered with it issues certificates as the caller, under the signing
+/// profile, device id and agent name given here. The caller's token must meet
+/// the enrollment rules now, and callers whose enrollments need approval
+/// cannot use ACME.
+#[post("/acme/eab", format = "application/json", data = "<dto>")]
+#[tracing::instrument(skip(dto, principal, signing, profiles, acme, approval, ledger), fields(sub = %principal.claims.sub))]
+pub async fn new_eab(
+    dto: Json<EabRequest>,
+    principal: Principal,
+    signing: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    acme: &State<AcmeState>,
+    approval: &State<ApprovalPolicy>,
+    ledger: &State<Ledger>,
+) -> Result<Json<EabCredentials>, AppError> {
+    info!("POST /acme/eab called");
+    signing
+        .enrollment_rules
+        .check(&principal.claims, unix_now())?;
+    if approval.requires_approval(&principal.claims, principal.is_admin) {
+        return Err(AppError::Forbidden(
+            "enrollments of this caller need admin approval; use /api/register-agent".into(),
+        ));
+    }
+    let EabRequest {
+        profile,
+        device_id,
+        wazuh_agent_name,
+    } = dto.into_inner();
+    profiles.select(profile.as_deref(), &principal.claims, principal.is_admin)?;
+    if let Some(ref device_id) = device_id {
+        validate_device_id(device_id)?;
+    }
+    if let Some(ref name) = wazuh_agent_name {
+        validate_agent_name(name)?;
+    }
+    let binding = Binding {
+        claims: principal.claims,
+        profile,
+        device_id,
+        wazuh_agent_name,
+    };
+    Ok(Json(acme.issue_eab(ledger, binding).await?))
+}
+
+#[get("/directory")]
+pub fn directory(acme: &State<AcmeState>) -> AcmeReply {
+    AcmeReply::json(Status::Ok, None, acme.directory())
+}
+
+#[head("/new-nonce")]
+pub fn head_new_nonce() -> AcmeReply {
+    AcmeReply::empty(Status::Ok)
+}
+
+#[get("/new-nonce")]
+pub fn get_new_nonce() -> AcmeReply {
+    AcmeReply::empty(Status::NoContent)
+}
+
+#[post("/new-account", format = "application/jose+json", data = "<jws>")]
+pub async fn new_account(
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.new_account(req, ledger).await
+}
+
+#[post("/account/<id>", format = "application/jose+json", data = "<jws>")]
+pub async fn account(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.account(req, id, ledger).await
+}
+
+#[post(
+    "/account/<id>/orders",
+    format = "application/jose+json",
+    data = "<jws>"
+)]
+pub async fn account_orders(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.account_orders(req, id, ledger).await
+}
+
+#[post("/new-order", format = "application/jose+json", data = "<jws>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn new_order(
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    oidc: &State<OidcState>,
+    access: &State<AccessPolicy>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.new_order(req, profile, profiles, oidc, access, ledger)
+        .await
+}
+
+#[post("/order/<id>", format = "application/jose+json", data = "<jws>")]
+pub async fn order(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.order(req, id, ledger).await
+}
+
+#[post("/authz/<id>", format = "application/jose+json", data = "<jws>")]
+pub async fn authz(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.authz(req, id, ledger).await
+}
+
+#[post(
+    "/order/<id>/finalize",
+    format = "application/jose+json",
+    data = "<jws>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn finalize(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    oidc: &State<OidcState>,
+    access: &State<AccessPolicy>,
+    approval: &State<ApprovalPolicy>,
+    ca: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+    webhook: &State<Option<WebhookNotifier>>,
+    policy: &State<Option<PolicyHook>>,
+    client: ClientInfo,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.finalize(
+        req,
+        id,
+        &client,
+        profile,
+        profiles,
+        oidc,
+        access,
+        approval,
+        ca,
+        ledger,
+        crl,
+        webhook.inner().as_ref(),
+        policy.inner().as_ref(),
+    )
+    .await
+}
+
+#[post("/cert/<id>", format = "application/jose+json", data = "<jws>")]
+pub async fn certificate(
+    id: &str,
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ledger: &State<Ledger>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.certificate(req, id, ledger).await
+}
+
+#[post("/revoke-cert", format = "application/jose+json", data = "<jws>")]
+pub async fn revoke_cert(
+    jws: Json<Jws>,
+    uri: &Origin<'_>,
+    acme: &State<AcmeState>,
+    ca: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+) -> AcmeResult<AcmeReply> {
+    let req = acme.verify(&jws, uri.path().as_str(), ledger).await?;
+    acme.revoke_cert(req, ca, ledger, crl).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64URL;
+    use openssl::bn::BigNumContext;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::sign::Signer;
+    use openssl::stack::Stack;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509, X509NameBuilder, X509ReqBuilder};
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::LocalResponse;
+    use serde_json::{Value, json};
+
+    use crate::handlers::acme_fairing::AcmeNonceFairing;
+    use crate::handlers::test_support::{TestServer, bearer};
+    use crate::handlers::{acme_api_routes, acme_routes};
+    use crate::shared::acme::{ACME_BASE, AcmeState};
+
+    const BASE_URL: &str = "http://localhost";
+
+    async fn start() -> TestServer {
+        start_bound_for(Duration::from_secs(3600)).await
+    }
+
+    /// Start with accounts bound for `binding_ttl`.
+    async fn start_bound_for(binding_ttl: Duration) -> TestServer {
+        TestServer::start_with(move |rocket| {
+            rocket
+                .manage(AcmeState::new(
+                    BASE_URL,
+                    Duration::from_secs(3600),
+                    binding_ttl,
+                ))
+                .attach(AcmeNonceFairing)
+                .mount(ACME_BASE, acme_routes())
+                .mount("/api", acme_api_routes())
+        })
+        .await
+    }
+
+    /// A minimal ACME client with a P-256 account key.
+    struct Client<'a> {
+        server: &'a TestServer,
+        key: PKey<Private>,
+        jwk: Value,
+        kid: Option<String>,
+    }
+
+    impl<'a> Client<'a> {
+        fn new(server: &'a TestServer) -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("group");
+            let ec = EcKey::generate(&group).expect("ec key");
+            let mut ctx = BigNumContext::new().expect("ctx");
+            let (mut x, mut y) = (
+                openssl::bn::BigNum::new().expect("x"),
+                openssl::bn::BigNum::new().expect("y"),
+            );
+            ec.public_key()
+                .affine_coordinates(&group, &mut x, &mut y, &mut ctx)
+                .expect("coordinates");
+            let jwk = json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": B64URL.encode(x.to_vec_padded(32).expect("x")),
+                "y": B64URL.encode(y.to_vec_padded(32).expect("y")),
+            });
+            Self {
+                server,
+                key: PKey::from_ec_key(ec).expect("pkey"),
+                jwk,
+                kid: None,
+            }
+        }
+
+        async fn nonce(&self) -> String {
+            let res = self
+                .server
+                .client
+                .get(format!("{ACME_BASE}/new-nonce"))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::NoContent);
+            res.headers()
+                .get_one("Replay-Nonce")
+                .expect("nonce")
+                .to_string()
+        }
+
+        fn jws(&self, path: &str, nonce: &str, payload: Option<&Value>) -> String {
+            let mut header = json!({
+                "alg": "ES256",
+                "nonce": nonce,
+                "url": format!("{BASE_URL}{path}"),
+            });
+            match &self.kid {
+                Some(kid) => header["kid"] = json!(kid),
+                None => header["jwk"] = self.jwk.clone(),
+            }
+            let protected = B64URL.encode(header.to_string());
+            let payload = payload
+                .map(|p| B64URL.encode(p.to_string()))
+                .unwrap_or_default();
+            let mut signer = Signer::new(MessageDigest::sha256(), &self.key).expect("signer");
+            let der = signer
+                .sign_oneshot_to_vec(format!("{protected}.{payload}").as_bytes())
+                .expect("sign");
+            let sig = EcdsaSig::from_der(&der).expect("sig");
+            let mut raw = sig.r().to_vec_padded(32).expect("r");
+            raw.extend(sig.s().to_vec_padded(32).expect("s"));
+            json!({
+                "protected": protected,
+                "payload": payload,
+                "signature": B64URL.encode(raw),
+            })
+            .to_string()
+        }
+
+        async fn post(&self, path: &str, payload: Option<&Value>) -> LocalResponse<'a> {
+            let nonce = self.nonce().await;
+            let path = format!("{ACME_BASE}{path}");
+            self.server
+                .client
+                .post(path.clone())
+                .header(ContentType::new("application", "jose+json"))
+                .body(self.jws(&path, &nonce, payload))
+                .dispatch()
+                .await
+        }
+
+        /// Register with an EAB key minted for `sub`.
+        async fn register(&mut self, sub: &str) -> LocalResponse<'a> {
+            self.register_as(sub, &[]).await
+        }
+
+        /// Register with an EAB key minted for `sub` holding `roles`.
+        async fn register_as(&mut self, sub: &str, roles: &[&str]) -> LocalResponse<'a> {
+            let res = self
+                .server
+                .client
+                .post("/api/acme/eab")
+                .header(ContentType::JSON)
+                .header(bearer(sub, roles))
+                .body("{}")
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+            let eab: Value = res.into_json().await.expect("eab");
+            let res = self
+                .post("/new-account", Some(&self.new_account_payload(&eab)))
+                .await;
+            if let Some(location) = res.headers().get_one("Location") {
+                self.kid = Some(location.to_string());
+            }
+            res
+        }
+
+        fn new_account_payload(&self, eab: &Value) -> Value {
+            let protected = B64URL.encode(
+                json!({
+                    "alg": "HS256",
+                    "kid": eab["kid"],
+                    "url": format!("{BASE_URL}{ACME_BASE}/new-account"),
+                })
+                .to_string(),
+            );
+            let payload = B64URL.encode(self.jwk.to_string());
+            let mac_key = B64URL
+                .decode(eab["hmac_key"].as_str().expect("key"))
+                .expect("b64");
+            let mac_key = PKey::hmac(&mac_key).expect("hmac");
+            let mac = Signer::new(MessageDigest::sha256(), &mac_key)
+                .expect("signer")
+                .sign_oneshot_to_vec(format!("{protected}.{payload}").as_bytes())
+                .expect("mac");
+            json!({
+                "termsOfServiceAgreed": true,
+                "externalAccountBinding": {
+                    "protected": protected,
+                    "payload": payload,
+                    "signature": B64URL.encode(mac),
+                },
+            })
+        }
+    }
+
+    async fn problem(res: LocalResponse<'_>) -> String {
+        let body: Value = res.into_json().await.expect("problem");
+        body["type"].as_str().expect("type").to_string()
+    }
+
+    fn csr_der(cn: &str, dns: &[&str]) -> String {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("group");
+        let key = PKey::from_ec_key(EcKey::generate(&group).expect("ec")).expect("pkey");
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", cn).expect("cn");
+        let mut req = X509ReqBuilder::new().expect("req");
+        req.set_subject_name(&name.build()).expect("subject");
+        req.set_pubkey(&key).expect("pubkey");
+        let mut san = SubjectAlternativeName::new();
+        for name in dns {
+            san.dns(name);
+        }
+        let mut exts = Stack::new().expect("stack");
+        exts.push(san.build(&req.x509v3_context(None)).expect("san"))
+            .expect("push");
+        req.add_extensions(&exts).expect("extensions");
+        req.sign(&key, MessageDigest::sha256()).expect("sign");
+        B64URL.encode(req.build().to_der().expect("der"))
+    }
+
+    fn path_of(url: &str) -> &str {
+        url.strip_prefix(&format!("{BASE_URL}{ACME_BASE}"))
+            .expect("ACME URL")
+    }
+
+    #[rocket::async_test]
+    async fn eab_bound_account_orders_finalizes_and_revokes() {
+        let server = start().await;
+        let res = server
+            .client
+            .get(format!("{ACME_BASE}/directory"))
+            .dispatch()
+            .await;
+        let directory: Value = res.into_json().await.expect("directory");
+        assert_eq!(directory["meta"]["externalAccountRequired"], true);
+
+        let mut client = Client::new(&server);
+        let res = client
+            .post("/new-account", Some(&json!({"termsOfServiceAgreed": true})))
+            .await;
+        assert_eq!(
+            problem(res).await,
+            "urn:ietf:params:acme:error:externalAccountRequired"
+        );
+        assert_eq!(client.register("user-a").await.status(), Status::Created);
+
+        let res = client
+            .post(
+                "/new-order",
+                Some(&json!({"identifiers": [{"type": "dns", "value": "other.example"}]})),
+            )
+            .await;
+        assert_eq!(
+            problem(res).await,
+            "urn:ietf:params:acme:error:rejectedIdentifier"
+        );
+
+        let res = client
+            .post(
+                "/new-order",
+                Some(&json!({"identifiers": [{"type": "dns", "value": "user-a"}]})),
+            )
+            .await;
+        assert_eq!(res.status(), Status::Created);
+        let order: Value = res.into_json().await.expect("order");
+        assert_eq!(order["status"], "ready");
+        let authz_url = order["authorizations"][0].as_str().expect("authz");
+        let res = client.post(path_of(authz_url), None).await;
+        let authz: Value = res.into_json().await.expect("authz");
+        assert_eq!(authz["status"], "valid");
+
+        let finalize = path_of(order["finalize"].as_str().expect("finalize")).to_string();
+        let res = client
+            .post(
+                &finalize,
+                Some(&json!({"csr": csr_der("user-a", &["user-b"])})),
+            )
+            .await;
+        assert_eq!(problem(res).await, "urn:ietf:params:acme:error:badCSR");
+
+        let res = client
+            .post(
+                "/new-order",
+                Some(&json!({"identifiers": [{"type": "dns", "value": "user-a"}]})),
+            )
+            .await;
+        let order: Value = res.into_json().await.expect("order");
+        let finalize = path_of(order["finalize"].as_str().expect("finalize")).to_string();
+        let res = client
+            .post(
+                &finalize,
+                Some(&json!({"csr": csr_der("user-a", &["user-a"])})),
+            )
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let order: Value = res.into_json().await.expect("order");
+        assert_eq!(order["status"], "valid");
+
+        let cert_url = order["certificate"].as_str().expect("certificate");
+        let res = client.post(path_of(cert_url), None).await;
+        assert_eq!(
+            res.content_type(),
+            Some(ContentType::new("application", "pem-certificate-chain"))
+        );
+        let pem = res.into_string().await.expect("pem");
+        let cert = X509::from_pem(pem.as_bytes()).expect("cert");
+        assert!(server.active("user-a").await);
+
+        let revoke =
+            json!({"certificate": B64URL.encode(cert.to_der().expect("der")), "reason": 1});
+        let mut intruder = Client::new(&server);
+        assert_eq!(intruder.register("user-b").await.status(), Status::Created);
+        let res = intruder.post("/revoke-cert", Some(&revoke)).await;
+        assert_eq!(res.status(), Status::Forbidden);
+        // Admins revoke through /api/revoke, not through their ACME account.
+        let mut admin = Client::new(&server);
+        assert_eq!(
+            admin.register_as("admin", &["wazuh_admin"]).await.status(),
+            Status::Created
+        );
+        let res = admin.post("/revoke-cert", Some(&revoke)).await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client.post("/revoke-cert", Some(&revoke)).await;
+        assert_eq!(res.status(), Status::Ok);
+        assert!(!server.active("user-a").await);
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        assert_eq!(entries[0].reason.as_deref(), Some("keyCompromise"));
+        let res = client.post("/revoke-cert", Some(&revoke)).await;
+        assert_eq!(
+            problem(res).await,
+            "urn:ietf:params:acme:error:alreadyRevoked"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn nonces_and_eab_keys_are_single_use() {
+        let server = start().await;
+        let res = server
+            .client
+            .post("/api/acme/eab")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &[]))
+            .body("{}")
+            .dispatch()
+            .await;
+        let eab: Value = res.into_json().await.expect("eab");
+
+        let client = Client::new(&server);
+        let path = format!("{ACME_BASE}/new-account");
+        let nonce = client.nonce().await;
+        let body = client.jws(&path, &nonce, Some(&client.new_account_payload(&eab)));
+        let send = |body: String| {
+            server
+                .client
+                .post(path.clone())
+                .header(ContentType::new("application", "jose+json"))
+                .body(body)
+                .dispatch()
+        };
+        assert_eq!(send(body.clone()).await.status(), Status::Created);
+        assert_eq!(
+            problem(send(body).await).await,
+            "urn:ietf:params:acme:error:badNonce"
+        );
+
+        // The same EAB key cannot bind a second account key.
+        let other = Client::new(&server);
+        let res = other
+            .post("/new-account", Some(&other.new_account_payload(&eab)))
+            .await;
+        assert_eq!(
+            problem(res).await,
+            "urn:ietf:params:acme:error:unauthorized"
+        );
+        let res = server
+            .client
+            .post("/api/acme/eab")
+            .header(ContentType::JSON)
+            .body("{}")
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn expired_bindings_are_refused_until_bound_again() {
+        let server = start_bound_for(Duration::from_secs(2)).await;
+        let mut client = Client::new(&server);
+        assert_eq!(client.register("user-a").await.status(), Status::Created);
+        let order = json!({"identifiers": [{"type": "dns", "value": "user-a"}]});
+        let res = client.post("/new-order", Some(&order)).await;
+        assert_eq!(res.status(), Status::Created);
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        let res = client.post("/new-order", Some(&order)).await;
+        let body: Value = res.into_json().await.expect("problem");
+        assert_eq!(body["type"], "urn:ietf:params:acme:error:unauthorized");
+        assert!(
+            body["detail"]
+                .as_str()
+                .expect("detail")
+                .contains("binding expired")
+        );
+
+        // A fresh key for another caller cannot take the account over.
+        let account = client.kid.take();
+        assert_eq!(client.register("user-b").await.status(), Status::Forbidden);
+        assert_eq!(client.register("user-a").await.status(), Status::Ok);
+        assert_eq!(client.kid, account);
+        let res = client.post("/new-order", Some(&order)).await;
+        assert_eq!(res.status(), Status::Created);
+    }
+}
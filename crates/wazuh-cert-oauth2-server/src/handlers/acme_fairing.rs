@@ -0,0 +1,39 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+use tracing::error;
+
+use crate::shared::acme::{ACME_BASE, AcmeState};
+use crate::shared::ledger::Ledger;
+
+/// Adds a fresh `Replay-Nonce` to every response under [`ACME_BASE`].
+///
+/// Nonces are recorded in the ledger so any replica accepts them, which
+/// takes an await that responders cannot do.
+pub struct AcmeNonceFairing;
+
+#[rocket::async_trait]
+impl Fairing for AcmeNonceFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "ACME Replay-Nonce",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !req.uri().path().starts_with(ACME_BASE) {
+            return;
+        }
+        let rocket = req.rocket();
+        let (Some(acme), Some(ledger)) = (rocket.state::<AcmeState>(), rocket.state::<Ledger>())
+        else {
+            return;
+        };
+        match acme.new_nonce(ledger).await {
+            Ok(nonce) => {
+                res.set_raw_header("Replay-Nonce", nonce);
+            }
+            Err(e) => error!("Failed to record ACME nonce: {}", e),
+        }
+    }
+}
@@ -5,14 +5,15 @@ use rocket::response::Responder;
 use rocket::serde::json::Json;
 
 use crate::handlers::crl_fairing::ExtractedClientEtag;
-use crate::models::ca_config::CaProvider;
-use crate::shared::crl::CrlState;
+use crate::models::ca_config::{CaProvider, IssuingCa};
 use crate::shared::crl::RevocationEntry;
 use crate::shared::crl::compute_etag;
+use crate::shared::crl::{CrlKind, CrlState};
 use crate::shared::ledger::Ledger;
 use openssl::asn1::Asn1Time;
 use openssl::x509::X509Crl;
 use std::io::Cursor;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::time;
 use tracing::{debug, error, info};
@@ -56,6 +57,7 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for CrlOrNotModified {
     }
 }
 
+/// CRL of the active CA.
 #[get("/crl/issuing.crl")]
 pub async fn get_crl(
     crl: &State<CrlState>,
@@ -64,10 +66,87 @@ pub async fn get_crl(
     client_etag: ExtractedClientEtag,
 ) -> Result<CrlOrNotModified, Status> {
     info!("GET /crl/issuing.crl requested");
+    let issuer = active_issuer(ca).await?;
+    serve_issuer_crl(issuer, CrlKind::Complete, crl, ledger, &client_etag.0).await
+}
+
+/// CRL of the active or a retiring CA, addressed as `/crl/<key id>.crl`.
+#[get("/crl/<file>", rank = 2)]
+pub async fn get_issuer_crl(
+    file: &str,
+    crl: &State<CrlState>,
+    ledger: &State<Ledger>,
+    ca: &State<CaProvider>,
+    client_etag: ExtractedClientEtag,
+) -> Result<CrlOrNotModified, Status> {
+    info!("GET /crl/{} requested", file);
+    let issuer = issuer_for_file(ca, file).await?;
+    serve_issuer_crl(issuer, CrlKind::Complete, crl, ledger, &client_etag.0).await
+}
+
+/// Delta CRL of the active CA; `404` unless delta CRLs are enabled.
+#[get("/crl/delta/issuing.crl")]
+pub async fn get_delta_crl(
+    crl: &State<CrlState>,
+    ledger: &State<Ledger>,
+    ca: &State<CaProvider>,
+    client_etag: ExtractedClientEtag,
+) -> Result<CrlOrNotModified, Status> {
+    info!("GET /crl/delta/issuing.crl requested");
+    if !crl.deltas_enabled() {
+        return Err(Status::NotFound);
+    }
+    let issuer = active_issuer(ca).await?;
+    serve_issuer_crl(issuer, CrlKind::Delta, crl, ledger, &client_etag.0).await
+}
+
+/// Delta CRL of one configured CA, addressed as `/crl/delta/<key id>.crl`.
+#[get("/crl/delta/<file>", rank = 2)]
+pub async fn get_issuer_delta_crl(
+    file: &str,
+    crl: &State<CrlState>,
+    ledger: &State<Ledger>,
+    ca: &State<CaProvider>,
+    client_etag: ExtractedClientEtag,
+) -> Result<CrlOrNotModified, Status> {
+    info!("GET /crl/delta/{} requested", file);
+    if !crl.deltas_enabled() {
+        return Err(Status::NotFound);
+    }
+    let issuer = issuer_for_file(ca, file).await?;
+    serve_issuer_crl(issuer, CrlKind::Delta, crl, ledger, &client_etag.0).await
+}
 
-    let client_etag = &client_etag.0;
+async fn active_issuer(ca: &CaProvider) -> Result<Arc<IssuingCa>, Status> {
+    ca.active().await.map_err(|e| {
+        error!("Failed to load CA: {}", e);
+        Status::InternalServerError
+    })
+}
+
+/// The CA addressed by a `<key id>.crl` file name.
+async fn issuer_for_file(ca: &CaProvider, file: &str) -> Result<Arc<IssuingCa>, Status> {
+    let key_id = file.strip_suffix(".crl").ok_or(Status::NotFound)?;
+    ca.issuer(key_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load CA: {}", e);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)
+}
 
-    let mut rx = crl.subscribe_rebuild();
+/// Serve the complete or delta CRL of `issuer`. Both kinds share the
+/// rebuild but have their own ETag, so long-polls on one are not woken by
+/// rebuilds that leave it unchanged.
+async fn serve_issuer_crl(
+    issuer: Arc<IssuingCa>,
+    kind: CrlKind,
+    crl: &State<CrlState>,
+    ledger: &State<Ledger>,
+    client_etag: &str,
+) -> Result<CrlOrNotModified, Status> {
+    let mut rx = crl.subscribe_rebuild(&issuer, kind);
 
     // Borrow both the cached ETag and body from the watch channel in one go.
     let (cached_etag, cached_body) = {
@@ -81,7 +160,7 @@ pub async fn get_crl(
         }
         None => {
             debug!("No cached CRL; reading from backend");
-            match crl.read_crl().await {
+            match crl.read_crl(&issuer, kind).await {
                 Ok(b) => b,
                 Err(e) => {
                     error!("Failed to read CRL: {}", e);
@@ -93,19 +172,11 @@ pub async fn get_crl(
 
     // If missing or expired, rebuild via mpsc and re-read
     if bytes.is_empty() || is_crl_expired(&bytes) {
-        info!("CRL missing or expired; triggering on-demand rebuild");
-        let (ca_cert, ca_key) = match ca.get().await {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to load CA for CRL rebuild: {}", e);
-                return Err(Status::InternalServerError);
-            }
-        };
-        let revs = ledger.revoked_as_revocations().await.map_err(|e| {
-            error!("Failed to load revocations for CRL rebuild: {}", e);
-            Status::InternalServerError
-        })?;
-        if let Err(e) = crl.request_rebuild(ca_cert, ca_key, revs).await {
+        info!(
+            "CRL of issuer {} missing or expired; triggering on-demand rebuild",
+            issuer.key_id
+        );
+        if let Err(e) = crl.rebuild(&issuer, ledger).await {
             error!("Failed to rebuild CRL: {}", e);
             return Err(Status::InternalServerError);
         }
@@ -126,11 +197,11 @@ pub async fn get_crl(
         // Use the ETag from the watch channel
         let etag = new_etag;
         debug!("CRL bytes length: {}, ETag: {}", bytes.len(), etag);
-        return serve_crl_or_long_poll(etag, bytes, client_etag, crl, &mut rx).await;
+        return serve_crl_or_long_poll(etag, bytes, client_etag, crl, &issuer, kind, &mut rx).await;
     }
 
     // Use the cached ETag from the watch channel when available; only
-    // re-hash when we fell through to a disk read without a cached ETag.
+    // re-hash when we fell through to a backend read without a cached ETag.
     let etag = if !cached_etag.is_empty() {
         cached_etag
     } else {
@@ -138,7 +209,7 @@ pub async fn get_crl(
     };
     debug!("CRL bytes length: {}, ETag: {}", bytes.len(), etag);
 
-    serve_crl_or_long_poll(etag, bytes, client_etag, crl, &mut rx).await
+    serve_crl_or_long_poll(etag, bytes, client_etag, crl, &issuer, kind, &mut rx).await
 }
 
 /// Serve the CRL immediately or enter the long-poll loop if the client's
@@ -148,7 +219,9 @@ async fn serve_crl_or_long_poll(
     mut bytes: Vec<u8>,
     client_etag: &str,
     crl: &State<CrlState>,
-    rx: &mut tokio::sync::watch::Receiver<(String, Option<std::sync::Arc<Vec<u8>>>)>,
+    issuer: &IssuingCa,
+    kind: CrlKind,
+    rx: &mut tokio::sync::watch::Receiver<(String, Option<Arc<Vec<u8>>>)>,
 ) -> Result<CrlOrNotModified, Status> {
     // --- Long-poll negotiation ---
     if !client_etag.is_empty() && *client_etag == etag {
@@ -201,7 +274,7 @@ async fn serve_crl_or_long_poll(
                         "CRL watch channel closed during long-poll; falling back to backend read"
                     );
                     bytes = crl
-                        .read_crl()
+                        .read_crl(issuer, kind)
                         .await
                         .map_err(|_| Status::InternalServerError)?;
                     if bytes.is_empty() || is_crl_expired(&bytes) {
@@ -231,10 +304,10 @@ async fn serve_crl_or_long_poll(
     Ok(CrlOrNotModified::Crl(CrlResponse { etag, body: bytes }))
 }
 
-/// Fetch the current revocation DB as JSON (admin/auth token recommended)
+/// Fetch the current revocation DB as JSON; admin only
 #[get("/revocations")]
 pub async fn get_revocations(
-    _token: crate::handlers::middle::JwtToken,
+    _token: crate::handlers::middle::AdminToken,
     ledger: &State<Ledger>,
 ) -> Result<Json<Vec<RevocationEntry>>, Status> {
     info!("GET /api/revocations requested");
@@ -274,9 +347,120 @@ fn is_crl_expired(bytes: &[u8]) -> bool {
 mod tests {
     use super::*;
 
+    use crate::handlers::test_support::{TestServer, bearer};
+    use rocket::http::{ContentType, Header, Status};
+
     #[test]
     fn expired_crl_detected() {
         // An empty / unparseable CRL should be treated as expired
         assert!(is_crl_expired(&[]));
     }
+
+    #[rocket::async_test]
+    async fn revocations_require_admin() {
+        let server = TestServer::start().await;
+
+        let res = server
+            .client
+            .get("/api/revocations")
+            .header(bearer("user-a", &[]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = server
+            .client
+            .get("/api/revocations")
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn crls_are_served_per_issuer() {
+        let server = TestServer::start_with_rollover().await;
+        let (retiring, _) = server.retiring.as_ref().expect("retiring ca");
+        let retiring_id = crate::models::ca_config::key_id(retiring).expect("key id");
+
+        let res = server
+            .client
+            .get(format!("/crl/{}.crl", retiring_id))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let etag = res.headers().get_one("ETag").expect("etag").to_string();
+        let crl = X509Crl::from_der(&res.into_bytes().await.expect("body")).expect("crl");
+        assert_eq!(
+            crl.issuer_name().to_der().expect("der"),
+            retiring.subject_name().to_der().expect("der")
+        );
+
+        let active = server.client.get("/crl/issuing.crl").dispatch().await;
+        assert_eq!(active.status(), Status::Ok);
+        assert_ne!(active.headers().get_one("ETag"), Some(etag.as_str()));
+
+        let res = server.client.get("/crl/00FF.crl").dispatch().await;
+        assert_eq!(res.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn delta_crls_have_their_own_etag_and_long_poll() {
+        let server = TestServer::start().await;
+        let issued = server.enroll("user-a").await;
+        let cert = openssl::x509::X509::from_pem(issued.certificate_pem.as_bytes()).expect("cert");
+        let serial = cert
+            .serial_number()
+            .to_bn()
+            .and_then(|bn| bn.to_hex_str().map(|h| h.to_string()))
+            .expect("serial");
+
+        let complete = server.client.get("/crl/issuing.crl").dispatch().await;
+        let delta = server.client.get("/crl/delta/issuing.crl").dispatch().await;
+        assert_eq!(delta.status(), Status::Ok);
+        assert_eq!(
+            delta.content_type(),
+            Some(ContentType::new("application", "pkix-crl"))
+        );
+        let etag = delta.headers().get_one("ETag").expect("etag").to_string();
+        assert_ne!(complete.headers().get_one("ETag"), Some(etag.as_str()));
+
+        // A long-poll on the delta wakes up when a revocation lands in it.
+        let poll = server
+            .client
+            .get("/crl/delta/issuing.crl")
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        let revoke = async {
+            time::sleep(Duration::from_millis(200)).await;
+            server
+                .client
+                .post("/api/revoke")
+                .header(ContentType::JSON)
+                .header(bearer("admin-1", &["wazuh_admin"]))
+                .body(format!(r#"{{"serial_hex":"{serial}"}}"#))
+                .dispatch()
+                .await
+                .status()
+        };
+        let (res, revoked) = tokio::join!(poll, revoke);
+        assert_eq!(revoked, Status::NoContent);
+        assert_eq!(res.status(), Status::Ok);
+        assert_ne!(res.headers().get_one("ETag"), Some(etag.as_str()));
+        let crl = X509Crl::from_der(&res.into_bytes().await.expect("body")).expect("crl");
+        let listed: Vec<_> = crl
+            .get_revoked()
+            .expect("revoked")
+            .iter()
+            .map(|r| {
+                r.serial_number()
+                    .to_bn()
+                    .expect("bn")
+                    .to_hex_str()
+                    .expect("hex")
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(listed, vec![serial]);
+    }
 }
@@ -1,8 +1,9 @@
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::request::{self, FromRequest, Request};
 
-/// Caches the `If-None-Match` header from `GET /crl/issuing.crl` as
-/// [`ExtractedClientEtag`] for the request.
+/// Caches the `If-None-Match` header from `GET /crl/*.crl` (the active CA's
+/// `issuing.crl` and each issuer's `<key id>.crl`) and `GET /crl/delta/*.crl`
+/// as [`ExtractedClientEtag`] for the request.
 ///
 /// Only reads requests; response handling is done by the handler.
 pub struct CrlEtagFairing;
@@ -23,8 +24,15 @@ impl<'r> FromRequest<'r> for ExtractedClientEtag {
     }
 }
 
-/// Path we intercept in `on_request` to avoid header parsing on unrelated routes.
-const CRL_PATH: &str = "/crl/issuing.crl";
+/// Paths we intercept in `on_request` to avoid header parsing on unrelated routes.
+const CRL_PREFIX: &str = "/crl/";
+const CRL_SUFFIX: &str = ".crl";
+
+fn is_crl_path(path: &str) -> bool {
+    path.strip_prefix(CRL_PREFIX)
+        .map(|file| file.strip_prefix("delta/").unwrap_or(file))
+        .is_some_and(|file| file.ends_with(CRL_SUFFIX) && !file.contains('/'))
+}
 
 /// Strips surrounding double-quotes and the weak validator prefix `W/` from an ETag header value.
 pub(crate) fn strip_etag(raw: &str) -> &str {
@@ -41,8 +49,8 @@ impl Fairing for CrlEtagFairing {
     }
 
     async fn on_request(&self, req: &mut Request<'_>, _data: &mut rocket::Data<'_>) {
-        // Only intercept GET /crl/issuing.crl
-        if req.method() != rocket::http::Method::Get || req.uri().path().as_str() != CRL_PATH {
+        // Only intercept GET /crl/*.crl
+        if req.method() != rocket::http::Method::Get || !is_crl_path(req.uri().path().as_str()) {
             return;
         }
 
@@ -59,7 +67,19 @@ impl Fairing for CrlEtagFairing {
 
 #[cfg(test)]
 mod tests {
-    use super::strip_etag;
+    use super::{is_crl_path, strip_etag};
+
+    /// The active CA's and per-issuer CRL paths, complete and delta, are
+    /// intercepted.
+    #[test]
+    fn matches_crl_paths() {
+        assert!(is_crl_path("/crl/issuing.crl"));
+        assert!(is_crl_path("/crl/3F2A9C.crl"));
+        assert!(is_crl_path("/crl/delta/issuing.crl"));
+        assert!(is_crl_path("/crl/delta/3F2A9C.crl"));
+        assert!(!is_crl_path("/crl/a/b.crl"));
+        assert!(!is_crl_path("/api/revocations"));
+    }
 
     /// Strips surrounding double-quotes from a raw ETag header value.
     #[test]
@@ -0,0 +1,386 @@
+use crate::handlers::middle::{AdminToken, Principal};
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::oidc_state::OidcState;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::{approve_enrollment, reject_enrollment};
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+use rocket::State;
+use rocket::serde::json::Json;
+use tracing::error;
+use wazuh_cert_oauth2_model::models::enrollment_request::{
+    EnrollmentRequest, EnrollmentStatus, RejectEnrollmentRequest,
+};
+use wazuh_cert_oauth2_model::models::errors::AppError;
+
+/// Enrollment requests, optionally only those in `status`; admin only
+#[get("/enrollments?<status>")]
+#[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub))]
+pub async fn list_enrollments(
+    token: AdminToken,
+    ledger: &State<Ledger>,
+    status: Option<String>,
+) -> Result<Json<Vec<EnrollmentRequest>>, AppError> {
+    let status = status
+        .map(|s| {
+            EnrollmentStatus::parse(&s).ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "status must be pending, approved or rejected, not '{s}'"
+                ))
+            })
+        })
+        .transpose()?;
+    Ok(Json(
+        ledger
+            .find_enrollments(status)
+            .await?
+            .into_iter()
+            .map(|e| e.request)
+            .collect(),
+    ))
+}
+
+/// One enrollment request, with the certificate once approved; admins or the
+/// requester
+#[get("/enrollments/<id>")]
+#[tracing::instrument(skip(principal, ledger), fields(sub = %principal.claims.sub))]
+pub async fn get_enrollment(
+    principal: Principal,
+    ledger: &State<Ledger>,
+    id: &str,
+) -> Result<Json<EnrollmentRequest>, AppError> {
+    let enrollment = ledger
+        .find_enrollment(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("enrollment request {id}")))?;
+    let request = enrollment.request;
+    if !principal.is_admin
+        && (principal.claims.sub != request.subject
+            || principal.claims.iss != enrollment.claims.iss)
+    {
+        return Err(AppError::Forbidden(
+            "not allowed to read enrollment requests of another subject".into(),
+        ));
+    }
+    Ok(Json(request))
+}
+
+/// Approve a pending enrollment and sign its certificate; admin only
+#[post("/enrollments/<id>/approve")]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(token, oidc, profile, profiles, config, ledger, crl, webhook, policy), fields(sub = %token.claims.sub))]
+pub async fn approve(
+    token: AdminToken,
+    id: &str,
+    oidc: &State<OidcState>,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    config: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+    webhook: &State<Option<WebhookNotifier>>,
+    policy: &State<Option<PolicyHook>>,
+) -> Result<Json<EnrollmentRequest>, AppError> {
+    approve_enrollment(
+        id,
+        &token.claims,
+        oidc.inner(),
+        profile.inner(),
+        profiles.inner(),
+        config.inner(),
+        ledger.inner(),
+        crl.inner(),
+        webhook.inner().as_ref(),
+        policy.inner().as_ref(),
+    )
+    .await
+    .map(Json)
+    .inspect_err(|e| error!("approving enrollment {} failed: {}", id, e))
+}
+
+/// Reject a pending enrollment with an optional reason; admin only
+#[post(
+    "/enrollments/<id>/reject",
+    format = "application/json",
+    data = "<dto>"
+)]
+#[tracing::instrument(skip(token, ledger, dto), fields(sub = %token.claims.sub))]
+pub async fn reject(
+    token: AdminToken,
+    id: &str,
+    ledger: &State<Ledger>,
+    dto: Json<RejectEnrollmentRequest>,
+) -> Result<Json<EnrollmentRequest>, AppError> {
+    Ok(Json(
+        reject_enrollment(id, &token.claims, dto.into_inner().reason, ledger.inner()).await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{TestServer, bearer, csr_pem};
+    use crate::models::approval_policy::ApprovalPolicy;
+    use crate::shared::certs::unix_now;
+    use crate::shared::ledger::{IssuedCert, PendingEnrollment};
+    use openssl::x509::X509;
+    use rocket::http::{ContentType, Status};
+    use serde_json::json;
+    use wazuh_cert_oauth2_model::models::claims::Claims;
+    use wazuh_cert_oauth2_model::models::enrollment_request::{
+        EnrollmentRequest, EnrollmentStatus,
+    };
+
+    async fn server() -> TestServer {
+        TestServer::start_with(|rocket| {
+            rocket.manage(ApprovalPolicy::new(Vec::new(), vec!["contractor".into()]))
+        })
+        .await
+    }
+
+    async fn request_enrollment(server: &TestServer, sub: &str) -> EnrollmentRequest {
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer(sub, &["contractor"]))
+            .body(json!({ "csr_pem": csr_pem(sub) }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Accepted);
+        let request: EnrollmentRequest = res.into_json().await.expect("json body");
+        assert_eq!(request.status, EnrollmentStatus::Pending);
+        request
+    }
+
+    #[rocket::async_test]
+    async fn approved_enrollment_is_signed_and_collected_by_the_requester() {
+        let server = server().await;
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+        let request = request_enrollment(&server, "contractor-a").await;
+        assert!(!server.active("contractor-a").await);
+
+        let path = format!("/api/enrollments/{}", request.id);
+        let res = server
+            .client
+            .get(path.clone())
+            .header(bearer("contractor-b", &["contractor"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+        let res = server
+            .client
+            .post(format!("{path}/approve"))
+            .header(bearer("contractor-a", &["contractor"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = server
+            .client
+            .get("/api/enrollments?status=pending")
+            .header(admin.clone())
+            .dispatch()
+            .await;
+        let pending: Vec<EnrollmentRequest> = res.into_json().await.expect("json body");
+        assert!(pending.iter().any(|r| r.id == request.id));
+
+        let res = server
+            .client
+            .post(format!("{path}/approve"))
+            .header(admin.clone())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        assert!(server.active("contractor-a").await);
+
+        let res = server
+            .client
+            .get(path.clone())
+            .header(bearer("contractor-a", &["contractor"]))
+            .dispatch()
+            .await;
+        let approved: EnrollmentRequest = res.into_json().await.expect("json body");
+        assert_eq!(approved.status, EnrollmentStatus::Approved);
+        assert_eq!(approved.decided_by.as_deref(), Some("admin-1"));
+        assert!(approved.serial_hex.is_some());
+        assert!(approved.certificate.is_some());
+
+        let res = server
+            .client
+            .post(format!("{path}/approve"))
+            .header(admin)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Conflict);
+    }
+
+    #[rocket::async_test]
+    async fn rejected_enrollment_is_never_signed() {
+        let server = server().await;
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+
+        // Requests that could never be signed fail right away.
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("contractor-a", &["contractor"]))
+            .body(json!({ "csr_pem": csr_pem("someone-else") }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+
+        let request = request_enrollment(&server, "contractor-a").await;
+        let path = format!("/api/enrollments/{}", request.id);
+        let res = server
+            .client
+            .post(format!("{path}/reject"))
+            .header(ContentType::JSON)
+            .header(admin.clone())
+            .body(json!({ "reason": "unknown device" }).to_string())
+            .dispatch()
+            .await;
+        let rejected: EnrollmentRequest = res.into_json().await.expect("json body");
+        assert_eq!(rejected.status, EnrollmentStatus::Rejected);
+        assert_eq!(rejected.reason.as_deref(), Some("unknown device"));
+
+        let res = server
+            .client
+            .post(format!("{path}/approve"))
+            .header(admin.clone())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Conflict);
+        assert!(!server.active("contractor-a").await);
+
+        let res = server
+            .client
+            .post("/api/enrollments/unknown/approve")
+            .header(admin)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn pending_requests_are_capped_per_subject() {
+        let server = server().await;
+        for _ in 0..5 {
+            request_enrollment(&server, "contractor-a").await;
+        }
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("contractor-a", &["contractor"]))
+            .body(json!({ "csr_pem": csr_pem("contractor-a") }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Conflict);
+        request_enrollment(&server, "contractor-b").await;
+    }
+
+    #[rocket::async_test]
+    async fn interrupted_approval_can_be_decided_again() {
+        let server = server().await;
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+        let request = request_enrollment(&server, "contractor-a").await;
+        // An approval that signed a certificate ten minutes ago but never
+        // recorded it on the request.
+        let stuck = EnrollmentRequest {
+            status: EnrollmentStatus::Approved,
+            decided_at_unix: Some(unix_now() - 600),
+            decided_by: Some("admin-1".into()),
+            serial_hex: Some("0A11".into()),
+            ..request.clone()
+        };
+        server
+            .ledger
+            .update_enrollment(stuck, EnrollmentStatus::Pending)
+            .await
+            .expect("update");
+        server.issue("contractor-a", "0A11").await;
+        // Issued under another profile in the meantime, not by this approval.
+        server
+            .ledger
+            .record_issued(IssuedCert {
+                subject: "contractor-a".into(),
+                serial_hex: "0B22".into(),
+                profile: Some("vpn".into()),
+                ..Default::default()
+            })
+            .await
+            .expect("record_issued");
+
+        let res = server
+            .client
+            .post(format!("/api/enrollments/{}/approve", request.id))
+            .header(admin)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let approved: EnrollmentRequest = res.into_json().await.expect("json body");
+        assert!(approved.certificate.is_some());
+        let orphan = server
+            .ledger
+            .find_by_serial("0A11")
+            .await
+            .expect("find")
+            .expect("entry");
+        assert!(orphan.revoked);
+        let other = server
+            .ledger
+            .find_by_serial("0B22")
+            .await
+            .expect("find")
+            .expect("entry");
+        assert!(!other.revoked);
+
+        let cert = approved.certificate.expect("certificate");
+        let serial = X509::from_pem(cert.certificate_pem.as_bytes())
+            .expect("pem")
+            .serial_number()
+            .to_bn()
+            .expect("bn")
+            .to_hex_str()
+            .expect("hex")
+            .to_string();
+        assert_eq!(approved.serial_hex, Some(serial));
+    }
+
+    #[rocket::async_test]
+    async fn approval_requires_a_still_trusted_issuer() {
+        let server = server().await;
+        let held = request_enrollment(&server, "contractor-a").await;
+        let request = EnrollmentRequest {
+            id: "from-a-dropped-issuer".into(),
+            ..held
+        };
+        server
+            .ledger
+            .record_enrollment(PendingEnrollment {
+                request: request.clone(),
+                csr_pem: csr_pem("contractor-a"),
+                claims: Claims {
+                    sub: "contractor-a".into(),
+                    iss: "https://dropped.example/realms/old".into(),
+                    ..Default::default()
+                },
+                overwrite: false,
+            })
+            .await
+            .expect("record_enrollment");
+
+        let res = server
+            .client
+            .post(format!("/api/enrollments/{}/approve", request.id))
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+        assert!(!server.active("contractor-a").await);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::handlers::middle::{ClientInfo, Principal};
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::{hold_for_approval, renew_cert, sign_csr};
+use crate::shared::crl::CrlState;
+use crate::shared::est::{EstCerts, certs_only, decode_csr};
+use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+use openssl::x509::X509;
+use rocket::State;
+use rocket::http::Header;
+use rocket::mtls::Certificate;
+use tracing::{error, info};
+use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentStatus;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
+use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+
+/// Seconds an EST client is asked to wait before repeating a held request.
+const RETRY_AFTER_SECS: u64 = 600;
+
+/// Answer to `simpleenroll`: the certificates, or `202` with `Retry-After`
+/// while the request awaits approval (RFC 7030 §4.2.3).
+#[derive(Responder)]
+pub enum EstEnrollment {
+    Issued(EstCerts),
+    #[response(status = 202)]
+    Pending(String, Header<'static>),
+}
+
+fn est_request(body: &str) -> AppResult<SignCsrRequest> {
+    Ok(SignCsrRequest {
+        csr_pem: decode_csr(body)?,
+        overwrite: None,
+        wazuh_agent_name: None,
+        profile: None,
+        device_id: None,
+    })
+}
+
+/// The issued certificate followed by the intermediates, as PKCS#7.
+fn issued_certs(res: SignedCertResponse) -> AppResult<EstCerts> {
+    let pem = res.full_chain_pem.unwrap_or(res.certificate_pem);
+    let certs = X509::stack_from_pem(pem.as_bytes())?;
+    Ok(EstCerts(certs_only(certs.iter().map(|c| c.as_ref()))?))
+}
+
+/// CA certificates (RFC 7030 §4.1): the active issuing CA up to the last
+/// configured issuer.
+#[get("/cacerts")]
+#[tracing::instrument(skip(config))]
+pub async fn cacerts(config: &State<CaProvider>) -> Result<EstCerts, AppError> {
+    let active = config.active().await?;
+    Ok(EstCerts(certs_only(
+        active.chain.iter().map(|c| c.as_ref()),
+    )?))
+}
+
+/// Hold an enrollment for approval, or report on the one held for the same
+/// CSR: EST clients repeat the identical request until it is decided.
+async fn held_enrollment(
+    dto: SignCsrRequest,
+    token: Principal,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+    ledger: &Ledger,
+    webhook: Option<&WebhookNotifier>,
+) -> AppResult<EstEnrollment> {
+    let previous = ledger
+        .find_enrollments_by_subject(&token.claims.sub)
+        .await?
+        .into_iter()
+        .rfind(|e| e.claims.iss == token.claims.iss && e.csr_pem == dto.csr_pem);
+    let request = match previous {
+        Some(enrollment) => enrollment.request,
+        None => hold_for_approval(dto, token, profile, profiles, ledger, webhook).await?,
+    };
+    match (request.status, request.certificate) {
+        (EnrollmentStatus::Approved, Some(certificate)) => {
+            Ok(EstEnrollment::Issued(issued_certs(certificate)?))
+        }
+        (EnrollmentStatus::Rejected, _) => Err(AppError::Forbidden(format!(
+            "enrollment request {} was rejected: {}",
+            request.id,
+            request.reason.as_deref().unwrap_or("no reason given")
+        ))),
+        // Approved but still being signed counts as pending.
+        _ => Ok(EstEnrollment::Pending(
+            format!("enrollment request {} awaits approval", request.id),
+            Header::new("Retry-After", RETRY_AFTER_SECS.to_string()),
+        )),
+    }
+}
+
+/// Initial enrollment (RFC 7030 §4.2.1), authenticated by an OIDC bearer token.
+/// Expects a base64 DER PKCS#10 CSR; issues under the default signing profile,
+/// or answers `202` while the request awaits approval.
+#[post("/simpleenroll", format = "application/pkcs10", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(body, token, client, profile, profiles, config, ledger, crl, webhook, policy, approval), fields(sub = %token.claims.sub))]
+pub async fn simpleenroll(
+    body: String,
+    token: Principal,
+    client: ClientInfo,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    config: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+    webhook: &State<Option<WebhookNotifier>>,
+    policy: &State<Option<PolicyHook>>,
+    approval: &State<ApprovalPolicy>,
+) -> Result<EstEnrollment, AppError> {
+    info!("EST simpleenroll called for subject={}", token.claims.sub);
+    let dto = est_request(&body)?;
+    if approval.requires_approval(&token.claims, token.is_admin) {
+        return held_enrollment(
+            dto,
+            token,
+            profile.inner(),
+            profiles.inner(),
+            ledger.inner(),
+            webhook.inner().as_ref(),
+        )
+        .await
+        .inspect_err(|e| error!("EST enrollment failed: {}", e));
+    }
+    let res = sign_csr(
+        dto,
+        token,
+        &client,
+        profile.inner(),
+        profiles.inner(),
+        config.inner(),
+        ledger.inner(),
+        crl.inner(),
+        webhook.inner().as_ref(),
+        policy.inner().as_ref(),
+    )
+    .await
+    .inspect_err(|e| error!("EST enrollment failed: {}", e))?;
+    Ok(EstEnrollment::Issued(issued_certs(res)?))
+}
+
+/// Re-enrollment (RFC 7030 §4.2.2), authenticated by the current certificate
+/// over mTLS; follows the rules of `/api/renew`, including the enrollment
+/// rules, approval policy and policy hook `simpleenroll` applies.
+#[post("/simplereenroll", format = "application/pkcs10", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(
+    body,
+    client_cert,
+    client,
+    profile,
+    profiles,
+    config,
+    ledger,
+    crl,
+    webhook,
+    policy,
+    approval
+))]
+pub async fn simplereenroll(
+    body: String,
+    client_cert: Certificate<'_>,
+    client: ClientInfo,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    config: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+    webhook: &State<Option<WebhookNotifier>>,
+    policy: &State<Option<PolicyHook>>,
+    approval: &State<ApprovalPolicy>,
+) -> Result<EstCerts, AppError> {
+    info!(
+        "EST simplereenroll called for serial={}",
+        client_cert.serial()
+    );
+    let res = renew_cert(
+        est_request(&body)?,
+        client_cert.as_bytes(),
+        &client,
+        profile.inner(),
+        profiles.inner(),
+        approval.inner(),
+        config.inner(),
+        ledger.inner(),
+        crl.inner(),
+        webhook.inner().as_ref(),
+        policy.inner().as_ref(),
+    )
+    .await
+    .inspect_err(|e| error!("EST re-enrollment failed: {}", e))?;
+    issued_certs(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::handlers::test_support::{TestServer, bearer, csr_pem, policy_endpoint};
+    use crate::models::approval_policy::ApprovalPolicy;
+    use crate::shared::policy_hook::PolicyHook;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as B64;
+    use openssl::pkcs7::Pkcs7;
+    use openssl::x509::{X509, X509Req};
+    use rocket::http::{ContentType, Header, Status};
+    use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentRequest;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+    fn pkcs10() -> ContentType {
+        ContentType::new("application", "pkcs10")
+    }
+
+    fn est_body(cn: &str) -> String {
+        let der = X509Req::from_pem(csr_pem(cn).as_bytes())
+            .unwrap()
+            .to_der()
+            .unwrap();
+        B64.encode(der)
+    }
+
+    async fn certs(res: rocket::local::asynchronous::LocalResponse<'_>) -> Vec<X509> {
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(
+            res.headers().get_one("Content-Transfer-Encoding"),
+            Some("base64")
+        );
+        let ct = res.content_type().expect("content type");
+        assert_eq!(
+            (ct.top().as_str(), ct.sub().as_str()),
+            ("application", "pkcs7-mime")
+        );
+        let body = res.into_string().await.expect("body");
+        let compact: String = body.split_whitespace().collect();
+        let p7 = Pkcs7::from_der(&B64.decode(compact).unwrap()).unwrap();
+        let stack = p7.signed().unwrap().certificates().unwrap();
+        stack.iter().map(|c| c.to_owned()).collect()
+    }
+
+    #[rocket::async_test]
+    async fn enroll_then_reenroll_over_est() {
+        let server = TestServer::start().await;
+
+        let cacerts = certs(
+            server
+                .client
+                .get("/.well-known/est/cacerts")
+                .dispatch()
+                .await,
+        )
+        .await;
+        assert_eq!(cacerts.len(), 1);
+
+        let res = server
+            .client
+            .post("/.well-known/est/simpleenroll")
+            .header(pkcs10())
+            .header(bearer("appliance-1", &[]))
+            .body(est_body("appliance-1"))
+            .dispatch()
+            .await;
+        let issued = certs(res).await;
+        let leaf = &issued[0];
+        assert!(leaf.verify(&cacerts[0].public_key().unwrap()).unwrap());
+        assert!(server.active("appliance-1").await);
+
+        let res = server
+            .client
+            .post("/.well-known/est/simplereenroll")
+            .header(pkcs10())
+            .identity(leaf.to_pem().unwrap().as_slice())
+            .body(est_body("appliance-1"))
+            .dispatch()
+            .await;
+        let renewed = certs(res).await;
+        assert_ne!(renewed[0].to_der().unwrap(), leaf.to_der().unwrap());
+        assert!(renewed[0].not_after() <= leaf.not_after());
+    }
+
+    #[rocket::async_test]
+    async fn est_requires_authentication_and_a_valid_body() {
+        let server = TestServer::start().await;
+
+        let res = server
+            .client
+            .post("/.well-known/est/simpleenroll")
+            .header(pkcs10())
+            .body(est_body("appliance-1"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let res = server
+            .client
+            .post("/.well-known/est/simpleenroll")
+            .header(pkcs10())
+            .header(bearer("appliance-1", &[]))
+            .body("-----BEGIN CERTIFICATE REQUEST-----")
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+
+        let res = server
+            .client
+            .post("/.well-known/est/simplereenroll")
+            .header(pkcs10())
+            .header(Header::new("Authorization", "Bearer x"))
+            .body(est_body("appliance-1"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn held_enrollment_is_answered_when_the_client_retries() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(ApprovalPolicy::new(Vec::new(), vec!["contractor".into()]))
+        })
+        .await;
+        let body = est_body("appliance-2");
+        let enroll = || {
+            server
+                .client
+                .post("/.well-known/est/simpleenroll")
+                .header(pkcs10())
+                .header(bearer("appliance-2", &["contractor"]))
+                .body(body.clone())
+                .dispatch()
+        };
+
+        for _ in 0..2 {
+            let res = enroll().await;
+            assert_eq!(res.status(), Status::Accepted);
+            assert_eq!(res.headers().get_one("Retry-After"), Some("600"));
+        }
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+        let res = server
+            .client
+            .get("/api/enrollments?status=pending")
+            .header(admin.clone())
+            .dispatch()
+            .await;
+        let pending: Vec<EnrollmentRequest> = res.into_json().await.expect("json body");
+        assert_eq!(pending.len(), 1, "a retry must not queue another request");
+
+        let res = server
+            .client
+            .post(format!("/api/enrollments/{}/approve", pending[0].id))
+            .header(admin)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let issued = certs(enroll().await).await;
+        assert!(!issued.is_empty());
+        assert!(server.active("appliance-2").await);
+    }
+
+    #[rocket::async_test]
+    async fn reenrollment_passes_the_checks_of_simpleenroll() {
+        async fn reenroll(server: &TestServer, roles: &[&str]) -> Status {
+            let res = server
+                .client
+                .post("/.well-known/est/simpleenroll")
+                .header(pkcs10())
+                .header(bearer("appliance-1", roles))
+                .body(est_body("appliance-1"))
+                .dispatch()
+                .await;
+            let leaf = certs(res).await.remove(0);
+            server
+                .client
+                .post("/.well-known/est/simplereenroll")
+                .header(pkcs10())
+                .identity(leaf.to_pem().unwrap().as_slice())
+                .body(est_body("appliance-1"))
+                .dispatch()
+                .await
+                .status()
+        }
+
+        // Admins are never held, but a re-enrollment carries no roles.
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(ApprovalPolicy::new(vec!["test".into()], Vec::new()))
+        })
+        .await;
+        assert_eq!(reenroll(&server, &["wazuh_admin"]).await, Status::Forbidden);
+        assert!(server.active("appliance-1").await);
+
+        // The endpoint answers the enrollment and is gone for the re-enrollment.
+        let hook = PolicyHook::new(
+            HttpClient::new_with_defaults().expect("http"),
+            policy_endpoint(Some(r#"{"allow":true}"#)).await,
+            None,
+            Duration::from_secs(2),
+            false,
+        );
+        let server = TestServer::start_with(|rocket| rocket.manage(Some(hook))).await;
+        assert_eq!(reenroll(&server, &[]).await, Status::BadGateway);
+        assert!(server.active("appliance-1").await);
+    }
+}
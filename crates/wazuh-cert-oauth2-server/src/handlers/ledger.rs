@@ -1,47 +1,455 @@
-use crate::handlers::middle::JwtToken;
+use crate::handlers::middle::{AdminToken, Principal};
+use crate::models::access_policy::AccessPolicy;
 use crate::shared::ledger::Ledger;
-use crate::shared::ledger::LedgerEntry;
+use crate::shared::ledger::{
+    DEFAULT_SEARCH_LIMIT, LedgerCursor, LedgerEntry, LedgerPage, LedgerQuery, LedgerSort,
+    MAX_SEARCH_LIMIT, PolicyDecision,
+};
 use rocket::State;
 use rocket::serde::json::Json;
-use wazuh_cert_oauth2_model::models::errors::AppError;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 
-/// All certificates (active and revoked)
+/// All certificates (active and revoked); admin only
 #[get("/ledger")]
 #[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub))]
 pub async fn get_all_ledger(
-    token: JwtToken,
+    token: AdminToken,
     ledger: &State<Ledger>,
 ) -> Result<Json<Vec<LedgerEntry>>, AppError> {
     Ok(Json(ledger.find_all().await?))
 }
 
-/// Active (non-revoked) certificates only
+/// Active (non-revoked) certificates only; admin only
 #[get("/ledger/active")]
 #[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub))]
 pub async fn get_active_ledger(
-    token: JwtToken,
+    token: AdminToken,
     ledger: &State<Ledger>,
 ) -> Result<Json<Vec<LedgerEntry>>, AppError> {
     Ok(Json(ledger.find_active().await?))
 }
 
-/// Revoked certificates only
+/// Revoked certificates only; admin only
 #[get("/ledger/revoked")]
 #[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub))]
 pub async fn get_revoked_ledger(
-    token: JwtToken,
+    token: AdminToken,
     ledger: &State<Ledger>,
 ) -> Result<Json<Vec<LedgerEntry>>, AppError> {
     Ok(Json(ledger.find_revoked().await?))
 }
 
-/// Ledger entries for a specific subject
+/// Query string of [`search_ledger`]. Times are unix seconds; `*_after` is
+/// inclusive and `*_before` exclusive.
+#[derive(Debug, FromForm)]
+pub struct LedgerSearchParams {
+    realm: Option<String>,
+    issuer: Option<String>,
+    agent_name: Option<String>,
+    agent_name_prefix: Option<String>,
+    serial: Option<String>,
+    reason: Option<String>,
+    revoked: Option<bool>,
+    issued_after: Option<u64>,
+    issued_before: Option<u64>,
+    revoked_after: Option<u64>,
+    revoked_before: Option<u64>,
+    /// `issued_at` (default), `serial`, or either prefixed with `-`.
+    sort: Option<String>,
+    /// `next_cursor` of the previous page, searched with the same sort.
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+impl LedgerSearchParams {
+    fn into_query(self) -> AppResult<LedgerQuery> {
+        let sort = match self.sort.as_deref() {
+            None => LedgerSort::default(),
+            Some(s) => LedgerSort::parse(s).ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "sort must be issued_at, -issued_at, serial or -serial, not '{s}'"
+                ))
+            })?,
+        };
+        let limit = self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+            return Err(AppError::ValidationError(format!(
+                "limit must be between 1 and {MAX_SEARCH_LIMIT}"
+            )));
+        }
+        Ok(LedgerQuery {
+            realm: self.realm,
+            issuer: self.issuer,
+            agent_name: self.agent_name,
+            agent_name_prefix: self.agent_name_prefix,
+            serial_hex: self.serial,
+            reason: self.reason,
+            revoked: self.revoked,
+            issued_after: self.issued_after,
+            issued_before: self.issued_before,
+            revoked_after: self.revoked_after,
+            revoked_before: self.revoked_before,
+            sort,
+            after: self
+                .cursor
+                .map(|c| LedgerCursor::decode(&c, sort))
+                .transpose()?,
+            limit,
+        })
+    }
+}
+
+/// One page of the certificates matching the filters; admin only
+#[get("/ledger/search?<params..>")]
+#[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub))]
+pub async fn search_ledger(
+    token: AdminToken,
+    ledger: &State<Ledger>,
+    params: LedgerSearchParams,
+) -> Result<Json<LedgerPage>, AppError> {
+    Ok(Json(ledger.search(&params.into_query()?).await?))
+}
+
+/// Ledger entries for a specific subject; admins, or the subject itself for
+/// the entries of its own token issuer
 #[get("/ledger/subject/<subject>")]
-#[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub, target = %subject))]
+#[tracing::instrument(skip(principal, policy, ledger), fields(sub = %principal.claims.sub, target = %subject))]
 pub async fn get_ledger_by_subject(
-    token: JwtToken,
+    principal: Principal,
+    policy: &State<AccessPolicy>,
     ledger: &State<Ledger>,
     subject: String,
 ) -> Result<Json<Vec<LedgerEntry>>, AppError> {
-    Ok(Json(ledger.find_by_subject(&subject).await?))
+    if principal.is_admin {
+        return Ok(Json(ledger.find_by_subject(&subject).await?));
+    }
+    let issuer = principal.claims.iss.as_str();
+    if !principal.can_access_subject(policy, Some(issuer), &subject) {
+        return Err(AppError::Forbidden(
+            "not allowed to read ledger entries of another subject".into(),
+        ));
+    }
+    Ok(Json(ledger.find_issued_to(issuer, &subject).await?))
+}
+
+/// Policy hook decisions about a subject's requests, oldest first; admin only
+#[get("/ledger/policy-decisions/<subject>")]
+#[tracing::instrument(skip(token, ledger), fields(sub = %token.claims.sub, target = %subject))]
+pub async fn get_policy_decisions(
+    token: AdminToken,
+    ledger: &State<Ledger>,
+    subject: String,
+) -> Result<Json<Vec<PolicyDecision>>, AppError> {
+    Ok(Json(ledger.find_policy_decisions(&subject).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{
+        TEST_ISSUER, TestServer, bearer, bearer_from, bearer_with, policy_endpoint, test_issuer,
+    };
+    use crate::models::access_policy::AccessPolicy;
+    use crate::models::oidc_state::{IssuerConfig, OidcIssuer, OidcState};
+    use crate::shared::introspection::Introspector;
+    use jsonwebtoken::jwk::JwkSet;
+    use rocket::http::{ContentType, Header, Status};
+    use serde_json::json;
+    use std::time::Duration;
+    use wazuh_cert_oauth2_model::models::claim_mapping::ClaimMapping;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+    use wazuh_cert_oauth2_model::services::refresh_cache::RefreshPolicy;
+
+    #[rocket::async_test]
+    async fn ledger_listings_require_admin() {
+        let server = TestServer::start().await;
+        let user = bearer("user-a", &[]);
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+
+        for path in [
+            "/api/ledger",
+            "/api/ledger/active",
+            "/api/ledger/revoked",
+            "/api/ledger/search?realm=dev",
+            "/api/ledger/policy-decisions/user-a",
+        ] {
+            let res = server
+                .client
+                .get(path)
+                .header(user.clone())
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Forbidden, "{path} as user");
+
+            let res = server
+                .client
+                .get(path)
+                .header(admin.clone())
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok, "{path} as admin");
+
+            let res = server.client.get(path).dispatch().await;
+            assert_eq!(res.status(), Status::Unauthorized, "{path} anonymous");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn ledger_search_validates_sort_limit_and_cursor() {
+        let server = TestServer::start().await;
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+        for (query, expected) in [
+            ("sort=-serial&limit=1000", Status::Ok),
+            ("sort=subject", Status::BadRequest),
+            ("limit=0", Status::BadRequest),
+            ("limit=1001", Status::BadRequest),
+            ("cursor=garbage", Status::BadRequest),
+        ] {
+            let res = server
+                .client
+                .get(format!("/api/ledger/search?{query}"))
+                .header(admin.clone())
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), expected, "{query}");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn ledger_search_filters_on_the_reason_code() {
+        let server = TestServer::start().await;
+        let admin = bearer("admin-1", &["wazuh_admin"]);
+        server.issue("user-a", "AB01").await;
+        server.issue("user-b", "AB02").await;
+        for body in [
+            r#"{"serial_hex":"AB01","reason_code":"keyCompromise","reason":"laptop stolen"}"#,
+            r#"{"serial_hex":"AB02","reason":"keyCompromised laptop"}"#,
+        ] {
+            let res = server
+                .client
+                .post("/api/revoke")
+                .header(ContentType::JSON)
+                .header(admin.clone())
+                .body(body)
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::NoContent, "{body}");
+        }
+
+        for (reason, expected) in [
+            ("keyCompromise", vec!["AB01"]),
+            ("keyCompromise:%20laptop%20stolen", vec!["AB01"]),
+            ("keyCompromised%20laptop", vec!["AB02"]),
+            ("superseded", vec![]),
+        ] {
+            let res = server
+                .client
+                .get(format!("/api/ledger/search?reason={reason}"))
+                .header(admin.clone())
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+            let page: serde_json::Value = res.into_json().await.expect("json");
+            let serials: Vec<&str> = page["entries"]
+                .as_array()
+                .expect("entries")
+                .iter()
+                .filter_map(|e| e["serial_hex"].as_str())
+                .collect();
+            assert_eq!(serials, expected, "{reason}");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn ledger_by_subject_is_self_service() {
+        let server = TestServer::start().await;
+
+        let res = server
+            .client
+            .get("/api/ledger/subject/user-a")
+            .header(bearer("user-a", &[]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+
+        let res = server
+            .client
+            .get("/api/ledger/subject/user-b")
+            .header(bearer("user-a", &[]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = server
+            .client
+            .get("/api/ledger/subject/user-b")
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn tokens_are_checked_against_their_own_issuer() {
+        const PARTNERS: &str = "https://issuer.example/realms/partners";
+        let issuers = vec![
+            test_issuer(TEST_ISSUER, None).await,
+            test_issuer(PARTNERS, Some(vec!["partner-admin".into()])).await,
+        ];
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(OidcState::new(issuers).expect("oidc state"))
+        })
+        .await;
+
+        let cases = [
+            (bearer("admin-1", &["wazuh_admin"]), Status::Ok),
+            (bearer_from(PARTNERS, "p-1", &["partner-admin"]), Status::Ok),
+            // Admin roles of one issuer do not carry over to another.
+            (
+                bearer_from(PARTNERS, "p-2", &["wazuh_admin"]),
+                Status::Forbidden,
+            ),
+            (
+                bearer_from(
+                    "https://issuer.example/realms/other",
+                    "o-1",
+                    &["wazuh_admin"],
+                ),
+                Status::Unauthorized,
+            ),
+            (
+                bearer_from(&format!("{TEST_ISSUER}/"), "t-1", &["wazuh_admin"]),
+                Status::Unauthorized,
+            ),
+        ];
+        for (header, expected) in cases {
+            let res = server
+                .client
+                .get("/api/ledger")
+                .header(header)
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), expected);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn unknown_key_ids_refetch_the_issuer_jwks() {
+        // The issuer has rotated to the test key; only the old set is cached.
+        let iss = policy_endpoint(Some(
+            r#"{"keys":[{"kty":"oct","kid":"test-kid","alg":"HS256","k":"c2VjcmV0"}]}"#,
+        ))
+        .await;
+        let issuer = test_issuer(&iss, None).await;
+        issuer.seed_jwks(JwkSet { keys: Vec::new() }).await;
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(OidcState::new(vec![issuer]).expect("oidc state"))
+        })
+        .await;
+
+        // The first request refetches the set; the second is served from it.
+        for _ in 0..2 {
+            let res = server
+                .client
+                .get("/api/ledger")
+                .header(bearer_from(&iss, "admin-1", &["wazuh_admin"]))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn opaque_tokens_are_introspected_and_cached() {
+        // Answers a single introspection call.
+        let iss = policy_endpoint(Some(
+            r#"{"active":true,"sub":"gw-admin","exp":4102444800,"realm_access":{"roles":["wazuh_admin"]}}"#,
+        ))
+        .await;
+        let http = HttpClient::new_with_defaults().expect("http client");
+        let gateway = OidcIssuer::new(
+            IssuerConfig {
+                issuer: iss.clone(),
+                audiences: None,
+                admin_roles: None,
+                leeway_secs: None,
+                introspection: true,
+            },
+            RefreshPolicy::with_ttl(Duration::from_secs(3600)),
+            RefreshPolicy::with_ttl(Duration::from_secs(3600)),
+            http.clone(),
+        )
+        .with_introspector(Introspector::new(
+            http,
+            "wazuh-server".into(),
+            "secret".into(),
+        ));
+        gateway.seed_jwks(JwkSet { keys: Vec::new() }).await;
+        let issuers = vec![test_issuer(TEST_ISSUER, None).await, gateway];
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(OidcState::new(issuers).expect("oidc state"))
+        })
+        .await;
+
+        let cases = [
+            ("opaque-1", Status::Ok),
+            // Served from the cache; the endpoint is gone by now.
+            ("opaque-1", Status::Ok),
+            ("opaque-2", Status::Unauthorized),
+        ];
+        for (token, expected) in cases {
+            let res = server
+                .client
+                .get("/api/ledger")
+                .header(Header::new("Authorization", format!("Bearer {token}")))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), expected, "{token}");
+        }
+        // JWTs of the other issuers are still checked against their JWKS.
+        let res = server
+            .client
+            .get("/api/ledger")
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn claim_mapping_reads_roles_from_groups_and_client_roles() {
+        let server = TestServer::start_with(|rocket| {
+            rocket
+                .manage(ClaimMapping {
+                    roles: vec!["resource_access.wazuh.roles".into()],
+                    groups: vec!["groups".into()],
+                    ..Default::default()
+                })
+                .manage(AccessPolicy::new(vec!["pki-admins".into()], true))
+        })
+        .await;
+
+        let cases = [
+            (json!({"groups": ["pki-admins"]}), Status::Ok),
+            (
+                json!({"resource_access": {"wazuh": {"roles": ["pki-admins"]}}}),
+                Status::Ok,
+            ),
+            // Realm roles are no longer read.
+            (
+                json!({"realm_access": {"roles": ["pki-admins"]}}),
+                Status::Forbidden,
+            ),
+        ];
+        for (mut claims, expected) in cases {
+            claims["sub"] = json!("user-a");
+            claims["iss"] = json!(TEST_ISSUER);
+            let res = server
+                .client
+                .get("/api/ledger")
+                .header(bearer_with(claims))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), expected);
+        }
+    }
 }
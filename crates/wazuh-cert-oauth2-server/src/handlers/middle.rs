@@ -1,10 +1,14 @@
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
+use wazuh_cert_oauth2_model::models::claim_mapping::ClaimMapping;
 use wazuh_cert_oauth2_model::models::claims::Claims;
-use wazuh_cert_oauth2_model::services::jwks::validate_token;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::ledger_entry::LedgerEntry;
+use wazuh_cert_oauth2_model::services::jwks::{unverified_issuer, validate_token_with};
 
-use crate::models::oidc_state::OidcState;
-use tracing::{debug, error, info};
+use crate::models::access_policy::AccessPolicy;
+use crate::models::oidc_state::{OidcIssuer, OidcState};
+use tracing::{debug, error, info, warn};
 
 pub struct JwtToken {
     pub claims: Claims,
@@ -16,6 +20,28 @@ impl JwtToken {
     }
 }
 
+/// Validate `token` against the key set of `issuer`, fetching the set again
+/// once when it lacks the token's key, as right after a key rotation. Tokens
+/// of an issuer using introspection are looked up there instead.
+async fn validate_with_issuer(token: &str, issuer: &OidcIssuer) -> AppResult<Claims> {
+    if issuer.introspects() {
+        return issuer.introspect(token).await;
+    }
+    let jwks = issuer.get_jwks().await?;
+    match validate_token_with(token, &jwks, issuer.requirements()).await {
+        Err(AppError::JwtKeyNotFound(kid)) => {
+            info!(
+                "key {} not in the cached JWKS of {}; refetching",
+                kid,
+                issuer.issuer()
+            );
+            let jwks = issuer.refresh_jwks().await?;
+            validate_token_with(token, &jwks, issuer.requirements()).await
+        }
+        result => result,
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for JwtToken {
     type Error = ();
@@ -30,22 +56,36 @@ impl<'r> FromRequest<'r> for JwtToken {
         if let Some(token) = token {
             debug!("Bearer token present; validating");
             let state = request.rocket().state::<OidcState>().unwrap();
-            match state.get_jwks().await {
-                Ok(jwks) => match validate_token(token, jwks.as_ref(), &state.audiences).await {
-                    Ok(claims) => {
-                        info!(
-                            "JWT validated for subject={} audiences={:?}",
-                            claims.sub, state.audiences
-                        );
-                        Outcome::Success(JwtToken::new(claims))
-                    }
-                    Err(e) => {
-                        error!("Could not get claims {}", e);
-                        Outcome::Error((Status::Unauthorized, ()))
-                    }
-                },
+            // The unverified `iss` only picks the key set; validation then
+            // requires the token to carry exactly that issuer. Tokens that
+            // are not JWTs go to the issuer validating by introspection.
+            let issuer = match unverified_issuer(token) {
+                Ok(iss) => state.issuer(&iss).ok_or(AppError::JwtUntrustedIssuer(iss)),
+                Err(e) => state.opaque_issuer().ok_or(e),
+            };
+            let issuer = match issuer {
+                Ok(issuer) => issuer,
+                Err(e) => {
+                    error!("Could not get claims {}", e);
+                    return Outcome::Error((Status::Unauthorized, ()));
+                }
+            };
+            match validate_with_issuer(token, issuer).await {
+                Ok(claims) => {
+                    let claims = match request.rocket().state::<ClaimMapping>() {
+                        Some(mapping) => mapping.apply(claims),
+                        None => ClaimMapping::default().apply(claims),
+                    };
+                    info!(
+                        "JWT validated for subject={} issuer={} audiences={:?}",
+                        claims.sub,
+                        claims.iss,
+                        issuer.requirements().audiences
+                    );
+                    Outcome::Success(JwtToken::new(claims))
+                }
                 Err(e) => {
-                    error!("Could not get JWKS {}", e);
+                    error!("Could not get claims {}", e);
                     Outcome::Error((Status::Unauthorized, ()))
                 }
             }
@@ -55,3 +95,103 @@ impl<'r> FromRequest<'r> for JwtToken {
         }
     }
 }
+
+/// Authenticated caller together with its admin status under the configured
+/// [`AccessPolicy`]. Handlers use it for self-service checks.
+pub struct Principal {
+    pub claims: Claims,
+    pub is_admin: bool,
+}
+
+impl Principal {
+    /// Whether the caller may read or revoke certificates issued to `subject`
+    /// of the token issuer `issuer`.
+    pub fn can_access_subject(
+        &self,
+        policy: &AccessPolicy,
+        issuer: Option<&str>,
+        subject: &str,
+    ) -> bool {
+        policy.can_access_subject(&self.claims, self.is_admin, issuer, subject)
+    }
+
+    /// Whether the caller may read or revoke the certificate of `entry`.
+    pub fn can_access_entry(&self, policy: &AccessPolicy, entry: &LedgerEntry) -> bool {
+        self.can_access_subject(policy, entry.issuer.as_deref(), &entry.subject)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Principal {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let JwtToken { claims } = match request.guard::<JwtToken>().await {
+            Outcome::Success(token) => token,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+        let issuer_roles = request
+            .rocket()
+            .state::<OidcState>()
+            .and_then(|state| state.issuer(&claims.iss))
+            .and_then(|issuer| issuer.admin_roles());
+        let is_admin = match request.rocket().state::<AccessPolicy>() {
+            Some(policy) => policy.is_admin_for(&claims, issuer_roles),
+            None => {
+                error!("AccessPolicy is not managed; denying admin privileges");
+                false
+            }
+        };
+        Outcome::Success(Principal { claims, is_admin })
+    }
+}
+
+/// Where a request came from, as told to the policy hook.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ClientInfo {
+    /// Remote address, or the `ip_header` Rocket is configured to trust.
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            ip: request.client_ip().map(|ip| ip.to_string()),
+            user_agent: request.headers().get_one("User-Agent").map(str::to_string),
+        })
+    }
+}
+
+/// Caller holding one of the configured admin roles; anyone else gets `403`.
+pub struct AdminToken {
+    pub claims: Claims,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.guard::<Principal>().await {
+            Outcome::Success(Principal {
+                claims,
+                is_admin: true,
+            }) => Outcome::Success(AdminToken { claims }),
+            Outcome::Success(Principal { claims, .. }) => {
+                warn!(
+                    "subject={} lacks an admin role for {}",
+                    claims.sub,
+                    request.uri()
+                );
+                Outcome::Error((Status::Forbidden, ()))
+            }
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(f) => Outcome::Forward(f),
+        }
+    }
+}
@@ -1,7 +1,66 @@
+pub mod acme;
+pub mod acme_fairing;
 pub mod crl;
 pub mod crl_fairing;
+pub mod enrollments;
+pub mod est;
 pub mod health;
 pub mod ledger;
 pub mod middle;
+pub mod ocsp;
 pub mod register_agent;
+pub mod renew;
 pub mod revoke;
+#[cfg(test)]
+pub(crate) mod test_support;
+
+use rocket::Route;
+
+/// Routes mounted under `/api`.
+pub fn api_routes() -> Vec<Route> {
+    routes![
+        register_agent::register_agent,
+        renew::renew,
+        revoke::revoke,
+        crl::get_revocations,
+        ledger::get_all_ledger,
+        ledger::get_active_ledger,
+        ledger::get_revoked_ledger,
+        ledger::search_ledger,
+        ledger::get_ledger_by_subject,
+        ledger::get_policy_decisions,
+        enrollments::list_enrollments,
+        enrollments::get_enrollment,
+        enrollments::approve,
+        enrollments::reject
+    ]
+}
+
+/// EST routes, mounted under [`EST_BASE`](crate::shared::est::EST_BASE).
+pub fn est_routes() -> Vec<Route> {
+    routes![est::cacerts, est::simpleenroll, est::simplereenroll]
+}
+
+/// Routes mounted under [`ACME_BASE`](crate::shared::acme::ACME_BASE) when
+/// ACME is enabled.
+pub fn acme_routes() -> Vec<Route> {
+    routes![
+        acme::directory,
+        acme::head_new_nonce,
+        acme::get_new_nonce,
+        acme::new_account,
+        acme::account,
+        acme::account_orders,
+        acme::new_order,
+        acme::order,
+        acme::authz,
+        acme::finalize,
+        acme::certificate,
+        acme::revoke_cert
+    ]
+}
+
+/// Routes mounted under `/api` when ACME is enabled.
+pub fn acme_api_routes() -> Vec<Route> {
+    routes![acme::new_eab]
+}
@@ -0,0 +1,178 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use rocket::State;
+use rocket::http::uri::Segments;
+use rocket::http::uri::fmt::Path;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::Responder;
+use std::io::Cursor;
+use tracing::{debug, info};
+
+use crate::models::ca_config::CaProvider;
+use crate::shared::crl::compute_etag;
+use crate::shared::ledger::Ledger;
+use crate::shared::ocsp::{OcspReply, OcspResponder};
+
+pub struct OcspResponse(OcspReply);
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for OcspResponse {
+    fn respond_to(self, _req: &'r Request<'_>) -> rocket::response::Result<'o> {
+        let OcspReply {
+            der,
+            this_update,
+            max_age,
+        } = self.0;
+        let mut res = rocket::Response::build();
+        res.header(ContentType::new("application", "ocsp-response"));
+        // RFC 5019 §6: let HTTP caches serve the response until nextUpdate.
+        match max_age {
+            Some(max_age) => res
+                .raw_header("ETag", format!("\"{}\"", compute_etag(&der)))
+                .raw_header("Last-Modified", http_date(this_update))
+                .raw_header("Expires", http_date(this_update + max_age))
+                .raw_header(
+                    "Cache-Control",
+                    format!("max-age={max_age}, public, no-transform, must-revalidate"),
+                ),
+            None => res.raw_header("Cache-Control", "no-store"),
+        };
+        res.sized_body(der.len(), Cursor::new(der)).ok()
+    }
+}
+
+/// OCSP request in the body (RFC 6960 §A.1).
+#[post("/ocsp", format = "application/ocsp-request", data = "<body>")]
+pub async fn post_ocsp(
+    body: Vec<u8>,
+    responder: &State<OcspResponder>,
+    ca: &State<CaProvider>,
+    ledger: &State<Ledger>,
+) -> OcspResponse {
+    info!("POST /ocsp requested ({} bytes)", body.len());
+    OcspResponse(responder.respond(&body, ca, ledger).await)
+}
+
+/// OCSP request as URL-encoded base64 in the path, cacheable by proxies.
+#[get("/ocsp/<request..>")]
+pub async fn get_ocsp(
+    request: Segments<'_, Path>,
+    responder: &State<OcspResponder>,
+    ca: &State<CaProvider>,
+    ledger: &State<Ledger>,
+) -> Result<OcspResponse, Status> {
+    info!("GET /ocsp requested");
+    // Unescaped '/' in the base64 splits it into several segments.
+    let encoded = request.collect::<Vec<_>>().join("/");
+    let der = B64.decode(encoded.trim()).map_err(|e| {
+        debug!("invalid base64 OCSP request: {}", e);
+        Status::BadRequest
+    })?;
+    Ok(OcspResponse(responder.respond(&der, ca, ledger).await))
+}
+
+fn http_date(unix: u64) -> String {
+    chrono::DateTime::from_timestamp(unix as i64, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as B64;
+    use openssl::hash::MessageDigest;
+    use openssl::ocsp::{OcspCertId, OcspCertStatus, OcspRequest, OcspResponse};
+    use openssl::x509::X509;
+    use rocket::http::{ContentType, Status};
+
+    use crate::handlers::test_support::{TestServer, bearer};
+
+    /// Query the status of `cert` and return it with the Cache-Control header.
+    async fn query(
+        server: &TestServer,
+        cert: &X509,
+        ca: &X509,
+        via_get: bool,
+    ) -> (OcspCertStatus, String) {
+        let mut req = OcspRequest::new().expect("request");
+        req.add_id(OcspCertId::from_cert(MessageDigest::sha1(), cert, ca).expect("id"))
+            .expect("add id");
+        let req = req.to_der().expect("der");
+        let res = if via_get {
+            let encoded = B64.encode(&req).replace('/', "%2F").replace('+', "%2B");
+            server
+                .client
+                .get(format!("/ocsp/{encoded}"))
+                .dispatch()
+                .await
+        } else {
+            server
+                .client
+                .post("/ocsp")
+                .header(ContentType::new("application", "ocsp-request"))
+                .body(req)
+                .dispatch()
+                .await
+        };
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(
+            res.content_type(),
+            Some(ContentType::new("application", "ocsp-response"))
+        );
+        let cache_control = res
+            .headers()
+            .get_one("Cache-Control")
+            .unwrap_or_default()
+            .to_string();
+        let der = res.into_bytes().await.expect("body");
+        let basic = OcspResponse::from_der(&der)
+            .expect("der")
+            .basic()
+            .expect("successful response");
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), cert, ca).expect("id");
+        (
+            basic.find_status(&id).expect("status").status,
+            cache_control,
+        )
+    }
+
+    #[rocket::async_test]
+    async fn enrolled_certificates_are_good_until_revoked() {
+        let server = TestServer::start().await;
+        let issued = server.enroll("user-a").await;
+        let cert = X509::from_pem(issued.certificate_pem.as_bytes()).expect("cert");
+        let ca = X509::from_pem(issued.ca_cert_pem.as_bytes()).expect("ca");
+
+        let (status, cache_control) = query(&server, &cert, &ca, false).await;
+        assert_eq!(status, OcspCertStatus::GOOD);
+        assert!(cache_control.starts_with("max-age=3600"), "{cache_control}");
+        let (status, _) = query(&server, &cert, &ca, true).await;
+        assert_eq!(status, OcspCertStatus::GOOD);
+
+        let serial = cert
+            .serial_number()
+            .to_bn()
+            .and_then(|bn| bn.to_hex_str().map(|h| h.to_string()))
+            .expect("serial");
+        let res = server
+            .client
+            .post("/api/revoke")
+            .header(ContentType::JSON)
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .body(format!(r#"{{"serial_hex":"{serial}"}}"#))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::NoContent);
+        let (status, _) = query(&server, &cert, &ca, true).await;
+        assert_eq!(status, OcspCertStatus::REVOKED);
+    }
+
+    #[rocket::async_test]
+    async fn invalid_base64_is_a_bad_request() {
+        let server = TestServer::start().await;
+        let res = server.client.get("/ocsp/not*base64").dispatch().await;
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+}
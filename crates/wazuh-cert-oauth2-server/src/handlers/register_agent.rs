@@ -1,47 +1,480 @@
-use crate::handlers::middle::JwtToken;
+use crate::handlers::middle::{ClientInfo, Principal};
+use crate::models::approval_policy::ApprovalPolicy;
 use crate::models::ca_config::CaProvider;
-use crate::shared::certs::sign_csr;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::{hold_for_approval, sign_csr};
 use crate::shared::crl::CrlState;
 use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
 use crate::shared::webhook_notifier::WebhookNotifier;
 use rocket::State;
+use rocket::http::Header;
 use rocket::serde::json::Json;
 use tracing::{debug, error, info};
+use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentRequest;
 use wazuh_cert_oauth2_model::models::errors::AppError;
 use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
 use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
 
+/// Outcome of an enrollment: the certificate, or `202 Accepted` with the
+/// request held for admin approval and its location.
+#[derive(Responder)]
+#[allow(clippy::large_enum_variant)]
+pub enum Enrollment {
+    Issued(Json<SignedCertResponse>),
+    #[response(status = 202)]
+    Pending(Json<EnrollmentRequest>, Header<'static>),
+}
+
 /// Sign a CSR for a new agent using the issuing CA
-/// Expects a PKCS#10 CSR in PEM format; returns the signed certificate and CA cert
+/// Expects a PKCS#10 CSR in PEM format; returns the signed certificate and CA cert,
+/// or `202` with a pending request when the caller's enrollments need approval
 #[post("/register-agent", format = "application/json", data = "<dto>")]
-#[tracing::instrument(skip(dto, token, config, ledger, crl, webhook), fields(sub = %token.claims.sub))]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(dto, token, client, profile, profiles, config, ledger, crl, webhook, policy, approval), fields(sub = %token.claims.sub))]
 pub async fn register_agent(
     dto: Json<SignCsrRequest>,
-    token: JwtToken,
+    token: Principal,
+    client: ClientInfo,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
     config: &State<CaProvider>,
     ledger: &State<Ledger>,
     crl: &State<CrlState>,
     webhook: &State<Option<WebhookNotifier>>,
-) -> Result<Json<SignedCertResponse>, AppError> {
+    policy: &State<Option<PolicyHook>>,
+    approval: &State<ApprovalPolicy>,
+) -> Result<Enrollment, AppError> {
     info!(
         "POST /register-agent called for subject={}",
         token.claims.sub
     );
     debug!("CSR payload received (not logging PEM contents)");
+    if approval.requires_approval(&token.claims, token.is_admin) {
+        let request = hold_for_approval(
+            dto.into_inner(),
+            token,
+            profile.inner(),
+            profiles.inner(),
+            ledger.inner(),
+            webhook.inner().as_ref(),
+        )
+        .await
+        .inspect_err(|e| error!("holding enrollment failed: {}", e))?;
+        let location = Header::new("Location", format!("/api/enrollments/{}", request.id));
+        return Ok(Enrollment::Pending(Json(request), location));
+    }
     match sign_csr(
         dto.into_inner(),
         token,
+        &client,
+        profile.inner(),
+        profiles.inner(),
         config.inner(),
         ledger.inner(),
         crl.inner(),
         webhook.inner().as_ref(),
+        policy.inner().as_ref(),
     )
     .await
     {
-        Ok(res) => Ok(Json(res)),
+        Ok(res) => Ok(Enrollment::Issued(Json(res))),
         Err(e) => {
             error!("CSR signing failed: {}", e);
             Err(e)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{
+        TEST_ISSUER, TestServer, bearer, bearer_with, csr_pem, policy_endpoint,
+    };
+    use crate::models::cert_profile::parse_cert_profiles;
+    use crate::models::enrollment_rules::EnrollmentRules;
+    use crate::models::naming_template::parse_naming_template;
+    use crate::models::signing_profile::{SigningProfile, parse_device_quota_overrides};
+    use crate::shared::ledger::PolicyDecision;
+    use crate::shared::policy_hook::PolicyHook;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::stack::Stack;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509, X509NameBuilder, X509ReqBuilder};
+    use rocket::http::{ContentType, Status};
+    use serde_json::json;
+    use std::time::Duration;
+    use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+    #[rocket::async_test]
+    async fn issued_certificate_uses_profile_validity() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(SigningProfile {
+                validity_days: 30,
+                ..Default::default()
+            })
+        })
+        .await;
+
+        let body = server.enroll("user-a").await;
+        let not_after = body.not_after_unix.expect("not_after should be returned");
+
+        let cert = X509::from_pem(body.certificate_pem.as_bytes()).expect("cert");
+        let reported = crate::shared::certs::asn1_to_unix(cert.not_after()).expect("time");
+        assert_eq!(reported, not_after);
+        let now = crate::shared::certs::unix_now();
+        assert!(not_after <= now + 30 * 86_400 && not_after > now + 29 * 86_400);
+
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        assert_eq!(entries[0].not_after_unix, Some(not_after));
+    }
+
+    #[rocket::async_test]
+    async fn subject_and_sans_follow_the_naming_template() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(SigningProfile {
+                naming: parse_naming_template(
+                    "O=Example,CN={preferred_username}",
+                    "dns:{wazuh_agent_name},email:{email},uri:{identity_uri}",
+                )
+                .expect("template"),
+                ..Default::default()
+            })
+        })
+        .await;
+
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("jdoe", &[]))
+            .body(json!({ "csr_pem": csr_pem("jdoe"), "wazuh_agent_name": "Web-01" }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let body: SignedCertResponse = res.into_json().await.expect("json");
+        let cert = X509::from_pem(body.certificate_pem.as_bytes()).expect("cert");
+        let subject: Vec<_> = cert
+            .subject_name()
+            .entries()
+            .map(|e| e.data().to_string().expect("utf8"))
+            .collect();
+        assert_eq!(subject, ["Example", "jdoe"]);
+        // The token has no email claim, so only the DNS and URI entries remain.
+        let sans: Vec<_> = cert
+            .subject_alt_names()
+            .expect("sans")
+            .iter()
+            .map(|n| {
+                n.dnsname()
+                    .or(n.uri())
+                    .or(n.email())
+                    .expect("name")
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(sans.len(), 2);
+        assert_eq!(sans[0], "web-01");
+        assert!(sans[1].ends_with("#sub=jdoe"), "{}", sans[1]);
+    }
+
+    #[rocket::async_test]
+    async fn a_token_without_the_cn_claim_is_rejected() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(SigningProfile {
+                naming: parse_naming_template("CN={email}", "").expect("template"),
+                ..Default::default()
+            })
+        })
+        .await;
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &[]))
+            .body(json!({ "csr_pem": csr_pem("user-a") }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+        assert!(
+            server
+                .ledger
+                .find_by_subject("user-a")
+                .await
+                .expect("ledger")
+                .is_empty()
+        );
+    }
+
+    /// CSR for `cn` requesting `dns` as DNS SANs.
+    fn csr_with_dns(cn: &str, dns: &[&str]) -> String {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("group");
+        let key = PKey::from_ec_key(EcKey::generate(&group).expect("ec key")).expect("pkey");
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", cn).expect("cn");
+        let mut req = X509ReqBuilder::new().expect("req");
+        req.set_subject_name(&name.build()).expect("subject");
+        req.set_pubkey(&key).expect("pubkey");
+        let mut san = SubjectAlternativeName::new();
+        for name in dns {
+            san.dns(name);
+        }
+        let mut extensions = Stack::new().expect("stack");
+        extensions
+            .push(san.build(&req.x509v3_context(None)).expect("san"))
+            .expect("push");
+        req.add_extensions(&extensions).expect("extensions");
+        req.sign(&key, MessageDigest::sha256()).expect("sign");
+        String::from_utf8(req.build().to_pem().expect("pem")).expect("utf8")
+    }
+
+    async fn start_with_manager_profile() -> TestServer {
+        TestServer::start_with(|rocket| {
+            rocket.manage(
+                parse_cert_profiles(
+                    r#"{"manager": {
+                        "extended_key_usage": ["serverAuth"],
+                        "validity_days": 30,
+                        "subject_template": "CN={wazuh_agent_name}",
+                        "requested_dns": ["*.wazuh.test"],
+                        "allowed_roles": ["wazuh_manager"]
+                    }}"#,
+                )
+                .expect("profiles"),
+            )
+        })
+        .await
+    }
+
+    #[rocket::async_test]
+    async fn named_profile_issues_server_certificate_next_to_agent_certificate() {
+        let server = start_with_manager_profile().await;
+        server.enroll("svc-a").await;
+
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("svc-a", &["wazuh_manager"]))
+            .body(
+                json!({
+                    "csr_pem": csr_with_dns("node1.wazuh.test", &["node1.wazuh.test"]),
+                    "wazuh_agent_name": "node1.wazuh.test",
+                    "profile": "manager",
+                })
+                .to_string(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let body: SignedCertResponse = res.into_json().await.expect("json");
+        let cert = X509::from_pem(body.certificate_pem.as_bytes()).expect("cert");
+        let text = String::from_utf8(cert.to_text().expect("text")).expect("utf8");
+        assert!(text.contains("TLS Web Server Authentication"));
+        assert!(!text.contains("TLS Web Client Authentication"));
+        let sans: Vec<_> = cert
+            .subject_alt_names()
+            .expect("sans")
+            .iter()
+            .filter_map(|n| n.dnsname().map(str::to_string))
+            .collect();
+        assert_eq!(sans, ["node1.wazuh.test"]);
+        let now = crate::shared::certs::unix_now();
+        assert!(body.not_after_unix.expect("not_after") <= now + 30 * 86_400);
+
+        // The agent certificate of the same subject stays active.
+        let entries = server
+            .ledger
+            .find_by_subject("svc-a")
+            .await
+            .expect("ledger");
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| !e.revoked));
+        assert_eq!(entries[0].profile.as_deref(), Some("agent"));
+        assert_eq!(entries[1].profile.as_deref(), Some("manager"));
+    }
+
+    #[rocket::async_test]
+    async fn profile_selection_is_checked() {
+        let server = start_with_manager_profile().await;
+        let request = |profile: &str| {
+            json!({
+                "csr_pem": csr_pem("user-a"),
+                "wazuh_agent_name": "user-a",
+                "profile": profile,
+            })
+            .to_string()
+        };
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &[]))
+            .body(request("manager"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &["wazuh_manager"]))
+            .body(request("dashboard"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+        assert!(!server.active("user-a").await);
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_rotated_separately_within_the_role_quota() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(SigningProfile {
+                device_quota_overrides: parse_device_quota_overrides("role:engineer=2")
+                    .expect("quotas"),
+                ..Default::default()
+            })
+        })
+        .await;
+        let enroll = |device: &str, overwrite: bool| {
+            server
+                .client
+                .post("/api/register-agent")
+                .header(ContentType::JSON)
+                .header(bearer("user-a", &["engineer"]))
+                .body(
+                    json!({
+                        "csr_pem": csr_pem("user-a"),
+                        "device_id": device,
+                        "overwrite": overwrite,
+                    })
+                    .to_string(),
+                )
+                .dispatch()
+        };
+        assert_eq!(enroll("laptop", false).await.status(), Status::Ok);
+        assert_eq!(enroll("desktop", false).await.status(), Status::Ok);
+        assert_eq!(enroll("tablet", true).await.status(), Status::Conflict);
+        // Re-enrolling a known device replaces only its own certificate.
+        assert_eq!(enroll("laptop", true).await.status(), Status::Ok);
+
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        let active: Vec<_> = entries
+            .iter()
+            .filter(|e| !e.revoked)
+            .map(|e| e.device_id.as_deref())
+            .collect();
+        assert_eq!(active, [Some("desktop"), Some("laptop")]);
+        assert_eq!(entries.len(), 3);
+
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &["engineer"]))
+            .body(json!({ "csr_pem": csr_pem("user-a"), "device_id": "my laptop" }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    async fn server_with_policy(answer: &'static str) -> TestServer {
+        let hook = PolicyHook::new(
+            HttpClient::new_with_defaults().expect("http"),
+            policy_endpoint(Some(answer)).await,
+            None,
+            Duration::from_secs(2),
+            false,
+        );
+        TestServer::start_with(|rocket| rocket.manage(Some(hook))).await
+    }
+
+    #[rocket::async_test]
+    async fn policy_hook_adds_sans_or_refuses_before_signing() {
+        let server =
+            server_with_policy(r#"{"allow":true,"extra_sans":["dns:asset-42.example"]}"#).await;
+        let body = server.enroll("user-a").await;
+        let cert = X509::from_pem(body.certificate_pem.as_bytes()).expect("cert");
+        let sans: Vec<_> = cert
+            .subject_alt_names()
+            .expect("sans")
+            .iter()
+            .filter_map(|n| n.dnsname().map(str::to_string))
+            .collect();
+        assert!(sans.iter().any(|s| s == "asset-42.example"));
+
+        let server = server_with_policy(r#"{"allow":false,"reason":"offboarded"}"#).await;
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer("user-a", &[]))
+            .body(json!({ "csr_pem": csr_pem("user-a") }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+        assert!(!server.active("user-a").await);
+
+        let res = server
+            .client
+            .get("/api/ledger/policy-decisions/user-a")
+            .header(bearer("admin-1", &["wazuh_admin"]))
+            .dispatch()
+            .await;
+        let decisions: Vec<PolicyDecision> = res.into_json().await.expect("json body");
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].outcome, "deny");
+        assert_eq!(decisions[0].reason.as_deref(), Some("offboarded"));
+    }
+
+    #[rocket::async_test]
+    async fn enrollment_rules_refuse_tokens_without_mfa() {
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(SigningProfile {
+                enrollment_rules: EnrollmentRules {
+                    amr_values: vec!["otp".into()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        })
+        .await;
+
+        for (amr, expected) in [
+            (json!(["pwd"]), Status::Forbidden),
+            (json!(["pwd", "otp"]), Status::Ok),
+        ] {
+            let token = bearer_with(json!({
+                "sub": "user-a",
+                "iss": TEST_ISSUER,
+                "amr": amr,
+            }));
+            let res = server
+                .client
+                .post("/api/register-agent")
+                .header(ContentType::JSON)
+                .header(token)
+                .body(json!({ "csr_pem": csr_pem("user-a") }).to_string())
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), expected);
+            if expected == Status::Forbidden {
+                let body = res.into_string().await.expect("body");
+                assert!(body.contains("multi-factor"), "{body}");
+            }
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use crate::handlers::middle::ClientInfo;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::renew_cert;
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+use rocket::State;
+use rocket::mtls::Certificate;
+use rocket::serde::json::Json;
+use tracing::{error, info};
+use wazuh_cert_oauth2_model::models::errors::AppError;
+use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
+use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+
+/// Replace the caller's certificate, authenticated by that certificate over mTLS
+/// Expects a PKCS#10 CSR in PEM format; `overwrite` is ignored
+#[post("/renew", format = "application/json", data = "<dto>")]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(
+    dto,
+    client_cert,
+    client,
+    profile,
+    profiles,
+    approval,
+    config,
+    ledger,
+    crl,
+    webhook,
+    policy
+))]
+pub async fn renew(
+    dto: Json<SignCsrRequest>,
+    client_cert: Certificate<'_>,
+    client: ClientInfo,
+    profile: &State<SigningProfile>,
+    profiles: &State<CertProfiles>,
+    approval: &State<ApprovalPolicy>,
+    config: &State<CaProvider>,
+    ledger: &State<Ledger>,
+    crl: &State<CrlState>,
+    webhook: &State<Option<WebhookNotifier>>,
+    policy: &State<Option<PolicyHook>>,
+) -> Result<Json<SignedCertResponse>, AppError> {
+    info!("POST /renew called for serial={}", client_cert.serial());
+    match renew_cert(
+        dto.into_inner(),
+        client_cert.as_bytes(),
+        &client,
+        profile.inner(),
+        profiles.inner(),
+        approval.inner(),
+        config.inner(),
+        ledger.inner(),
+        crl.inner(),
+        webhook.inner().as_ref(),
+        policy.inner().as_ref(),
+    )
+    .await
+    {
+        Ok(res) => Ok(Json(res)),
+        Err(e) => {
+            error!("certificate renewal failed: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::handlers::test_support::{
+        TEST_ISSUER, TestServer, bearer_with, csr_pem, policy_endpoint,
+    };
+    use crate::models::approval_policy::ApprovalPolicy;
+    use crate::models::ca_config::{CaProvider, key_id};
+    use crate::models::enrollment_rules::EnrollmentRules;
+    use crate::models::signing_profile::SigningProfile;
+    use crate::shared::crl::{CrlKind, CrlState};
+    use crate::shared::policy_hook::PolicyHook;
+    use openssl::stack::Stack;
+    use openssl::x509::store::X509StoreBuilder;
+    use openssl::x509::{X509, X509Crl, X509StoreContext};
+    use rocket::http::{ContentType, Status};
+    use serde_json::json;
+    use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+    fn renew_body() -> String {
+        json!({ "csr_pem": csr_pem("user-a") }).to_string()
+    }
+
+    #[rocket::async_test]
+    async fn renew_replaces_and_supersedes_presented_certificate() {
+        let server = TestServer::start().await;
+        let old = server.enroll("user-a").await;
+
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(old.certificate_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let new: SignedCertResponse = res.into_json().await.expect("json body");
+        assert_ne!(new.certificate_pem, old.certificate_pem);
+        assert!(new.not_after_unix <= old.not_after_unix);
+
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.iter().filter(|e| !e.revoked).count(), 1);
+        let superseded = entries.iter().find(|e| e.revoked).expect("old entry");
+        assert_eq!(superseded.reason.as_deref(), Some("superseded"));
+
+        // The superseded certificate can no longer be used to renew.
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(old.certificate_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    /// Enroll `user-a` with a token made of `claims` and return its
+    /// certificate.
+    async fn enroll_with(server: &TestServer, claims: serde_json::Value) -> String {
+        let res = server
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer_with(claims))
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let body: SignedCertResponse = res.into_json().await.expect("json body");
+        body.certificate_pem
+    }
+
+    async fn renew_with(server: &TestServer, cert_pem: &str) -> Status {
+        server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(cert_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await
+            .status()
+    }
+
+    #[rocket::async_test]
+    async fn renewal_meets_the_enrollment_rules_and_approval_policy() {
+        let rules = SigningProfile {
+            enrollment_rules: EnrollmentRules {
+                allowed_azp: vec!["wazuh-client".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let server = TestServer::start_with(|rocket| rocket.manage(rules)).await;
+        let cert = enroll_with(
+            &server,
+            json!({ "sub": "user-a", "iss": TEST_ISSUER, "azp": "wazuh-client" }),
+        )
+        .await;
+        // The client of the original token is not known to a renewal.
+        assert_eq!(renew_with(&server, &cert).await, Status::Forbidden);
+        assert!(server.active("user-a").await);
+
+        let approval = ApprovalPolicy::new(vec!["test".into()], Vec::new());
+        let server = TestServer::start_with(|rocket| rocket.manage(approval)).await;
+        // Admins are never held, but their renewals carry no roles.
+        let cert = enroll_with(
+            &server,
+            json!({ "sub": "user-a", "iss": TEST_ISSUER, "realm_access": { "roles": ["wazuh_admin"] } }),
+        )
+        .await;
+        assert_eq!(renew_with(&server, &cert).await, Status::Forbidden);
+        assert!(server.active("user-a").await);
+    }
+
+    #[rocket::async_test]
+    async fn renewal_consults_the_policy_hook_before_revoking() {
+        // The endpoint answers the enrollment and is gone for the renewal.
+        let hook = PolicyHook::new(
+            HttpClient::new_with_defaults().expect("http"),
+            policy_endpoint(Some(r#"{"allow":true}"#)).await,
+            None,
+            Duration::from_secs(2),
+            false,
+        );
+        let server = TestServer::start_with(|rocket| rocket.manage(Some(hook))).await;
+        let cert = server.enroll("user-a").await.certificate_pem;
+
+        assert_eq!(renew_with(&server, &cert).await, Status::BadGateway);
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        assert_eq!(entries.len(), 1);
+        assert!(!entries[0].revoked);
+        let decisions = server
+            .ledger
+            .find_policy_decisions("user-a")
+            .await
+            .expect("decisions");
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[1].outcome, "error_deny");
+    }
+
+    #[rocket::async_test]
+    async fn renew_requires_client_certificate_from_our_ca() {
+        let server = TestServer::start().await;
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        // A certificate from another CA is rejected even if Rocket accepted it.
+        let other = TestServer::start().await;
+        let foreign = other.enroll("user-a").await;
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(foreign.certificate_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    #[rocket::async_test]
+    async fn intermediate_issuer_chains_verifies_and_signs_crl() {
+        let server = TestServer::start_with_intermediate().await;
+        let signed = server.enroll("user-a").await;
+
+        let bundle = X509::stack_from_pem(signed.ca_cert_pem.as_bytes()).expect("bundle");
+        assert_eq!(bundle.len(), 2, "issuing CA then root");
+        let (issuing, root) = (&bundle[0], &bundle[1]);
+        let full_chain = X509::stack_from_pem(
+            signed
+                .full_chain_pem
+                .as_deref()
+                .expect("full chain returned")
+                .as_bytes(),
+        )
+        .expect("full chain");
+        assert_eq!(full_chain.len(), 2, "leaf then intermediate, no root");
+        let leaf = &full_chain[0];
+
+        assert_eq!(
+            leaf.authority_key_id().expect("aki").as_slice(),
+            issuing.subject_key_id().expect("ski").as_slice()
+        );
+        let mut store = X509StoreBuilder::new().expect("store");
+        store.add_cert(root.clone()).expect("root");
+        let store = store.build();
+        let mut untrusted = Stack::new().expect("stack");
+        untrusted.push(full_chain[1].clone()).expect("push");
+        let mut ctx = X509StoreContext::new().expect("ctx");
+        assert!(
+            ctx.init(&store, leaf, &untrusted, |c| c.verify_cert())
+                .expect("verify")
+        );
+
+        // Renewal authenticates the intermediate-issued certificate and the
+        // resulting CRL is issued and signed by the intermediate.
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(signed.certificate_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let crl_state = server
+            .client
+            .rocket()
+            .state::<CrlState>()
+            .expect("crl state");
+        let active = server
+            .client
+            .rocket()
+            .state::<CaProvider>()
+            .expect("ca provider")
+            .active()
+            .await
+            .expect("active ca");
+        let crl = X509Crl::from_der(
+            &crl_state
+                .read_crl(&active, CrlKind::Complete)
+                .await
+                .expect("crl"),
+        )
+        .expect("crl der");
+        assert_eq!(
+            crl.issuer_name().to_der().expect("der"),
+            issuing.subject_name().to_der().expect("der")
+        );
+        assert!(
+            crl.verify(&issuing.public_key().expect("key"))
+                .expect("verify")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn retiring_ca_certificate_renews_under_active_ca() {
+        let server = TestServer::start_with_rollover().await;
+        let old_pem = server.issue_from_retiring("user-a").await;
+        let old = X509::from_pem(old_pem.as_bytes()).expect("old cert");
+        let (retiring, _) = server.retiring.as_ref().expect("retiring ca");
+
+        let res = server
+            .client
+            .post("/api/renew")
+            .header(ContentType::JSON)
+            .identity(old_pem.as_bytes())
+            .body(renew_body())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let new: SignedCertResponse = res.into_json().await.expect("json body");
+        let new = X509::from_pem(new.certificate_pem.as_bytes()).expect("new cert");
+
+        let active = server
+            .client
+            .rocket()
+            .state::<CaProvider>()
+            .expect("ca provider")
+            .active()
+            .await
+            .expect("active ca");
+        assert!(
+            new.verify(&active.cert.public_key().expect("key"))
+                .expect("verify")
+        );
+        let entries = server
+            .ledger
+            .find_by_subject("user-a")
+            .await
+            .expect("ledger");
+        let current = entries.iter().find(|e| !e.revoked).expect("new entry");
+        assert_eq!(
+            current.issuer_key_id.as_deref(),
+            Some(active.key_id.as_str())
+        );
+
+        // The superseded serial is listed by the retiring CA's CRL only.
+        let old_serial = old.serial_number().to_bn().expect("bn");
+        let retiring_id = key_id(retiring).expect("key id");
+        let retiring_crl = server
+            .client
+            .get(format!("/crl/{}.crl", retiring_id))
+            .dispatch()
+            .await;
+        assert_eq!(retiring_crl.status(), Status::Ok);
+        let retiring_crl =
+            X509Crl::from_der(&retiring_crl.into_bytes().await.expect("body")).expect("crl");
+        assert!(
+            retiring_crl
+                .verify(&retiring.public_key().expect("key"))
+                .expect("verify")
+        );
+        let listed = |crl: &X509Crl| {
+            crl.get_revoked().is_some_and(|revoked| {
+                revoked
+                    .iter()
+                    .any(|r| r.serial_number().to_bn().expect("bn") == old_serial)
+            })
+        };
+        assert!(listed(&retiring_crl));
+
+        let active_crl = server.client.get("/crl/issuing.crl").dispatch().await;
+        let active_crl =
+            X509Crl::from_der(&active_crl.into_bytes().await.expect("body")).expect("crl");
+        assert!(!listed(&active_crl));
+    }
+}
@@ -2,57 +2,125 @@ use rocket::State;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
 use wazuh_cert_oauth2_model::models::revoke_request::RevokeRequest;
 
-use crate::handlers::middle::JwtToken;
+use crate::handlers::middle::Principal;
+use crate::models::access_policy::AccessPolicy;
 use crate::models::ca_config::CaProvider;
+use crate::shared::certs::unix_now;
 use crate::shared::crl::CrlState;
 use crate::shared::ledger::Ledger;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-/// Revoke a certificate by serial and optional reason, then rebuild CRL
+/// Revoke a certificate by serial and optional reason, then rebuild CRL.
+/// Admins may revoke anything; other callers only their own certificates.
 #[post("/revoke", format = "application/json", data = "<dto>")]
-#[tracing::instrument(skip(_token, dto, crl, ledger, ca))]
+#[tracing::instrument(skip(principal, policy, dto, crl, ledger, ca), fields(sub = %principal.claims.sub))]
 pub async fn revoke(
-    _token: JwtToken,
+    principal: Principal,
+    policy: &State<AccessPolicy>,
     dto: Json<RevokeRequest>,
     crl: &State<CrlState>,
     ledger: &State<Ledger>,
     ca: &State<CaProvider>,
 ) -> Result<Status, Status> {
     info!("POST /revoke called");
+    let dto = dto.into_inner();
+    if dto.reason_code == Some(RevocationReason::RemoveFromCrl) {
+        // removeFromCRL only has meaning in delta CRLs, for lifting a hold.
+        warn!("rejecting revocation with reason removeFromCRL");
+        return Err(Status::BadRequest);
+    }
+    if dto.invalidity_date_unix.is_some_and(|at| at > unix_now()) {
+        warn!("rejecting revocation with an invalidity date in the future");
+        return Err(Status::BadRequest);
+    }
+    let reason = dto.ledger_reason();
     let RevokeRequest {
         serial_hex,
         subject,
-        reason,
-    } = dto.into_inner();
+        invalidity_date_unix,
+        ..
+    } = dto;
     debug!(
         "revoke request params: serial_hex_present={} subject_present={} reason={:?}",
         serial_hex.as_ref().map(|s| !s.is_empty()).unwrap_or(false),
         subject.as_ref().map(|s| !s.is_empty()).unwrap_or(false),
         reason
     );
-    let targets = resolve_targets(ledger, serial_hex, subject).await?;
+    if !principal.is_admin {
+        authorize_self_service(
+            &principal,
+            policy,
+            ledger,
+            serial_hex.as_deref(),
+            subject.as_deref(),
+        )
+        .await?;
+    }
+    // Subjects are only unique per token issuer, so self-service revocation
+    // by subject stays within the caller's own issuer.
+    let issuer = (!principal.is_admin).then_some(principal.claims.iss.as_str());
+    let targets = resolve_targets(ledger, serial_hex, subject, issuer).await?;
     info!(
         "revocation targets resolved: {} certificates",
         targets.len()
     );
     for s in targets {
-        ledger.mark_revoked(s, reason.clone()).await.map_err(|e| {
-            error!("Failed to record revocation: {}", e);
-            Status::InternalServerError
-        })?;
+        ledger
+            .mark_revoked(s, reason.clone(), invalidity_date_unix)
+            .await
+            .map_err(|e| {
+                error!("Failed to record revocation: {}", e);
+                Status::InternalServerError
+            })?;
     }
     rebuild_crl_now(crl, ledger, ca).await?;
     info!("revocation recorded and CRL rebuild triggered");
     Ok(Status::NoContent)
 }
 
+/// Ensure a non-admin caller only targets certificates issued to itself.
+async fn authorize_self_service(
+    principal: &Principal,
+    policy: &AccessPolicy,
+    ledger: &Ledger,
+    serial_hex: Option<&str>,
+    subject: Option<&str>,
+) -> Result<(), Status> {
+    let own = &principal.claims.sub;
+    let issuer = Some(principal.claims.iss.as_str());
+    if !principal.can_access_subject(policy, issuer, own) {
+        warn!("self-service revocation disabled; denying subject={}", own);
+        return Err(Status::Forbidden);
+    }
+    if let Some(s) = serial_hex.filter(|s| !s.trim().is_empty()) {
+        let entry = ledger.find_by_serial(s.trim()).await.map_err(|e| {
+            error!("Failed to look up serial {}: {}", s, e);
+            Status::InternalServerError
+        })?;
+        if !entry.is_some_and(|e| principal.can_access_entry(policy, &e)) {
+            warn!("subject={} tried to revoke foreign serial {}", own, s);
+            return Err(Status::Forbidden);
+        }
+        return Ok(());
+    }
+    if let Some(subj) = subject
+        && !principal.can_access_subject(policy, issuer, subj)
+    {
+        warn!("subject={} tried to revoke subject={}", own, subj);
+        return Err(Status::Forbidden);
+    }
+    Ok(())
+}
+
 #[tracing::instrument(skip(ledger))]
 async fn resolve_targets(
     ledger: &State<Ledger>,
     serial_hex: Option<String>,
     subject: Option<String>,
+    issuer: Option<&str>,
 ) -> Result<Vec<String>, Status> {
     debug!("resolving revocation targets");
     if let Some(s) = serial_hex {
@@ -63,7 +131,11 @@ async fn resolve_targets(
         };
     };
     if let Some(subj) = subject {
-        let entries = ledger.find_by_subject(&subj).await.map_err(|e| {
+        let entries = match issuer {
+            Some(iss) => ledger.find_issued_to(iss, &subj).await,
+            None => ledger.find_by_subject(&subj).await,
+        };
+        let entries = entries.map_err(|e| {
             error!("Failed to look up subject {}: {}", subj, e);
             Status::InternalServerError
         })?;
@@ -85,19 +157,192 @@ async fn rebuild_crl_now(
     ledger: &State<Ledger>,
     ca: &State<CaProvider>,
 ) -> Result<(), Status> {
-    let (ca_cert, ca_key) = ca.get().await.map_err(|e| {
-        error!("Failed to load CA for CRL rebuild: {}", e);
-        Status::InternalServerError
-    })?;
-    let revs = ledger.revoked_as_revocations().await.map_err(|e| {
-        error!("Failed to load revocations for CRL rebuild: {}", e);
-        Status::InternalServerError
-    })?;
-    info!("rebuilding CRL with {} revocations", revs.len());
-    crl.request_rebuild(ca_cert, ca_key, revs)
+    info!("rebuilding CRLs after revocation");
+    crl.rebuild_all(ca.inner(), ledger.inner())
         .await
         .map_err(|e| {
             error!("Failed to rebuild CRL: {}", e);
             Status::InternalServerError
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{
+        TEST_ISSUER, TestServer, bearer, bearer_from, test_issuer,
+    };
+    use crate::models::oidc_state::OidcState;
+    use crate::shared::certs::unix_now;
+    use crate::shared::ledger::IssuedCert;
+    use rocket::http::{ContentType, Status};
+
+    async fn revoke(server: &TestServer, sub: &str, roles: &[&str], body: &str) -> Status {
+        server
+            .client
+            .post("/api/revoke")
+            .header(ContentType::JSON)
+            .header(bearer(sub, roles))
+            .body(body)
+            .dispatch()
+            .await
+            .status()
+    }
+
+    #[rocket::async_test]
+    async fn users_cannot_revoke_foreign_subjects_or_serials() {
+        let server = TestServer::start().await;
+        server.issue("user-b", "BB01").await;
+
+        assert_eq!(
+            revoke(&server, "user-a", &[], r#"{"subject":"user-b"}"#).await,
+            Status::Forbidden
+        );
+        assert_eq!(
+            revoke(&server, "user-a", &[], r#"{"serial_hex":"BB01"}"#).await,
+            Status::Forbidden
+        );
+        assert!(server.active("user-b").await);
+    }
+
+    #[rocket::async_test]
+    async fn the_same_subject_of_another_issuer_is_a_foreign_caller() {
+        const PARTNERS: &str = "https://issuer.example/realms/partners";
+        let issuers = vec![
+            test_issuer(TEST_ISSUER, None).await,
+            test_issuer(PARTNERS, None).await,
+        ];
+        let server = TestServer::start_with(|rocket| {
+            rocket.manage(OidcState::new(issuers).expect("oidc state"))
+        })
+        .await;
+        server.issue("user-a", "AA10").await;
+
+        let partner = |body: &'static str| {
+            server
+                .client
+                .post("/api/revoke")
+                .header(ContentType::JSON)
+                .header(bearer_from(PARTNERS, "user-a", &[]))
+                .body(body)
+                .dispatch()
+        };
+        assert_eq!(
+            partner(r#"{"serial_hex":"AA10"}"#).await.status(),
+            Status::Forbidden
+        );
+        // Revoking by subject only reaches the partner's own certificates.
+        assert_eq!(
+            partner(r#"{"subject":"user-a"}"#).await.status(),
+            Status::NoContent
+        );
+        assert!(server.active("user-a").await);
+
+        let res = server
+            .client
+            .get("/api/ledger/subject/user-a")
+            .header(bearer_from(PARTNERS, "user-a", &[]))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().await.as_deref(), Some("[]"));
+
+        // Rows recorded without an issuer are left to admins.
+        server
+            .ledger
+            .record_issued(IssuedCert {
+                subject: "user-a".to_string(),
+                serial_hex: "AA11".to_string(),
+                ..Default::default()
+            })
+            .await
+            .expect("record_issued should succeed");
+        assert_eq!(
+            revoke(&server, "user-a", &[], r#"{"serial_hex":"AA11"}"#).await,
+            Status::Forbidden
+        );
+    }
+
+    #[rocket::async_test]
+    async fn users_can_revoke_their_own_certificates() {
+        let server = TestServer::start().await;
+        server.issue("user-a", "AA01").await;
+
+        assert_eq!(
+            revoke(&server, "user-a", &[], r#"{"serial_hex":"aa01"}"#).await,
+            Status::NoContent
+        );
+        assert!(!server.active("user-a").await);
+    }
+
+    #[rocket::async_test]
+    async fn admins_can_revoke_any_subject() {
+        let server = TestServer::start().await;
+        server.issue("user-b", "BB02").await;
+
+        assert_eq!(
+            revoke(
+                &server,
+                "admin-1",
+                &["wazuh_admin"],
+                r#"{"subject":"user-b"}"#
+            )
+            .await,
+            Status::NoContent
+        );
+        assert!(!server.active("user-b").await);
+    }
+
+    #[rocket::async_test]
+    async fn remove_from_crl_is_not_a_revocation_reason() {
+        let server = TestServer::start().await;
+        server.issue("user-a", "AA02").await;
+
+        assert_eq!(
+            revoke(
+                &server,
+                "user-a",
+                &[],
+                r#"{"serial_hex":"AA02","reason_code":"removeFromCRL"}"#
+            )
+            .await,
+            Status::BadRequest
+        );
+        assert!(server.active("user-a").await);
+    }
+
+    #[rocket::async_test]
+    async fn invalidity_dates_are_recorded_unless_in_the_future() {
+        let server = TestServer::start().await;
+        server.issue("user-a", "AA03").await;
+
+        let future = unix_now() + 3600;
+        assert_eq!(
+            revoke(
+                &server,
+                "user-a",
+                &[],
+                &format!(r#"{{"serial_hex":"AA03","invalidity_date_unix":{future}}}"#)
+            )
+            .await,
+            Status::BadRequest
+        );
+        assert_eq!(
+            revoke(
+                &server,
+                "user-a",
+                &[],
+                r#"{"serial_hex":"AA03","reason_code":"keyCompromise","invalidity_date_unix":1700000000}"#
+            )
+            .await,
+            Status::NoContent
+        );
+        let entry = server
+            .ledger
+            .find_by_serial("AA03")
+            .await
+            .expect("find")
+            .expect("entry");
+        assert_eq!(entry.reason.as_deref(), Some("keyCompromise"));
+        assert_eq!(entry.invalidity_date_unix, Some(1_700_000_000));
+    }
+}
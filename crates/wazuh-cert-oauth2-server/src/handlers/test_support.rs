@@ -0,0 +1,439 @@
+//! In-process Rocket instance for handler tests.
+//!
+//! Tokens are HS256-signed with a static secret whose JWK is seeded into
+//! [`OidcState`], so no issuer is contacted. Storage uses the CSV ledger and
+//! file CRL in a per-test temp directory.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectKeyIdentifier};
+use openssl::x509::{X509, X509NameBuilder, X509ReqBuilder};
+use rocket::http::{ContentType, Header as HttpHeader, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+use wazuh_cert_oauth2_model::services::refresh_cache::RefreshPolicy;
+
+use crate::handlers::crl::{get_crl, get_delta_crl, get_issuer_crl, get_issuer_delta_crl};
+use crate::handlers::crl_fairing::CrlEtagFairing;
+use crate::handlers::ocsp::{get_ocsp, post_ocsp};
+use crate::handlers::{api_routes, est_routes};
+use crate::models::access_policy::AccessPolicy;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::{CaProvider, key_id};
+use crate::models::cert_profile::CertProfiles;
+use crate::models::oidc_state::{IssuerConfig, OidcIssuer, OidcState};
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::{new_serial, set_serial_number, set_validity, unix_now};
+use crate::shared::crl::{CrlBackend, CrlState, DeltaCrlConfig};
+use crate::shared::est::EST_BASE;
+use crate::shared::ledger::{IssuedCert, Ledger, LedgerBackend};
+use crate::shared::ocsp::OcspResponder;
+use crate::shared::policy_hook::PolicyHook;
+
+pub(crate) const TEST_ISSUER: &str = "https://issuer.example/realms/test";
+const TEST_KID: &str = "test-kid";
+const TEST_SECRET: &[u8] = b"secret";
+
+pub(crate) struct TestServer {
+    pub client: Client,
+    pub ledger: Ledger,
+    /// Retiring CA configured by [`TestServer::start_with_rollover`].
+    pub retiring: Option<(X509, PKey<Private>)>,
+    dir: PathBuf,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TestCa {
+    Root,
+    Intermediate,
+    Rollover,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(|rocket| rocket).await
+    }
+
+    /// Start the server, letting the caller manage extra or replacement
+    /// policy state first.
+    pub async fn start_with(
+        configure: impl FnOnce(rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build>,
+    ) -> Self {
+        Self::build(TestCa::Root, configure).await
+    }
+
+    /// Start the server issuing from an intermediate under a test root.
+    pub async fn start_with_intermediate() -> Self {
+        Self::build(TestCa::Intermediate, |rocket| rocket).await
+    }
+
+    /// Start the server with an active CA and a retiring one.
+    pub async fn start_with_rollover() -> Self {
+        Self::build(TestCa::Rollover, |rocket| rocket).await
+    }
+
+    async fn build(
+        ca: TestCa,
+        configure: impl FnOnce(rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build>,
+    ) -> Self {
+        let dir = temp_dir("server-handlers");
+        let (ca_cert_path, ca_key_path) = write_test_ca(&dir, ca == TestCa::Intermediate);
+        let retiring = (ca == TestCa::Rollover).then(|| make_ca("test-old-ca", None));
+        let mut retiring_paths = Vec::new();
+        if let Some((cert, key)) = &retiring {
+            let cert_path = dir.join("old-ca.pem");
+            let key_path = dir.join("old-ca.key");
+            std::fs::write(&cert_path, cert.to_pem().expect("pem")).expect("write cert");
+            std::fs::write(&key_path, key.private_key_to_pem_pkcs8().expect("key pem"))
+                .expect("write key");
+            retiring_paths.push((
+                cert_path.display().to_string(),
+                key_path.display().to_string(),
+            ));
+        }
+
+        let ledger = Ledger::new(LedgerBackend::Csv(dir.join("ledger.csv")))
+            .await
+            .expect("ledger should initialize");
+        let delta = DeltaCrlConfig {
+            url: "http://crl.test/crl/delta/{issuer}.crl".to_string(),
+            base_interval: Duration::from_secs(86_400),
+        };
+        let crl = CrlState::new(CrlBackend::File(dir.join("issuing.crl")), Some(delta))
+            .await
+            .expect("crl state should initialize");
+
+        let mut rocket = configure(rocket::build())
+            .manage(
+                CaProvider::new(ca_cert_path, ca_key_path, Duration::from_secs(300), None)
+                    .with_retiring(retiring_paths),
+            )
+            .manage(ledger.clone())
+            .manage(crl)
+            .manage(None::<crate::shared::webhook_notifier::WebhookNotifier>)
+            .attach(CrlEtagFairing)
+            .mount(
+                "/",
+                routes![
+                    get_crl,
+                    get_issuer_crl,
+                    get_delta_crl,
+                    get_issuer_delta_crl,
+                    post_ocsp,
+                    get_ocsp
+                ],
+            )
+            .mount("/api", api_routes())
+            .mount(EST_BASE, est_routes());
+        // Policy state the caller did not provide falls back to defaults.
+        if rocket.state::<OidcState>().is_none() {
+            let issuer = test_issuer(TEST_ISSUER, None).await;
+            rocket = rocket.manage(OidcState::new(vec![issuer]).expect("oidc state"));
+        }
+        if rocket.state::<AccessPolicy>().is_none() {
+            rocket = rocket.manage(AccessPolicy::default());
+        }
+        if rocket.state::<ApprovalPolicy>().is_none() {
+            rocket = rocket.manage(ApprovalPolicy::default());
+        }
+        if rocket.state::<SigningProfile>().is_none() {
+            rocket = rocket.manage(SigningProfile::default());
+        }
+        if rocket.state::<CertProfiles>().is_none() {
+            rocket = rocket.manage(CertProfiles::default());
+        }
+        if rocket.state::<OcspResponder>().is_none() {
+            rocket = rocket.manage(OcspResponder::new(Duration::from_secs(3600)));
+        }
+        if rocket.state::<Option<PolicyHook>>().is_none() {
+            rocket = rocket.manage(None::<PolicyHook>);
+        }
+        let client = Client::tracked(rocket).await.expect("rocket should ignite");
+
+        Self {
+            client,
+            ledger,
+            retiring,
+            dir,
+        }
+    }
+
+    /// Record an active certificate for `subject` directly in the ledger.
+    pub async fn issue(&self, subject: &str, serial_hex: &str) {
+        self.ledger
+            .record_issued(IssuedCert {
+                subject: subject.to_string(),
+                serial_hex: serial_hex.to_string(),
+                issuer: Some(TEST_ISSUER.to_string()),
+                realm: Some("test".to_string()),
+                ..Default::default()
+            })
+            .await
+            .expect("record_issued should succeed");
+    }
+
+    /// Enroll `subject` through `/api/register-agent` with a fresh CSR.
+    pub async fn enroll(&self, subject: &str) -> SignedCertResponse {
+        let res = self
+            .client
+            .post("/api/register-agent")
+            .header(ContentType::JSON)
+            .header(bearer(subject, &[]))
+            .body(json!({ "csr_pem": csr_pem(subject) }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok, "enrollment should succeed");
+        res.into_json().await.expect("json body")
+    }
+
+    /// PEM certificate for `subject` signed by the retiring CA and recorded
+    /// in the ledger, as if issued before the rollover.
+    pub async fn issue_from_retiring(&self, subject: &str) -> String {
+        let (ca, ca_key) = self.retiring.as_ref().expect("rollover server");
+        let leaf_key = PKey::from_rsa(Rsa::generate(2048).expect("rsa")).expect("pkey");
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", subject).expect("cn");
+        let mut builder = X509::builder().expect("builder");
+        builder.set_version(2).expect("version");
+        set_serial_number(&mut builder, &new_serial().expect("serial")).expect("serial");
+        builder.set_subject_name(&name.build()).expect("subject");
+        builder.set_issuer_name(ca.subject_name()).expect("issuer");
+        builder.set_pubkey(&leaf_key).expect("pubkey");
+        let now = unix_now();
+        set_validity(&mut builder, now - 60, now + 30 * 86_400).expect("validity");
+        builder.sign(ca_key, MessageDigest::sha256()).expect("sign");
+        let cert = builder.build();
+
+        self.ledger
+            .record_issued(IssuedCert {
+                subject: subject.to_string(),
+                serial_hex: cert
+                    .serial_number()
+                    .to_bn()
+                    .and_then(|bn| bn.to_hex_str().map(|h| h.to_string()))
+                    .expect("serial hex"),
+                issuer: Some(TEST_ISSUER.to_string()),
+                realm: Some("test".to_string()),
+                issuer_key_id: Some(key_id(ca).expect("key id")),
+                ..Default::default()
+            })
+            .await
+            .expect("record_issued should succeed");
+        String::from_utf8(cert.to_pem().expect("pem")).expect("utf8")
+    }
+
+    /// Whether `subject` still has an active certificate.
+    pub async fn active(&self, subject: &str) -> bool {
+        self.ledger
+            .find_by_subject(subject)
+            .await
+            .expect("find_by_subject should succeed")
+            .iter()
+            .any(|e| !e.revoked)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Trusted issuer `iss` whose key set is the test key.
+pub(crate) async fn test_issuer(iss: &str, admin_roles: Option<Vec<String>>) -> OidcIssuer {
+    let issuer = OidcIssuer::new(
+        IssuerConfig {
+            issuer: iss.to_string(),
+            audiences: None,
+            admin_roles,
+            leeway_secs: None,
+            introspection: false,
+        },
+        RefreshPolicy::with_ttl(Duration::from_secs(3600)),
+        RefreshPolicy::with_ttl(Duration::from_secs(3600)),
+        HttpClient::new_with_defaults().expect("http client"),
+    );
+    issuer.seed_jwks(test_jwks()).await;
+    issuer
+}
+
+/// `Authorization` header carrying a token for `sub` with the given realm roles.
+pub(crate) fn bearer(sub: &str, roles: &[&str]) -> HttpHeader<'static> {
+    bearer_from(TEST_ISSUER, sub, roles)
+}
+
+/// Like [`bearer`], for a token issued by `iss`.
+pub(crate) fn bearer_from(iss: &str, sub: &str, roles: &[&str]) -> HttpHeader<'static> {
+    bearer_with(json!({
+        "sub": sub,
+        "iss": iss,
+        "preferred_username": sub,
+        "realm_access": { "roles": roles },
+    }))
+}
+
+/// `Authorization` header carrying a token with exactly `claims`, plus an
+/// `exp` far in the future.
+pub(crate) fn bearer_with(mut claims: serde_json::Value) -> HttpHeader<'static> {
+    claims["exp"] = json!(4_102_444_800u64);
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(TEST_KID.to_string());
+    let token = encode(&header, &claims, &EncodingKey::from_secret(TEST_SECRET))
+        .expect("token should encode");
+    HttpHeader::new("Authorization", format!("Bearer {}", token))
+}
+
+/// PEM CSR with CN `cn` for a fresh P-256 key.
+pub(crate) fn csr_pem(cn: &str) -> String {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("group");
+    let key = PKey::from_ec_key(EcKey::generate(&group).expect("ec key")).expect("pkey");
+    let mut name = X509NameBuilder::new().expect("name");
+    name.append_entry_by_text("CN", cn).expect("cn");
+    let mut req = X509ReqBuilder::new().expect("req");
+    req.set_subject_name(&name.build()).expect("subject");
+    req.set_pubkey(&key).expect("pubkey");
+    req.sign(&key, MessageDigest::sha256()).expect("sign");
+    String::from_utf8(req.build().to_pem().expect("pem")).expect("utf8")
+}
+
+fn test_jwks() -> JwkSet {
+    serde_json::from_value(json!({
+        "keys": [{
+            "kty": "oct",
+            "kid": TEST_KID,
+            "alg": "HS256",
+            // base64url("secret")
+            "k": "c2VjcmV0",
+        }]
+    }))
+    .expect("jwks should parse")
+}
+
+/// A new, empty directory under the system temp dir named after `prefix`,
+/// unique per process and call.
+pub(crate) fn temp_dir(prefix: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time should be monotonic")
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!(
+        "wazuh-{prefix}-{}-{nanos}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).expect("temp dir should be created");
+    dir
+}
+
+/// Write a CA usable for signing certificates and CRLs.
+///
+/// With `intermediate`, `ca.pem` holds the root followed by an issuing
+/// intermediate (deliberately out of order) and `ca.key` the intermediate key.
+fn write_test_ca(dir: &std::path::Path, intermediate: bool) -> (String, String) {
+    let (root, root_key) = make_ca("test-ca", None);
+    let (pem, key) = if intermediate {
+        let (issuing, issuing_key) = make_ca("test-issuing-ca", Some((&root, &root_key)));
+        let mut pem = root.to_pem().expect("root pem");
+        pem.extend(issuing.to_pem().expect("issuing pem"));
+        (pem, issuing_key)
+    } else {
+        (root.to_pem().expect("cert pem"), root_key)
+    };
+
+    let cert_path = dir.join("ca.pem");
+    let key_path = dir.join("ca.key");
+    std::fs::write(&cert_path, pem).expect("write cert");
+    std::fs::write(&key_path, key.private_key_to_pem_pkcs8().expect("key pem")).expect("write key");
+    (
+        cert_path.display().to_string(),
+        key_path.display().to_string(),
+    )
+}
+
+/// Build a CA certificate, self-signed unless `issuer` is given.
+pub(crate) fn make_ca(cn: &str, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).expect("rsa")).expect("pkey");
+    let mut name = X509NameBuilder::new().expect("name builder");
+    name.append_entry_by_text("CN", cn).expect("cn");
+    let name = name.build();
+
+    let mut builder = X509::builder().expect("x509 builder");
+    builder.set_version(2).expect("version");
+    let serial = openssl::bn::BigNum::from_u32(1)
+        .and_then(|bn| bn.to_asn1_integer())
+        .expect("serial");
+    builder.set_serial_number(&serial).expect("serial");
+    builder.set_subject_name(&name).expect("subject");
+    match issuer {
+        Some((cert, _)) => builder.set_issuer_name(cert.subject_name()),
+        None => builder.set_issuer_name(&name),
+    }
+    .expect("issuer");
+    builder.set_pubkey(&key).expect("pubkey");
+    builder
+        .set_not_before(Asn1Time::days_from_now(0).expect("time").as_ref())
+        .expect("not before");
+    builder
+        .set_not_after(Asn1Time::days_from_now(3650).expect("time").as_ref())
+        .expect("not after");
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build().expect("bc"))
+        .expect("bc");
+    builder
+        .append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .build()
+                .expect("ku"),
+        )
+        .expect("ku");
+    let ski = SubjectKeyIdentifier::new()
+        .build(&builder.x509v3_context(issuer.map(|(c, _)| c.as_ref()), None))
+        .expect("ski");
+    builder.append_extension(ski).expect("ski");
+    let signing_key = issuer.map(|(_, k)| k).unwrap_or(&key);
+    builder
+        .sign(signing_key, MessageDigest::sha256())
+        .expect("sign");
+    (builder.build(), key)
+}
+
+/// Answer one request with `body`, or never answer when `None`.
+pub(crate) async fn policy_endpoint(body: Option<&'static str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.expect("accept");
+        let mut buf = vec![0u8; 16 * 1024];
+        let _ = stream.read(&mut buf).await;
+        match body {
+            Some(body) => {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+            None => tokio::time::sleep(Duration::from_secs(5)).await,
+        }
+    });
+    format!("http://{addr}/decide")
+}
@@ -3,30 +3,45 @@ extern crate rocket;
 
 use std::time::Duration;
 
-use crate::handlers::crl::{get_crl, get_revocations};
+use crate::handlers::acme_fairing::AcmeNonceFairing;
+use crate::handlers::crl::{get_crl, get_delta_crl, get_issuer_crl, get_issuer_delta_crl};
 use crate::handlers::crl_fairing::CrlEtagFairing;
 use crate::handlers::health::health;
-use crate::handlers::ledger::{
-    get_active_ledger, get_all_ledger, get_ledger_by_subject, get_revoked_ledger,
+use crate::handlers::ocsp::{get_ocsp, post_ocsp};
+use crate::handlers::{acme_api_routes, acme_routes, api_routes, est_routes};
+use crate::models::access_policy::AccessPolicy;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::cert_profile::{CertProfiles, parse_cert_profiles};
+use crate::models::enrollment_rules::EnrollmentRules;
+use crate::models::key_policy::parse_key_policy;
+use crate::models::naming_template::parse_naming_template;
+use crate::models::oidc_state::{IssuerConfig, OidcIssuer, OidcState, parse_issuers};
+use crate::models::signing_profile::{
+    SigningProfile, parse_csr_extension_mode, parse_device_quota_overrides,
+    parse_validity_overrides,
 };
-use crate::handlers::register_agent::register_agent;
-use crate::handlers::revoke::revoke;
-use crate::models::oidc_state::OidcState;
 
 mod handlers;
 mod migrate;
 mod models;
 mod shared;
-use crate::models::ca_config::CaProvider;
-use crate::shared::crl::{CrlBackend, CrlState};
+use crate::models::ca_config::{CaProvider, parse_retiring_cas};
+use crate::shared::acme::{ACME_BASE, AcmeState};
+use crate::shared::crl::{CrlBackend, CrlState, DeltaCrlConfig};
+use crate::shared::est::EST_BASE;
+use crate::shared::introspection::Introspector;
 use crate::shared::ledger::{Ledger, LedgerBackend};
+use crate::shared::ocsp::{OcspDelegate, OcspResponder};
 use crate::shared::opts::{Command, Opt, ServeOpt};
+use crate::shared::policy_hook::PolicyHook;
 use clap::Parser;
 use mimalloc::MiMalloc;
 use tracing::info;
+use wazuh_cert_oauth2_model::models::claim_mapping::{ClaimMapping, parse_claim_list};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
 use wazuh_cert_oauth2_model::services::logging::setup_logging;
+use wazuh_cert_oauth2_model::services::refresh_cache::RefreshPolicy;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -59,22 +74,163 @@ async fn run_server(opt: ServeOpt) -> AppResult<()> {
     let ServeOpt {
         oauth_issuer,
         kc_audiences,
+        oidc_issuers_path,
+        oidc_leeway_secs,
+        oauth_introspection,
+        introspection_client_id,
+        introspection_client_secret,
         root_ca_path,
         root_ca_key_path,
+        retiring_cas,
         discovery_ttl_secs,
         jwks_ttl_secs,
+        jwks_refresh_min_interval_secs,
+        jwks_max_stale_secs,
         ca_cache_ttl_secs,
         crl_dist_url,
+        delta_crl_url,
+        delta_crl_base_interval_secs,
+        ocsp_url,
+        ocsp_signer_cert_path,
+        ocsp_signer_key_path,
+        ocsp_validity_secs,
         crl_path,
         ledger_path,
         database_url,
         webhook_base_url,
         webhook_bearer_token,
+        admin_roles,
+        claim_roles,
+        claim_groups,
+        claim_name,
+        claim_email,
+        self_service,
+        enroll_acr_values,
+        enroll_amr_values,
+        enroll_require_email_verified,
+        enroll_max_auth_age_secs,
+        enroll_allowed_clients,
+        enroll_exempt_roles,
+        approval_realms,
+        approval_roles,
+        policy_hook_url,
+        policy_hook_bearer_token,
+        policy_hook_timeout_ms,
+        policy_hook_fail_open,
+        cert_validity_days,
+        cert_max_validity_days,
+        cert_validity_overrides,
+        cert_backdate_secs,
+        cert_renewal_window_days,
+        key_policy,
+        cert_subject_template,
+        cert_san_template,
+        cert_san_skip_invalid_dns,
+        csr_extensions,
+        cert_max_devices,
+        cert_device_quotas,
+        acme_base_url,
+        acme_eab_ttl_secs,
+        acme_binding_ttl_secs,
+        cert_profiles_path,
     } = opt;
     let kc_audiences = kc_audiences.map(|a| a.split(",").map(|s| s.to_string()).collect());
+    let claim_mapping = ClaimMapping {
+        roles: parse_claim_list(&claim_roles),
+        groups: parse_claim_list(&claim_groups),
+        name: parse_claim_list(&claim_name),
+        email: parse_claim_list(&claim_email),
+        admin_roles: parse_claim_list(&admin_roles),
+    };
+    let signing_profile = SigningProfile {
+        validity_days: cert_validity_days,
+        max_validity_days: cert_max_validity_days,
+        overrides: parse_validity_overrides(&cert_validity_overrides)?,
+        backdate_secs: cert_backdate_secs,
+        renewal_window_days: cert_renewal_window_days,
+        key_policy: parse_key_policy(&key_policy)?,
+        naming: parse_naming_template(&cert_subject_template, &cert_san_template)?
+            .skipping_invalid_dns(cert_san_skip_invalid_dns),
+        csr_extensions: parse_csr_extension_mode(&csr_extensions)?,
+        max_devices: cert_max_devices,
+        device_quota_overrides: parse_device_quota_overrides(&cert_device_quotas)?,
+        enrollment_rules: EnrollmentRules {
+            acr_values: parse_claim_list(&enroll_acr_values),
+            amr_values: parse_claim_list(&enroll_amr_values),
+            require_email_verified: enroll_require_email_verified,
+            max_auth_age_secs: enroll_max_auth_age_secs,
+            allowed_azp: parse_claim_list(&enroll_allowed_clients),
+            exempt_roles: parse_claim_list(&enroll_exempt_roles),
+        },
+    };
+    if cert_max_devices == 0 {
+        return Err(AppError::ValidationError(
+            "CERT_MAX_DEVICES must be at least 1".into(),
+        ));
+    }
+    let cert_profiles = match cert_profiles_path {
+        Some(path) => {
+            let json = tokio::fs::read_to_string(&path).await?;
+            parse_cert_profiles(&json)?
+        }
+        None => CertProfiles::default(),
+    };
+    signing_profile.check_validity()?;
+    cert_profiles.check_validity(&signing_profile)?;
+
+    let mut issuers = match oidc_issuers_path {
+        Some(path) => parse_issuers(&tokio::fs::read_to_string(&path).await?)?,
+        None => Vec::new(),
+    };
+    if let Some(issuer) = oauth_issuer {
+        issuers.insert(
+            0,
+            IssuerConfig {
+                issuer,
+                audiences: kc_audiences,
+                admin_roles: None,
+                leeway_secs: None,
+                introspection: oauth_introspection,
+            },
+        );
+    }
 
     // Shared HTTP client service with connection pooling
     let http_client = HttpClient::new_with_defaults()?;
+    let refresh_policy = |ttl_secs| RefreshPolicy {
+        ttl: Duration::from_secs(ttl_secs),
+        min_refresh_interval: Duration::from_secs(jwks_refresh_min_interval_secs),
+        max_stale: Duration::from_secs(jwks_max_stale_secs),
+    };
+    let oidc = OidcState::new(
+        issuers
+            .into_iter()
+            .map(|mut config| {
+                config.leeway_secs.get_or_insert(oidc_leeway_secs);
+                let introspection = config.introspection;
+                let issuer = OidcIssuer::new(
+                    config,
+                    refresh_policy(discovery_ttl_secs),
+                    refresh_policy(jwks_ttl_secs),
+                    http_client.clone(),
+                );
+                if !introspection {
+                    return Ok(issuer);
+                }
+                match (&introspection_client_id, &introspection_client_secret) {
+                    (Some(id), Some(secret)) => Ok(issuer.with_introspector(Introspector::new(
+                        http_client.clone(),
+                        id.clone(),
+                        secret.clone(),
+                    ))),
+                    _ => Err(AppError::ValidationError(format!(
+                        "issuer '{}' uses introspection, which needs INTROSPECTION_CLIENT_ID and INTROSPECTION_CLIENT_SECRET",
+                        issuer.issuer()
+                    ))),
+                }
+            })
+            .collect::<AppResult<Vec<_>>>()?,
+    )?;
 
     // Storage backends: PostgreSQL when DATABASE_URL is set (system of
     // record), otherwise fall back to the on-disk CSV ledger / file CRL for
@@ -108,6 +264,16 @@ async fn run_server(opt: ServeOpt) -> AppResult<()> {
         }
     };
 
+    let delta_crl = delta_crl_url.clone().map(|url| DeltaCrlConfig {
+        url,
+        base_interval: Duration::from_secs(delta_crl_base_interval_secs),
+    });
+
+    let ocsp_delegate = match (ocsp_signer_cert_path, ocsp_signer_key_path) {
+        (Some(cert), Some(key)) => Some(OcspDelegate::load(&cert, &key).await?),
+        _ => None,
+    };
+
     let webhook_notifier = webhook_base_url.map(|base_url| {
         crate::shared::webhook_notifier::WebhookNotifier::new(
             http_client.clone(),
@@ -116,38 +282,77 @@ async fn run_server(opt: ServeOpt) -> AppResult<()> {
         )
     });
 
-    rocket::build()
+    let policy_hook = policy_hook_url.map(|url| {
+        PolicyHook::new(
+            http_client.clone(),
+            url,
+            policy_hook_bearer_token,
+            Duration::from_millis(policy_hook_timeout_ms),
+            policy_hook_fail_open,
+        )
+    });
+
+    let ca = CaProvider::new(
+        root_ca_path,
+        root_ca_key_path,
+        Duration::from_secs(ca_cache_ttl_secs),
+        crl_dist_url,
+    )
+    .with_retiring(parse_retiring_cas(&retiring_cas)?)
+    .with_delta_crl_url(delta_crl_url)
+    .with_ocsp_url(ocsp_url);
+    ca.check_crl_urls()?;
+
+    let mut rocket = rocket::build()
         .manage(http_client.clone())
-        .manage(OidcState::new(
-            oauth_issuer,
-            kc_audiences,
-            Duration::from_secs(discovery_ttl_secs),
-            Duration::from_secs(jwks_ttl_secs),
-            http_client,
-        ))
-        .manage(CaProvider::new(
-            root_ca_path,
-            root_ca_key_path,
-            Duration::from_secs(ca_cache_ttl_secs),
-            crl_dist_url,
-        ))
+        .manage(oidc)
+        .manage(ca)
         .manage(ledger)
-        .manage(CrlState::new(crl_backend).await?)
+        .manage(CrlState::new(crl_backend, delta_crl).await?)
+        .manage(
+            OcspResponder::new(Duration::from_secs(ocsp_validity_secs))
+                .with_delegate(ocsp_delegate),
+        )
         .manage(webhook_notifier)
+        .manage(policy_hook)
+        .manage(AccessPolicy::new(
+            claim_mapping.admin_roles.clone(),
+            self_service,
+        ))
+        .manage(claim_mapping)
+        .manage(ApprovalPolicy::parse(&approval_realms, &approval_roles))
+        .manage(signing_profile)
+        .manage(cert_profiles)
         .attach(CrlEtagFairing)
-        .mount("/", routes![health, get_crl])
         .mount(
-            "/api",
+            "/",
             routes![
-                register_agent,
-                revoke,
-                get_revocations,
-                get_all_ledger,
-                get_active_ledger,
-                get_revoked_ledger,
-                get_ledger_by_subject
+                health,
+                get_crl,
+                get_issuer_crl,
+                get_delta_crl,
+                get_issuer_delta_crl,
+                post_ocsp,
+                get_ocsp
             ],
         )
+        .mount("/api", api_routes())
+        .mount(EST_BASE, est_routes());
+    if let Some(base_url) = acme_base_url.as_deref().map(str::trim)
+        && !base_url.is_empty()
+    {
+        info!("ACME enabled at {}{}/directory", base_url, ACME_BASE);
+        rocket = rocket
+            .manage(AcmeState::new(
+                base_url,
+                Duration::from_secs(acme_eab_ttl_secs),
+                Duration::from_secs(acme_binding_ttl_secs),
+            ))
+            .attach(AcmeNonceFairing)
+            .mount(ACME_BASE, acme_routes())
+            .mount("/api", acme_api_routes());
+    }
+    rocket
         .launch()
         .await
         .map_err(|e| AppError::RocketError(Box::new(e)))?;
@@ -119,6 +119,11 @@ pub async fn run_migration(opt: MigrateOpt) -> AppResult<()> {
                 .wazuh_agent_name
                 .clone()
                 .or_else(|| result.as_ref().map(|m| m.agent_name.clone())),
+            not_after_unix: entry.not_after_unix,
+            issuer_key_id: entry.issuer_key_id.clone(),
+            profile: entry.profile.clone(),
+            device_id: entry.device_id.clone(),
+            invalidity_date_unix: entry.invalidity_date_unix,
         });
 
         match &result {
@@ -73,8 +73,8 @@ pub async fn run_migration(opt: MigrateV2Opt) -> AppResult<()> {
         };
 
         sqlx::query(
-            "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, revoked_at_unix, reason, issuer, realm, wazuh_agent_name)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
         )
         .bind(event_type)
         .bind(&entry.subject)
@@ -85,13 +85,18 @@ pub async fn run_migration(opt: MigrateV2Opt) -> AppResult<()> {
         .bind(&entry.issuer)
         .bind(&entry.realm)
         .bind(&entry.wazuh_agent_name)
+        .bind(entry.not_after_unix.map(|v| v as i64))
+        .bind(&entry.issuer_key_id)
+        .bind(&entry.profile)
+        .bind(&entry.device_id)
+        .bind(entry.invalidity_date_unix.map(|v| v as i64))
         .execute(&mut *tx)
         .await
         .map_err(|e| AppError::UpstreamError(format!("failed to insert ledger_event: {}", e)))?;
 
         sqlx::query(
-            "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
              ON CONFLICT (serial_hex) DO UPDATE SET
                subject = EXCLUDED.subject,
                issued_at_unix = EXCLUDED.issued_at_unix,
@@ -101,6 +106,11 @@ pub async fn run_migration(opt: MigrateV2Opt) -> AppResult<()> {
                issuer = EXCLUDED.issuer,
                realm = EXCLUDED.realm,
                wazuh_agent_name = EXCLUDED.wazuh_agent_name,
+               not_after_unix = EXCLUDED.not_after_unix,
+               issuer_key_id = EXCLUDED.issuer_key_id,
+               profile = EXCLUDED.profile,
+               device_id = EXCLUDED.device_id,
+               invalidity_date_unix = EXCLUDED.invalidity_date_unix,
                updated_at = now()",
         )
         .bind(&serial)
@@ -112,6 +122,11 @@ pub async fn run_migration(opt: MigrateV2Opt) -> AppResult<()> {
         .bind(&entry.issuer)
         .bind(&entry.realm)
         .bind(&entry.wazuh_agent_name)
+        .bind(entry.not_after_unix.map(|v| v as i64))
+        .bind(&entry.issuer_key_id)
+        .bind(&entry.profile)
+        .bind(&entry.device_id)
+        .bind(entry.invalidity_date_unix.map(|v| v as i64))
         .execute(&mut *tx)
         .await
         .map_err(|e| AppError::UpstreamError(format!("failed to insert ledger_entry: {}", e)))?;
@@ -0,0 +1,104 @@
+use wazuh_cert_oauth2_model::models::claims::Claims;
+
+/// Authorization rules for the `/api` routes.
+///
+/// Admins (any of `admin_roles` in `realm_access.roles`) may read the whole
+/// ledger and revoke any certificate. When `self_service` is enabled, other
+/// callers may still read and revoke the certificates issued to their own
+/// subject under their own token issuer.
+pub struct AccessPolicy {
+    admin_roles: Vec<String>,
+    self_service: bool,
+}
+
+impl AccessPolicy {
+    pub fn new(admin_roles: Vec<String>, self_service: bool) -> Self {
+        Self {
+            admin_roles,
+            self_service,
+        }
+    }
+
+    /// Whether `claims` hold an admin role: one of the token issuer's own
+    /// `issuer_roles` when it has them, otherwise one of `admin_roles`.
+    pub fn is_admin_for(&self, claims: &Claims, issuer_roles: Option<&[String]>) -> bool {
+        claims.has_any_role(issuer_roles.unwrap_or(&self.admin_roles))
+    }
+
+    /// Whether the caller may read or revoke certificates issued to `subject`
+    /// of the token issuer `issuer`; `None` stands for rows recorded without
+    /// one, which only admins may touch.
+    pub fn can_access_subject(
+        &self,
+        claims: &Claims,
+        is_admin: bool,
+        issuer: Option<&str>,
+        subject: &str,
+    ) -> bool {
+        is_admin || (self.self_service && claims.sub == subject && issuer == Some(&claims.iss))
+    }
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self::new(vec!["wazuh_admin".to_string()], true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccessPolicy;
+    use wazuh_cert_oauth2_model::models::claims::{Claims, RealmAccess};
+
+    fn claims(sub: &str, roles: &[&str]) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            name: None,
+            iss: "https://issuer.example/realms/main".to_string(),
+            exp: 9_999_999_999,
+            preferred_username: None,
+            email: None,
+            realm_access: Some(RealmAccess {
+                roles: roles.iter().map(|r| r.to_string()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn admin_roles_are_configurable() {
+        let policy = AccessPolicy::new(vec!["pki-admin".to_string()], true);
+        assert!(!policy.is_admin_for(&claims("a", &["wazuh_admin"]), None));
+        let partners = ["partner-admin".to_string()];
+        assert!(policy.is_admin_for(&claims("a", &["partner-admin"]), Some(&partners)));
+        assert!(!policy.is_admin_for(&claims("a", &["pki-admin"]), Some(&partners)));
+        assert!(policy.is_admin_for(&claims("a", &["pki-admin"]), None));
+    }
+
+    #[test]
+    fn self_service_limits_access_to_own_subject() {
+        let policy = AccessPolicy::default();
+        let user = claims("user-a", &[]);
+        let own = Some(user.iss.as_str());
+        assert!(policy.can_access_subject(&user, false, own, "user-a"));
+        assert!(!policy.can_access_subject(&user, false, own, "user-b"));
+        assert!(policy.can_access_subject(&user, true, own, "user-b"));
+    }
+
+    #[test]
+    fn self_service_is_scoped_to_the_token_issuer() {
+        let policy = AccessPolicy::default();
+        let user = claims("user-a", &[]);
+        let other = Some("https://issuer.example/realms/partner");
+        assert!(!policy.can_access_subject(&user, false, other, "user-a"));
+        assert!(!policy.can_access_subject(&user, false, None, "user-a"));
+        assert!(policy.can_access_subject(&user, true, other, "user-a"));
+    }
+
+    #[test]
+    fn disabled_self_service_requires_admin() {
+        let policy = AccessPolicy::new(vec!["wazuh_admin".to_string()], false);
+        let user = claims("user-a", &[]);
+        assert!(!policy.can_access_subject(&user, false, Some(&user.iss), "user-a"));
+    }
+}
@@ -0,0 +1,75 @@
+use wazuh_cert_oauth2_model::models::claims::Claims;
+
+use crate::shared::certs::extract_realm_from_issuer;
+
+/// Which enrollments wait for an admin's decision instead of being signed
+/// immediately.
+///
+/// A token from one of `realms` (the Keycloak realm of its issuer) or holding
+/// one of `roles` is held for approval. Admins are never held. Both lists
+/// empty, the default, disables approval.
+#[derive(Default)]
+pub struct ApprovalPolicy {
+    realms: Vec<String>,
+    roles: Vec<String>,
+}
+
+impl ApprovalPolicy {
+    pub fn new(realms: Vec<String>, roles: Vec<String>) -> Self {
+        Self { realms, roles }
+    }
+
+    /// Build from the comma-separated `APPROVAL_REALMS` and `APPROVAL_ROLES`.
+    pub fn parse(realms: &str, roles: &str) -> Self {
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        Self::new(list(realms), list(roles))
+    }
+
+    /// Whether an enrollment by the holder of `claims` needs approval.
+    pub fn requires_approval(&self, claims: &Claims, is_admin: bool) -> bool {
+        if is_admin {
+            return false;
+        }
+        claims.has_any_role(&self.roles)
+            || extract_realm_from_issuer(&claims.iss)
+                .is_some_and(|realm| self.realms.contains(&realm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApprovalPolicy;
+    use wazuh_cert_oauth2_model::models::claims::{Claims, RealmAccess};
+
+    fn claims(realm: &str, roles: &[&str]) -> Claims {
+        Claims {
+            sub: "user-a".to_string(),
+            name: None,
+            iss: format!("https://issuer.example/realms/{realm}"),
+            exp: 9_999_999_999,
+            preferred_username: None,
+            email: None,
+            realm_access: Some(RealmAccess {
+                roles: roles.iter().map(|r| r.to_string()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn holds_listed_realms_and_roles_but_not_admins() {
+        let policy = ApprovalPolicy::parse("partners, ", "contractor");
+        assert!(policy.requires_approval(&claims("partners", &[]), false));
+        assert!(policy.requires_approval(&claims("main", &["contractor"]), false));
+        assert!(!policy.requires_approval(&claims("main", &["staff"]), false));
+        assert!(!policy.requires_approval(&claims("partners", &[]), true));
+        assert!(!ApprovalPolicy::default().requires_approval(&claims("partners", &[]), false));
+    }
+}
@@ -2,23 +2,48 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::fs::read;
 
-use openssl::pkey::PKey;
-use openssl::pkey::Private;
-use openssl::x509::X509;
+use openssl::hash::{MessageDigest, hash};
+use openssl::pkey::{HasPublic, PKeyRef};
+use openssl::x509::{X509, X509Ref};
 use tokio::sync::RwLock;
-use wazuh_cert_oauth2_model::models::errors::AppResult;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 
+use crate::shared::ca_signer::{CaSigner, load_signer};
+
+/// Issuing CAs loaded from disk and cached for `ttl`.
+///
+/// The active CA (`root_ca_path` / `root_ca_key_path`) signs new
+/// certificates. Retiring CAs no longer issue but keep their own CRL until
+/// the certificates they signed have expired, so a CA can be rolled over
+/// without downtime.
+///
+/// Each certificate path may hold a single certificate or a chain (issuing
+/// CA, intermediates, optionally the root) in any order; the certificate
+/// whose public key matches the CA key is the issuer used for signing. The
+/// key path selects the signing backend, see
+/// [`load_signer`](crate::shared::ca_signer::load_signer).
 pub struct CaProvider {
-    root_ca_path: String,
-    root_ca_key_path: String,
+    sources: Vec<(String, String)>,
     ttl: Duration,
     crl_dist_url: Option<String>,
-    inner: RwLock<Inner>,
+    delta_crl_url: Option<String>,
+    ocsp_url: Option<String>,
+    inner: RwLock<Option<(Issuers, Instant)>>,
 }
 
-struct Inner {
-    ca_cert: Option<(Arc<X509>, Instant)>,
-    ca_key: Option<(Arc<PKey<Private>>, Instant)>,
+/// Loaded CAs, active first.
+pub type Issuers = Arc<Vec<Arc<IssuingCa>>>;
+
+/// A loaded issuing CA.
+pub struct IssuingCa {
+    /// Hex key identifier (SKI, or SHA-1 of the public key without one).
+    pub key_id: String,
+    pub cert: Arc<X509>,
+    pub signer: Arc<dyn CaSigner>,
+    /// Issuing CA first, then each issuer up to the last certificate given.
+    pub chain: Arc<Vec<X509>>,
+    /// Whether this CA signs new certificates.
+    pub active: bool,
 }
 
 impl CaProvider {
@@ -29,40 +54,322 @@ impl CaProvider {
         crl_dist_url: Option<String>,
     ) -> Self {
         Self {
-            root_ca_path,
-            root_ca_key_path,
+            sources: vec![(root_ca_path, root_ca_key_path)],
             ttl,
             crl_dist_url,
-            inner: RwLock::new(Inner {
-                ca_cert: None,
-                ca_key: None,
-            }),
+            delta_crl_url: None,
+            ocsp_url: None,
+            inner: RwLock::new(None),
         }
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn get(&self) -> AppResult<(Arc<X509>, Arc<PKey<Private>>)> {
-        let now = Instant::now();
+    /// Add retiring CAs as `(cert_path, key_spec)` pairs.
+    pub fn with_retiring(mut self, retiring: Vec<(String, String)>) -> Self {
+        self.sources.extend(retiring);
+        self
+    }
+
+    /// Delta CRL location embedded as Freshest CRL in new certificates;
+    /// `{issuer}` is replaced like in the CRL distribution point.
+    pub fn with_delta_crl_url(mut self, delta_crl_url: Option<String>) -> Self {
+        self.delta_crl_url = delta_crl_url;
+        self
+    }
+
+    /// OCSP responder URL embedded in the AIA extension of new certificates.
+    pub fn with_ocsp_url(mut self, ocsp_url: Option<String>) -> Self {
+        self.ocsp_url = ocsp_url;
+        self
+    }
+
+    /// Refuse CRL locations that would send the certificates of every CA to
+    /// one CRL: with retiring CAs configured, the CRL distribution point and
+    /// the delta CRL location must contain `{issuer}`.
+    pub fn check_crl_urls(&self) -> AppResult<()> {
+        if self.sources.len() < 2 {
+            return Ok(());
+        }
+        let urls = [
+            ("CRL_DIST_URL", &self.crl_dist_url),
+            ("DELTA_CRL_URL", &self.delta_crl_url),
+        ];
+        for (name, url) in urls {
+            if let Some(url) = url
+                && !url.contains("{issuer}")
+            {
+                return Err(AppError::ValidationError(format!(
+                    "{name} must contain {{issuer}} when retiring CAs are configured, \
+                     so each CA's certificates point at its own CRL"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The active CA, used to sign new certificates.
+    pub async fn active(&self) -> AppResult<Arc<IssuingCa>> {
+        Ok(self.issuers().await?[0].clone())
+    }
 
+    /// All configured CAs, active first.
+    #[tracing::instrument(skip(self))]
+    pub async fn issuers(&self) -> AppResult<Issuers> {
         let mut inner = self.inner.write().await;
-        if let (Some((cert, c_ts)), Some((key, k_ts))) = (&inner.ca_cert, &inner.ca_key)
-            && now.duration_since(*c_ts) < self.ttl
-            && now.duration_since(*k_ts) < self.ttl
+        if let Some((issuers, ts)) = inner.as_ref()
+            && ts.elapsed() < self.ttl
         {
-            return Ok((cert.clone(), key.clone()));
+            return Ok(issuers.clone());
         }
 
         // Refresh from disk
-        let cert_pem = read(&self.root_ca_path).await?;
-        let key_pem = read(&self.root_ca_key_path).await?;
-        let cert = Arc::new(X509::from_pem(&cert_pem)?);
-        let key = Arc::new(PKey::private_key_from_pem(&key_pem)?);
-        inner.ca_cert = Some((cert.clone(), Instant::now()));
-        inner.ca_key = Some((key.clone(), Instant::now()));
-        Ok((cert, key))
+        let mut issuers = Vec::with_capacity(self.sources.len());
+        for (idx, (cert_path, key_spec)) in self.sources.iter().enumerate() {
+            let ca = load_issuer(cert_path, key_spec, idx == 0).await?;
+            if issuers
+                .iter()
+                .any(|i: &Arc<IssuingCa>| i.key_id == ca.key_id)
+            {
+                return Err(AppError::ValidationError(format!(
+                    "CA {} is configured more than once",
+                    ca.key_id
+                )));
+            }
+            issuers.push(Arc::new(ca));
+        }
+        let issuers = Arc::new(issuers);
+        *inner = Some((issuers.clone(), Instant::now()));
+        Ok(issuers)
+    }
+
+    /// The configured CA with `key_id`, if any.
+    pub async fn issuer(&self, key_id: &str) -> AppResult<Option<Arc<IssuingCa>>> {
+        Ok(self
+            .issuers()
+            .await?
+            .iter()
+            .find(|ca| ca.key_id.eq_ignore_ascii_case(key_id))
+            .cloned())
+    }
+
+    /// CRL distribution point for certificates signed by `ca`, with
+    /// `{issuer}` replaced by its key id.
+    pub fn crl_dist_url(&self, ca: &IssuingCa) -> Option<String> {
+        self.crl_dist_url
+            .as_deref()
+            .map(|url| url.replace("{issuer}", &ca.key_id))
+    }
+
+    /// Freshest CRL (delta CRL) location for certificates signed by `ca`.
+    pub fn delta_crl_url(&self, ca: &IssuingCa) -> Option<String> {
+        self.delta_crl_url
+            .as_deref()
+            .map(|url| url.replace("{issuer}", &ca.key_id))
+    }
+
+    pub fn ocsp_url(&self) -> Option<&str> {
+        self.ocsp_url.as_deref()
+    }
+}
+
+async fn load_issuer(cert_path: &str, key_spec: &str, active: bool) -> AppResult<IssuingCa> {
+    let cert_pem = read(cert_path).await?;
+    let signer = load_signer(key_spec).await?;
+    let chain = order_chain(
+        X509::stack_from_pem(&cert_pem)?,
+        signer.public_key().as_deref(),
+    )?;
+    let cert = Arc::new(chain[0].clone());
+    Ok(IssuingCa {
+        key_id: key_id(&cert)?,
+        cert,
+        signer,
+        chain: Arc::new(chain),
+        active,
+    })
+}
+
+/// Uppercase hex key identifier of a CA certificate.
+pub fn key_id(cert: &X509Ref) -> AppResult<String> {
+    let bytes = match cert.subject_key_id() {
+        Some(ski) => ski.as_slice().to_vec(),
+        None => hash(
+            MessageDigest::sha1(),
+            &cert.public_key()?.public_key_to_der()?,
+        )?
+        .to_vec(),
+    };
+    Ok(bytes.iter().map(|b| format!("{:02X}", b)).collect())
+}
+
+/// Parse `RETIRING_CAS`: comma-separated `<cert_path>=<key_spec>` pairs.
+pub fn parse_retiring_cas(spec: &str) -> AppResult<Vec<(String, String)>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| match item.split_once('=') {
+            Some((cert, key)) if !cert.trim().is_empty() && !key.trim().is_empty() => {
+                Ok((cert.trim().to_string(), key.trim().to_string()))
+            }
+            _ => Err(AppError::ValidationError(format!(
+                "invalid retiring CA '{item}' (expected <cert_path>=<key_spec>)"
+            ))),
+        })
+        .collect()
+}
+
+/// Put the certificate matching `key` first and follow issuer names from it.
+/// Without a key the first certificate is taken as the issuing CA.
+///
+/// Every link must be signed by the next certificate; certificates that are
+/// not part of the issuing CA's path are rejected rather than served.
+fn order_chain<T: HasPublic>(certs: Vec<X509>, key: Option<&PKeyRef<T>>) -> AppResult<Vec<X509>> {
+    let mut rest = certs;
+    let idx = match key {
+        Some(key) => rest
+            .iter()
+            .position(|c| c.public_key().is_ok_and(|pk| pk.public_eq(key))),
+        None => (!rest.is_empty()).then_some(0),
+    }
+    .ok_or_else(|| {
+        AppError::ValidationError("no CA certificate matches the CA private key".into())
+    })?;
+    let mut chain = vec![rest.swap_remove(idx)];
+
+    loop {
+        let current = chain.last().expect("chain is never empty");
+        let issuer_name = current.issuer_name().to_der()?;
+        if current.subject_name().to_der()? == issuer_name {
+            break;
+        }
+        let Some(idx) = rest
+            .iter()
+            .position(|c| c.subject_name().to_der().is_ok_and(|n| n == issuer_name))
+        else {
+            break;
+        };
+        let issuer = rest.swap_remove(idx);
+        let issuer_key = issuer.public_key()?;
+        if !current.verify(&issuer_key)? {
+            return Err(AppError::ValidationError(
+                "CA chain signature does not verify".into(),
+            ));
+        }
+        chain.push(issuer);
+    }
+
+    if !rest.is_empty() {
+        return Err(AppError::ValidationError(format!(
+            "{} certificate(s) in the CA file are not on the issuing CA's chain",
+            rest.len()
+        )));
+    }
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CaProvider, key_id, order_chain, parse_retiring_cas};
+    use crate::handlers::test_support::make_ca;
+
+    #[test]
+    fn crl_urls_name_the_issuer_once_a_ca_retires() {
+        let provider = |crl: &str, delta: &str| {
+            CaProvider::new(
+                "ca.pem".into(),
+                "ca.key".into(),
+                Duration::from_secs(60),
+                Some(crl.into()),
+            )
+            .with_delta_crl_url(Some(delta.into()))
+        };
+        let shared = "https://pki.example/crl/issuing.crl";
+        let keyed = "https://pki.example/crl/{issuer}.crl";
+        assert!(provider(shared, shared).check_crl_urls().is_ok());
+
+        let retiring = || vec![("old.pem".to_string(), "old.key".to_string())];
+        assert!(
+            provider(keyed, keyed)
+                .with_retiring(retiring())
+                .check_crl_urls()
+                .is_ok()
+        );
+        let err = provider(shared, keyed)
+            .with_retiring(retiring())
+            .check_crl_urls()
+            .expect_err("shared CRL_DIST_URL");
+        assert!(err.to_string().contains("CRL_DIST_URL"));
+        let err = provider(keyed, shared)
+            .with_retiring(retiring())
+            .check_crl_urls()
+            .expect_err("shared DELTA_CRL_URL");
+        assert!(err.to_string().contains("DELTA_CRL_URL"));
+    }
+
+    #[test]
+    fn chain_is_ordered_from_issuing_ca_to_root() {
+        let (root, root_key) = make_ca("root", None);
+        let (mid, mid_key) = make_ca("mid", Some((&root, &root_key)));
+        let (issuing, issuing_key) = make_ca("issuing", Some((&mid, &mid_key)));
+
+        let chain = order_chain(
+            vec![root.clone(), issuing.clone(), mid.clone()],
+            Some(&issuing_key),
+        )
+        .expect("chain should order");
+        let names: Vec<_> = chain
+            .iter()
+            .map(|c| c.subject_name().to_der().expect("der"))
+            .collect();
+        assert_eq!(
+            names,
+            [&issuing, &mid, &root].map(|c| c.subject_name().to_der().expect("der"))
+        );
+
+        // The root may be left out of the file.
+        assert_eq!(
+            order_chain(vec![issuing.clone(), mid.clone()], Some(&issuing_key))
+                .expect("partial chain")
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn chain_rejects_missing_key_and_unrelated_certs() {
+        let (root, root_key) = make_ca("root", None);
+        let (other, _) = make_ca("other", None);
+        let (_, unrelated_key) = make_ca("unrelated", None);
+
+        assert!(order_chain(vec![root.clone()], Some(&unrelated_key)).is_err());
+        assert!(order_chain(vec![root, other], Some(&root_key)).is_err());
+    }
+
+    #[test]
+    fn key_id_is_the_subject_key_identifier() {
+        let (ca, _) = make_ca("root", None);
+        let ski = ca.subject_key_id().expect("ski").as_slice().to_vec();
+        let id = key_id(&ca).expect("key id");
+        assert_eq!(id.len(), ski.len() * 2);
+        assert_eq!(id, id.to_uppercase());
     }
 
-    pub fn crl_dist_url(&self) -> Option<&str> {
-        self.crl_dist_url.as_deref()
+    #[test]
+    fn parses_retiring_cas() {
+        assert!(parse_retiring_cas("").expect("empty").is_empty());
+        assert_eq!(
+            parse_retiring_cas("/ca/old.pem=/ca/old.key, /ca/older.pem=pkcs11:token=ca;object=old")
+                .expect("pairs"),
+            vec![
+                ("/ca/old.pem".to_string(), "/ca/old.key".to_string()),
+                (
+                    "/ca/older.pem".to_string(),
+                    "pkcs11:token=ca;object=old".to_string()
+                ),
+            ]
+        );
+        assert!(parse_retiring_cas("/ca/old.pem").is_err());
+        assert!(parse_retiring_cas("=/ca/old.key").is_err());
     }
 }
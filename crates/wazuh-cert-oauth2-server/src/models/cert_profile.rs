@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::ledger_entry::DEFAULT_CERT_PROFILE;
+
+use crate::models::naming_template::{
+    CertIdentity, DEFAULT_SUBJECT_TEMPLATE, NamingTemplate, SanEntry, is_dns_name,
+    parse_naming_template,
+};
+use crate::models::signing_profile::SigningProfile;
+
+/// Extended key usages a profile may grant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExtendedUsage {
+    ClientAuth,
+    ServerAuth,
+}
+
+/// Key usage bits a profile may set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyUsageBit {
+    DigitalSignature,
+    /// Only set for RSA keys.
+    KeyEncipherment,
+    /// Only set for EC keys.
+    KeyAgreement,
+}
+
+/// Key usage and extended key usage of issued certificates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertUsage {
+    pub extended: Vec<ExtendedUsage>,
+    /// `None` keeps the TLS client default: digitalSignature, plus
+    /// keyEncipherment for RSA keys.
+    pub key_usage: Option<Vec<KeyUsageBit>>,
+}
+
+impl Default for CertUsage {
+    fn default() -> Self {
+        Self {
+            extended: vec![ExtendedUsage::ClientAuth],
+            key_usage: None,
+        }
+    }
+}
+
+/// A named kind of certificate the CA issues: Wazuh agent client
+/// certificates, manager or dashboard server certificates, and so on.
+///
+/// Unset lifetime and naming fall back to the server-wide
+/// [`SigningProfile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertProfile {
+    pub name: String,
+    pub usage: CertUsage,
+    pub validity_days: Option<u32>,
+    pub naming: Option<NamingTemplate>,
+    /// DNS names a CSR may request on top of the templated SANs: exact
+    /// names or `*.<domain>` for any name directly under `<domain>`.
+    pub requested_dns: Vec<String>,
+    /// Realm roles allowed to request the profile; `None` allows every
+    /// caller. Admins may always request it.
+    pub allowed_roles: Option<Vec<String>>,
+}
+
+impl CertProfile {
+    /// The built-in agent client certificate profile.
+    pub fn agent() -> Self {
+        Self {
+            name: DEFAULT_CERT_PROFILE.to_string(),
+            usage: CertUsage::default(),
+            validity_days: None,
+            naming: None,
+            requested_dns: Vec::new(),
+            allowed_roles: None,
+        }
+    }
+
+    /// `base` with this profile's lifetime and naming applied.
+    pub fn apply(&self, base: &SigningProfile) -> SigningProfile {
+        SigningProfile {
+            validity_days: self.validity_days.unwrap_or(base.validity_days),
+            naming: self.naming.clone().unwrap_or_else(|| base.naming.clone()),
+            ..base.clone()
+        }
+    }
+
+    pub fn allows_caller(&self, claims: &Claims, is_admin: bool) -> bool {
+        match &self.allowed_roles {
+            None => true,
+            Some(roles) => is_admin || claims.has_any_role(roles),
+        }
+    }
+
+    /// Whether a CSR may request `name` as a DNS SAN.
+    pub fn allows_dns(&self, name: &str) -> bool {
+        self.requested_dns
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => name
+                    .strip_suffix(domain)
+                    .and_then(|host| host.strip_suffix('.'))
+                    .is_some_and(|host| !host.is_empty() && !host.contains('.')),
+                None => pattern == name,
+            })
+    }
+
+    /// Add the DNS names a CSR requests to `identity` when the profile
+    /// allows them; the rest are left for the CSR inspection to report.
+    pub fn admit_requested_dns(&self, identity: &mut CertIdentity, requested: &[String]) {
+        for name in requested {
+            let entry = SanEntry::Dns(name.clone());
+            if self.allows_dns(name) && !identity.san.contains(&entry) {
+                identity.san.push(entry);
+            }
+        }
+    }
+}
+
+/// The signing profiles a request may select by name.
+///
+/// The agent profile is always present; a registry file may redefine it,
+/// e.g. to restrict its roles.
+#[derive(Debug, Clone)]
+pub struct CertProfiles {
+    profiles: HashMap<String, CertProfile>,
+}
+
+impl Default for CertProfiles {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl CertProfiles {
+    pub fn new(profiles: Vec<CertProfile>) -> Self {
+        let mut map = HashMap::from([(DEFAULT_CERT_PROFILE.to_string(), CertProfile::agent())]);
+        map.extend(profiles.into_iter().map(|p| (p.name.clone(), p)));
+        Self { profiles: map }
+    }
+
+    pub fn get(&self, name: &str) -> AppResult<&CertProfile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| AppError::UnknownCertProfile { name: name.into() })
+    }
+
+    /// Refuse profiles whose lifetime is zero or longer than the base
+    /// profile's cap.
+    pub fn check_validity(&self, base: &SigningProfile) -> AppResult<()> {
+        for profile in self.profiles.values() {
+            let Some(days) = profile.validity_days else {
+                continue;
+            };
+            if days == 0 {
+                return Err(AppError::ValidationError(format!(
+                    "signing profile '{}' must have a positive validity",
+                    profile.name
+                )));
+            }
+            if let Some(max) = base.max_validity_days
+                && days > max
+            {
+                return Err(AppError::ValidationError(format!(
+                    "signing profile '{}' validity_days ({days}) exceeds CERT_MAX_VALIDITY_DAYS ({max})",
+                    profile.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The profile a request names (the agent profile when it names none),
+    /// if the caller may use it.
+    pub fn select(
+        &self,
+        requested: Option<&str>,
+        claims: &Claims,
+        is_admin: bool,
+    ) -> AppResult<&CertProfile> {
+        let profile = self.get(requested.unwrap_or(DEFAULT_CERT_PROFILE))?;
+        if !profile.allows_caller(claims, is_admin) {
+            return Err(AppError::Forbidden(format!(
+                "signing profile '{}' is not available to the caller",
+                profile.name
+            )));
+        }
+        Ok(profile)
+    }
+}
+
+/// One profile of the registry file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileSpec {
+    extended_key_usage: Vec<ExtendedUsage>,
+    #[serde(default)]
+    key_usage: Option<Vec<KeyUsageBit>>,
+    #[serde(default)]
+    validity_days: Option<u32>,
+    #[serde(default)]
+    subject_template: Option<String>,
+    #[serde(default)]
+    san_template: Option<String>,
+    #[serde(default)]
+    skip_invalid_dns: bool,
+    #[serde(default)]
+    requested_dns: Vec<String>,
+    #[serde(default)]
+    allowed_roles: Vec<String>,
+}
+
+/// Parse a registry file: a JSON object of profile name to profile.
+///
+/// ```json
+/// { "manager": { "extended_key_usage": ["serverAuth"], "validity_days": 90,
+///                "subject_template": "CN={wazuh_agent_name}", "san_template": "",
+///                "requested_dns": ["*.wazuh.example.com"],
+///                "allowed_roles": ["wazuh_manager"] } }
+/// ```
+///
+/// Profiles from the file are restricted to admins and `allowed_roles`.
+/// Setting only one of the templates leaves the other as `CN={sub}` or no
+/// SANs.
+pub fn parse_cert_profiles(json: &str) -> AppResult<CertProfiles> {
+    let specs: HashMap<String, ProfileSpec> = serde_json::from_str(json)
+        .map_err(|e| AppError::ValidationError(format!("invalid signing profiles: {e}")))?;
+    let profiles = specs
+        .into_iter()
+        .map(|(name, spec)| {
+            let invalid =
+                |why: &str| AppError::ValidationError(format!("signing profile '{name}' {why}"));
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(invalid(
+                    "has an invalid name (use letters, digits, - and _)",
+                ));
+            }
+            if spec.extended_key_usage.is_empty() {
+                return Err(invalid("must grant at least one extended key usage"));
+            }
+            if spec.key_usage.as_ref().is_some_and(Vec::is_empty) {
+                return Err(invalid("must set at least one key usage"));
+            }
+            if spec.validity_days == Some(0) {
+                return Err(invalid("must have a positive validity"));
+            }
+            for pattern in &spec.requested_dns {
+                let domain = pattern.strip_prefix("*.").unwrap_or(pattern);
+                if !is_dns_name(domain) || domain != domain.to_ascii_lowercase() {
+                    return Err(invalid(&format!(
+                        "has an invalid requested_dns pattern '{pattern}'"
+                    )));
+                }
+            }
+            let naming = match (&spec.subject_template, &spec.san_template) {
+                (None, None) => None,
+                (subject, san) => Some(
+                    parse_naming_template(
+                        subject.as_deref().unwrap_or(DEFAULT_SUBJECT_TEMPLATE),
+                        san.as_deref().unwrap_or(""),
+                    )?
+                    .skipping_invalid_dns(spec.skip_invalid_dns),
+                ),
+            };
+            Ok(CertProfile {
+                name,
+                usage: CertUsage {
+                    extended: spec.extended_key_usage,
+                    key_usage: spec.key_usage,
+                },
+                validity_days: spec.validity_days,
+                naming,
+                requested_dns: spec.requested_dns,
+                allowed_roles: Some(spec.allowed_roles),
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    Ok(CertProfiles::new(profiles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CertProfile, CertProfiles, ExtendedUsage, KeyUsageBit, parse_cert_profiles};
+    use crate::models::signing_profile::SigningProfile;
+    use wazuh_cert_oauth2_model::models::claims::{Claims, RealmAccess};
+    use wazuh_cert_oauth2_model::models::errors::AppError;
+
+    fn claims(roles: &[&str]) -> Claims {
+        Claims {
+            sub: "svc".to_string(),
+            name: None,
+            iss: "https://issuer.example/realms/main".to_string(),
+            exp: 9_999_999_999,
+            preferred_username: None,
+            email: None,
+            realm_access: Some(RealmAccess {
+                roles: roles.iter().map(|r| r.to_string()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    const REGISTRY: &str = r#"{
+        "manager": {
+            "extended_key_usage": ["serverAuth", "clientAuth"],
+            "key_usage": ["digitalSignature", "keyEncipherment"],
+            "validity_days": 90,
+            "subject_template": "CN={wazuh_agent_name}",
+            "requested_dns": ["*.wazuh.example.com", "wazuh.example.com"],
+            "allowed_roles": ["wazuh_manager"]
+        },
+        "crl-sidecar": { "extended_key_usage": ["serverAuth"] }
+    }"#;
+
+    #[test]
+    fn parses_registry_and_keeps_agent_profile() {
+        let profiles = parse_cert_profiles(REGISTRY).unwrap();
+        let manager = profiles.get("manager").unwrap();
+        assert_eq!(
+            manager.usage.extended,
+            vec![ExtendedUsage::ServerAuth, ExtendedUsage::ClientAuth]
+        );
+        assert_eq!(
+            manager.usage.key_usage.as_deref(),
+            Some(&[KeyUsageBit::DigitalSignature, KeyUsageBit::KeyEncipherment][..])
+        );
+        assert_eq!(manager.validity_days, Some(90));
+        assert!(manager.naming.is_some());
+        assert_eq!(profiles.get("crl-sidecar").unwrap().naming, None);
+        assert_eq!(profiles.get("agent").unwrap(), &CertProfile::agent());
+        assert!(matches!(
+            profiles.get("dashboard"),
+            Err(AppError::UnknownCertProfile { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_registries() {
+        assert!(parse_cert_profiles("[]").is_err());
+        assert!(parse_cert_profiles(r#"{"m": {"extended_key_usage": []}}"#).is_err());
+        assert!(parse_cert_profiles(r#"{"m": {"extended_key_usage": ["codeSigning"]}}"#).is_err());
+        assert!(
+            parse_cert_profiles(r#"{"m": {"extended_key_usage": ["serverAuth"], "eku": []}}"#)
+                .is_err()
+        );
+        assert!(
+            parse_cert_profiles(
+                r#"{"m": {"extended_key_usage": ["serverAuth"], "requested_dns": ["*.*.x"]}}"#
+            )
+            .is_err()
+        );
+        assert!(parse_cert_profiles(r#"{"a b": {"extended_key_usage": ["serverAuth"]}}"#).is_err());
+    }
+
+    #[test]
+    fn profile_validity_respects_the_cap() {
+        let profiles = parse_cert_profiles(REGISTRY).unwrap();
+        assert!(profiles.check_validity(&SigningProfile::default()).is_ok());
+        let capped = |max| SigningProfile {
+            max_validity_days: Some(max),
+            validity_days: max,
+            ..Default::default()
+        };
+        assert!(profiles.check_validity(&capped(90)).is_ok());
+        assert!(profiles.check_validity(&capped(30)).is_err());
+    }
+
+    #[test]
+    fn selection_enforces_allowed_roles() {
+        let profiles = parse_cert_profiles(REGISTRY).unwrap();
+        let agent = claims(&[]);
+        assert_eq!(profiles.select(None, &agent, false).unwrap().name, "agent");
+        assert!(matches!(
+            profiles.select(Some("manager"), &agent, false),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(profiles.select(Some("manager"), &agent, true).is_ok());
+        let manager = claims(&["wazuh_manager"]);
+        assert!(profiles.select(Some("manager"), &manager, false).is_ok());
+        // Without allowed_roles a configured profile is admin-only.
+        assert!(
+            profiles
+                .select(Some("crl-sidecar"), &manager, false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn requested_dns_patterns_match_one_label() {
+        let profiles = parse_cert_profiles(REGISTRY).unwrap();
+        let manager = profiles.get("manager").unwrap();
+        assert!(manager.allows_dns("node1.wazuh.example.com"));
+        assert!(manager.allows_dns("wazuh.example.com"));
+        assert!(!manager.allows_dns("a.node1.wazuh.example.com"));
+        assert!(!manager.allows_dns("evilwazuh.example.com"));
+        assert!(!manager.allows_dns("example.com"));
+        assert!(
+            !CertProfiles::default()
+                .get("agent")
+                .unwrap()
+                .allows_dns("x")
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+/// Conditions a token must meet before a certificate is issued for it.
+///
+/// Multi-factor authentication is proven by an `acr` in `acr_values` or an
+/// `amr` entry in `amr_values`; with both lists empty it is not required.
+/// Holders of an `exempt_roles` role, such as the service accounts renewing
+/// agents unattended, skip every rule. The default requires nothing.
+#[derive(Debug, Clone, Default)]
+pub struct EnrollmentRules {
+    pub acr_values: Vec<String>,
+    pub amr_values: Vec<String>,
+    pub require_email_verified: bool,
+    /// Oldest `auth_time` accepted, in seconds before the request.
+    pub max_auth_age_secs: Option<u64>,
+    /// Clients (`azp`) whose tokens may enroll; empty allows any.
+    pub allowed_azp: Vec<String>,
+    pub exempt_roles: Vec<String>,
+}
+
+impl EnrollmentRules {
+    /// Refuse `claims` with `403` and what to do about it when a rule is
+    /// not met at `now`.
+    pub fn check(&self, claims: &Claims, now: u64) -> AppResult<()> {
+        if claims.has_any_role(&self.exempt_roles) {
+            return Ok(());
+        }
+        let refuse = |why: String| Err(AppError::Forbidden(why));
+        if !self.acr_values.is_empty() || !self.amr_values.is_empty() {
+            let acr = claims
+                .acr
+                .as_ref()
+                .is_some_and(|acr| self.acr_values.contains(acr));
+            let amr = claims
+                .amr
+                .iter()
+                .flatten()
+                .any(|method| self.amr_values.contains(method));
+            if !acr && !amr {
+                return refuse(
+                    "enrollment requires multi-factor authentication; sign in again with MFA"
+                        .into(),
+                );
+            }
+        }
+        if self.require_email_verified && claims.email_verified != Some(true) {
+            return refuse("enrollment requires a verified email address".into());
+        }
+        if let Some(max_age) = self.max_auth_age_secs {
+            match claims.auth_time {
+                Some(at) if now.saturating_sub(at) <= max_age => {}
+                _ => {
+                    return refuse(format!(
+                        "enrollment requires signing in within the last {max_age} seconds; sign in again"
+                    ));
+                }
+            }
+        }
+        if !self.allowed_azp.is_empty()
+            && !claims
+                .azp
+                .as_ref()
+                .is_some_and(|azp| self.allowed_azp.contains(azp))
+        {
+            return refuse(format!(
+                "client '{}' may not request certificates",
+                claims.azp.as_deref().unwrap_or("unknown")
+            ));
+        }
+        Ok(())
+    }
+
+    /// These rules for a token checked when it was presented and used later,
+    /// e.g. on approval: its `auth_time` has aged since, so freshness is not
+    /// checked again.
+    pub fn deferred(&self) -> Self {
+        Self {
+            max_auth_age_secs: None,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EnrollmentRules;
+    use wazuh_cert_oauth2_model::models::claims::{Claims, RealmAccess};
+    use wazuh_cert_oauth2_model::models::errors::AppError;
+
+    fn refused(rules: &EnrollmentRules, claims: &Claims) -> String {
+        match rules.check(claims, 1_000) {
+            Err(AppError::Forbidden(why)) => why,
+            other => panic!("expected a refusal, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn each_rule_refuses_with_its_reason() {
+        let rules = EnrollmentRules {
+            acr_values: vec!["gold".into()],
+            amr_values: vec!["otp".into(), "hwk".into()],
+            require_email_verified: true,
+            max_auth_age_secs: Some(300),
+            allowed_azp: vec!["wazuh-client".into()],
+            exempt_roles: vec!["renewal-bot".into()],
+        };
+        let mut claims = Claims {
+            sub: "user-a".into(),
+            amr: Some(vec!["pwd".into()]),
+            ..Default::default()
+        };
+        assert!(refused(&rules, &claims).contains("multi-factor"));
+        claims.amr = Some(vec!["pwd".into(), "otp".into()]);
+        assert!(refused(&rules, &claims).contains("verified email"));
+        claims.email_verified = Some(true);
+        assert!(refused(&rules, &claims).contains("300 seconds"));
+        claims.auth_time = Some(600);
+        assert!(refused(&rules, &claims).contains("sign in again"));
+        claims.auth_time = Some(900);
+        assert!(refused(&rules, &claims).contains("'unknown'"));
+        claims.azp = Some("wazuh-client".into());
+        assert!(rules.check(&claims, 1_000).is_ok());
+
+        // acr alone also proves MFA.
+        claims.amr = None;
+        claims.acr = Some("gold".into());
+        assert!(rules.check(&claims, 1_000).is_ok());
+        // Freshness is not checked again for deferred requests.
+        assert!(rules.deferred().check(&claims, 100_000).is_ok());
+
+        let bot = Claims {
+            realm_access: Some(RealmAccess {
+                roles: vec!["renewal-bot".into()],
+            }),
+            ..Default::default()
+        };
+        assert!(rules.check(&bot, 1_000).is_ok());
+        assert!(
+            EnrollmentRules::default()
+                .check(&Claims::default(), 0)
+                .is_ok()
+        );
+    }
+}
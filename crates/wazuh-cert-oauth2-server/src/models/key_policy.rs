@@ -0,0 +1,135 @@
+use openssl::nid::Nid;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+/// Named EC curves a key policy can allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcCurve {
+    P256,
+    P384,
+    P521,
+}
+
+impl EcCurve {
+    pub fn nid(self) -> Nid {
+        match self {
+            Self::P256 => Nid::X9_62_PRIME256V1,
+            Self::P384 => Nid::SECP384R1,
+            Self::P521 => Nid::SECP521R1,
+        }
+    }
+}
+
+/// Allowed RSA modulus sizes, in bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RsaBounds {
+    pub min_bits: usize,
+    pub max_bits: Option<usize>,
+}
+
+/// Public key algorithms accepted in CSRs.
+///
+/// An algorithm is allowed only when listed; the default keeps the historic
+/// policy of RSA ≥ 2048 bits and P-256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPolicy {
+    pub rsa: Option<RsaBounds>,
+    pub ec_curves: Vec<EcCurve>,
+    pub ed25519: bool,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self {
+            rsa: Some(RsaBounds {
+                min_bits: 2048,
+                max_bits: None,
+            }),
+            ec_curves: vec![EcCurve::P256],
+            ed25519: false,
+        }
+    }
+}
+
+/// Parse `rsa:<min>[-<max>],p256,p384,p521,ed25519` into a key policy.
+///
+/// A bare `rsa` allows 2048 bits and up.
+pub fn parse_key_policy(spec: &str) -> AppResult<KeyPolicy> {
+    let mut policy = KeyPolicy {
+        rsa: None,
+        ec_curves: Vec::new(),
+        ed25519: false,
+    };
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let invalid = || {
+            AppError::ValidationError(format!(
+                "invalid key policy entry '{item}' (expected rsa[:<min>[-<max>]], p256, p384, p521 or ed25519)"
+            ))
+        };
+        match item.to_ascii_lowercase().as_str() {
+            "p256" | "p-256" => policy.ec_curves.push(EcCurve::P256),
+            "p384" | "p-384" => policy.ec_curves.push(EcCurve::P384),
+            "p521" | "p-521" => policy.ec_curves.push(EcCurve::P521),
+            "ed25519" => policy.ed25519 = true,
+            "rsa" => {
+                policy.rsa = Some(RsaBounds {
+                    min_bits: 2048,
+                    max_bits: None,
+                })
+            }
+            other => {
+                let bounds = other.strip_prefix("rsa:").ok_or_else(invalid)?;
+                let (min, max) = match bounds.split_once('-') {
+                    Some((min, max)) => (min, Some(max)),
+                    None => (bounds, None),
+                };
+                let min_bits: usize = min.trim().parse().map_err(|_| invalid())?;
+                let max_bits = max
+                    .map(|m| m.trim().parse::<usize>())
+                    .transpose()
+                    .map_err(|_| invalid())?;
+                if min_bits == 0 || max_bits.is_some_and(|max| max < min_bits) {
+                    return Err(invalid());
+                }
+                policy.rsa = Some(RsaBounds { min_bits, max_bits });
+            }
+        }
+    }
+    if policy.rsa.is_none() && policy.ec_curves.is_empty() && !policy.ed25519 {
+        return Err(AppError::ValidationError(
+            "key policy must allow at least one algorithm".into(),
+        ));
+    }
+    Ok(policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EcCurve, KeyPolicy, RsaBounds, parse_key_policy};
+
+    #[test]
+    fn parses_algorithms_and_rsa_bounds() {
+        let policy = parse_key_policy("rsa:3072-4096, p384, P-521, ed25519").unwrap();
+        assert_eq!(
+            policy,
+            KeyPolicy {
+                rsa: Some(RsaBounds {
+                    min_bits: 3072,
+                    max_bits: Some(4096),
+                }),
+                ec_curves: vec![EcCurve::P384, EcCurve::P521],
+                ed25519: true,
+            }
+        );
+        assert_eq!(parse_key_policy("rsa,p256").unwrap(), KeyPolicy::default());
+        assert_eq!(parse_key_policy("ed25519").unwrap().rsa, None);
+    }
+
+    #[test]
+    fn rejects_malformed_policies() {
+        assert!(parse_key_policy("").is_err());
+        assert!(parse_key_policy("dsa").is_err());
+        assert!(parse_key_policy("rsa:abc").is_err());
+        assert!(parse_key_policy("rsa:4096-2048").is_err());
+        assert!(parse_key_policy("rsa:0").is_err());
+    }
+}
@@ -1,3 +1,10 @@
+pub mod access_policy;
+pub mod approval_policy;
 pub mod ca_config;
+pub mod cert_profile;
+pub mod enrollment_rules;
 pub mod health;
+pub mod key_policy;
+pub mod naming_template;
 pub mod oidc_state;
+pub mod signing_profile;
@@ -0,0 +1,643 @@
+use openssl::nid::Nid;
+use url::Url;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+/// Values a naming template refers to as `{placeholder}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Sub,
+    Name,
+    PreferredUsername,
+    Email,
+    Realm,
+    Iss,
+    WazuhAgentName,
+    /// `{iss}#sub={sub}`, the identity URI certificates have always carried.
+    IdentityUri,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 8] = [
+        Self::Sub,
+        Self::Name,
+        Self::PreferredUsername,
+        Self::Email,
+        Self::Realm,
+        Self::Iss,
+        Self::WazuhAgentName,
+        Self::IdentityUri,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sub => "sub",
+            Self::Name => "name",
+            Self::PreferredUsername => "preferred_username",
+            Self::Email => "email",
+            Self::Realm => "realm",
+            Self::Iss => "iss",
+            Self::WazuhAgentName => "wazuh_agent_name",
+            Self::IdentityUri => "identity_uri",
+        }
+    }
+
+    /// Whether the value is itself a URL, inserted into URI SANs unencoded.
+    fn is_url(self) -> bool {
+        matches!(self, Self::Iss | Self::IdentityUri)
+    }
+}
+
+/// Subject DN attributes a template may set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubjectAttr {
+    CommonName,
+    Organization,
+    OrganizationalUnit,
+    Country,
+    State,
+    Locality,
+}
+
+impl SubjectAttr {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "CN" => Some(Self::CommonName),
+            "O" => Some(Self::Organization),
+            "OU" => Some(Self::OrganizationalUnit),
+            "C" => Some(Self::Country),
+            "ST" => Some(Self::State),
+            "L" => Some(Self::Locality),
+            _ => None,
+        }
+    }
+
+    pub fn nid(self) -> Nid {
+        match self {
+            Self::CommonName => Nid::COMMONNAME,
+            Self::Organization => Nid::ORGANIZATIONNAME,
+            Self::OrganizationalUnit => Nid::ORGANIZATIONALUNITNAME,
+            Self::Country => Nid::COUNTRYNAME,
+            Self::State => Nid::STATEORPROVINCENAME,
+            Self::Locality => Nid::LOCALITYNAME,
+        }
+    }
+
+    /// RFC 5280 Appendix A upper bound on the value length, in characters.
+    fn max_len(self) -> usize {
+        match self {
+            Self::Country => 2,
+            Self::State | Self::Locality => 128,
+            _ => 64,
+        }
+    }
+}
+
+/// SAN entry types a template may emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanKind {
+    Dns,
+    Email,
+    Uri,
+}
+
+impl SanKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "dns" => Some(Self::Dns),
+            "email" => Some(Self::Email),
+            "uri" => Some(Self::Uri),
+            _ => None,
+        }
+    }
+
+    /// Check `value` as a SAN of this kind; DNS names are lowercased.
+    fn entry(self, value: String) -> AppResult<SanEntry> {
+        Ok(match self {
+            Self::Dns => {
+                let value = value.to_ascii_lowercase();
+                if !is_dns_name(&value) {
+                    return Err(AppError::ValidationError(format!(
+                        "'{value}' is not a valid DNS name for the certificate SAN"
+                    )));
+                }
+                SanEntry::Dns(value)
+            }
+            Self::Email => {
+                if !is_email(&value) {
+                    return Err(AppError::ValidationError(format!(
+                        "'{value}' is not a valid email address for the certificate SAN"
+                    )));
+                }
+                SanEntry::Email(value)
+            }
+            Self::Uri => {
+                if Url::parse(&value).is_err() {
+                    return Err(AppError::ValidationError(format!(
+                        "'{value}' is not a valid URI for the certificate SAN"
+                    )));
+                }
+                SanEntry::Uri(value)
+            }
+        })
+    }
+}
+
+/// A subject alternative name of an issued certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanEntry {
+    Dns(String),
+    Email(String),
+    Uri(String),
+}
+
+impl SanEntry {
+    /// Parse a literal `dns:`, `email:` or `uri:` entry.
+    pub fn parse(item: &str) -> AppResult<Self> {
+        let (kind, value) = item
+            .split_once(':')
+            .and_then(|(kind, value)| Some((SanKind::parse(kind)?, value)))
+            .ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "invalid SAN '{item}' (expected dns|email|uri:<value>)"
+                ))
+            })?;
+        kind.entry(value.trim().to_string())
+    }
+}
+
+impl std::fmt::Display for SanEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dns(v) => write!(f, "dns:{v}"),
+            Self::Email(v) => write!(f, "email:{v}"),
+            Self::Uri(v) => write!(f, "uri:{v}"),
+        }
+    }
+}
+
+/// Names written into an issued certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertIdentity {
+    /// Subject DN attributes, in order.
+    pub subject: Vec<(Nid, String)>,
+    pub san: Vec<SanEntry>,
+}
+
+impl CertIdentity {
+    pub fn common_name(&self) -> Option<&str> {
+        self.subject
+            .iter()
+            .find(|(nid, _)| *nid == Nid::COMMONNAME)
+            .map(|(_, cn)| cn.as_str())
+    }
+}
+
+/// What a template is rendered from.
+pub struct NamingContext<'a> {
+    pub claims: &'a Claims,
+    pub realm: Option<&'a str>,
+    pub wazuh_agent_name: Option<&'a str>,
+}
+
+impl NamingContext<'_> {
+    /// The value of `placeholder`; `None` when missing or empty.
+    fn value(&self, placeholder: Placeholder) -> Option<String> {
+        let claims = self.claims;
+        let value = match placeholder {
+            Placeholder::Sub => Some(claims.sub.clone()),
+            Placeholder::Name => claims.name.clone(),
+            Placeholder::PreferredUsername => claims.preferred_username.clone(),
+            Placeholder::Email => claims.email.clone(),
+            Placeholder::Realm => self.realm.map(str::to_string),
+            Placeholder::Iss => Some(claims.iss.clone()),
+            Placeholder::WazuhAgentName => self.wazuh_agent_name.map(str::to_string),
+            Placeholder::IdentityUri => Some(identity_uri(&claims.iss, &claims.sub)),
+        };
+        value.filter(|v| !v.trim().is_empty())
+    }
+}
+
+fn identity_uri(issuer: &str, sub: &str) -> String {
+    match Url::parse(issuer) {
+        Ok(url) => format!("{}#sub={}", url.as_str().trim_end_matches('#'), sub),
+        Err(_) => format!("urn:keycloak:sub:{}", sub),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Value(Placeholder),
+}
+
+/// Text with `{placeholder}` references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template(Vec<Part>);
+
+impl Template {
+    fn parse(text: &str) -> AppResult<Self> {
+        let invalid = |why: &str| {
+            AppError::ValidationError(format!("invalid naming template '{text}': {why}"))
+        };
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'"))?;
+            let name = &rest[start + 1..start + end];
+            let placeholder = Placeholder::ALL
+                .into_iter()
+                .find(|p| p.as_str() == name)
+                .ok_or_else(|| invalid(&format!("unknown placeholder '{{{name}}}'")))?;
+            parts.push(Part::Value(placeholder));
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(invalid("unmatched '}'"));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err(invalid("empty value"));
+        }
+        Ok(Self(parts))
+    }
+
+    /// Substitute the context's values; `None` when one of them is missing.
+    /// With `encode_values`, values that are not URLs are percent-encoded.
+    fn render(&self, ctx: &NamingContext<'_>, encode_values: bool) -> Option<String> {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Value(p) => {
+                    let value = ctx.value(*p)?;
+                    if encode_values && !p.is_url() {
+                        out.push_str(&percent_encode(&value));
+                    } else {
+                        out.push_str(&value);
+                    }
+                }
+            }
+        }
+        Some(out)
+    }
+
+    fn uses(&self, placeholder: Placeholder) -> bool {
+        self.0.contains(&Part::Value(placeholder))
+    }
+
+    fn refers_to(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .filter_map(|p| match p {
+                Part::Value(p) => Some(p.as_str()),
+                Part::Literal(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// How the subject DN and SANs of issued certificates are built from the
+/// caller's token.
+///
+/// SAN entries and non-CN attributes whose values are missing from the token
+/// are left out; a missing CN rejects the request. SAN entries built from
+/// `{email}` are left out unless the token's `email_verified` is true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingTemplate {
+    subject: Vec<(SubjectAttr, Template)>,
+    san: Vec<(SanKind, Template)>,
+    skip_invalid_dns: bool,
+}
+
+impl Default for NamingTemplate {
+    fn default() -> Self {
+        parse_naming_template(DEFAULT_SUBJECT_TEMPLATE, DEFAULT_SAN_TEMPLATE)
+            .expect("default naming template is valid")
+            .skipping_invalid_dns(true)
+    }
+}
+
+pub(crate) const DEFAULT_SUBJECT_TEMPLATE: &str = "CN={sub}";
+pub(crate) const DEFAULT_SAN_TEMPLATE: &str = "dns:{sub},uri:{identity_uri}";
+
+impl NamingTemplate {
+    /// Leave out DNS SANs that are not valid DNS names instead of rejecting
+    /// the request, e.g. `dns:{sub}` for subjects such as `auth0|abc`.
+    pub fn skipping_invalid_dns(self, skip_invalid_dns: bool) -> Self {
+        Self {
+            skip_invalid_dns,
+            ..self
+        }
+    }
+
+    pub fn render(&self, ctx: &NamingContext<'_>) -> AppResult<CertIdentity> {
+        let mut subject = Vec::new();
+        for (attr, template) in &self.subject {
+            match template.render(ctx, false) {
+                Some(value) => {
+                    validate_dn_value(*attr, &value)?;
+                    subject.push((attr.nid(), value));
+                }
+                None if *attr == SubjectAttr::CommonName => {
+                    return Err(AppError::ValidationError(format!(
+                        "token lacks a claim required for the certificate CN ({})",
+                        template.refers_to().join(", ")
+                    )));
+                }
+                None => {}
+            }
+        }
+        let mut san = Vec::new();
+        for (kind, template) in &self.san {
+            if template.uses(Placeholder::Email) && ctx.claims.email_verified != Some(true) {
+                continue;
+            }
+            let Some(value) = template.render(ctx, *kind == SanKind::Uri) else {
+                continue;
+            };
+            match kind.entry(value) {
+                Ok(entry) => san.push(entry),
+                Err(_) if *kind == SanKind::Dns && self.skip_invalid_dns => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(CertIdentity { subject, san })
+    }
+}
+
+/// Parse `CN={preferred_username},OU={realm},O=Example` and
+/// `dns:{wazuh_agent_name},email:{email},uri:{identity_uri}`.
+///
+/// Entries are comma-separated; `\,` and `\\` escape a literal comma and
+/// backslash. The subject needs a CN.
+pub fn parse_naming_template(subject_spec: &str, san_spec: &str) -> AppResult<NamingTemplate> {
+    let subject = split_escaped(subject_spec)?
+        .into_iter()
+        .map(|item| {
+            let (attr, value) = item
+                .split_once('=')
+                .and_then(|(attr, value)| Some((SubjectAttr::parse(attr.trim())?, value)))
+                .ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "invalid subject template entry '{item}' (expected CN|O|OU|C|ST|L=<template>)"
+                    ))
+                })?;
+            Ok((attr, Template::parse(value.trim())?))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    if !subject
+        .iter()
+        .any(|(attr, _)| *attr == SubjectAttr::CommonName)
+    {
+        return Err(AppError::ValidationError(
+            "subject template must set a CN".into(),
+        ));
+    }
+    let san = split_escaped(san_spec)?
+        .into_iter()
+        .map(|item| {
+            let (kind, value) = item
+                .split_once(':')
+                .and_then(|(kind, value)| Some((SanKind::parse(kind)?, value)))
+                .ok_or_else(|| invalid_san(&item))?;
+            Ok((kind, Template::parse(value.trim())?))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    Ok(NamingTemplate {
+        subject,
+        san,
+        skip_invalid_dns: false,
+    })
+}
+
+fn invalid_san(item: &str) -> AppError {
+    AppError::ValidationError(format!(
+        "invalid SAN template entry '{item}' (expected dns|email|uri:<template>)"
+    ))
+}
+
+/// Split on commas not preceded by a backslash, unescaping `\,` and `\\`.
+fn split_escaped(spec: &str) -> AppResult<Vec<String>> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ (',' | '\\')) => current.push(next),
+                _ => {
+                    return Err(AppError::ValidationError(format!(
+                        "invalid escape in naming template '{spec}' (only \\, and \\\\ are allowed)"
+                    )));
+                }
+            },
+            ',' => items.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    items.push(current);
+    Ok(items
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect())
+}
+
+fn validate_dn_value(attr: SubjectAttr, value: &str) -> AppResult<()> {
+    let invalid =
+        |why: &str| AppError::ValidationError(format!("certificate subject value '{value}' {why}"));
+    if value.chars().any(char::is_control) {
+        return Err(invalid("contains control characters"));
+    }
+    if value.chars().count() > attr.max_len() {
+        return Err(invalid(&format!("exceeds {} characters", attr.max_len())));
+    }
+    if attr == SubjectAttr::Country && !value.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(invalid("is not a two-letter country code"));
+    }
+    Ok(())
+}
+
+pub(crate) fn is_dns_name(value: &str) -> bool {
+    value.len() <= 253
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && local.chars().all(|c| c.is_ascii_graphic() && c != '@')
+                && is_dns_name(&domain.to_ascii_lowercase())
+        }
+        None => false,
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::nid::Nid;
+    use wazuh_cert_oauth2_model::models::claims::Claims;
+
+    use super::{NamingContext, NamingTemplate, SanEntry, parse_naming_template};
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "8f0c-42".into(),
+            name: Some("Jane Doe".into()),
+            iss: "https://kc.example/realms/main".into(),
+            exp: 9_999_999_999,
+            preferred_username: Some("jdoe".into()),
+            email: Some("jane@example.com".into()),
+            email_verified: Some(true),
+            realm_access: None,
+            ..Default::default()
+        }
+    }
+
+    fn ctx<'a>(claims: &'a Claims, agent: Option<&'a str>) -> NamingContext<'a> {
+        NamingContext {
+            claims,
+            realm: Some("main"),
+            wazuh_agent_name: agent,
+        }
+    }
+
+    #[test]
+    fn default_template_matches_historic_names() {
+        let claims = claims();
+        let identity = NamingTemplate::default()
+            .render(&ctx(&claims, None))
+            .unwrap();
+        assert_eq!(identity.subject, vec![(Nid::COMMONNAME, "8f0c-42".into())]);
+        assert_eq!(
+            identity.san,
+            vec![
+                SanEntry::Dns("8f0c-42".into()),
+                SanEntry::Uri("https://kc.example/realms/main#sub=8f0c-42".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_template_skips_subjects_that_are_not_dns_names() {
+        let mut claims = claims();
+        claims.sub = "auth0|abc".into();
+        let identity = NamingTemplate::default()
+            .render(&ctx(&claims, None))
+            .unwrap();
+        assert_eq!(
+            identity.subject,
+            vec![(Nid::COMMONNAME, "auth0|abc".into())]
+        );
+        assert_eq!(
+            identity.san,
+            vec![SanEntry::Uri(
+                "https://kc.example/realms/main#sub=auth0|abc".into()
+            )]
+        );
+
+        // Templates reject it unless told to skip such names.
+        let template = parse_naming_template("CN={sub}", "dns:{sub}").unwrap();
+        assert!(template.render(&ctx(&claims, None)).is_err());
+        let identity = template
+            .skipping_invalid_dns(true)
+            .render(&ctx(&claims, None))
+            .unwrap();
+        assert!(identity.san.is_empty());
+    }
+
+    #[test]
+    fn renders_claims_and_skips_missing_san_values() {
+        let template = parse_naming_template(
+            r"O=Example\, Inc,OU={realm},CN={preferred_username}",
+            "dns:{wazuh_agent_name}.agents.example,email:{email},uri:urn:user:{name}",
+        )
+        .unwrap();
+        let claims = claims();
+        let identity = template.render(&ctx(&claims, Some("Web-01"))).unwrap();
+        assert_eq!(
+            identity.subject,
+            vec![
+                (Nid::ORGANIZATIONNAME, "Example, Inc".into()),
+                (Nid::ORGANIZATIONALUNITNAME, "main".into()),
+                (Nid::COMMONNAME, "jdoe".into()),
+            ]
+        );
+        assert_eq!(
+            identity.san,
+            vec![
+                SanEntry::Dns("web-01.agents.example".into()),
+                SanEntry::Email("jane@example.com".into()),
+                SanEntry::Uri("urn:user:Jane%20Doe".into()),
+            ]
+        );
+
+        let identity = template.render(&ctx(&claims, None)).unwrap();
+        assert_eq!(identity.san.len(), 2);
+    }
+
+    #[test]
+    fn unverified_email_is_left_out_of_sans() {
+        let template =
+            parse_naming_template("CN={sub}", "email:{email},uri:urn:mail:{email}").unwrap();
+        let mut claims = claims();
+        claims.email_verified = None;
+        let identity = template.render(&ctx(&claims, None)).unwrap();
+        assert!(identity.san.is_empty());
+        claims.email_verified = Some(false);
+        let identity = template.render(&ctx(&claims, None)).unwrap();
+        assert!(identity.san.is_empty());
+    }
+
+    #[test]
+    fn missing_cn_and_invalid_values_are_rejected() {
+        let template = parse_naming_template("CN={email}", "dns:{name}").unwrap();
+        let mut claims = claims();
+        // "Jane Doe" is not a DNS name.
+        assert!(template.render(&ctx(&claims, None)).is_err());
+        claims.email = None;
+        assert!(template.render(&ctx(&claims, None)).is_err());
+
+        let long = parse_naming_template("CN={name}", "").unwrap();
+        claims.name = Some("x".repeat(65));
+        assert!(long.render(&ctx(&claims, None)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(parse_naming_template("O=Example", "").is_err());
+        assert!(parse_naming_template("CN={unknown}", "").is_err());
+        assert!(parse_naming_template("CN={sub", "").is_err());
+        assert!(parse_naming_template("XX={sub}", "").is_err());
+        assert!(parse_naming_template("CN={sub}", "ip:{sub}").is_err());
+        assert!(parse_naming_template(r"CN=a\b", "").is_err());
+    }
+}
@@ -1,89 +1,269 @@
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
 
-use tokio::sync::RwLock;
+use jsonwebtoken::jwk::JwkSet;
+use serde::Deserialize;
 
+use wazuh_cert_oauth2_model::models::claims::Claims;
 use wazuh_cert_oauth2_model::models::document::DiscoveryDocument;
-use wazuh_cert_oauth2_model::models::errors::AppResult;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+use wazuh_cert_oauth2_model::services::jwks::TokenRequirements;
+use wazuh_cert_oauth2_model::services::refresh_cache::{RefreshPolicy, RefreshingCache};
 
-pub struct OidcState {
-    pub(crate) audiences: Option<Vec<String>>,
-    issuer: String,
-    discovery_ttl: Duration,
-    jwks_ttl: Duration,
-    http: HttpClient,
-    inner: RwLock<Inner>,
+use crate::shared::introspection::Introspector;
+
+/// Settings of one trusted issuer, as listed in `OIDC_ISSUERS_PATH`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IssuerConfig {
+    /// Exact `iss` of its tokens, also the base of its discovery document.
+    pub issuer: String,
+    /// Accepted `aud` values; absent skips the audience check.
+    #[serde(default)]
+    pub audiences: Option<Vec<String>>,
+    /// Realm roles granting admin access to holders of its tokens; absent
+    /// falls back to `ADMIN_ROLES`.
+    #[serde(default)]
+    pub admin_roles: Option<Vec<String>>,
+    /// Clock skew tolerated on `exp` and `nbf`; absent uses `OIDC_LEEWAY_SECS`.
+    #[serde(default)]
+    pub leeway_secs: Option<u64>,
+    /// Validate its tokens at its introspection endpoint instead of against
+    /// its JWKS, for issuers handing out opaque tokens.
+    #[serde(default)]
+    pub introspection: bool,
 }
 
-struct Inner {
-    discovery: Option<(Arc<DiscoveryDocument>, Instant)>,
-    jwks: Option<(Arc<jsonwebtoken::jwk::JwkSet>, Instant)>,
+/// Parse the JSON array of `OIDC_ISSUERS_PATH`, e.g.
+///
+/// ```json
+/// [{"issuer": "https://sso.example.com/realms/staff", "audiences": ["wazuh"]},
+///  {"issuer": "https://sso.example.com/realms/partners", "admin_roles": [], "leeway_secs": 30},
+///  {"issuer": "https://gw.example.com", "introspection": true}]
+/// ```
+pub fn parse_issuers(json: &str) -> AppResult<Vec<IssuerConfig>> {
+    serde_json::from_str(json)
+        .map_err(|e| AppError::ValidationError(format!("invalid OIDC issuers: {e}")))
 }
 
-impl OidcState {
+/// One trusted issuer with its own discovery document and JWKS cache.
+pub struct OidcIssuer {
+    issuer: String,
+    requirements: TokenRequirements,
+    admin_roles: Option<Vec<String>>,
+    discovery: RefreshingCache<DiscoveryDocument>,
+    jwks: RefreshingCache<JwkSet>,
+    introspection: bool,
+    introspector: Option<Introspector>,
+}
+
+impl OidcIssuer {
     pub fn new(
-        issuer: String,
-        audiences: Option<Vec<String>>,
-        discovery_ttl: Duration,
-        jwks_ttl: Duration,
+        config: IssuerConfig,
+        discovery: RefreshPolicy,
+        jwks: RefreshPolicy,
         http: HttpClient,
     ) -> Self {
         Self {
-            audiences,
-            issuer,
-            discovery_ttl,
-            jwks_ttl,
-            http,
-            inner: RwLock::new(Inner {
-                discovery: None,
-                jwks: None,
-            }),
+            requirements: TokenRequirements {
+                audiences: config.audiences,
+                issuer: Some(config.issuer.clone()),
+                leeway_secs: config.leeway_secs,
+            },
+            issuer: config.issuer,
+            admin_roles: config.admin_roles,
+            discovery: RefreshingCache::new(discovery, http.clone()),
+            jwks: RefreshingCache::new(jwks, http),
+            introspection: config.introspection,
+            introspector: None,
         }
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn get_discovery(&self) -> AppResult<Arc<DiscoveryDocument>> {
-        let now = Instant::now();
-        let mut inner = self.inner.write().await;
-        // Check again after acquiring write lock
-        if let Some((doc, fetched)) = &inner.discovery
-            && now.duration_since(*fetched) < self.discovery_ttl
-        {
-            return Ok(doc.clone());
+    /// Use `introspector` for the tokens of an issuer configured with
+    /// `introspection`.
+    pub fn with_introspector(self, introspector: Introspector) -> Self {
+        Self {
+            introspector: Some(introspector),
+            ..self
         }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
 
+    /// Audience, issuer and leeway its tokens are validated against.
+    pub fn requirements(&self) -> &TokenRequirements {
+        &self.requirements
+    }
+
+    /// Admin roles specific to this issuer, if configured.
+    pub fn admin_roles(&self) -> Option<&[String]> {
+        self.admin_roles.as_deref()
+    }
+
+    #[tracing::instrument(skip(self), fields(issuer = %self.issuer))]
+    pub async fn get_discovery(&self) -> AppResult<Arc<DiscoveryDocument>> {
         let url = format!("{}/.well-known/openid-configuration", self.issuer);
-        let doc: DiscoveryDocument = self.http.fetch_json(&url).await?;
-        let doc = Arc::new(doc);
-        inner.discovery = Some((doc.clone(), Instant::now()));
-        Ok(doc)
+        self.discovery.get(&url).await
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn get_jwks(&self) -> AppResult<Arc<jsonwebtoken::jwk::JwkSet>> {
-        let now = Instant::now();
-        let mut inner = self.inner.write().await;
-        // Check again after acquiring write lock
-        if let Some((jwks, fetched)) = &inner.jwks
-            && now.duration_since(*fetched) < self.jwks_ttl
-        {
-            return Ok(jwks.clone());
-        }
+    #[tracing::instrument(skip(self), fields(issuer = %self.issuer))]
+    pub async fn get_jwks(&self) -> AppResult<Arc<JwkSet>> {
+        let doc = self.get_discovery().await?;
+        self.jwks.get(&doc.jwks_uri).await
+    }
+
+    /// Fetch the key set again because a token names a key it lacks, as
+    /// happens right after the issuer rotates its keys. Rate limited, so
+    /// tokens with made-up key ids cannot make us hammer the issuer.
+    #[tracing::instrument(skip(self), fields(issuer = %self.issuer))]
+    pub async fn refresh_jwks(&self) -> AppResult<Arc<JwkSet>> {
+        let doc = self.get_discovery().await?;
+        self.jwks.refresh(&doc.jwks_uri).await
+    }
+
+    /// Whether its tokens are validated by introspection.
+    pub fn introspects(&self) -> bool {
+        self.introspection
+    }
 
-        let doc = match &inner.discovery {
-            Some((d, fetched)) if now.duration_since(*fetched) < self.discovery_ttl => d.clone(),
-            _ => {
-                drop(inner);
-                let doc = self.get_discovery().await?;
-                inner = self.inner.write().await;
-                doc
+    /// Claims of `token` according to the issuer's introspection endpoint.
+    #[tracing::instrument(skip(self, token), fields(issuer = %self.issuer))]
+    pub async fn introspect(&self, token: &str) -> AppResult<Claims> {
+        let introspector = self.introspector.as_ref().ok_or_else(|| {
+            AppError::ValidationError(format!(
+                "no introspection credentials for issuer '{}'",
+                self.issuer
+            ))
+        })?;
+        let doc = self.get_discovery().await?;
+        let endpoint = doc.introspection_endpoint().ok_or_else(|| {
+            AppError::UpstreamError(format!(
+                "issuer '{}' advertises no introspection endpoint",
+                self.issuer
+            ))
+        })?;
+        introspector
+            .introspect(endpoint, token, &self.requirements)
+            .await
+    }
+
+    /// Pre-populate the caches so tests never reach out to an issuer.
+    #[cfg(test)]
+    pub(crate) async fn seed_jwks(&self, jwks: JwkSet) {
+        self.discovery
+            .insert(DiscoveryDocument {
+                issuer: self.issuer.clone(),
+                authorization_endpoint: format!("{}/auth", self.issuer),
+                token_endpoint: format!("{}/token", self.issuer),
+                jwks_uri: format!("{}/certs", self.issuer),
+                extra: serde_json::json!({
+                    "introspection_endpoint": format!("{}/introspect", self.issuer),
+                }),
+            })
+            .await;
+        self.jwks.insert(jwks).await;
+    }
+}
+
+/// The set of trusted issuers. A token is only checked against the issuer
+/// named by its `iss`, and rejected when that issuer is not listed.
+pub struct OidcState {
+    issuers: Vec<OidcIssuer>,
+}
+
+impl OidcState {
+    pub fn new(issuers: Vec<OidcIssuer>) -> AppResult<Self> {
+        if issuers.is_empty() {
+            return Err(AppError::ValidationError(
+                "at least one OIDC issuer must be configured".into(),
+            ));
+        }
+        if issuers.iter().filter(|i| i.introspects()).count() > 1 {
+            return Err(AppError::ValidationError(
+                "opaque tokens name no issuer, so only one issuer may use introspection".into(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for issuer in &issuers {
+            if !seen.insert(issuer.issuer()) {
+                return Err(AppError::ValidationError(format!(
+                    "OIDC issuer '{}' is configured twice",
+                    issuer.issuer()
+                )));
             }
+        }
+        Ok(Self { issuers })
+    }
+
+    /// The trusted issuer whose `iss` is exactly `iss`.
+    pub fn issuer(&self, iss: &str) -> Option<&OidcIssuer> {
+        self.issuers.iter().find(|i| i.issuer == iss)
+    }
+
+    /// The issuer validating tokens by introspection, which takes the tokens
+    /// that are not JWTs.
+    pub fn opaque_issuer(&self) -> Option<&OidcIssuer> {
+        self.issuers.iter().find(|i| i.introspects())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+    use wazuh_cert_oauth2_model::services::refresh_cache::RefreshPolicy;
+
+    use super::{OidcIssuer, OidcState, parse_issuers};
+
+    #[test]
+    fn issuers_are_matched_exactly_and_listed_once() {
+        let configs = parse_issuers(
+            r#"[{"issuer":"https://sso/realms/a","audiences":["wazuh"]},
+                {"issuer":"https://sso/realms/b","admin_roles":["pki"],"leeway_secs":5}]"#,
+        )
+        .expect("issuers should parse");
+        let http = HttpClient::new_with_defaults().expect("http");
+        let build = |configs: Vec<_>| {
+            OidcState::new(
+                configs
+                    .into_iter()
+                    .map(|c| {
+                        OidcIssuer::new(
+                            c,
+                            RefreshPolicy::with_ttl(Duration::from_secs(1)),
+                            RefreshPolicy::with_ttl(Duration::from_secs(1)),
+                            http.clone(),
+                        )
+                    })
+                    .collect(),
+            )
         };
+        let state = build(configs.clone()).expect("state");
+        let b = state.issuer("https://sso/realms/b").expect("b is trusted");
+        assert_eq!(b.admin_roles(), Some(&["pki".to_string()][..]));
+        assert_eq!(b.requirements().leeway_secs, Some(5));
+        assert!(state.issuer("https://sso/realms/b/").is_none());
+        assert!(state.issuer("https://sso/realms/c").is_none());
 
-        let jwks: jsonwebtoken::jwk::JwkSet = self.http.fetch_json(&doc.jwks_uri).await?;
-        let jwks = Arc::new(jwks);
-        inner.jwks = Some((jwks.clone(), Instant::now()));
-        Ok(jwks)
+        let twice = vec![configs[0].clone(), configs[0].clone()];
+        assert!(build(twice).is_err());
+        assert!(build(Vec::new()).is_err());
+        let opaque = parse_issuers(
+            r#"[{"issuer":"https://gw/a","introspection":true},
+                {"issuer":"https://gw/b","introspection":true}]"#,
+        )
+        .expect("issuers should parse");
+        assert!(
+            build(opaque[..1].to_vec())
+                .expect("one")
+                .opaque_issuer()
+                .is_some()
+        );
+        assert!(build(opaque).is_err());
+        assert!(parse_issuers(r#"[{"issuer":"x","audience":["typo"]}]"#).is_err());
     }
 }
@@ -0,0 +1,347 @@
+use crate::models::enrollment_rules::EnrollmentRules;
+use crate::models::key_policy::KeyPolicy;
+use crate::models::naming_template::NamingTemplate;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// What a validity override matches on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValiditySelector {
+    /// A realm role in `realm_access.roles`.
+    Role(String),
+    /// The realm extracted from the token issuer.
+    Realm(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidityOverride {
+    pub selector: ValiditySelector,
+    pub days: u32,
+}
+
+/// A larger device quota for holders of a realm role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceQuotaOverride {
+    pub role: String,
+    pub max_devices: u32,
+}
+
+/// What to do with CSR extension requests the issued certificate would not
+/// honour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsrExtensionMode {
+    /// Ignore them with a warning.
+    Strip,
+    /// Reject the CSR.
+    Reject,
+}
+
+/// Parse `strip` or `reject`.
+pub fn parse_csr_extension_mode(value: &str) -> AppResult<CsrExtensionMode> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "strip" => Ok(CsrExtensionMode::Strip),
+        "reject" => Ok(CsrExtensionMode::Reject),
+        other => Err(AppError::ValidationError(format!(
+            "invalid CSR extension mode '{other}' (expected strip or reject)"
+        ))),
+    }
+}
+
+/// Certificate lifetime, key and naming settings applied when signing a CSR.
+///
+/// The lifetime is `validity_days`, or the shortest matching override when
+/// the caller's roles or realm match one. `max_validity_days` caps every
+/// certificate regardless of overrides, so lowering it shortens new
+/// certificates immediately.
+#[derive(Debug, Clone)]
+pub struct SigningProfile {
+    pub validity_days: u32,
+    pub max_validity_days: Option<u32>,
+    pub overrides: Vec<ValidityOverride>,
+    /// Seconds `notBefore` is moved into the past to absorb clock skew.
+    pub backdate_secs: u64,
+    /// Days before expiry during which a subject may re-enroll without
+    /// `overwrite`; `0` disables the window.
+    pub renewal_window_days: u32,
+    /// Public key algorithms accepted in CSRs.
+    pub key_policy: KeyPolicy,
+    /// Subject DN and SANs of issued certificates.
+    pub naming: NamingTemplate,
+    pub csr_extensions: CsrExtensionMode,
+    /// Active certificates a subject may hold per signing profile, one per
+    /// device.
+    pub max_devices: u32,
+    pub device_quota_overrides: Vec<DeviceQuotaOverride>,
+    /// Conditions on the caller's token, checked before anything else.
+    pub enrollment_rules: EnrollmentRules,
+}
+
+impl Default for SigningProfile {
+    fn default() -> Self {
+        Self {
+            validity_days: 365,
+            max_validity_days: None,
+            overrides: Vec::new(),
+            backdate_secs: 300,
+            renewal_window_days: 0,
+            key_policy: KeyPolicy::default(),
+            naming: NamingTemplate::default(),
+            csr_extensions: CsrExtensionMode::Strip,
+            max_devices: 1,
+            device_quota_overrides: Vec::new(),
+            enrollment_rules: EnrollmentRules::default(),
+        }
+    }
+}
+
+impl SigningProfile {
+    /// Refuse lifetimes that would issue certificates already expired, and a
+    /// default lifetime longer than the cap.
+    pub fn check_validity(&self) -> AppResult<()> {
+        if self.validity_days == 0 {
+            return Err(AppError::ValidationError(
+                "CERT_VALIDITY_DAYS must be at least 1".into(),
+            ));
+        }
+        match self.max_validity_days {
+            Some(0) => Err(AppError::ValidationError(
+                "CERT_MAX_VALIDITY_DAYS must be at least 1".into(),
+            )),
+            Some(max) if self.validity_days > max => Err(AppError::ValidationError(format!(
+                "CERT_VALIDITY_DAYS ({}) exceeds CERT_MAX_VALIDITY_DAYS ({max})",
+                self.validity_days
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Lifetime in days for a caller with these realm roles and realm.
+    pub fn validity_days_for(&self, roles: &[String], realm: Option<&str>) -> u32 {
+        let days = self
+            .overrides
+            .iter()
+            .filter(|o| match &o.selector {
+                ValiditySelector::Role(role) => roles.contains(role),
+                ValiditySelector::Realm(r) => realm == Some(r.as_str()),
+            })
+            .map(|o| o.days)
+            .min()
+            .unwrap_or(self.validity_days);
+        match self.max_validity_days {
+            Some(max) => days.min(max),
+            None => days,
+        }
+    }
+
+    /// Device quota for a caller with these realm roles: the largest
+    /// matching override, or `max_devices`.
+    pub fn device_quota_for(&self, roles: &[String]) -> u32 {
+        self.device_quota_overrides
+            .iter()
+            .filter(|o| roles.contains(&o.role))
+            .map(|o| o.max_devices)
+            .max()
+            .unwrap_or(self.max_devices)
+    }
+
+    /// `(notBefore, notAfter)` in unix seconds, never extending past the
+    /// issuing CA's own `notAfter`.
+    pub fn validity_window(
+        &self,
+        roles: &[String],
+        realm: Option<&str>,
+        now_unix: u64,
+        ca_not_after_unix: u64,
+    ) -> (u64, u64) {
+        let days = self.validity_days_for(roles, realm);
+        self.validity_window_of(days, now_unix, ca_not_after_unix)
+    }
+
+    /// `(notBefore, notAfter)` for a lifetime of `days`, capped by
+    /// `max_validity_days` and the issuing CA's own `notAfter`.
+    pub fn validity_window_of(
+        &self,
+        days: u32,
+        now_unix: u64,
+        ca_not_after_unix: u64,
+    ) -> (u64, u64) {
+        let days = self.max_validity_days.map_or(days, |max| days.min(max)) as u64;
+        let not_before = now_unix.saturating_sub(self.backdate_secs);
+        let not_after = now_unix
+            .saturating_add(days * SECS_PER_DAY)
+            .min(ca_not_after_unix);
+        (not_before, not_after)
+    }
+
+    /// Whether a certificate expiring at `not_after_unix` is inside the
+    /// renewal window.
+    pub fn in_renewal_window(&self, not_after_unix: u64, now_unix: u64) -> bool {
+        self.renewal_window_days > 0
+            && not_after_unix <= now_unix + self.renewal_window_days as u64 * SECS_PER_DAY
+    }
+}
+
+/// Parse `role:<name>=<days>,realm:<name>=<days>` into overrides.
+pub fn parse_validity_overrides(spec: &str) -> AppResult<Vec<ValidityOverride>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| {
+            let invalid = || {
+                AppError::ValidationError(format!(
+                    "invalid validity override '{item}' (expected role:<name>=<days> or realm:<name>=<days>)"
+                ))
+            };
+            let (selector, days) = item.split_once('=').ok_or_else(invalid)?;
+            let days: u32 = days.trim().parse().map_err(|_| invalid())?;
+            if days == 0 {
+                return Err(invalid());
+            }
+            let selector = match selector.trim().split_once(':') {
+                Some(("role", name)) if !name.is_empty() => ValiditySelector::Role(name.into()),
+                Some(("realm", name)) if !name.is_empty() => ValiditySelector::Realm(name.into()),
+                _ => return Err(invalid()),
+            };
+            Ok(ValidityOverride { selector, days })
+        })
+        .collect()
+}
+
+/// Parse `role:<name>=<devices>,...` into device quota overrides.
+pub fn parse_device_quota_overrides(spec: &str) -> AppResult<Vec<DeviceQuotaOverride>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| {
+            let invalid = || {
+                AppError::ValidationError(format!(
+                    "invalid device quota override '{item}' (expected role:<name>=<devices>)"
+                ))
+            };
+            let (selector, max) = item.split_once('=').ok_or_else(invalid)?;
+            let max_devices: u32 = max.trim().parse().map_err(|_| invalid())?;
+            match selector.trim().split_once(':') {
+                Some(("role", role)) if !role.is_empty() && max_devices > 0 => {
+                    Ok(DeviceQuotaOverride {
+                        role: role.into(),
+                        max_devices,
+                    })
+                }
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        SigningProfile, ValiditySelector, parse_device_quota_overrides, parse_validity_overrides,
+    };
+
+    fn roles(roles: &[&str]) -> Vec<String> {
+        roles.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_role_and_realm_overrides() {
+        let parsed = parse_validity_overrides("role:contractor=30, realm:dev=90").unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].selector,
+            ValiditySelector::Role("contractor".into())
+        );
+        assert_eq!(parsed[0].days, 30);
+        assert_eq!(parsed[1].selector, ValiditySelector::Realm("dev".into()));
+        assert!(parse_validity_overrides("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_overrides() {
+        assert!(parse_validity_overrides("contractor=30").is_err());
+        assert!(parse_validity_overrides("role:contractor").is_err());
+        assert!(parse_validity_overrides("role:contractor=0").is_err());
+        assert!(parse_validity_overrides("group:x=30").is_err());
+    }
+
+    #[test]
+    fn rejects_zero_and_uncapped_validity() {
+        let profile = |validity_days, max_validity_days| SigningProfile {
+            validity_days,
+            max_validity_days,
+            ..Default::default()
+        };
+        assert!(profile(365, None).check_validity().is_ok());
+        assert!(profile(90, Some(90)).check_validity().is_ok());
+        assert!(profile(0, None).check_validity().is_err());
+        assert!(profile(30, Some(0)).check_validity().is_err());
+        assert!(profile(365, Some(90)).check_validity().is_err());
+    }
+
+    #[test]
+    fn shortest_matching_override_wins_and_cap_applies() {
+        let profile = SigningProfile {
+            overrides: parse_validity_overrides("role:contractor=30,realm:main=90").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(profile.validity_days_for(&roles(&[]), None), 365);
+        assert_eq!(profile.validity_days_for(&roles(&[]), Some("main")), 90);
+        assert_eq!(
+            profile.validity_days_for(&roles(&["contractor"]), Some("main")),
+            30
+        );
+
+        let capped = SigningProfile {
+            max_validity_days: Some(60),
+            ..profile
+        };
+        assert_eq!(capped.validity_days_for(&roles(&[]), Some("main")), 60);
+    }
+
+    #[test]
+    fn validity_window_is_backdated_and_clamped_to_ca() {
+        let profile = SigningProfile::default();
+        let now = 1_000_000;
+        let (nb, na) = profile.validity_window(&roles(&[]), None, now, u64::MAX);
+        assert_eq!(nb, now - 300);
+        assert_eq!(na, now + 365 * 86_400);
+
+        let (_, na) = profile.validity_window(&roles(&[]), None, now, now + 10);
+        assert_eq!(na, now + 10);
+
+        let capped = SigningProfile {
+            max_validity_days: Some(30),
+            ..profile
+        };
+        let (_, na) = capped.validity_window_of(90, now, u64::MAX);
+        assert_eq!(na, now + 30 * 86_400);
+    }
+
+    #[test]
+    fn renewal_window() {
+        let now = 1_000_000;
+        assert!(!SigningProfile::default().in_renewal_window(now + 1, now));
+        let profile = SigningProfile {
+            renewal_window_days: 7,
+            ..Default::default()
+        };
+        assert!(profile.in_renewal_window(now + 7 * 86_400, now));
+        assert!(!profile.in_renewal_window(now + 8 * 86_400, now));
+    }
+
+    #[test]
+    fn largest_matching_device_quota_wins() {
+        let profile = SigningProfile {
+            device_quota_overrides: parse_device_quota_overrides("role:engineer=2, role:lab=5")
+                .unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(profile.device_quota_for(&roles(&[])), 1);
+        assert_eq!(profile.device_quota_for(&roles(&["engineer"])), 2);
+        assert_eq!(profile.device_quota_for(&roles(&["engineer", "lab"])), 5);
+        assert!(parse_device_quota_overrides("role:engineer=0").is_err());
+        assert!(parse_device_quota_overrides("realm:dev=2").is_err());
+        assert!(parse_device_quota_overrides("engineer").is_err());
+    }
+}
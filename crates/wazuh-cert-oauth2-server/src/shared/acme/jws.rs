@@ -0,0 +1,251 @@
+//! Flattened JWS (RFC 7515 §7.2.2) as used by ACME requests, and the JWKs
+//! (RFC 7517) of account keys.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64URL;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::nid::Nid;
+use openssl::pkey::{Id as PKeyId, PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::problem::{AcmeError, AcmeResult, ProblemType};
+
+/// A request body in the flattened JWS JSON serialization.
+#[derive(Debug, Deserialize)]
+pub struct Jws {
+    pub protected: String,
+    pub payload: String,
+    pub signature: String,
+}
+
+/// The protected header of an ACME request (RFC 8555 §6.2).
+#[derive(Debug, Deserialize)]
+pub struct ProtectedHeader {
+    pub alg: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub jwk: Option<Value>,
+    #[serde(default)]
+    pub kid: Option<String>,
+}
+
+impl Jws {
+    pub fn header(&self) -> AcmeResult<ProtectedHeader> {
+        serde_json::from_slice(&b64url_decode(&self.protected)?)
+            .map_err(|e| AcmeError::malformed(format!("invalid protected header: {e}")))
+    }
+
+    pub fn payload(&self) -> AcmeResult<Vec<u8>> {
+        b64url_decode(&self.payload)
+    }
+
+    fn signing_input(&self) -> String {
+        format!("{}.{}", self.protected, self.payload)
+    }
+
+    /// Check the signature with the account key `key` under `alg`.
+    pub fn verify(&self, alg: &str, key: &PKey<Public>) -> AcmeResult<()> {
+        let signature = b64url_decode(&self.signature)?;
+        let input = self.signing_input();
+        let bad_alg = || {
+            AcmeError::new(
+                ProblemType::BadSignatureAlgorithm,
+                format!("algorithm {alg} does not match the account key"),
+            )
+        };
+        let valid = match (alg, key.id()) {
+            ("RS256", PKeyId::RSA) => {
+                let mut verifier = Verifier::new(MessageDigest::sha256(), key)?;
+                verifier.verify_oneshot(&signature, input.as_bytes())?
+            }
+            ("ES256" | "ES384" | "ES512", PKeyId::EC) => {
+                let (curve, digest, size) = match alg {
+                    "ES256" => (Nid::X9_62_PRIME256V1, MessageDigest::sha256(), 32),
+                    "ES384" => (Nid::SECP384R1, MessageDigest::sha384(), 48),
+                    _ => (Nid::SECP521R1, MessageDigest::sha512(), 66),
+                };
+                if key.ec_key()?.group().curve_name() != Some(curve) {
+                    return Err(bad_alg());
+                }
+                // JWS carries r || s; OpenSSL expects a DER ECDSA-Sig-Value.
+                if signature.len() != 2 * size {
+                    return Err(AcmeError::malformed("invalid ECDSA signature length"));
+                }
+                let (r, s) = signature.split_at(size);
+                let der = EcdsaSig::from_private_components(
+                    BigNum::from_slice(r)?,
+                    BigNum::from_slice(s)?,
+                )?
+                .to_der()?;
+                let mut verifier = Verifier::new(digest, key)?;
+                verifier.verify_oneshot(&der, input.as_bytes())?
+            }
+            ("EdDSA", PKeyId::ED25519) => {
+                let mut verifier = Verifier::new_without_digest(key)?;
+                verifier.verify_oneshot(&signature, input.as_bytes())?
+            }
+            ("RS256" | "ES256" | "ES384" | "ES512" | "EdDSA", _) => return Err(bad_alg()),
+            _ => {
+                return Err(AcmeError::new(
+                    ProblemType::BadSignatureAlgorithm,
+                    format!("unsupported algorithm {alg}"),
+                ));
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(AcmeError::malformed("JWS signature is invalid"))
+        }
+    }
+
+    /// Check a MAC-protected external account binding signed with `key`.
+    pub fn verify_hmac(&self, alg: &str, key: &[u8]) -> AcmeResult<()> {
+        let digest = match alg {
+            "HS256" => MessageDigest::sha256(),
+            "HS384" => MessageDigest::sha384(),
+            "HS512" => MessageDigest::sha512(),
+            _ => {
+                return Err(AcmeError::new(
+                    ProblemType::BadSignatureAlgorithm,
+                    format!("unsupported external account binding algorithm {alg}"),
+                ));
+            }
+        };
+        let signature = b64url_decode(&self.signature)?;
+        let key = PKey::hmac(key)?;
+        let mac =
+            Signer::new(digest, &key)?.sign_oneshot_to_vec(self.signing_input().as_bytes())?;
+        if mac.len() == signature.len() && memcmp::eq(&mac, &signature) {
+            Ok(())
+        } else {
+            Err(AcmeError::unauthorized(
+                "external account binding MAC is invalid",
+            ))
+        }
+    }
+}
+
+pub fn b64url_decode(value: &str) -> AcmeResult<Vec<u8>> {
+    B64URL
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| AcmeError::malformed("invalid base64url encoding"))
+}
+
+pub fn b64url_encode(bytes: &[u8]) -> String {
+    B64URL.encode(bytes)
+}
+
+fn member<'a>(jwk: &'a Value, name: &str) -> AcmeResult<&'a str> {
+    jwk.get(name).and_then(Value::as_str).ok_or_else(|| {
+        AcmeError::new(
+            ProblemType::BadPublicKey,
+            format!("JWK lacks the '{name}' member"),
+        )
+    })
+}
+
+fn bignum(jwk: &Value, name: &str) -> AcmeResult<BigNum> {
+    Ok(BigNum::from_slice(&b64url_decode(member(jwk, name)?)?)?)
+}
+
+/// Public key of an RSA, EC (P-256/384/521) or Ed25519 JWK.
+pub fn jwk_to_pkey(jwk: &Value) -> AcmeResult<PKey<Public>> {
+    let unsupported = |what: &str| {
+        AcmeError::new(
+            ProblemType::BadPublicKey,
+            format!("unsupported account key: {what}"),
+        )
+    };
+    match member(jwk, "kty")? {
+        "RSA" => {
+            let rsa = Rsa::from_public_components(bignum(jwk, "n")?, bignum(jwk, "e")?)?;
+            if rsa.size() < 256 {
+                return Err(unsupported("RSA keys must have at least 2048 bits"));
+            }
+            Ok(PKey::from_rsa(rsa)?)
+        }
+        "EC" => {
+            let nid = match member(jwk, "crv")? {
+                "P-256" => Nid::X9_62_PRIME256V1,
+                "P-384" => Nid::SECP384R1,
+                "P-521" => Nid::SECP521R1,
+                crv => return Err(unsupported(crv)),
+            };
+            let group = EcGroup::from_curve_name(nid)?;
+            let (x, y) = (bignum(jwk, "x")?, bignum(jwk, "y")?);
+            let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                .map_err(|_| unsupported("EC point is not on the curve"))?;
+            key.check_key()
+                .map_err(|_| unsupported("EC point is not on the curve"))?;
+            Ok(PKey::from_ec_key(key)?)
+        }
+        "OKP" if member(jwk, "crv")? == "Ed25519" => Ok(PKey::public_key_from_raw_bytes(
+            &b64url_decode(member(jwk, "x")?)?,
+            PKeyId::ED25519,
+        )
+        .map_err(|_| unsupported("invalid Ed25519 key"))?),
+        kty => Err(unsupported(kty)),
+    }
+}
+
+/// RFC 7638 thumbprint: SHA-256 over the required members in lexicographic
+/// order, base64url-encoded.
+pub fn thumbprint(jwk: &Value) -> AcmeResult<String> {
+    let names: &[&str] = match member(jwk, "kty")? {
+        "RSA" => &["e", "kty", "n"],
+        "EC" => &["crv", "kty", "x", "y"],
+        _ => &["crv", "kty", "x"],
+    };
+    let members = names
+        .iter()
+        .map(|name| {
+            let value = serde_json::to_string(member(jwk, name)?)?;
+            Ok(format!("\"{name}\":{value}"))
+        })
+        .collect::<AcmeResult<Vec<_>>>()?;
+    let canonical = format!("{{{}}}", members.join(","));
+    let digest = openssl::sha::sha256(canonical.as_bytes());
+    Ok(b64url_encode(&digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{b64url_encode, jwk_to_pkey, thumbprint};
+    use serde_json::json;
+
+    #[test]
+    fn rfc7638_thumbprint() {
+        // RFC 7638 §3.1.
+        let jwk = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        });
+        assert_eq!(
+            thumbprint(&jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert!(jwk_to_pkey(&jwk).is_ok());
+    }
+
+    #[test]
+    fn rejects_small_rsa_and_unknown_keys() {
+        let small = json!({"kty": "RSA", "n": b64url_encode(&[0xc5; 128]), "e": "AQAB"});
+        assert!(jwk_to_pkey(&small).is_err());
+        assert!(jwk_to_pkey(&json!({"kty": "oct", "k": "c2VjcmV0"})).is_err());
+        let off_curve = json!({"kty": "EC", "crv": "P-256", "x": b64url_encode(&[1; 32]), "y": b64url_encode(&[2; 32])});
+        assert!(jwk_to_pkey(&off_curve).is_err());
+    }
+}
@@ -0,0 +1,997 @@
+//! ACME (RFC 8555) issuance for clients such as cert-manager and certbot.
+//!
+//! Accounts require External Account Binding: an OIDC-authenticated caller
+//! obtains a key id and MAC key from `POST /api/acme/eab`, and the account
+//! registered with them issues certificates as that caller (subject, roles,
+//! signing profile and device id). The binding lasts the binding TTL from
+//! when the key was minted; the account then presents a fresh EAB key to
+//! newAccount to be bound again. Whether the caller is an admin, whether its
+//! issuer is still trusted and whether its enrollments need approval are
+//! worked out again on every order. Identifiers are pre-authorized: an order
+//! may name the DNS names the caller's certificate carries under its profile,
+//! and its authorizations are valid from the start, so there are no
+//! challenges. Finalize goes through [`sign_csr`]; revokeCert marks the
+//! ledger and rebuilds the CRLs.
+//!
+//! Nonces, EAB keys, accounts, orders and authorizations are kept in the
+//! ledger store, so every replica sharing the PostgreSQL ledger serves the
+//! same accounts, and accounts survive a restart. The CSV ledger keeps them
+//! beside its file, with nonces in memory only.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use openssl::x509::{X509, X509Req};
+use rand::TryRng;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Builder, Responder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::info;
+use unwrap_infallible::UnwrapInfallible;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
+use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
+
+use crate::handlers::middle::{ClientInfo, Principal};
+use crate::models::access_policy::AccessPolicy;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::naming_template::{NamingContext, SanEntry};
+use crate::models::oidc_state::OidcState;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::certs::{extract_realm_from_issuer, requested_dns_names, sign_csr, unix_now};
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::Ledger;
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+mod jws;
+mod problem;
+
+pub use jws::Jws;
+pub use problem::{AcmeError, AcmeResult, ProblemType};
+
+use jws::{b64url_decode, b64url_encode, jwk_to_pkey, thumbprint};
+
+/// Path the ACME routes are mounted under.
+pub const ACME_BASE: &str = "/acme";
+
+/// Lifetime of an unused nonce.
+const NONCE_LIFETIME_SECS: u64 = 3_600;
+
+/// Lifetime of orders and their authorizations.
+const ORDER_LIFETIME_SECS: u64 = 86_400;
+
+/// Who an account issues certificates as, captured from the OIDC token that
+/// requested the EAB key. Admin rights are not captured: they are derived
+/// from the claims whenever the account is used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    pub claims: Claims,
+    pub profile: Option<String>,
+    pub device_id: Option<String>,
+    pub wazuh_agent_name: Option<String>,
+}
+
+/// Body of `POST /api/acme/eab`; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct EabRequest {
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub wazuh_agent_name: Option<String>,
+}
+
+/// EAB key id and MAC key handed to the ACME client.
+#[derive(Debug, Serialize)]
+pub struct EabCredentials {
+    pub kid: String,
+    /// base64url-encoded HMAC key.
+    pub hmac_key: String,
+    pub directory: String,
+    /// Unix time after which the key can no longer register an account.
+    pub expires_unix: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Ready,
+    Processing,
+    Valid,
+    Invalid,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Processing => "processing",
+            Self::Valid => "valid",
+            Self::Invalid => "invalid",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ready" => Some(Self::Ready),
+            "processing" => Some(Self::Processing),
+            "valid" => Some(Self::Valid),
+            "invalid" => Some(Self::Invalid),
+            _ => None,
+        }
+    }
+}
+
+/// An EAB key that has not registered an account yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EabKey {
+    pub kid: String,
+    pub hmac_key: Vec<u8>,
+    pub binding: Binding,
+    pub expires_unix: u64,
+    /// When an account registered with this key stops being bound.
+    pub bound_until: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    /// RFC 7638 thumbprint of `jwk`; a key registers one account.
+    pub thumbprint: String,
+    pub jwk: Value,
+    pub deactivated: bool,
+    pub contact: Vec<String>,
+    pub binding: Binding,
+    pub bound_until: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: String,
+    pub account_id: String,
+    pub status: OrderStatus,
+    pub expires_unix: u64,
+    pub identifiers: Vec<Identifier>,
+    pub authz_ids: Vec<String>,
+    pub certificate: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Authz {
+    pub id: String,
+    pub account_id: String,
+    pub identifier: Identifier,
+    pub expires_unix: u64,
+}
+
+async fn find_account(ledger: &Ledger, id: &str) -> AcmeResult<Account> {
+    ledger
+        .find_acme_account(id)
+        .await?
+        .ok_or_else(|| AcmeError::new(ProblemType::AccountDoesNotExist, "unknown account"))
+}
+
+/// The key a request was signed with.
+pub enum RequestKey {
+    /// A registered account (`kid`).
+    Account(String),
+    /// A key given inline (`jwk`), for newAccount and revokeCert.
+    Jwk(Value),
+}
+
+/// A request whose signature, nonce and URL have been checked.
+pub struct Verified {
+    pub key: RequestKey,
+    pub url: String,
+    pub payload: Vec<u8>,
+}
+
+impl Verified {
+    fn account_id(&self) -> AcmeResult<&str> {
+        match &self.key {
+            RequestKey::Account(id) => Ok(id),
+            RequestKey::Jwk(_) => Err(AcmeError::malformed(
+                "request must be signed with an account key (kid)",
+            )),
+        }
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(&self) -> AcmeResult<T> {
+        serde_json::from_slice(&self.payload)
+            .map_err(|e| AcmeError::malformed(format!("invalid payload: {e}")))
+    }
+
+    /// POST-as-GET requests carry an empty payload.
+    fn ensure_post_as_get(&self) -> AcmeResult<()> {
+        if self.payload.is_empty() {
+            Ok(())
+        } else {
+            Err(AcmeError::malformed("expected a POST-as-GET request"))
+        }
+    }
+}
+
+/// ACME server configuration; the state itself lives in the [`Ledger`].
+pub struct AcmeState {
+    base_url: String,
+    eab_ttl: Duration,
+    binding_ttl: Duration,
+}
+
+/// What an ACME handler returns on success.
+pub struct AcmeReply {
+    status: Status,
+    location: Option<String>,
+    body: ReplyBody,
+}
+
+enum ReplyBody {
+    Json(Value),
+    PemChain(String),
+    Empty,
+}
+
+impl AcmeReply {
+    pub fn json(status: Status, location: Option<String>, body: Value) -> Self {
+        Self {
+            status,
+            location,
+            body: ReplyBody::Json(body),
+        }
+    }
+
+    pub fn empty(status: Status) -> Self {
+        Self {
+            status,
+            location: None,
+            body: ReplyBody::Empty,
+        }
+    }
+}
+
+/// Headers every ACME response carries: the directory link and `no-store`.
+/// The fresh nonce is added by
+/// [`AcmeNonceFairing`](crate::handlers::acme_fairing::AcmeNonceFairing), as
+/// recording it needs the ledger.
+fn with_acme_headers(res: &mut Builder<'_>, req: &Request<'_>) {
+    if let Some(acme) = req.rocket().state::<AcmeState>() {
+        res.raw_header(
+            "Link",
+            format!("<{}>;rel=\"index\"", acme.url("/directory")),
+        );
+    }
+    res.raw_header("Cache-Control", "no-store");
+}
+
+impl<'r> Responder<'r, 'static> for AcmeReply {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = Response::build();
+        res.status(self.status);
+        if let Some(location) = self.location {
+            res.raw_header("Location", location);
+        }
+        match self.body {
+            ReplyBody::Json(value) => {
+                let body = value.to_string();
+                res.header(ContentType::JSON)
+                    .sized_body(body.len(), Cursor::new(body));
+            }
+            ReplyBody::PemChain(pem) => {
+                res.header(ContentType::new("application", "pem-certificate-chain"))
+                    .sized_body(pem.len(), Cursor::new(pem));
+            }
+            ReplyBody::Empty => {}
+        }
+        with_acme_headers(&mut res, req);
+        res.ok()
+    }
+}
+
+fn random_id() -> String {
+    let mut buf = [0u8; 16];
+    rand::rng().try_fill_bytes(&mut buf).unwrap_infallible();
+    b64url_encode(&buf)
+}
+
+fn rfc3339(unix: u64) -> String {
+    chrono::DateTime::from_timestamp(unix as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewAccountPayload {
+    #[serde(default)]
+    contact: Vec<String>,
+    #[serde(default)]
+    only_return_existing: bool,
+    #[serde(default)]
+    external_account_binding: Option<Jws>,
+}
+
+#[derive(Deserialize)]
+struct AccountUpdatePayload {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    contact: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct NewOrderPayload {
+    identifiers: Vec<Identifier>,
+}
+
+#[derive(Deserialize)]
+struct FinalizePayload {
+    csr: String,
+}
+
+#[derive(Deserialize)]
+struct RevokePayload {
+    certificate: String,
+    #[serde(default)]
+    reason: Option<i32>,
+}
+
+/// Verify an external account binding for the account key `thumb` and
+/// consume its EAB key: each key binds one account once.
+async fn take_eab_key(ledger: &Ledger, eab: &Jws, url: &str, thumb: &str) -> AcmeResult<EabKey> {
+    let eab_header = eab.header()?;
+    if eab_header.url != url || eab_header.nonce.is_some() || eab_header.jwk.is_some() {
+        return Err(AcmeError::malformed(
+            "invalid external account binding header",
+        ));
+    }
+    let kid = eab_header
+        .kid
+        .ok_or_else(|| AcmeError::malformed("external account binding lacks a kid"))?;
+    let bound_jwk: Value = serde_json::from_slice(&eab.payload()?)
+        .map_err(|_| AcmeError::malformed("external account binding payload is not a JWK"))?;
+    if thumbprint(&bound_jwk)? != thumb {
+        return Err(AcmeError::unauthorized(
+            "external account binding is for a different key",
+        ));
+    }
+    let unknown = || AcmeError::unauthorized("unknown or expired EAB key id");
+    let key = ledger
+        .find_acme_eab_key(&kid)
+        .await?
+        .filter(|key| key.expires_unix > unix_now())
+        .ok_or_else(unknown)?;
+    eab.verify_hmac(&eab_header.alg, &key.hmac_key)?;
+    // Another registration may have consumed the key since it was read.
+    ledger.take_acme_eab_key(&kid).await?.ok_or_else(unknown)
+}
+
+/// The caller an account acts as, with admin rights derived from its claims
+/// under the current configuration. Refused once the token's issuer is no
+/// longer trusted.
+fn principal(binding: &Binding, oidc: &OidcState, access: &AccessPolicy) -> AcmeResult<Principal> {
+    let issuer = oidc.issuer(&binding.claims.iss).ok_or_else(|| {
+        AcmeError::unauthorized("the account's token issuer is no longer trusted")
+    })?;
+    Ok(Principal {
+        is_admin: access.is_admin_for(&binding.claims, issuer.admin_roles()),
+        claims: binding.claims.clone(),
+    })
+}
+
+impl AcmeState {
+    /// `base_url` is the externally visible origin, e.g.
+    /// `https://cert.example.com`; ACME URLs live under [`ACME_BASE`].
+    /// Accounts stay bound for `binding_ttl` after their EAB key was minted.
+    pub fn new(base_url: &str, eab_ttl: Duration, binding_ttl: Duration) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            eab_ttl,
+            binding_ttl,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}{}", self.base_url, ACME_BASE, path)
+    }
+
+    fn account_url(&self, id: &str) -> String {
+        self.url(&format!("/account/{id}"))
+    }
+
+    fn order_url(&self, id: &str) -> String {
+        self.url(&format!("/order/{id}"))
+    }
+
+    pub fn directory(&self) -> Value {
+        json!({
+            "newNonce": self.url("/new-nonce"),
+            "newAccount": self.url("/new-account"),
+            "newOrder": self.url("/new-order"),
+            "revokeCert": self.url("/revoke-cert"),
+            "meta": { "externalAccountRequired": true },
+        })
+    }
+
+    pub async fn new_nonce(&self, ledger: &Ledger) -> AppResult<String> {
+        let nonce = random_id();
+        ledger
+            .record_acme_nonce(nonce.clone(), unix_now() + NONCE_LIFETIME_SECS)
+            .await?;
+        Ok(nonce)
+    }
+
+    /// Mint an EAB key binding future accounts to `binding`.
+    pub async fn issue_eab(&self, ledger: &Ledger, binding: Binding) -> AppResult<EabCredentials> {
+        let now = unix_now();
+        let kid = random_id();
+        let mut hmac_key = vec![0u8; 32];
+        rand::rng()
+            .try_fill_bytes(&mut hmac_key)
+            .unwrap_infallible();
+        let bound_until = now + self.binding_ttl.as_secs();
+        let expires_unix = (now + self.eab_ttl.as_secs()).min(bound_until);
+        info!(sub = %binding.claims.sub, kid = %kid, "issued ACME EAB key");
+        let credentials = EabCredentials {
+            kid: kid.clone(),
+            hmac_key: b64url_encode(&hmac_key),
+            directory: self.url("/directory"),
+            expires_unix,
+        };
+        ledger.purge_acme().await?;
+        ledger
+            .record_acme_eab_key(EabKey {
+                kid,
+                hmac_key,
+                binding,
+                expires_unix,
+                bound_until,
+            })
+            .await?;
+        Ok(credentials)
+    }
+
+    /// Check the nonce, URL and signature of a request to `path`.
+    pub async fn verify(&self, jws: &Jws, path: &str, ledger: &Ledger) -> AcmeResult<Verified> {
+        let header = jws.header()?;
+        let url = format!("{}{}", self.base_url, path);
+        if header.url != url {
+            return Err(AcmeError::unauthorized(format!(
+                "JWS url {} does not match the request URL",
+                header.url
+            )));
+        }
+        let nonce = header
+            .nonce
+            .ok_or_else(|| AcmeError::new(ProblemType::BadNonce, "missing nonce"))?;
+        if !ledger.take_acme_nonce(&nonce).await? {
+            return Err(AcmeError::new(
+                ProblemType::BadNonce,
+                "nonce is unknown or already used",
+            ));
+        }
+        let (key, pkey) = match (header.jwk, header.kid) {
+            (Some(jwk), None) => {
+                let pkey = jwk_to_pkey(&jwk)?;
+                (RequestKey::Jwk(jwk), pkey)
+            }
+            (None, Some(kid)) => {
+                let id = kid
+                    .strip_prefix(&self.account_url(""))
+                    .ok_or_else(|| AcmeError::malformed("kid is not an account URL"))?;
+                let account = find_account(ledger, id).await?;
+                if account.deactivated {
+                    return Err(AcmeError::unauthorized("account is deactivated"));
+                }
+                let pkey = jwk_to_pkey(&account.jwk)?;
+                (RequestKey::Account(id.to_string()), pkey)
+            }
+            _ => {
+                return Err(AcmeError::malformed(
+                    "exactly one of jwk and kid must be present",
+                ));
+            }
+        };
+        jws.verify(&header.alg, &pkey)?;
+        Ok(Verified {
+            key,
+            url,
+            payload: jws.payload()?,
+        })
+    }
+
+    fn account_json(&self, account: &Account) -> Value {
+        json!({
+            "status": if account.deactivated { "deactivated" } else { "valid" },
+            "contact": account.contact,
+            "orders": self.url(&format!("/account/{}/orders", account.id)),
+        })
+    }
+
+    /// newAccount: register the request key, bound through EAB, or return
+    /// the account already registered for it.
+    pub async fn new_account(&self, req: Verified, ledger: &Ledger) -> AcmeResult<AcmeReply> {
+        let RequestKey::Jwk(jwk) = &req.key else {
+            return Err(AcmeError::malformed("newAccount must be signed with a jwk"));
+        };
+        let payload: NewAccountPayload = req.json()?;
+        let thumb = thumbprint(jwk)?;
+        if let Some(mut account) = ledger.find_acme_account_by_key(&thumb).await? {
+            if account.bound_until <= unix_now()
+                && let Some(eab) = payload.external_account_binding
+            {
+                let key = take_eab_key(ledger, &eab, &req.url, &thumb).await?;
+                // A fresh key renews the binding of the same caller only.
+                if key.binding.claims.sub != account.binding.claims.sub
+                    || key.binding.claims.iss != account.binding.claims.iss
+                {
+                    return Err(AcmeError::unauthorized(
+                        "EAB key was issued to a different caller than this account",
+                    ));
+                }
+                info!(sub = %key.binding.claims.sub, account = %account.id, "renewed ACME account binding");
+                account.binding = key.binding;
+                account.bound_until = key.bound_until;
+                ledger.update_acme_account(account.clone()).await?;
+            }
+            return Ok(AcmeReply::json(
+                Status::Ok,
+                Some(self.account_url(&account.id)),
+                self.account_json(&account),
+            ));
+        }
+        if payload.only_return_existing {
+            return Err(AcmeError::new(
+                ProblemType::AccountDoesNotExist,
+                "no account is registered for this key",
+            ));
+        }
+        let eab = payload.external_account_binding.ok_or_else(|| {
+            AcmeError::new(
+                ProblemType::ExternalAccountRequired,
+                "an external account binding is required",
+            )
+        })?;
+        let key = take_eab_key(ledger, &eab, &req.url, &thumb).await?;
+
+        let account = Account {
+            id: random_id(),
+            thumbprint: thumb,
+            jwk: jwk.clone(),
+            deactivated: false,
+            contact: payload.contact,
+            binding: key.binding,
+            bound_until: key.bound_until,
+        };
+        info!(sub = %account.binding.claims.sub, account = %account.id, "registered ACME account");
+        ledger.record_acme_account(account.clone()).await?;
+        Ok(AcmeReply::json(
+            Status::Created,
+            Some(self.account_url(&account.id)),
+            self.account_json(&account),
+        ))
+    }
+
+    /// Account URL: fetch, update contacts or deactivate.
+    pub async fn account(&self, req: Verified, id: &str, ledger: &Ledger) -> AcmeResult<AcmeReply> {
+        if req.account_id()? != id {
+            return Err(AcmeError::unauthorized("not the requesting account"));
+        }
+        let update: AccountUpdatePayload = if req.payload.is_empty() {
+            AccountUpdatePayload {
+                status: None,
+                contact: None,
+            }
+        } else {
+            req.json()?
+        };
+        let mut account = find_account(ledger, id).await?;
+        match update.status.as_deref() {
+            None => {}
+            Some("deactivated") => account.deactivated = true,
+            Some(other) => {
+                return Err(AcmeError::malformed(format!(
+                    "cannot change account status to {other}"
+                )));
+            }
+        }
+        if let Some(contact) = update.contact {
+            account.contact = contact;
+        }
+        if !req.payload.is_empty() {
+            ledger.update_acme_account(account.clone()).await?;
+        }
+        Ok(AcmeReply::json(
+            Status::Ok,
+            None,
+            self.account_json(&account),
+        ))
+    }
+
+    /// The account's orders that have not expired.
+    pub async fn account_orders(
+        &self,
+        req: Verified,
+        id: &str,
+        ledger: &Ledger,
+    ) -> AcmeResult<AcmeReply> {
+        req.ensure_post_as_get()?;
+        if req.account_id()? != id {
+            return Err(AcmeError::unauthorized("not the requesting account"));
+        }
+        let orders: Vec<_> = ledger
+            .find_acme_orders(id)
+            .await?
+            .iter()
+            .map(|order| self.order_url(&order.id))
+            .collect();
+        Ok(AcmeReply::json(
+            Status::Ok,
+            None,
+            json!({ "orders": orders }),
+        ))
+    }
+
+    fn order_json(&self, order: &Order) -> Value {
+        let mut body = json!({
+            "status": order.status,
+            "expires": rfc3339(order.expires_unix),
+            "identifiers": order.identifiers,
+            "authorizations": order
+                .authz_ids
+                .iter()
+                .map(|a| self.url(&format!("/authz/{a}")))
+                .collect::<Vec<_>>(),
+            "finalize": self.url(&format!("/order/{}/finalize", order.id)),
+        });
+        if order.certificate.is_some() {
+            body["certificate"] = json!(self.url(&format!("/cert/{}", order.id)));
+        }
+        body
+    }
+
+    /// The requesting account and its binding, refused once the binding has
+    /// expired.
+    async fn bound_account(
+        &self,
+        req: &Verified,
+        ledger: &Ledger,
+    ) -> AcmeResult<(String, Binding)> {
+        let id = req.account_id()?;
+        let account = find_account(ledger, id).await?;
+        if account.bound_until <= unix_now() {
+            return Err(AcmeError::unauthorized(
+                "account binding expired; register again with a fresh EAB key",
+            ));
+        }
+        Ok((account.id, account.binding))
+    }
+
+    /// newOrder: accept DNS identifiers the bound caller's certificate may
+    /// carry, with authorizations that are already valid.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_order(
+        &self,
+        req: Verified,
+        profile: &SigningProfile,
+        profiles: &CertProfiles,
+        oidc: &OidcState,
+        access: &AccessPolicy,
+        ledger: &Ledger,
+    ) -> AcmeResult<AcmeReply> {
+        let payload: NewOrderPayload = req.json()?;
+        let (account_id, binding) = self.bound_account(&req, ledger).await?;
+        let principal = principal(&binding, oidc, access)?;
+        if payload.identifiers.is_empty() {
+            return Err(AcmeError::malformed("an order needs identifiers"));
+        }
+        let cert_profile = profiles.select(
+            binding.profile.as_deref(),
+            &binding.claims,
+            principal.is_admin,
+        )?;
+        let realm = extract_realm_from_issuer(&binding.claims.iss);
+        let identity = cert_profile.apply(profile).naming.render(&NamingContext {
+            claims: &binding.claims,
+            realm: realm.as_deref(),
+            wazuh_agent_name: binding.wazuh_agent_name.as_deref(),
+        })?;
+        for identifier in &payload.identifiers {
+            if identifier.kind != "dns" {
+                return Err(AcmeError::new(
+                    ProblemType::UnsupportedIdentifier,
+                    format!("identifier type {} is not supported", identifier.kind),
+                ));
+            }
+            let name = &identifier.value;
+            if !identity.san.contains(&SanEntry::Dns(name.clone()))
+                && !cert_profile.allows_dns(name)
+            {
+                return Err(AcmeError::new(
+                    ProblemType::RejectedIdentifier,
+                    format!("{name} is not authorized for this account"),
+                ));
+            }
+        }
+
+        let expires_unix = unix_now() + ORDER_LIFETIME_SECS;
+        let mut identifiers = payload.identifiers;
+        identifiers.sort_by(|a, b| a.value.cmp(&b.value));
+        identifiers.dedup();
+        let authzs: Vec<_> = identifiers
+            .iter()
+            .map(|identifier| Authz {
+                id: random_id(),
+                account_id: account_id.clone(),
+                identifier: identifier.clone(),
+                expires_unix,
+            })
+            .collect();
+        let order = Order {
+            id: random_id(),
+            account_id,
+            status: OrderStatus::Ready,
+            expires_unix,
+            identifiers,
+            authz_ids: authzs.iter().map(|authz| authz.id.clone()).collect(),
+            certificate: None,
+        };
+        ledger.purge_acme().await?;
+        ledger.record_acme_order(order.clone(), authzs).await?;
+        Ok(AcmeReply::json(
+            Status::Created,
+            Some(self.order_url(&order.id)),
+            self.order_json(&order),
+        ))
+    }
+
+    /// Order `id` of the requesting account, unless it has expired.
+    async fn own_order(&self, req: &Verified, id: &str, ledger: &Ledger) -> AcmeResult<Order> {
+        let account_id = req.account_id()?;
+        ledger
+            .find_acme_order(id)
+            .await?
+            .filter(|order| order.account_id == account_id && order.expires_unix > unix_now())
+            .ok_or_else(|| AcmeError::malformed("unknown order"))
+    }
+
+    pub async fn order(&self, req: Verified, id: &str, ledger: &Ledger) -> AcmeResult<AcmeReply> {
+        req.ensure_post_as_get()?;
+        let order = self.own_order(&req, id, ledger).await?;
+        Ok(AcmeReply::json(Status::Ok, None, self.order_json(&order)))
+    }
+
+    pub async fn authz(&self, req: Verified, id: &str, ledger: &Ledger) -> AcmeResult<AcmeReply> {
+        req.ensure_post_as_get()?;
+        let account_id = req.account_id()?;
+        let authz = ledger
+            .find_acme_authz(id)
+            .await?
+            .filter(|authz| authz.account_id == account_id && authz.expires_unix > unix_now())
+            .ok_or_else(|| AcmeError::malformed("unknown authorization"))?;
+        Ok(AcmeReply::json(
+            Status::Ok,
+            None,
+            json!({
+                "status": "valid",
+                "expires": rfc3339(authz.expires_unix),
+                "identifier": authz.identifier,
+                "challenges": [],
+            }),
+        ))
+    }
+
+    /// finalize: sign the CSR for the order's identifiers as the bound
+    /// caller, refused if its issuer is no longer trusted or its enrollments
+    /// now need approval.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn finalize(
+        &self,
+        req: Verified,
+        id: &str,
+        client: &ClientInfo,
+        profile: &SigningProfile,
+        profiles: &CertProfiles,
+        oidc: &OidcState,
+        access: &AccessPolicy,
+        approval: &ApprovalPolicy,
+        ca: &CaProvider,
+        ledger: &Ledger,
+        crl: &CrlState,
+        webhook: Option<&WebhookNotifier>,
+        hook: Option<&PolicyHook>,
+    ) -> AcmeResult<AcmeReply> {
+        let payload: FinalizePayload = req.json()?;
+        let (_, binding) = self.bound_account(&req, ledger).await?;
+        let principal = principal(&binding, oidc, access)?;
+        if approval.requires_approval(&principal.claims, principal.is_admin) {
+            return Err(AcmeError::unauthorized(
+                "enrollments of this caller need admin approval; use /api/register-agent",
+            ));
+        }
+        let not_ready = || {
+            AcmeError::new(
+                ProblemType::OrderNotReady,
+                "order is not ready for finalization",
+            )
+        };
+        let mut order = self.own_order(&req, id, ledger).await?;
+        if order.status != OrderStatus::Ready {
+            return Err(not_ready());
+        }
+        // Claim the order atomically: only one finalization signs it.
+        order.status = OrderStatus::Processing;
+        ledger
+            .update_acme_order(order.clone(), OrderStatus::Ready)
+            .await
+            .map_err(|e| match e {
+                AppError::Conflict(_) => not_ready(),
+                e => e.into(),
+            })?;
+
+        let identifiers = &order.identifiers;
+        let result = async {
+            let csr = X509Req::from_der(&b64url_decode(&payload.csr)?)
+                .map_err(|_| AcmeError::new(ProblemType::BadCsr, "CSR is not valid DER"))?;
+            let mut requested = requested_dns_names(&csr)?;
+            requested.sort();
+            requested.dedup();
+            let ordered: Vec<_> = identifiers.iter().map(|i| i.value.clone()).collect();
+            if requested != ordered {
+                return Err(AcmeError::new(
+                    ProblemType::BadCsr,
+                    "CSR DNS names do not match the order identifiers",
+                ));
+            }
+            let dto = SignCsrRequest {
+                csr_pem: String::from_utf8(csr.to_pem()?)
+                    .map_err(|_| AcmeError::new(ProblemType::BadCsr, "CSR is not valid"))?,
+                overwrite: Some(true),
+                wazuh_agent_name: binding.wazuh_agent_name.clone(),
+                profile: binding.profile.clone(),
+                device_id: binding.device_id.clone(),
+            };
+            // The enrollment rules apply as configured now; the token's
+            // `auth_time` was checked when the EAB key was minted.
+            let profile = SigningProfile {
+                enrollment_rules: profile.enrollment_rules.deferred(),
+                ..profile.clone()
+            };
+            let signed = sign_csr(
+                dto, principal, client, &profile, profiles, ca, ledger, crl, webhook, hook,
+            )
+            .await?;
+            Ok(signed.full_chain_pem.unwrap_or(signed.certificate_pem))
+        }
+        .await;
+
+        let result = match result {
+            Ok(chain) => {
+                order.status = OrderStatus::Valid;
+                order.certificate = Some(chain);
+                Ok(())
+            }
+            Err(err) => {
+                order.status = OrderStatus::Invalid;
+                Err(err)
+            }
+        };
+        ledger
+            .update_acme_order(order.clone(), OrderStatus::Processing)
+            .await?;
+        result?;
+        Ok(AcmeReply::json(
+            Status::Ok,
+            Some(self.order_url(id)),
+            self.order_json(&order),
+        ))
+    }
+
+    /// Certificate chain of a valid order.
+    pub async fn certificate(
+        &self,
+        req: Verified,
+        id: &str,
+        ledger: &Ledger,
+    ) -> AcmeResult<AcmeReply> {
+        req.ensure_post_as_get()?;
+        let chain = self
+            .own_order(&req, id, ledger)
+            .await?
+            .certificate
+            .ok_or_else(|| AcmeError::malformed("order has no certificate"))?;
+        Ok(AcmeReply {
+            status: Status::Ok,
+            location: None,
+            body: ReplyBody::PemChain(chain),
+        })
+    }
+
+    /// revokeCert, signed by the bound account of the certificate's subject,
+    /// or by the certificate's own key. Admins revoke through the API.
+    pub async fn revoke_cert(
+        &self,
+        req: Verified,
+        ca: &CaProvider,
+        ledger: &Ledger,
+        crl: &CrlState,
+    ) -> AcmeResult<AcmeReply> {
+        let payload: RevokePayload = req.json()?;
+        let cert = X509::from_der(&b64url_decode(&payload.certificate)?)
+            .map_err(|_| AcmeError::malformed("certificate is not valid DER"))?;
+        let ours = ca.issuers().await?.iter().any(|issuer| {
+            issuer
+                .cert
+                .public_key()
+                .and_then(|key| cert.verify(&key))
+                .unwrap_or(false)
+        });
+        if !ours {
+            return Err(AcmeError::unauthorized(
+                "certificate was not issued by this CA",
+            ));
+        }
+        let serial_hex = cert.serial_number().to_bn()?.to_hex_str()?.to_string();
+        let entry = ledger
+            .find_by_serial(&serial_hex)
+            .await?
+            .ok_or_else(|| AcmeError::unauthorized("certificate is not in the ledger"))?;
+        let authorized = match &req.key {
+            RequestKey::Account(id) => {
+                let account = find_account(ledger, id).await?;
+                account.bound_until > unix_now()
+                    && entry.issued_to(&account.binding.claims.iss, &account.binding.claims.sub)
+            }
+            RequestKey::Jwk(jwk) => {
+                let cert_key = cert.public_key()?;
+                jwk_to_pkey(jwk)?.public_eq(&cert_key)
+            }
+        };
+        if !authorized {
+            return Err(AcmeError::unauthorized(
+                "not authorized to revoke this certificate",
+            ));
+        }
+        if entry.revoked {
+            return Err(AcmeError::new(
+                ProblemType::AlreadyRevoked,
+                "certificate is already revoked",
+            ));
+        }
+        let reason = match payload.reason {
+            None => None,
+            Some(code) => match RevocationReason::from_code(code) {
+                Some(reason) if reason != RevocationReason::RemoveFromCrl => Some(reason),
+                _ => {
+                    return Err(AcmeError::new(
+                        ProblemType::BadRevocationReason,
+                        format!("unsupported revocation reason {code}"),
+                    ));
+                }
+            },
+        };
+        info!(serial = %entry.serial_hex, subject = %entry.subject, "ACME revocation");
+        ledger
+            .mark_revoked(
+                entry.serial_hex,
+                reason.map(|r| r.as_str().to_string()),
+                None,
+            )
+            .await?;
+        crl.rebuild_all(ca, ledger).await?;
+        Ok(AcmeReply::empty(Status::Ok))
+    }
+}
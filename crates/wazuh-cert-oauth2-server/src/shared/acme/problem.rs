@@ -0,0 +1,151 @@
+//! ACME error documents (RFC 8555 §6.7, RFC 7807).
+
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde_json::json;
+use std::io::Cursor;
+use tracing::{error, warn};
+use wazuh_cert_oauth2_model::models::errors::AppError;
+
+use super::with_acme_headers;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemType {
+    AccountDoesNotExist,
+    AlreadyRevoked,
+    BadCsr,
+    BadNonce,
+    BadPublicKey,
+    BadRevocationReason,
+    BadSignatureAlgorithm,
+    ExternalAccountRequired,
+    Malformed,
+    OrderNotReady,
+    RejectedIdentifier,
+    ServerInternal,
+    Unauthorized,
+    UnsupportedIdentifier,
+}
+
+impl ProblemType {
+    fn urn(self) -> &'static str {
+        match self {
+            Self::AccountDoesNotExist => "urn:ietf:params:acme:error:accountDoesNotExist",
+            Self::AlreadyRevoked => "urn:ietf:params:acme:error:alreadyRevoked",
+            Self::BadCsr => "urn:ietf:params:acme:error:badCSR",
+            Self::BadNonce => "urn:ietf:params:acme:error:badNonce",
+            Self::BadPublicKey => "urn:ietf:params:acme:error:badPublicKey",
+            Self::BadRevocationReason => "urn:ietf:params:acme:error:badRevocationReason",
+            Self::BadSignatureAlgorithm => "urn:ietf:params:acme:error:badSignatureAlgorithm",
+            Self::ExternalAccountRequired => "urn:ietf:params:acme:error:externalAccountRequired",
+            Self::Malformed => "urn:ietf:params:acme:error:malformed",
+            Self::OrderNotReady => "urn:ietf:params:acme:error:orderNotReady",
+            Self::RejectedIdentifier => "urn:ietf:params:acme:error:rejectedIdentifier",
+            Self::ServerInternal => "urn:ietf:params:acme:error:serverInternal",
+            Self::Unauthorized => "urn:ietf:params:acme:error:unauthorized",
+            Self::UnsupportedIdentifier => "urn:ietf:params:acme:error:unsupportedIdentifier",
+        }
+    }
+
+    fn status(self) -> Status {
+        match self {
+            Self::Unauthorized | Self::OrderNotReady => Status::Forbidden,
+            Self::ServerInternal => Status::InternalServerError,
+            _ => Status::BadRequest,
+        }
+    }
+}
+
+/// A problem document returned to the ACME client.
+#[derive(Debug)]
+pub struct AcmeError {
+    pub kind: ProblemType,
+    pub detail: String,
+}
+
+pub type AcmeResult<T> = Result<T, AcmeError>;
+
+impl AcmeError {
+    pub fn new(kind: ProblemType, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn malformed(detail: impl Into<String>) -> Self {
+        Self::new(ProblemType::Malformed, detail)
+    }
+
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Self::new(ProblemType::Unauthorized, detail)
+    }
+
+    fn internal(err: impl std::fmt::Display) -> Self {
+        error!("ACME request failed: {}", err);
+        Self::new(ProblemType::ServerInternal, "An internal error occurred")
+    }
+}
+
+/// Errors of the shared signing and ledger code, as ACME problems.
+impl From<AppError> for AcmeError {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::CsrMissingPublicKey
+            | AppError::CsrVerificationFailed
+            | AppError::CsrTooLarge { .. }
+            | AppError::CsrInvalidPem
+            | AppError::CsrMissingCommonName
+            | AppError::CsrMultipleCommonNames
+            | AppError::CsrCommonNameMismatch { .. }
+            | AppError::CsrMalformedExtensions
+            | AppError::CsrRequestsCa
+            | AppError::CsrExtensionNotAllowed { .. }
+            | AppError::CsrSanNotAllowed { .. }
+            | AppError::KeyPolicyRsaTooSmall { .. }
+            | AppError::KeyPolicyRsaTooLarge { .. }
+            | AppError::KeyPolicyUnsupportedEcCurve { .. }
+            | AppError::KeyPolicyUnknownEcCurve
+            | AppError::KeyPolicyUnsupportedKeyType { .. } => {
+                Self::new(ProblemType::BadCsr, err.to_string())
+            }
+            AppError::Forbidden(_) | AppError::Conflict(_) => Self::unauthorized(err.to_string()),
+            AppError::ValidationError(_) | AppError::UnknownCertProfile { .. } => {
+                Self::malformed(err.to_string())
+            }
+            err => Self::internal(err),
+        }
+    }
+}
+
+impl From<openssl::error::ErrorStack> for AcmeError {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl From<serde_json::Error> for AcmeError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for AcmeError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        warn!("ACME problem {}: {}", self.kind.urn(), self.detail);
+        let status = self.kind.status();
+        let body = json!({
+            "type": self.kind.urn(),
+            "detail": self.detail,
+            "status": status.code,
+        })
+        .to_string();
+        let mut res = Response::build();
+        res.status(status)
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(body.len(), Cursor::new(body));
+        with_acme_headers(&mut res, req);
+        res.ok()
+    }
+}
@@ -0,0 +1,269 @@
+//! Signing with keys OpenSSL has no handle for.
+//!
+//! OpenSSL only signs certificates, CRLs and OCSP responses with an
+//! `EVP_PKEY`, so for
+//! external signers the object is first signed with a throwaway key of the
+//! CA's key type. That fills in the signature AlgorithmIdentifier; the
+//! to-be-signed bytes are then sent to the signer and the placeholder
+//! signature is replaced with the real one, which is verified against the
+//! CA certificate before anything is returned.
+
+use std::ffi::{c_int, c_uchar};
+use std::sync::OnceLock;
+
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::ocsp::OcspBasicResponseRef;
+use openssl::pkey::{Id as PKeyId, PKey, PKeyRef, Private, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use openssl::x509::{X509, X509Builder, X509Ref};
+use openssl_sys as ffi;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::CaSigner;
+
+unsafe extern "C" {
+    fn i2d_re_X509_tbs(x: *mut ffi::X509, pp: *mut *mut c_uchar) -> c_int;
+    fn i2d_re_X509_CRL_tbs(crl: *mut ffi::X509_CRL, pp: *mut *mut c_uchar) -> c_int;
+    fn X509_CRL_get0_signature(
+        crl: *const ffi::X509_CRL,
+        psig: *mut *const ffi::ASN1_BIT_STRING,
+        palg: *mut *const ffi::X509_ALGOR,
+    );
+    fn ASN1_BIT_STRING_set(s: *mut ffi::ASN1_BIT_STRING, d: *mut c_uchar, len: c_int) -> c_int;
+    fn OCSP_basic_sign(
+        brsp: *mut ffi::OCSP_BASICRESP,
+        signer: *mut ffi::X509,
+        key: *mut ffi::EVP_PKEY,
+        dgst: *const ffi::EVP_MD,
+        certs: *mut ffi::stack_st_X509,
+        flags: std::ffi::c_ulong,
+    ) -> c_int;
+    fn OCSP_basic_add1_cert(resp: *mut ffi::OCSP_BASICRESP, cert: *mut ffi::X509) -> c_int;
+    fn OCSP_resp_get0_respdata(bs: *const ffi::OCSP_BASICRESP) -> *const OCSP_RESPDATA;
+    fn OCSP_resp_get0_signature(bs: *const ffi::OCSP_BASICRESP) -> *const ffi::ASN1_BIT_STRING;
+    fn i2d_OCSP_RESPDATA(a: *const OCSP_RESPDATA, pp: *mut *mut c_uchar) -> c_int;
+}
+
+#[allow(non_camel_case_types)]
+enum OCSP_RESPDATA {}
+
+/// Sign a certificate with the CA key behind `signer`.
+pub(crate) fn sign_certificate(
+    mut builder: X509Builder,
+    signer: &dyn CaSigner,
+    ca_cert: &X509Ref,
+) -> AppResult<X509> {
+    let digest = MessageDigest::sha256();
+    if let Some(key) = signer.pkey() {
+        builder.sign(key, digest)?;
+        return Ok(builder.build());
+    }
+
+    let ca_pub = ca_cert.public_key()?;
+    builder.sign(placeholder_key(&ca_pub)?, digest)?;
+    let cert = builder.build();
+    // SAFETY: `cert` is a valid, exclusively owned X509. The signature bit
+    // string is owned by it and only replaced in place.
+    unsafe {
+        let tbs = i2d_to_vec(|pp| i2d_re_X509_tbs(cert.as_ptr(), pp), "i2d_re_X509_tbs")?;
+        let signature = signer.sign(digest, &tbs)?;
+        let mut sig: *const ffi::ASN1_BIT_STRING = std::ptr::null();
+        ffi::X509_get0_signature(&mut sig, std::ptr::null_mut(), cert.as_ptr());
+        set_bit_string(sig, &signature)?;
+    }
+
+    // Re-parse so the cached encoding carries the new signature.
+    let cert = X509::from_der(&cert.to_der()?)?;
+    if !cert.verify(&ca_pub)? {
+        return Err(AppError::UpstreamError(
+            "external CA signer returned an invalid certificate signature".into(),
+        ));
+    }
+    Ok(cert)
+}
+
+/// Sign a CRL in place with the CA key behind `signer`.
+///
+/// # Safety
+/// `crl` must be a valid `X509_CRL` not shared with other threads.
+pub(crate) unsafe fn sign_crl(
+    crl: *mut ffi::X509_CRL,
+    signer: &dyn CaSigner,
+    ca_cert: &X509Ref,
+) -> AppResult<()> {
+    unsafe {
+        let md = ffi::EVP_sha256();
+        if let Some(key) = signer.pkey() {
+            if ffi::X509_CRL_sign(crl, key.as_ptr(), md) == 0 {
+                return Err(AppError::CrlFfi {
+                    func: "X509_CRL_sign",
+                });
+            }
+            return Ok(());
+        }
+
+        let ca_pub = ca_cert.public_key()?;
+        if ffi::X509_CRL_sign(crl, placeholder_key(&ca_pub)?.as_ptr(), md) == 0 {
+            return Err(AppError::CrlFfi {
+                func: "X509_CRL_sign",
+            });
+        }
+        let tbs = i2d_to_vec(|pp| i2d_re_X509_CRL_tbs(crl, pp), "i2d_re_X509_CRL_tbs")?;
+        let signature = signer.sign(MessageDigest::sha256(), &tbs)?;
+        let mut sig: *const ffi::ASN1_BIT_STRING = std::ptr::null();
+        X509_CRL_get0_signature(crl, &mut sig, std::ptr::null_mut());
+        set_bit_string(sig, &signature)?;
+
+        if ffi::X509_CRL_verify(crl, ca_pub.as_ptr()) != 1 {
+            return Err(AppError::UpstreamError(
+                "external CA signer returned an invalid CRL signature".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Sign a basic OCSP response in place as `signer_cert`.
+///
+/// The responder is identified by name, and `signer_cert` is embedded when
+/// `include_cert` is set (delegated responders). With an external signer the
+/// placeholder signature comes from a throwaway certificate carrying the
+/// same subject, so the ResponderID is already correct when it is replaced.
+pub(crate) fn sign_ocsp(
+    bs: &OcspBasicResponseRef,
+    signer: &dyn CaSigner,
+    signer_cert: &X509Ref,
+    include_cert: bool,
+) -> AppResult<()> {
+    let flags = ffi::OCSP_NOCERTS;
+    let digest = MessageDigest::sha256();
+    // SAFETY: `bs` is exclusively owned by the caller while it is signed;
+    // OCSP_basic_sign copies everything it keeps from the certificates.
+    unsafe {
+        if let Some(key) = signer.pkey() {
+            if OCSP_basic_sign(
+                bs.as_ptr(),
+                signer_cert.as_ptr(),
+                key.as_ptr(),
+                digest.as_ptr(),
+                std::ptr::null_mut(),
+                flags,
+            ) != 1
+            {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_basic_sign",
+                });
+            }
+        } else {
+            let ca_pub = signer_cert.public_key()?;
+            let key = placeholder_key(&ca_pub)?;
+            let mut builder = X509::builder()?;
+            builder.set_subject_name(signer_cert.subject_name())?;
+            builder.set_issuer_name(signer_cert.subject_name())?;
+            builder.set_pubkey(key)?;
+            builder.sign(key, digest)?;
+            let placeholder = builder.build();
+            if OCSP_basic_sign(
+                bs.as_ptr(),
+                placeholder.as_ptr(),
+                key.as_ptr(),
+                digest.as_ptr(),
+                std::ptr::null_mut(),
+                flags,
+            ) != 1
+            {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_basic_sign",
+                });
+            }
+            let tbs = i2d_to_vec(
+                |pp| i2d_OCSP_RESPDATA(OCSP_resp_get0_respdata(bs.as_ptr()), pp),
+                "i2d_OCSP_RESPDATA",
+            )?;
+            let signature = signer.sign(digest, &tbs)?;
+            let mut verifier = Verifier::new(digest, &ca_pub)?;
+            verifier.update(&tbs)?;
+            if !verifier.verify(&signature)? {
+                return Err(AppError::UpstreamError(
+                    "external CA signer returned an invalid OCSP signature".into(),
+                ));
+            }
+            set_bit_string(OCSP_resp_get0_signature(bs.as_ptr()), &signature)?;
+        }
+        if include_cert && OCSP_basic_add1_cert(bs.as_ptr(), signer_cert.as_ptr()) != 1 {
+            return Err(AppError::CrlFfi {
+                func: "OCSP_basic_add1_cert",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Throwaway key of the CA's key type, generated once per type.
+///
+/// Only the signature AlgorithmIdentifier it produces matters, and that does
+/// not depend on the RSA modulus size or EC curve.
+fn placeholder_key(ca_pub: &PKeyRef<Public>) -> AppResult<&'static PKey<Private>> {
+    static RSA: OnceLock<PKey<Private>> = OnceLock::new();
+    static EC: OnceLock<PKey<Private>> = OnceLock::new();
+
+    let cell = match ca_pub.id() {
+        PKeyId::RSA => &RSA,
+        PKeyId::EC => &EC,
+        other => {
+            return Err(AppError::ValidationError(format!(
+                "unsupported CA key type for external signing: {other:?}"
+            )));
+        }
+    };
+    if let Some(key) = cell.get() {
+        return Ok(key);
+    }
+    let key = match ca_pub.id() {
+        PKeyId::RSA => PKey::from_rsa(Rsa::generate(2048)?)?,
+        _ => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            PKey::from_ec_key(EcKey::generate(&group)?)?
+        }
+    };
+    Ok(cell.get_or_init(|| key))
+}
+
+unsafe fn i2d_to_vec(
+    encode: impl FnOnce(*mut *mut c_uchar) -> c_int,
+    func: &'static str,
+) -> AppResult<Vec<u8>> {
+    unsafe {
+        let mut buf: *mut c_uchar = std::ptr::null_mut();
+        let len = encode(&mut buf);
+        if len <= 0 || buf.is_null() {
+            return Err(AppError::CrlFfi { func });
+        }
+        let out = std::slice::from_raw_parts(buf, len as usize).to_vec();
+        ffi::OPENSSL_free(buf as *mut _);
+        Ok(out)
+    }
+}
+
+unsafe fn set_bit_string(sig: *const ffi::ASN1_BIT_STRING, bytes: &[u8]) -> AppResult<()> {
+    unsafe {
+        // SAFETY: the getters only hand out const pointers, but the bit
+        // string belongs to an object we own exclusively.
+        if sig.is_null()
+            || ASN1_BIT_STRING_set(
+                sig as *mut _,
+                bytes.as_ptr() as *mut _,
+                bytes.len() as c_int,
+            ) != 1
+        {
+            return Err(AppError::CrlFfi {
+                func: "ASN1_BIT_STRING_set",
+            });
+        }
+        Ok(())
+    }
+}
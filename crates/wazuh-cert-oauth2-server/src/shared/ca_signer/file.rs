@@ -0,0 +1,35 @@
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use wazuh_cert_oauth2_model::models::errors::AppResult;
+
+use super::{CaSigner, public_half, sign_with_pkey};
+
+/// CA key held in memory, read from a PEM file.
+pub struct FileSigner {
+    key: PKey<Private>,
+}
+
+impl FileSigner {
+    pub fn new(key: PKey<Private>) -> Self {
+        Self { key }
+    }
+
+    pub async fn load(path: &str) -> AppResult<Self> {
+        let pem = tokio::fs::read(path).await?;
+        Ok(Self::new(PKey::private_key_from_pem(&pem)?))
+    }
+}
+
+impl CaSigner for FileSigner {
+    fn pkey(&self) -> Option<&PKeyRef<Private>> {
+        Some(&self.key)
+    }
+
+    fn public_key(&self) -> Option<PKey<Public>> {
+        public_half(&self.key)
+    }
+
+    fn sign(&self, digest: MessageDigest, tbs: &[u8]) -> AppResult<Vec<u8>> {
+        sign_with_pkey(&self.key, digest, tbs)
+    }
+}
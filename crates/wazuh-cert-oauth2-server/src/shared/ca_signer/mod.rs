@@ -0,0 +1,72 @@
+//! CA signing backends.
+//!
+//! Certificates, CRLs and OCSP responses are signed through [`CaSigner`] so the CA key can
+//! live in a file (default), a PKCS#11 token, or a separate signing process.
+//! The backend is chosen from the `ROOT_CA_KEY_PATH` value by [`load_signer`].
+
+use std::sync::Arc;
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use openssl::sign::Signer;
+use wazuh_cert_oauth2_model::models::errors::AppResult;
+
+mod external;
+mod file;
+mod pkcs11;
+#[cfg(unix)]
+mod unix_socket;
+
+pub(crate) use external::{sign_certificate, sign_crl, sign_ocsp};
+pub use file::FileSigner;
+pub use pkcs11::Pkcs11Signer;
+#[cfg(unix)]
+pub use unix_socket::UnixSocketSigner;
+
+/// A CA private key able to sign certificates, CRLs and OCSP responses.
+pub trait CaSigner: Send + Sync {
+    /// In-process OpenSSL handle for the key, when the backend has one.
+    ///
+    /// File keys and PKCS#11 keys loaded through an OpenSSL provider return
+    /// `Some` and are signed natively; otherwise [`Self::sign`] is used.
+    fn pkey(&self) -> Option<&PKeyRef<Private>> {
+        None
+    }
+
+    /// Public half of the key, used to find the issuing CA in a chain file.
+    /// `None` means the first certificate of the file is the issuing CA.
+    fn public_key(&self) -> Option<PKey<Public>>;
+
+    /// Sign `tbs` (DER to-be-signed bytes) and return the raw signature:
+    /// PKCS#1 v1.5 for RSA keys, a DER `ECDSA-Sig-Value` for EC keys.
+    fn sign(&self, digest: MessageDigest, tbs: &[u8]) -> AppResult<Vec<u8>>;
+}
+
+/// Build the signer described by `spec`.
+///
+/// - `pkcs11:...` — RFC 7512 URI of a key reachable through an OpenSSL 3
+///   PKCS#11 provider configured via `OPENSSL_CONF`.
+/// - `unix:/path/to.sock` — external signing process, see [`UnixSocketSigner`].
+/// - anything else — path to a PEM private key.
+pub async fn load_signer(spec: &str) -> AppResult<Arc<dyn CaSigner>> {
+    if spec.starts_with("pkcs11:") {
+        return Ok(Arc::new(Pkcs11Signer::load(spec)?));
+    }
+    #[cfg(unix)]
+    if let Some(path) = spec.strip_prefix("unix:") {
+        return Ok(Arc::new(UnixSocketSigner::new(path)));
+    }
+    Ok(Arc::new(FileSigner::load(spec).await?))
+}
+
+fn sign_with_pkey(key: &PKeyRef<Private>, digest: MessageDigest, tbs: &[u8]) -> AppResult<Vec<u8>> {
+    let mut signer = Signer::new(digest, key)?;
+    signer.update(tbs)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn public_half(key: &PKeyRef<Private>) -> Option<PKey<Public>> {
+    key.public_key_to_der()
+        .and_then(|der| PKey::public_key_from_der(&der))
+        .ok()
+}
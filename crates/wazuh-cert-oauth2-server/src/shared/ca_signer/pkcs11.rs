@@ -0,0 +1,136 @@
+use std::ffi::{CString, c_char, c_int, c_void};
+
+use foreign_types::ForeignType;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use openssl_sys as ffi;
+use tracing::info;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::{CaSigner, public_half, sign_with_pkey};
+
+#[allow(non_camel_case_types)]
+enum OSSL_STORE_CTX {}
+#[allow(non_camel_case_types)]
+enum OSSL_STORE_INFO {}
+
+const OSSL_STORE_INFO_PKEY: c_int = 4;
+
+unsafe extern "C" {
+    fn OSSL_STORE_open(
+        uri: *const c_char,
+        ui_method: *const c_void,
+        ui_data: *mut c_void,
+        post_process: *const c_void,
+        post_process_data: *mut c_void,
+    ) -> *mut OSSL_STORE_CTX;
+    fn OSSL_STORE_load(ctx: *mut OSSL_STORE_CTX) -> *mut OSSL_STORE_INFO;
+    fn OSSL_STORE_eof(ctx: *mut OSSL_STORE_CTX) -> c_int;
+    fn OSSL_STORE_close(ctx: *mut OSSL_STORE_CTX) -> c_int;
+    fn OSSL_STORE_INFO_get_type(info: *const OSSL_STORE_INFO) -> c_int;
+    fn OSSL_STORE_INFO_get1_PKEY(info: *const OSSL_STORE_INFO) -> *mut ffi::EVP_PKEY;
+    fn OSSL_STORE_INFO_free(info: *mut OSSL_STORE_INFO);
+}
+
+/// CA key kept in a PKCS#11 token and used through an OpenSSL provider.
+///
+/// The key never leaves the token: OpenSSL holds a handle and forwards
+/// signing operations to it. The provider (e.g. `pkcs11-provider`) and the
+/// module path come from `OPENSSL_CONF`; the PIN can be given in the URI
+/// (`pin-value=` or `pin-source=`).
+pub struct Pkcs11Signer {
+    key: PKey<Private>,
+}
+
+impl Pkcs11Signer {
+    pub fn load(uri: &str) -> AppResult<Self> {
+        let c_uri = CString::new(uri)
+            .map_err(|_| AppError::ValidationError("PKCS#11 URI contains NUL".into()))?;
+        let not_found = || AppError::UpstreamError("no private key found at PKCS#11 URI".into());
+        unsafe {
+            let ctx = OSSL_STORE_open(
+                c_uri.as_ptr(),
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null_mut(),
+            );
+            if ctx.is_null() {
+                return Err(AppError::UpstreamError(format!(
+                    "failed to open PKCS#11 store: {}",
+                    openssl::error::ErrorStack::get()
+                )));
+            }
+            let mut key = None;
+            while key.is_none() && OSSL_STORE_eof(ctx) == 0 {
+                let info = OSSL_STORE_load(ctx);
+                if info.is_null() {
+                    continue;
+                }
+                if OSSL_STORE_INFO_get_type(info) == OSSL_STORE_INFO_PKEY {
+                    let pkey = OSSL_STORE_INFO_get1_PKEY(info);
+                    if !pkey.is_null() {
+                        key = Some(PKey::<Private>::from_ptr(pkey));
+                    }
+                }
+                OSSL_STORE_INFO_free(info);
+            }
+            OSSL_STORE_close(ctx);
+            let key = key.ok_or_else(not_found)?;
+            info!("loaded CA key from PKCS#11 token");
+            Ok(Self { key })
+        }
+    }
+}
+
+impl CaSigner for Pkcs11Signer {
+    fn pkey(&self) -> Option<&PKeyRef<Private>> {
+        Some(&self.key)
+    }
+
+    fn public_key(&self) -> Option<PKey<Public>> {
+        public_half(&self.key)
+    }
+
+    fn sign(&self, digest: MessageDigest, tbs: &[u8]) -> AppResult<Vec<u8>> {
+        sign_with_pkey(&self.key, digest, tbs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pkcs11Signer;
+    use crate::shared::ca_signer::{CaSigner, sign_certificate};
+    use openssl::x509::X509;
+
+    /// Sign with a token key (e.g. SoftHSM). Skips unless `TEST_PKCS11_KEY_URI`
+    /// and `TEST_PKCS11_CA_CERT` (PEM of the matching CA certificate) are set.
+    #[test]
+    fn signs_certificate_with_token_key() {
+        let (Ok(uri), Ok(cert_path)) = (
+            std::env::var("TEST_PKCS11_KEY_URI"),
+            std::env::var("TEST_PKCS11_CA_CERT"),
+        ) else {
+            eprintln!("TEST_PKCS11_KEY_URI not set; skipping PKCS#11 test");
+            return;
+        };
+        let signer = Pkcs11Signer::load(&uri).expect("token key should load");
+        let ca = X509::from_pem(&std::fs::read(cert_path).expect("ca cert")).expect("ca pem");
+        assert!(
+            ca.public_key()
+                .expect("ca key")
+                .public_eq(&signer.public_key().expect("public key"))
+        );
+
+        let mut builder = X509::builder().expect("builder");
+        builder.set_issuer_name(ca.subject_name()).expect("issuer");
+        builder
+            .set_subject_name(ca.subject_name())
+            .expect("subject");
+        builder
+            .set_pubkey(&ca.public_key().expect("key"))
+            .expect("pubkey");
+        let cert = sign_certificate(builder, &signer, &ca).expect("sign");
+        assert!(cert.verify(&ca.public_key().expect("key")).expect("verify"));
+    }
+}
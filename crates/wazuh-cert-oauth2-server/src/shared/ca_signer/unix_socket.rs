@@ -0,0 +1,289 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::CaSigner;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// CA key held by a separate signing process listening on a Unix socket.
+///
+/// Each signature is one connection carrying one line of JSON each way:
+///
+/// ```text
+/// -> {"digest":"sha256","tbs":"<base64 DER to-be-signed>"}
+/// <- {"signature":"<base64 signature>"}   or   {"error":"<message>"}
+/// ```
+///
+/// The signer does not expose its public key, so the first certificate in
+/// `ROOT_CA_PATH` is taken as the issuing CA.
+///
+/// Signing is synchronous, so on a multi-threaded runtime the exchange runs
+/// in [`tokio::task::block_in_place`]: the worker hands its other tasks to
+/// the rest of the pool while a slow signer answers.
+pub struct UnixSocketSigner {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct SignRequest<'a> {
+    digest: &'a str,
+    tbs: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: Option<String>,
+    error: Option<String>,
+}
+
+impl UnixSocketSigner {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn round_trip(&self, request: &[u8]) -> std::io::Result<String> {
+        let mut stream = UnixStream::connect(&self.path)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.write_all(request)?;
+        stream.write_all(b"\n")?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        Ok(line)
+    }
+}
+
+impl CaSigner for UnixSocketSigner {
+    fn public_key(&self) -> Option<PKey<Public>> {
+        None
+    }
+
+    fn sign(&self, digest: MessageDigest, tbs: &[u8]) -> AppResult<Vec<u8>> {
+        let digest = match digest.type_() {
+            Nid::SHA256 => "sha256",
+            Nid::SHA384 => "sha384",
+            Nid::SHA512 => "sha512",
+            other => {
+                return Err(AppError::ValidationError(format!(
+                    "unsupported digest for external signer: {other:?}"
+                )));
+            }
+        };
+        let request = serde_json::to_vec(&SignRequest {
+            digest,
+            tbs: B64.encode(tbs),
+        })?;
+        let line = off_runtime(|| self.round_trip(&request)).map_err(|e| {
+            AppError::UpstreamError(format!(
+                "CA signer at {} unavailable: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        let response: SignResponse = serde_json::from_str(&line).map_err(|e| {
+            AppError::UpstreamError(format!("invalid response from CA signer: {}", e))
+        })?;
+        match (response.signature, response.error) {
+            (Some(sig), _) => B64.decode(sig.trim()).map_err(|e| {
+                AppError::UpstreamError(format!("invalid signature from CA signer: {}", e))
+            }),
+            (None, error) => Err(AppError::UpstreamError(format!(
+                "CA signer refused to sign: {}",
+                error.unwrap_or_else(|| "no reason given".into())
+            ))),
+        }
+    }
+}
+
+/// Run the blocking `f` without stalling other tasks of the current runtime.
+fn off_runtime<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as B64;
+    use openssl::hash::MessageDigest;
+    use openssl::ocsp::{OcspCertId, OcspFlag, OcspRequest, OcspResponse};
+    use openssl::pkey::{PKey, Private};
+    use openssl::stack::Stack;
+    use openssl::x509::store::X509StoreBuilder;
+    use openssl::x509::{X509, X509Crl};
+
+    use crate::handlers::test_support::{make_ca, temp_dir};
+    use crate::models::ca_config::CaProvider;
+    use crate::shared::ca_signer::sign_certificate;
+    use crate::shared::crl::{CrlBackend, CrlKind, CrlState, RevocationEntry};
+    use crate::shared::ledger::{Ledger, LedgerBackend};
+    use crate::shared::ocsp::OcspResponder;
+
+    /// Minimal signing process: answers every request with `key` after `delay`.
+    fn spawn_signer(path: &PathBuf, key: PKey<Private>, delay: Duration) {
+        let listener = UnixListener::bind(path).expect("bind");
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("accept");
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).expect("read");
+                std::thread::sleep(delay);
+                let req: serde_json::Value = serde_json::from_str(&line).expect("json");
+                assert_eq!(req["digest"], "sha256");
+                let tbs = B64.decode(req["tbs"].as_str().expect("tbs")).expect("b64");
+                let mut signer =
+                    openssl::sign::Signer::new(MessageDigest::sha256(), &key).expect("signer");
+                signer.update(&tbs).expect("update");
+                let sig = B64.encode(signer.sign_to_vec().expect("sign"));
+                writeln!(stream, "{}", serde_json::json!({ "signature": sig })).expect("write");
+            }
+        });
+    }
+
+    fn leaf_builder(ca: &X509) -> openssl::x509::X509Builder {
+        let (_, leaf_key) = make_ca("leaf", None);
+        let mut builder = X509::builder().expect("builder");
+        builder.set_version(2).expect("version");
+        builder
+            .set_subject_name(ca.subject_name())
+            .expect("subject");
+        builder.set_issuer_name(ca.subject_name()).expect("issuer");
+        builder.set_pubkey(&leaf_key).expect("pubkey");
+        crate::shared::certs::set_validity(&mut builder, 1_700_000_000, 1_800_000_000)
+            .expect("validity");
+        builder
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn certificates_crls_and_ocsp_responses_are_signed_over_the_socket() {
+        let dir = temp_dir("ca-signer-test");
+        let (ca, ca_key) = make_ca("socket-ca", None);
+        let cert_path = dir.join("ca.pem");
+        std::fs::write(&cert_path, ca.to_pem().expect("pem")).expect("write ca");
+        let socket = dir.join("signer.sock");
+        spawn_signer(&socket, ca_key, Duration::ZERO);
+
+        let provider = CaProvider::new(
+            cert_path.display().to_string(),
+            format!("unix:{}", socket.display()),
+            Duration::from_secs(60),
+            None,
+        );
+        let issuer = provider.active().await.expect("provider");
+        assert!(issuer.signer.pkey().is_none());
+
+        let cert = sign_certificate(leaf_builder(&ca), issuer.signer.as_ref(), &issuer.cert)
+            .expect("sign");
+        let ca_pub = ca.public_key().expect("ca pub");
+        assert!(cert.verify(&ca_pub).expect("verify"));
+
+        let crl = CrlState::new(CrlBackend::File(dir.join("crl.der")), None)
+            .await
+            .expect("crl state");
+        let rx = crl.subscribe_rebuild(&issuer, CrlKind::Complete);
+        crl.request_rebuild(
+            issuer.clone(),
+            vec![RevocationEntry {
+                serial_hex: "0A".into(),
+                reason: None,
+                revoked_at_unix: 100,
+                invalidity_date_unix: None,
+            }],
+        )
+        .await
+        .expect("rebuild");
+        let der = rx.borrow().1.clone().expect("crl body");
+        let crl = X509Crl::from_der(&der).expect("crl der");
+        assert!(crl.verify(&ca_pub).expect("crl verify"));
+
+        let ledger = Ledger::new(LedgerBackend::Csv(dir.join("ledger.csv")))
+            .await
+            .expect("ledger");
+        let mut req = OcspRequest::new().expect("ocsp request");
+        req.add_id(OcspCertId::from_cert(MessageDigest::sha1(), &cert, &ca).expect("id"))
+            .expect("add id");
+        let reply = OcspResponder::new(Duration::from_secs(60))
+            .respond(&req.to_der().expect("der"), &provider, &ledger)
+            .await;
+        let basic = OcspResponse::from_der(&reply.der)
+            .expect("ocsp der")
+            .basic()
+            .expect("successful response");
+        let mut store = X509StoreBuilder::new().expect("store");
+        store.add_cert(ca.clone()).expect("add ca");
+        let mut certs = Stack::new().expect("stack");
+        certs.push(ca.clone()).expect("push");
+        basic
+            .verify(&certs, &store.build(), OcspFlag::empty())
+            .expect("ocsp verify");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn signature_from_the_wrong_key_is_rejected() {
+        let dir = temp_dir("ca-signer-test");
+        let (ca, _) = make_ca("socket-ca", None);
+        let (_, other_key) = make_ca("other", None);
+        let socket = dir.join("signer.sock");
+        spawn_signer(&socket, other_key, Duration::ZERO);
+
+        let signer = Arc::new(super::UnixSocketSigner::new(&socket));
+        let err = sign_certificate(leaf_builder(&ca), signer.as_ref(), &ca)
+            .expect_err("foreign signature must be rejected");
+        assert!(err.to_string().contains("invalid certificate signature"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn slow_signer_does_not_stall_other_tasks() {
+        let dir = temp_dir("ca-signer-test");
+        let (ca, ca_key) = make_ca("socket-ca", None);
+        let socket = dir.join("signer.sock");
+        spawn_signer(&socket, ca_key, Duration::from_millis(500));
+
+        let ticks = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = ticks.clone();
+        tokio::spawn(async move {
+            loop {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        tokio::task::yield_now().await;
+
+        // Sign on the runtime's only worker, as a request handler would.
+        let signer = super::UnixSocketSigner::new(&socket);
+        let before = ticks.load(std::sync::atomic::Ordering::SeqCst);
+        tokio::spawn(async move { sign_certificate(leaf_builder(&ca), &signer, &ca).map(|_| ()) })
+            .await
+            .expect("join")
+            .expect("sign");
+        let during = ticks.load(std::sync::atomic::Ordering::SeqCst) - before;
+        assert!(during >= 5, "other tasks ran {during} times while signing");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use rand::TryRng;
+use tracing::{info, warn};
+use unwrap_infallible::UnwrapInfallible;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
+
+use crate::handlers::middle::{ClientInfo, Principal};
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::oidc_state::OidcState;
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::{Ledger, PendingEnrollment};
+use crate::shared::policy_hook::PolicyHook;
+use crate::shared::webhook_notifier::WebhookNotifier;
+
+use super::sign::{check_request, extract_realm_from_issuer, sign_csr_as};
+use super::{new_serial, unix_now};
+
+/// Requests one subject may have awaiting a decision at once.
+const MAX_PENDING_PER_SUBJECT: usize = 5;
+
+/// An approval this old whose certificate was never recorded on the request
+/// was interrupted, and the request can be decided again.
+const STALE_APPROVAL_SECS: u64 = 300;
+
+/// Writes of an approval's outcome before giving up on it.
+const OUTCOME_ATTEMPTS: u32 = 3;
+
+/// Ledger reason of certificates signed by an interrupted approval.
+const INTERRUPTED_REASON: &str = "superseded: enrollment approval interrupted";
+
+fn request_id() -> String {
+    let mut buf = [0u8; 16];
+    rand::rng().try_fill_bytes(&mut buf).unwrap_infallible();
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Store `dto` as an enrollment awaiting an admin's decision instead of
+/// signing it.
+///
+/// The request is checked like [`sign_csr`] would check it, so requests that
+/// could never be signed fail now rather than on approval. The CSR and the
+/// caller's claims are kept to sign it as the caller later. A subject with
+/// [`MAX_PENDING_PER_SUBJECT`] requests awaiting a decision gets
+/// [`AppError::Conflict`].
+pub async fn hold_for_approval(
+    dto: SignCsrRequest,
+    Principal { claims, is_admin }: Principal,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+    ledger: &Ledger,
+    webhook: Option<&WebhookNotifier>,
+) -> AppResult<EnrollmentRequest> {
+    check_request(&dto, &claims, is_admin, profile, profiles)?;
+    let pending = ledger
+        .find_enrollments_by_subject(&claims.sub)
+        .await?
+        .iter()
+        .filter(|e| e.claims.iss == claims.iss && e.request.status == EnrollmentStatus::Pending)
+        .count();
+    if pending >= MAX_PENDING_PER_SUBJECT {
+        return Err(AppError::Conflict(format!(
+            "{pending} enrollment requests already await approval; wait for a decision first"
+        )));
+    }
+    let request = EnrollmentRequest {
+        id: request_id(),
+        subject: claims.sub.clone(),
+        status: EnrollmentStatus::Pending,
+        created_at_unix: unix_now(),
+        realm: extract_realm_from_issuer(&claims.iss),
+        profile: dto.profile,
+        device_id: dto.device_id,
+        wazuh_agent_name: dto.wazuh_agent_name,
+        decided_at_unix: None,
+        decided_by: None,
+        reason: None,
+        serial_hex: None,
+        certificate: None,
+    };
+    ledger
+        .record_enrollment(PendingEnrollment {
+            request: request.clone(),
+            csr_pem: dto.csr_pem,
+            claims,
+            overwrite: dto.overwrite == Some(true),
+        })
+        .await?;
+    info!(id = %request.id, sub = %request.subject, "enrollment held for approval");
+    if let Some(notifier) = webhook {
+        notifier.notify_pending(&request).await;
+    }
+    Ok(request)
+}
+
+/// Whether `request` was approved but its certificate never recorded on it,
+/// long enough ago that the approval cannot still be signing.
+fn interrupted(request: &EnrollmentRequest, now: u64) -> bool {
+    request.status == EnrollmentStatus::Approved
+        && request.certificate.is_none()
+        && request
+            .decided_at_unix
+            .is_some_and(|at| at + STALE_APPROVAL_SECS <= now)
+}
+
+/// Return an interrupted approval to pending. A certificate signed under the
+/// serial the approval recorded was never handed out, so it is revoked first;
+/// the subject's other certificates are left alone.
+async fn reopen(ledger: &Ledger, enrollment: &mut PendingEnrollment) -> AppResult<()> {
+    let request = &enrollment.request;
+    if let Some(serial) = &request.serial_hex
+        && ledger.find_by_serial(serial).await?.is_some()
+    {
+        ledger
+            .mark_revoked(serial.clone(), Some(INTERRUPTED_REASON.to_string()), None)
+            .await?;
+    }
+    let reopened = EnrollmentRequest {
+        status: EnrollmentStatus::Pending,
+        decided_at_unix: None,
+        decided_by: None,
+        reason: None,
+        serial_hex: None,
+        certificate: None,
+        ..request.clone()
+    };
+    ledger
+        .update_enrollment(reopened.clone(), EnrollmentStatus::Approved)
+        .await?;
+    warn!(id = %reopened.id, sub = %reopened.subject, "interrupted approval reopened");
+    enrollment.request = reopened;
+    Ok(())
+}
+
+/// The enrollment `id` if it still awaits a decision, reopening an
+/// interrupted approval.
+async fn find_pending(ledger: &Ledger, id: &str) -> AppResult<PendingEnrollment> {
+    let mut enrollment = ledger
+        .find_enrollment(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("enrollment request {id}")))?;
+    if interrupted(&enrollment.request, unix_now()) {
+        reopen(ledger, &mut enrollment).await?;
+    }
+    if enrollment.request.status != EnrollmentStatus::Pending {
+        return Err(AppError::Conflict(format!(
+            "enrollment request {id} is already {}",
+            enrollment.request.status.as_str()
+        )));
+    }
+    Ok(enrollment)
+}
+
+/// Approve enrollment `id` on behalf of `admin` and sign it.
+///
+/// The stored CSR goes through [`sign_csr`] as the original caller, with the
+/// same rotation and quota rules as an immediate enrollment, and the
+/// certificate is kept on the request for the caller to collect. The caller's
+/// token issuer must still be one of `oidc`'s trusted issuers. The request is
+/// marked approved, together with the serial it will be signed under, before
+/// signing so concurrent decisions conflict; if signing fails it returns to
+/// pending. If the certificate cannot be recorded on the request, the
+/// approval counts as interrupted after [`STALE_APPROVAL_SECS`] and can be
+/// decided again.
+#[allow(clippy::too_many_arguments)]
+pub async fn approve_enrollment(
+    id: &str,
+    admin: &Claims,
+    oidc: &OidcState,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+    ca: &CaProvider,
+    ledger: &Ledger,
+    crl: &CrlState,
+    webhook: Option<&WebhookNotifier>,
+    hook: Option<&PolicyHook>,
+) -> AppResult<EnrollmentRequest> {
+    let PendingEnrollment {
+        request: pending,
+        csr_pem,
+        claims,
+        overwrite,
+    } = find_pending(ledger, id).await?;
+    if oidc.issuer(&claims.iss).is_none() {
+        return Err(AppError::Forbidden(format!(
+            "issuer '{}' of enrollment request {id} is no longer trusted",
+            claims.iss
+        )));
+    }
+    let serial = new_serial()?;
+    let mut request = EnrollmentRequest {
+        status: EnrollmentStatus::Approved,
+        decided_at_unix: Some(unix_now()),
+        decided_by: Some(admin.sub.clone()),
+        serial_hex: Some(serial.to_hex_str()?.to_string()),
+        ..pending.clone()
+    };
+    ledger
+        .update_enrollment(request.clone(), EnrollmentStatus::Pending)
+        .await?;
+
+    let dto = SignCsrRequest {
+        csr_pem,
+        overwrite: Some(overwrite),
+        wazuh_agent_name: pending.wazuh_agent_name.clone(),
+        profile: pending.profile.clone(),
+        device_id: pending.device_id.clone(),
+    };
+    let principal = Principal {
+        claims,
+        is_admin: false,
+    };
+    // The requester's client details are not kept with the request.
+    let client = ClientInfo::default();
+    // The token met the enrollment rules when the request was held.
+    let profile = SigningProfile {
+        enrollment_rules: profile.enrollment_rules.deferred(),
+        ..profile.clone()
+    };
+    let signed = match sign_csr_as(
+        dto,
+        principal,
+        &client,
+        &profile,
+        profiles,
+        ca,
+        ledger,
+        crl,
+        webhook,
+        hook,
+        Some(&serial),
+    )
+    .await
+    {
+        Ok(signed) => signed,
+        Err(e) => {
+            ledger
+                .update_enrollment(pending, EnrollmentStatus::Approved)
+                .await?;
+            return Err(e);
+        }
+    };
+    request.certificate = Some(signed);
+    record_outcome(ledger, &request).await?;
+    info!(id, sub = %request.subject, admin = %admin.sub, "enrollment approved");
+    Ok(request)
+}
+
+/// Store the certificate of approved `request` on it. Writing the same
+/// outcome again is harmless, so failed writes are retried.
+async fn record_outcome(ledger: &Ledger, request: &EnrollmentRequest) -> AppResult<()> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match ledger
+            .update_enrollment(request.clone(), EnrollmentStatus::Approved)
+            .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e @ (AppError::Conflict(_) | AppError::NotFound(_))) => return Err(e),
+            Err(e) if attempts >= OUTCOME_ATTEMPTS => return Err(e),
+            Err(e) => {
+                warn!(id = %request.id, "recording the approved certificate failed: {e}");
+                tokio::time::sleep(Duration::from_millis(200 * u64::from(attempts))).await;
+            }
+        }
+    }
+}
+
+/// Reject enrollment `id` on behalf of `admin`.
+pub async fn reject_enrollment(
+    id: &str,
+    admin: &Claims,
+    reason: Option<String>,
+    ledger: &Ledger,
+) -> AppResult<EnrollmentRequest> {
+    let pending = find_pending(ledger, id).await?.request;
+    let request = EnrollmentRequest {
+        status: EnrollmentStatus::Rejected,
+        decided_at_unix: Some(unix_now()),
+        decided_by: Some(admin.sub.clone()),
+        reason,
+        ..pending
+    };
+    ledger
+        .update_enrollment(request.clone(), EnrollmentStatus::Pending)
+        .await?;
+    info!(id, sub = %request.subject, admin = %admin.sub, "enrollment rejected");
+    Ok(request)
+}
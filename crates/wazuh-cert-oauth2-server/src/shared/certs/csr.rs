@@ -0,0 +1,352 @@
+use std::ffi::{c_int, c_void};
+
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl::asn1::Asn1ObjectRef;
+use openssl::nid::Nid;
+use openssl::stack::Stack;
+use openssl::x509::{GeneralName, X509Req, X509ReqRef};
+use openssl_sys as ffi;
+use tracing::warn;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use crate::models::key_policy::KeyPolicy;
+use crate::models::naming_template::{CertIdentity, SanEntry};
+use crate::models::signing_profile::CsrExtensionMode;
+
+use super::enforce_key_policy;
+
+/// Largest CSR PEM accepted; an RSA-8192 request with a few SANs fits easily.
+pub(crate) const MAX_CSR_PEM_BYTES: usize = 16 * 1024;
+
+/// Extensions a CSR may request. Requested extensions are never copied into
+/// the certificate; the server sets its own.
+const ALLOWED_EXTENSIONS: [Nid; 5] = [
+    Nid::SUBJECT_ALT_NAME,
+    Nid::KEY_USAGE,
+    Nid::EXT_KEY_USAGE,
+    Nid::BASIC_CONSTRAINTS,
+    Nid::SUBJECT_KEY_IDENTIFIER,
+];
+
+#[repr(C)]
+struct BasicConstraints {
+    ca: c_int,
+    _pathlen: *mut ffi::ASN1_INTEGER,
+}
+
+unsafe extern "C" {
+    fn BASIC_CONSTRAINTS_free(bc: *mut BasicConstraints);
+}
+
+/// One entry of a CSR's `extensionRequest` attribute.
+#[derive(Debug)]
+pub(crate) struct RequestedExtension {
+    pub nid: Nid,
+    /// Long name, or the dotted OID for extensions OpenSSL does not know.
+    pub name: String,
+    pub detail: ExtensionDetail,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ExtensionDetail {
+    BasicConstraints {
+        ca: bool,
+    },
+    /// `None` for name types that are never issued (IP addresses,
+    /// directory names and the like).
+    SubjectAltName(Vec<Option<SanEntry>>),
+    Other,
+}
+
+/// Parse a PEM CSR, check its size and self-signature and apply the key policy.
+pub(crate) fn parse_and_verify_csr(csr_pem: &str, policy: &KeyPolicy) -> AppResult<X509Req> {
+    if csr_pem.len() > MAX_CSR_PEM_BYTES {
+        return Err(AppError::CsrTooLarge {
+            bytes: csr_pem.len(),
+            max: MAX_CSR_PEM_BYTES,
+        });
+    }
+    let csr = X509Req::from_pem(csr_pem.as_bytes()).map_err(|_| AppError::CsrInvalidPem)?;
+    let csr_pubkey = csr
+        .public_key()
+        .map_err(|_| AppError::CsrMissingPublicKey)?;
+    if !csr.verify(&csr_pubkey).unwrap_or(false) {
+        return Err(AppError::CsrVerificationFailed);
+    }
+    enforce_key_policy(&csr_pubkey, policy)?;
+    Ok(csr)
+}
+
+/// Check the CSR's subject and requested extensions against what will be
+/// issued.
+///
+/// The CSR must carry exactly one CN, equal to one of `accepted_cns`. A
+/// request for a CA certificate is always rejected. Other extensions outside
+/// [`ALLOWED_EXTENSIONS`], and requested SANs `issued` does not carry, are
+/// dropped with a warning or rejected depending on `mode`.
+pub(crate) fn inspect_csr(
+    csr: &X509ReqRef,
+    accepted_cns: &[&str],
+    issued: &CertIdentity,
+    mode: CsrExtensionMode,
+) -> AppResult<()> {
+    let mut cns = csr.subject_name().entries_by_nid(Nid::COMMONNAME);
+    let cn = cns.next().ok_or(AppError::CsrMissingCommonName)?;
+    if cns.next().is_some() {
+        return Err(AppError::CsrMultipleCommonNames);
+    }
+    let cn = cn
+        .data()
+        .to_string()
+        .map_err(|_| AppError::CsrCommonNameMismatch {
+            cn: "<not UTF-8>".into(),
+        })?;
+    if !accepted_cns.contains(&cn.as_str()) {
+        return Err(AppError::CsrCommonNameMismatch { cn });
+    }
+
+    for ext in requested_extensions(csr)? {
+        let violation = match &ext.detail {
+            ExtensionDetail::BasicConstraints { ca: true } => return Err(AppError::CsrRequestsCa),
+            _ if !ALLOWED_EXTENSIONS.contains(&ext.nid) => AppError::CsrExtensionNotAllowed {
+                extension: ext.name.clone(),
+            },
+            ExtensionDetail::SubjectAltName(names) => {
+                let unissued = names.iter().find(|name| match name {
+                    Some(name) => !issued.san.contains(name),
+                    None => true,
+                });
+                match unissued {
+                    Some(Some(name)) => AppError::CsrSanNotAllowed {
+                        name: format!("{name:?}"),
+                    },
+                    Some(None) => AppError::CsrSanNotAllowed {
+                        name: "unsupported name type".into(),
+                    },
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        match mode {
+            CsrExtensionMode::Reject => return Err(violation),
+            CsrExtensionMode::Strip => warn!("ignoring CSR extension request: {}", violation),
+        }
+    }
+    Ok(())
+}
+
+/// Decode the CSR's `extensionRequest` attribute.
+pub(crate) fn requested_extensions(csr: &X509ReqRef) -> AppResult<Vec<RequestedExtension>> {
+    let extensions = csr
+        .extensions()
+        .map_err(|_| AppError::CsrMalformedExtensions)?;
+    extensions
+        .iter()
+        .map(|ext| unsafe {
+            let ext = ext.as_ptr();
+            let object = Asn1ObjectRef::from_ptr(ffi::X509_EXTENSION_get_object(ext));
+            let nid = object.nid();
+            let detail = match nid {
+                Nid::BASIC_CONSTRAINTS => {
+                    let bc = decode(ext)? as *mut BasicConstraints;
+                    let ca = (*bc).ca != 0;
+                    BASIC_CONSTRAINTS_free(bc);
+                    ExtensionDetail::BasicConstraints { ca }
+                }
+                Nid::SUBJECT_ALT_NAME => {
+                    let names = Stack::<GeneralName>::from_ptr(decode(ext)? as *mut _);
+                    ExtensionDetail::SubjectAltName(
+                        names
+                            .iter()
+                            .map(|name| {
+                                name.dnsname()
+                                    .map(|v| SanEntry::Dns(v.to_ascii_lowercase()))
+                                    .or_else(|| name.email().map(|v| SanEntry::Email(v.into())))
+                                    .or_else(|| name.uri().map(|v| SanEntry::Uri(v.into())))
+                            })
+                            .collect(),
+                    )
+                }
+                _ => ExtensionDetail::Other,
+            };
+            Ok(RequestedExtension {
+                nid,
+                name: object.to_string(),
+                detail,
+            })
+        })
+        .collect()
+}
+
+/// DNS names in the CSR's requested SANs, lowercased.
+pub(crate) fn requested_dns_names(csr: &X509ReqRef) -> AppResult<Vec<String>> {
+    Ok(requested_extensions(csr)?
+        .into_iter()
+        .filter_map(|ext| match ext.detail {
+            ExtensionDetail::SubjectAltName(names) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|name| match name {
+            Some(SanEntry::Dns(name)) => Some(name),
+            _ => None,
+        })
+        .collect())
+}
+
+/// `X509V3_EXT_d2i`, failing on undecodable extension values.
+unsafe fn decode(ext: *mut ffi::X509_EXTENSION) -> AppResult<*mut c_void> {
+    let decoded = unsafe { ffi::X509V3_EXT_d2i(ext) };
+    if decoded.is_null() {
+        return Err(AppError::CsrMalformedExtensions);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::stack::Stack;
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::{X509Extension, X509NameBuilder, X509Req, X509ReqBuilder};
+    use wazuh_cert_oauth2_model::models::errors::AppError;
+
+    use super::{
+        ExtensionDetail, MAX_CSR_PEM_BYTES, inspect_csr, parse_and_verify_csr, requested_extensions,
+    };
+    use crate::models::key_policy::KeyPolicy;
+    use crate::models::naming_template::{CertIdentity, SanEntry};
+    use crate::models::signing_profile::CsrExtensionMode;
+
+    /// A CSR for `cns` requesting the extensions `build` returns.
+    fn csr(cns: &[&str], build: impl Fn(&X509ReqBuilder) -> Vec<X509Extension>) -> X509Req {
+        let key = PKey::from_rsa(Rsa::generate(2048).expect("rsa")).expect("pkey");
+        let mut name = X509NameBuilder::new().expect("name");
+        for cn in cns {
+            name.append_entry_by_nid(Nid::COMMONNAME, cn).expect("cn");
+        }
+        let mut req = X509ReqBuilder::new().expect("req");
+        req.set_subject_name(&name.build()).expect("subject");
+        req.set_pubkey(&key).expect("pubkey");
+        let exts = build(&req);
+        if !exts.is_empty() {
+            let mut stack = Stack::new().expect("stack");
+            for ext in exts {
+                stack.push(ext).expect("push");
+            }
+            req.add_extensions(&stack).expect("extensions");
+        }
+        req.sign(&key, MessageDigest::sha256()).expect("sign");
+        req.build()
+    }
+
+    fn issued() -> CertIdentity {
+        CertIdentity {
+            subject: vec![(Nid::COMMONNAME, "user-a".into())],
+            san: vec![SanEntry::Dns("user-a".into())],
+        }
+    }
+
+    fn inspect(csr: &X509Req, mode: CsrExtensionMode) -> Result<(), AppError> {
+        inspect_csr(csr, &["user-a"], &issued(), mode)
+    }
+
+    #[test]
+    fn the_cn_must_match_the_subject() {
+        assert!(inspect(&csr(&["user-a"], |_| vec![]), CsrExtensionMode::Reject).is_ok());
+        assert!(matches!(
+            inspect(&csr(&["user-b"], |_| vec![]), CsrExtensionMode::Strip),
+            Err(AppError::CsrCommonNameMismatch { cn }) if cn == "user-b"
+        ));
+        assert!(matches!(
+            inspect(&csr(&[], |_| vec![]), CsrExtensionMode::Strip),
+            Err(AppError::CsrMissingCommonName)
+        ));
+        assert!(matches!(
+            inspect(
+                &csr(&["user-a", "user-a"], |_| vec![]),
+                CsrExtensionMode::Strip
+            ),
+            Err(AppError::CsrMultipleCommonNames)
+        ));
+    }
+
+    #[test]
+    fn ca_requests_are_always_rejected() {
+        let req = csr(&["user-a"], |_| {
+            vec![BasicConstraints::new().critical().ca().build().expect("bc")]
+        });
+        let exts = requested_extensions(&req).expect("extensions");
+        assert_eq!(
+            exts[0].detail,
+            ExtensionDetail::BasicConstraints { ca: true }
+        );
+        assert!(matches!(
+            inspect(&req, CsrExtensionMode::Strip),
+            Err(AppError::CsrRequestsCa)
+        ));
+
+        let req = csr(&["user-a"], |_| {
+            vec![BasicConstraints::new().build().expect("bc")]
+        });
+        assert!(inspect(&req, CsrExtensionMode::Reject).is_ok());
+    }
+
+    #[test]
+    fn disallowed_extensions_are_stripped_or_rejected() {
+        let req = csr(&["user-a"], |req| {
+            #[allow(deprecated)]
+            let ns_comment = X509Extension::new_nid(
+                None,
+                Some(&req.x509v3_context(None)),
+                Nid::NETSCAPE_COMMENT,
+                "hello",
+            )
+            .expect("comment");
+            vec![ns_comment]
+        });
+        assert!(inspect(&req, CsrExtensionMode::Strip).is_ok());
+        assert!(matches!(
+            inspect(&req, CsrExtensionMode::Reject),
+            Err(AppError::CsrExtensionNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn requested_sans_must_be_issued() {
+        let san = |names: &'static [&'static str]| {
+            move |req: &X509ReqBuilder| {
+                let mut san = SubjectAlternativeName::new();
+                for name in names {
+                    san.dns(name);
+                }
+                vec![san.build(&req.x509v3_context(None)).expect("san")]
+            }
+        };
+        let req = csr(&["user-a"], san(&["USER-A"]));
+        assert!(inspect(&req, CsrExtensionMode::Reject).is_ok());
+        let req = csr(&["user-a"], san(&["user-a", "evil.example"]));
+        assert!(inspect(&req, CsrExtensionMode::Strip).is_ok());
+        assert!(matches!(
+            inspect(&req, CsrExtensionMode::Reject),
+            Err(AppError::CsrSanNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_and_garbage_pem_are_rejected() {
+        let big = "A".repeat(MAX_CSR_PEM_BYTES + 1);
+        assert!(matches!(
+            parse_and_verify_csr(&big, &KeyPolicy::default()),
+            Err(AppError::CsrTooLarge { .. })
+        ));
+        assert!(matches!(
+            parse_and_verify_csr("not a csr", &KeyPolicy::default()),
+            Err(AppError::CsrInvalidPem)
+        ));
+    }
+}
@@ -4,9 +4,12 @@ use openssl::x509::extension::{
     AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
     SubjectKeyIdentifier,
 };
-use url::Url;
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
+use super::KeyKind;
+use crate::models::cert_profile::{ExtendedUsage, KeyUsageBit};
+use crate::models::naming_template::SanEntry;
+
 pub(crate) fn append_core_extensions(
     builder: &mut openssl::x509::X509Builder,
     ca_cert: &X509Ref,
@@ -45,47 +48,109 @@ pub(crate) fn append_crl_dp(
     Ok(())
 }
 
+/// Freshest CRL (RFC 5280 §4.2.1.15): where to find delta CRLs.
+pub(crate) fn append_freshest_crl(
+    builder: &mut openssl::x509::X509Builder,
+    ca_cert: &X509Ref,
+    delta_crl_url: Option<&str>,
+) -> AppResult<()> {
+    if let Some(url) = delta_crl_url {
+        // Same syntax as crlDistributionPoints, so no typed builder either.
+        #[allow(deprecated)]
+        {
+            let freshest = X509Extension::new(
+                None,
+                Some(&builder.x509v3_context(Some(ca_cert), None)),
+                "freshestCRL",
+                &format!("URI:{}", url),
+            )?;
+            builder.append_extension(freshest)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn append_aia_ocsp(
+    builder: &mut openssl::x509::X509Builder,
+    ca_cert: &X509Ref,
+    ocsp_url: Option<&str>,
+) -> AppResult<()> {
+    if let Some(url) = ocsp_url {
+        // No typed builder for Authority Information Access either.
+        #[allow(deprecated)]
+        {
+            let aia = X509Extension::new(
+                None,
+                Some(&builder.x509v3_context(Some(ca_cert), None)),
+                "authorityInfoAccess",
+                &format!("OCSP;URI:{}", url),
+            )?;
+            builder.append_extension(aia)?;
+        }
+    }
+    Ok(())
+}
+
+/// KeyUsage from the profile, limited to what the key can do. Without an
+/// explicit list, the TLS client default: RSA keys may also encipher the key
+/// exchange, EC and Ed25519 keys only sign.
 pub(crate) fn append_key_usage(
     builder: &mut openssl::x509::X509Builder,
-    is_rsa: bool,
+    kind: KeyKind,
+    bits: Option<&[KeyUsageBit]>,
 ) -> AppResult<()> {
+    let bits = bits.unwrap_or(&[KeyUsageBit::DigitalSignature, KeyUsageBit::KeyEncipherment]);
     let mut ku = KeyUsage::new();
     ku.critical();
-    ku.digital_signature();
-    if is_rsa {
-        ku.key_encipherment();
+    let mut any = false;
+    for bit in bits {
+        match bit {
+            KeyUsageBit::DigitalSignature => ku.digital_signature(),
+            KeyUsageBit::KeyEncipherment if kind == KeyKind::Rsa => ku.key_encipherment(),
+            KeyUsageBit::KeyAgreement if kind == KeyKind::Ec => ku.key_agreement(),
+            _ => continue,
+        };
+        any = true;
+    }
+    if !any {
+        ku.digital_signature();
     }
     builder.append_extension(ku.build()?)?;
     Ok(())
 }
 
-pub(crate) fn append_client_eku(builder: &mut openssl::x509::X509Builder) -> AppResult<()> {
-    let eku = ExtendedKeyUsage::new().client_auth().build()?;
-    builder.append_extension(eku)?;
+pub(crate) fn append_eku(
+    builder: &mut openssl::x509::X509Builder,
+    usages: &[ExtendedUsage],
+) -> AppResult<()> {
+    let mut eku = ExtendedKeyUsage::new();
+    for usage in usages {
+        match usage {
+            ExtendedUsage::ClientAuth => eku.client_auth(),
+            ExtendedUsage::ServerAuth => eku.server_auth(),
+        };
+    }
+    builder.append_extension(eku.build()?)?;
     Ok(())
 }
 
-pub(crate) fn append_san_cn_and_identity_uri(
+pub(crate) fn append_san(
     builder: &mut openssl::x509::X509Builder,
     ca_cert: &X509Ref,
-    subject_cn: &str,
-    issuer: &str,
-    subject_sub: &str,
+    entries: &[SanEntry],
 ) -> AppResult<()> {
-    let value = match Url::parse(issuer) {
-        Ok(url) => {
-            let mut base = String::new();
-            base.push_str(url.as_str());
-            format!("{}#sub={}", base.trim_end_matches('#'), subject_sub)
-        }
-        Err(_) => format!("urn:keycloak:sub:{}", subject_sub),
-    };
-
-    let san = SubjectAlternativeName::new()
-        .dns(subject_cn)
-        .uri(&value)
-        .build(&builder.x509v3_context(Some(ca_cert), None))?;
-
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut san = SubjectAlternativeName::new();
+    for entry in entries {
+        match entry {
+            SanEntry::Dns(name) => san.dns(name),
+            SanEntry::Email(email) => san.email(email),
+            SanEntry::Uri(uri) => san.uri(uri),
+        };
+    }
+    let san = san.build(&builder.x509v3_context(Some(ca_cert), None))?;
     builder.append_extension(san)?;
     Ok(())
 }
@@ -1,10 +1,17 @@
+mod approval;
 mod build_base;
+mod csr;
 mod extensions;
 mod policy;
+mod renew;
 mod sign;
 
+pub use approval::{approve_enrollment, hold_for_approval, reject_enrollment};
+pub use renew::renew_cert;
 pub use sign::sign_csr;
+pub(crate) use sign::{extract_realm_from_issuer, validate_agent_name, validate_device_id};
 
 pub(crate) use build_base::*;
+pub(crate) use csr::*;
 pub(crate) use extensions::*;
 pub(crate) use policy::*;
@@ -1,41 +1,87 @@
 use openssl::nid::Nid;
 use openssl::pkey::Id as PKeyId;
-use openssl::pkey::PKey;
+use openssl::pkey::{HasPublic, PKeyRef};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 
-pub(crate) fn enforce_key_policy(pkey: &PKey<openssl::pkey::Public>) -> AppResult<()> {
-    match pkey.id() {
-        PKeyId::RSA => {
-            let rsa = pkey.rsa()?;
-            let bits = (rsa.size() as usize) * 8;
-            if bits < 2048 {
-                return Err(AppError::KeyPolicyRsaTooSmall { bits });
+use crate::models::key_policy::KeyPolicy;
+
+/// Key algorithm families, which determine the certificate's KeyUsage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyKind {
+    Rsa,
+    Ec,
+    Ed25519,
+}
+
+impl KeyKind {
+    pub(crate) fn of<T: HasPublic>(pkey: &PKeyRef<T>) -> AppResult<Self> {
+        match pkey.id() {
+            PKeyId::RSA => Ok(Self::Rsa),
+            PKeyId::EC => Ok(Self::Ec),
+            PKeyId::ED25519 => Ok(Self::Ed25519),
+            other => Err(AppError::KeyPolicyUnsupportedKeyType {
+                key_type: format!("{:?}", other),
+            }),
+        }
+    }
+}
+
+/// Check `pkey` against `policy` and return its kind.
+pub(crate) fn enforce_key_policy<T: HasPublic>(
+    pkey: &PKeyRef<T>,
+    policy: &KeyPolicy,
+) -> AppResult<KeyKind> {
+    let kind = KeyKind::of(pkey)?;
+    let unsupported = |key_type: &str| AppError::KeyPolicyUnsupportedKeyType {
+        key_type: key_type.into(),
+    };
+    match kind {
+        KeyKind::Rsa => {
+            let bounds = policy.rsa.ok_or_else(|| unsupported("RSA"))?;
+            let bits = (pkey.rsa()?.size() as usize) * 8;
+            if bits < bounds.min_bits {
+                return Err(AppError::KeyPolicyRsaTooSmall {
+                    bits,
+                    min: bounds.min_bits,
+                });
+            }
+            if let Some(max) = bounds.max_bits
+                && bits > max
+            {
+                return Err(AppError::KeyPolicyRsaTooLarge { bits, max });
             }
         }
-        PKeyId::EC => {
-            let ec = pkey.ec_key()?;
-            let nid = ec
+        KeyKind::Ec => {
+            let nid = pkey
+                .ec_key()?
                 .group()
                 .curve_name()
                 .ok_or(AppError::KeyPolicyUnknownEcCurve)?;
-            if nid != Nid::X9_62_PRIME256V1 {
+            if !policy.ec_curves.iter().any(|c| c.nid() == nid) {
                 return Err(AppError::KeyPolicyUnsupportedEcCurve {
-                    nid: format!("{:?}", nid),
+                    nid: curve_name(nid),
                 });
             }
         }
-        other => {
-            return Err(AppError::KeyPolicyUnsupportedKeyType {
-                key_type: format!("{:?}", other),
-            });
+        KeyKind::Ed25519 => {
+            if !policy.ed25519 {
+                return Err(unsupported("Ed25519"));
+            }
         }
     }
-    Ok(())
+    Ok(kind)
+}
+
+fn curve_name(nid: Nid) -> String {
+    nid.short_name()
+        .map(str::to_string)
+        .unwrap_or_else(|_| format!("{:?}", nid))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::enforce_key_policy;
+    use super::{KeyKind, enforce_key_policy};
+    use crate::models::key_policy::{KeyPolicy, parse_key_policy};
     use openssl::ec::{EcGroup, EcKey};
     use openssl::nid::Nid;
     use openssl::pkey::PKey;
@@ -49,42 +95,75 @@ mod tests {
         PKey::public_key_from_pem(&pem).expect("public pem parsing should work")
     }
 
+    fn rsa(bits: u32) -> PKey<openssl::pkey::Public> {
+        let rsa = Rsa::generate(bits).expect("rsa generation should succeed");
+        to_public_key(PKey::from_rsa(rsa).expect("pkey conversion should succeed"))
+    }
+
+    fn ec(curve: Nid) -> PKey<openssl::pkey::Public> {
+        let group = EcGroup::from_curve_name(curve).expect("group creation should succeed");
+        let ec = EcKey::generate(&group).expect("ec key generation should succeed");
+        to_public_key(PKey::from_ec_key(ec).expect("pkey conversion should succeed"))
+    }
+
     #[test]
     fn accepts_rsa_2048_keys() {
-        let rsa = Rsa::generate(2048).expect("rsa generation should succeed");
-        let public = to_public_key(PKey::from_rsa(rsa).expect("pkey conversion should succeed"));
-        assert!(enforce_key_policy(&public).is_ok());
+        let kind = enforce_key_policy(&rsa(2048), &KeyPolicy::default());
+        assert_eq!(kind.ok(), Some(KeyKind::Rsa));
     }
 
     #[test]
     fn rejects_rsa_keys_smaller_than_2048() {
-        let rsa = Rsa::generate(1024).expect("rsa generation should succeed");
-        let public = to_public_key(PKey::from_rsa(rsa).expect("pkey conversion should succeed"));
+        let err = enforce_key_policy(&rsa(1024), &KeyPolicy::default())
+            .expect_err("policy should reject small rsa");
+        assert!(matches!(
+            err,
+            AppError::KeyPolicyRsaTooSmall { bits, min } if bits == 1024 && min == 2048
+        ));
+    }
 
-        let err = enforce_key_policy(&public).expect_err("policy should reject small rsa");
+    #[test]
+    fn rejects_rsa_keys_above_the_maximum() {
+        let policy = parse_key_policy("rsa:2048-2048").unwrap();
+        let err = enforce_key_policy(&rsa(3072), &policy).expect_err("policy should reject 3072");
         assert!(matches!(
             err,
-            AppError::KeyPolicyRsaTooSmall { bits } if bits == 1024
+            AppError::KeyPolicyRsaTooLarge { bits, max } if bits == 3072 && max == 2048
         ));
     }
 
     #[test]
     fn accepts_p256_ec_keys() {
-        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
-            .expect("p256 group creation should succeed");
-        let ec = EcKey::generate(&group).expect("ec key generation should succeed");
-        let public = to_public_key(PKey::from_ec_key(ec).expect("pkey conversion should succeed"));
-        assert!(enforce_key_policy(&public).is_ok());
+        let kind = enforce_key_policy(&ec(Nid::X9_62_PRIME256V1), &KeyPolicy::default());
+        assert_eq!(kind.ok(), Some(KeyKind::Ec));
     }
 
     #[test]
-    fn rejects_non_p256_ec_keys() {
-        let group =
-            EcGroup::from_curve_name(Nid::SECP384R1).expect("p384 group creation should succeed");
-        let ec = EcKey::generate(&group).expect("ec key generation should succeed");
-        let public = to_public_key(PKey::from_ec_key(ec).expect("pkey conversion should succeed"));
+    fn ec_curves_must_be_listed() {
+        let err = enforce_key_policy(&ec(Nid::SECP384R1), &KeyPolicy::default())
+            .expect_err("default policy should reject p384");
+        assert!(matches!(err, AppError::KeyPolicyUnsupportedEcCurve { nid } if nid == "secp384r1"));
 
-        let err = enforce_key_policy(&public).expect_err("policy should reject p384");
-        assert!(matches!(err, AppError::KeyPolicyUnsupportedEcCurve { .. }));
+        let policy = parse_key_policy("p384,p521").unwrap();
+        assert!(enforce_key_policy(&ec(Nid::SECP384R1), &policy).is_ok());
+        assert!(enforce_key_policy(&ec(Nid::SECP521R1), &policy).is_ok());
+        assert!(enforce_key_policy(&ec(Nid::X9_62_PRIME256V1), &policy).is_err());
+    }
+
+    #[test]
+    fn ed25519_and_rsa_can_be_disallowed() {
+        let ed25519 = to_public_key(PKey::generate_ed25519().expect("ed25519 generation"));
+        assert!(matches!(
+            enforce_key_policy(&ed25519, &KeyPolicy::default()),
+            Err(AppError::KeyPolicyUnsupportedKeyType { .. })
+        ));
+
+        let policy = parse_key_policy("ed25519").unwrap();
+        let kind = enforce_key_policy(&ed25519, &policy);
+        assert_eq!(kind.ok(), Some(KeyKind::Ed25519));
+        assert!(matches!(
+            enforce_key_policy(&rsa(2048), &policy),
+            Err(AppError::KeyPolicyUnsupportedKeyType { .. })
+        ));
     }
 }
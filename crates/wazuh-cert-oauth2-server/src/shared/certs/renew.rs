@@ -0,0 +1,230 @@
+use openssl::x509::X509;
+use tracing::info;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
+use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
+
+use crate::handlers::middle::ClientInfo;
+use crate::models::approval_policy::ApprovalPolicy;
+use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::CertProfiles;
+use crate::models::naming_template::{CertIdentity, SanEntry};
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::crl::CrlState;
+use crate::shared::ledger::{IssuedCert, Ledger, Rotation};
+use crate::shared::policy_hook::{CsrSummary, PolicyHook, PolicyOverrides, PolicyRequest};
+use crate::shared::webhook_notifier::WebhookNotifier;
+
+use super::sign::{issue_certificate, validate_agent_name};
+use super::{asn1_to_unix, inspect_csr, parse_and_verify_csr, unix_now};
+
+/// Revocation reason recorded for the certificate replaced by a renewal.
+const SUPERSEDED_REASON: &str = "superseded";
+
+/// Replace the caller's current certificate, authenticated by that certificate.
+///
+/// `client_cert_der` is the certificate presented over mTLS. It must have been
+/// issued by one of our CAs (active or retiring), be within its validity
+/// period and be an unrevoked ledger entry. The replacement is signed by the
+/// active CA, keeps the ledger subject, issuer, realm and signing profile as
+/// well as the subject DN and SANs of the certificate it replaces, and never
+/// outlives that certificate. The `profile` field of the request is ignored.
+///
+/// The request passes the same checks as [`sign_csr`](super::sign_csr),
+/// judged on what the ledger knows of the subject since no token is
+/// presented: the enrollment rules, the approval policy, the policy `hook`
+/// and the default device quota. The old serial is then revoked as
+/// superseded (in its own issuer's CRL) before signing, atomically, so two
+/// renewals with one certificate cannot both succeed.
+#[allow(clippy::too_many_arguments)]
+pub async fn renew_cert(
+    dto: SignCsrRequest,
+    client_cert_der: &[u8],
+    client: &ClientInfo,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+    approval: &ApprovalPolicy,
+    ca: &CaProvider,
+    ledger: &Ledger,
+    crl: &CrlState,
+    webhook: Option<&WebhookNotifier>,
+    hook: Option<&PolicyHook>,
+) -> AppResult<SignedCertResponse> {
+    if let Some(ref name) = dto.wazuh_agent_name {
+        validate_agent_name(name)?;
+    }
+    let csr = parse_and_verify_csr(&dto.csr_pem, &profile.key_policy)?;
+
+    let client_cert = X509::from_der(client_cert_der)?;
+    let old_issuer = ca
+        .issuers()
+        .await?
+        .iter()
+        .find(|issuer| {
+            issuer
+                .cert
+                .public_key()
+                .and_then(|key| client_cert.verify(&key))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .ok_or_else(|| {
+            AppError::Forbidden("client certificate was not issued by this CA".into())
+        })?;
+    let now = unix_now();
+    let old_not_before = asn1_to_unix(client_cert.not_before())?;
+    let old_not_after = asn1_to_unix(client_cert.not_after())?;
+    if now < old_not_before || now > old_not_after {
+        return Err(AppError::Forbidden(
+            "client certificate is outside its validity period".into(),
+        ));
+    }
+
+    let serial_hex = client_cert
+        .serial_number()
+        .to_bn()?
+        .to_hex_str()?
+        .to_string();
+    // The CN follows the naming template, so look the certificate up by serial.
+    let entry = ledger
+        .find_by_serial(&serial_hex)
+        .await?
+        .filter(|e| !e.revoked)
+        .ok_or_else(|| AppError::Forbidden("client certificate is unknown or revoked".into()))?;
+    let Some(issuer) = entry.issuer.clone() else {
+        return Err(AppError::Forbidden(
+            "certificate predates issuer tracking; re-enroll interactively".into(),
+        ));
+    };
+    // What the ledger knows of the token the certificate was issued for;
+    // roles and the other claims are not known without a token.
+    let claims = Claims {
+        sub: entry.subject.clone(),
+        iss: issuer,
+        ..Default::default()
+    };
+    profile.enrollment_rules.check(&claims, now)?;
+    if approval.requires_approval(&claims, false) {
+        return Err(AppError::Forbidden(
+            "renewals of this subject need approval; enroll again with a token".into(),
+        ));
+    }
+    let cert_profile = profiles.get(entry.profile_name())?;
+    let profile = &cert_profile.apply(profile);
+
+    let mut identity = identity_of(&client_cert)?;
+    let old_cn = identity.common_name().unwrap_or_default();
+    inspect_csr(
+        &csr,
+        &[&entry.subject, old_cn],
+        &identity,
+        profile.csr_extensions,
+    )?;
+
+    let wazuh_agent_name = dto.wazuh_agent_name.or(entry.wazuh_agent_name);
+    let overrides = match hook {
+        Some(hook) => {
+            let request = PolicyRequest {
+                claims: &claims,
+                is_admin: false,
+                profile: &cert_profile.name,
+                wazuh_agent_name: wazuh_agent_name.as_deref(),
+                device_id: entry.device_id.as_deref(),
+                csr: CsrSummary::of(&csr)?,
+                client,
+            };
+            hook.decide(&request, ledger).await?
+        }
+        None => PolicyOverrides::default(),
+    };
+    for san in overrides.extra_sans {
+        if !identity.san.contains(&san) {
+            identity.san.push(san);
+        }
+    }
+
+    if !ledger
+        .mark_revoked(
+            serial_hex.clone(),
+            Some(SUPERSEDED_REASON.to_string()),
+            None,
+        )
+        .await?
+    {
+        return Err(AppError::Forbidden(
+            "client certificate is unknown or revoked".into(),
+        ));
+    }
+    crl.rebuild(&old_issuer, ledger).await?;
+    let rotation = Rotation {
+        subject: entry.subject.clone(),
+        issuer: entry.issuer.clone(),
+        profile: cert_profile.name.clone(),
+        device_id: entry.device_id.clone(),
+        quota: profile.device_quota_for(&[]),
+        overwrite: true,
+    };
+    if let Some(names) = ledger.check_and_revoke_active(rotation.clone()).await? {
+        crl.rebuild_all(ca, ledger).await?;
+        if let Some(notifier) = webhook {
+            notifier.notify_evict(&entry.subject, names).await;
+        }
+    }
+
+    // Roles are not known without a token, so cap the replacement at the
+    // lifetime of the certificate being replaced to keep role overrides sticky.
+    let ca_not_after = asn1_to_unix(ca.active().await?.cert.not_after())?;
+    let (not_before, not_after) = match overrides.validity_days {
+        Some(days) => profile.validity_window_of(days, now, ca_not_after),
+        None => profile.validity_window(&[], entry.realm.as_deref(), now, ca_not_after),
+    };
+    let not_after = not_after.min(now + (old_not_after - old_not_before));
+
+    let res = issue_certificate(
+        &csr,
+        &identity,
+        &cert_profile.usage,
+        IssuedCert {
+            subject: entry.subject.clone(),
+            issuer: entry.issuer.clone(),
+            realm: entry.realm.clone(),
+            wazuh_agent_name,
+            profile: Some(cert_profile.name.clone()),
+            device_id: entry.device_id,
+            ..Default::default()
+        },
+        (not_before, not_after),
+        Some(rotation),
+        ca,
+        ledger,
+    )
+    .await?;
+    info!(sub = %entry.subject, old_serial = %serial_hex, "certificate renewed via mTLS");
+    Ok(res)
+}
+
+/// The subject DN and SANs of `cert`, which a renewal carries over since no
+/// token is available to render the naming template from.
+fn identity_of(cert: &X509) -> AppResult<CertIdentity> {
+    let subject = cert
+        .subject_name()
+        .entries()
+        .map(|e| Ok((e.object().nid(), e.data().to_string()?)))
+        .collect::<AppResult<_>>()?;
+    let san = cert
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    name.dnsname()
+                        .map(|v| SanEntry::Dns(v.into()))
+                        .or_else(|| name.email().map(|v| SanEntry::Email(v.into())))
+                        .or_else(|| name.uri().map(|v| SanEntry::Uri(v.into())))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(CertIdentity { subject, san })
+}
@@ -1,23 +1,29 @@
-use openssl::pkey::PKey;
+use openssl::bn::{BigNum, BigNumRef};
 use openssl::x509::{X509, X509Ref, X509Req};
+use wazuh_cert_oauth2_model::models::claims::Claims;
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 use wazuh_cert_oauth2_model::models::sign_csr_request::SignCsrRequest;
 use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
 
-use crate::handlers::middle::JwtToken;
+use crate::handlers::middle::{ClientInfo, Principal};
 use crate::models::ca_config::CaProvider;
+use crate::models::cert_profile::{CertProfile, CertProfiles, CertUsage};
+use crate::models::naming_template::{CertIdentity, NamingContext};
+use crate::models::signing_profile::SigningProfile;
+use crate::shared::ca_signer::{CaSigner, sign_certificate};
 use crate::shared::crl::CrlState;
-use crate::shared::ledger::Ledger;
+use crate::shared::ledger::{IssuedCert, Ledger, Rotation};
+use crate::shared::policy_hook::{CsrSummary, PolicyHook, PolicyOverrides, PolicyRequest};
 use crate::shared::webhook_notifier::WebhookNotifier;
 use tracing::info;
 
 use super::{
-    append_client_eku, append_core_extensions, append_crl_dp, append_key_usage,
-    append_san_cn_and_identity_uri, enforce_key_policy, set_serial_number, set_subject_and_pubkey,
-    set_validity_1y, sign_builder,
+    append_aia_ocsp, append_core_extensions, append_crl_dp, append_eku, append_freshest_crl,
+    append_key_usage, append_san, asn1_to_unix, inspect_csr, new_serial, parse_and_verify_csr,
+    requested_dns_names, set_serial_number, set_subject_and_pubkey, set_validity, unix_now,
 };
 
-fn extract_realm_from_issuer(iss: &str) -> Option<String> {
+pub(crate) fn extract_realm_from_issuer(iss: &str) -> Option<String> {
     if let Ok(url) = url::Url::parse(iss)
         && let Some(segments) = url.path_segments()
     {
@@ -36,102 +42,338 @@ fn extract_realm_from_issuer(iss: &str) -> Option<String> {
 }
 
 /// Sign a client-provided CSR with the issuing CA; never generate or return private keys
+///
+/// The certificate follows the signing profile the request names, and the
+/// one-active-certificate rule applies per profile and device: a request
+/// rotates out the caller's certificate for the same `device_id`, and other
+/// devices stay active up to the caller's device quota.
+///
+/// With a policy `hook`, the checked request is put to the policy endpoint
+/// before any certificate is rotated out; its overrides adjust the lifetime
+/// and add SANs.
+#[allow(clippy::too_many_arguments)]
 pub async fn sign_csr(
     dto: SignCsrRequest,
-    JwtToken { claims }: JwtToken,
+    principal: Principal,
+    client: &ClientInfo,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
     ca: &CaProvider,
     ledger: &Ledger,
     crl: &CrlState,
     webhook: Option<&WebhookNotifier>,
+    hook: Option<&PolicyHook>,
 ) -> AppResult<SignedCertResponse> {
-    // Validate wazuh_agent_name if provided — it is client-supplied and later
-    // interpolated into Wazuh API URLs during eviction. Reject characters that
-    // could break URL parsing even though reqwest .query() encodes them, as
-    // defense-in-depth against revocation-evasion via crafted agent names.
-    if let Some(ref name) = dto.wazuh_agent_name {
-        validate_agent_name(name)?;
-    }
+    sign_csr_as(
+        dto, principal, client, profile, profiles, ca, ledger, crl, webhook, hook, None,
+    )
+    .await
+}
 
-    let csr = X509Req::from_pem(dto.csr_pem.as_bytes())?;
-    let csr_pubkey = csr
-        .public_key()
-        .map_err(|_| AppError::CsrMissingPublicKey)?;
-    let verified = csr.verify(&csr_pubkey)?;
-    if !verified {
-        return Err(AppError::CsrVerificationFailed);
-    }
+/// [`sign_csr`], signing with `serial` when given so the caller can record
+/// it before the certificate exists.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn sign_csr_as(
+    dto: SignCsrRequest,
+    Principal { claims, is_admin }: Principal,
+    client: &ClientInfo,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+    ca: &CaProvider,
+    ledger: &Ledger,
+    crl: &CrlState,
+    webhook: Option<&WebhookNotifier>,
+    hook: Option<&PolicyHook>,
+    serial: Option<&BigNumRef>,
+) -> AppResult<SignedCertResponse> {
+    let Prepared {
+        cert_profile,
+        profile,
+        csr,
+        realm,
+        mut identity,
+    } = prepare(&dto, &claims, is_admin, profile, profiles)?;
+    let profile = &profile;
 
-    let is_admin = claims.is_admin();
+    let overrides = match hook {
+        Some(hook) => {
+            let request = PolicyRequest {
+                claims: &claims,
+                is_admin,
+                profile: &cert_profile.name,
+                wazuh_agent_name: dto.wazuh_agent_name.as_deref(),
+                device_id: dto.device_id.as_deref(),
+                csr: CsrSummary::of(&csr)?,
+                client,
+            };
+            hook.decide(&request, ledger).await?
+        }
+        None => PolicyOverrides::default(),
+    };
+    for san in overrides.extra_sans {
+        if !identity.san.contains(&san) {
+            identity.san.push(san);
+        }
+    }
 
-    if is_admin {
+    let now = unix_now();
+    let roles = claims
+        .realm_access
+        .as_ref()
+        .map(|r| r.roles.as_slice())
+        .unwrap_or_default();
+    let rotation = if is_admin {
         info!(sub = %claims.sub, "admin user; skipping single-cert policy");
+        None
     } else {
-        let old_agent_names = ledger
-            .check_and_revoke_active(claims.sub.clone(), dto.overwrite == Some(true))
+        let overwrite = dto.overwrite == Some(true)
+            || renewal_due(
+                ledger,
+                profile,
+                &claims.iss,
+                &claims.sub,
+                &cert_profile.name,
+                dto.device_id.as_deref(),
+                now,
+            )
             .await?;
+        let rotation = Rotation {
+            subject: claims.sub.clone(),
+            issuer: Some(claims.iss.clone()),
+            profile: cert_profile.name.clone(),
+            device_id: dto.device_id.clone(),
+            quota: profile.device_quota_for(roles),
+            overwrite,
+        };
+        let old_agent_names = ledger.check_and_revoke_active(rotation.clone()).await?;
         if let Some(names) = old_agent_names {
-            // Rebuild the CRL immediately
-            let (ca_cert, ca_key) = ca.get().await?;
-            let revs = ledger.revoked_as_revocations().await?;
-            crl.request_rebuild(ca_cert, ca_key, revs).await?;
+            // Rebuild the CRLs immediately; the revoked certs may come
+            // from any configured CA.
+            crl.rebuild_all(ca, ledger).await?;
             // Notify the webhook to evict the stale Wazuh agent entries (fire-and-forget)
             if let Some(notifier) = webhook {
                 notifier.notify_evict(&claims.sub, names).await;
             }
         }
-    }
+        Some(rotation)
+    };
 
-    enforce_key_policy(&csr_pubkey)?;
-    let (ca_cert, ca_key) = ca.get().await?;
-    let cert = sign_csr_with_ca(
+    let active = ca.active().await?;
+    let ca_not_after = asn1_to_unix(active.cert.not_after())?;
+    let validity = match overrides.validity_days {
+        Some(days) => profile.validity_window_of(days, now, ca_not_after),
+        None => profile.validity_window(roles, realm.as_deref(), now, ca_not_after),
+    };
+    issue_certificate(
         &csr,
-        &ca_cert,
-        &ca_key,
-        &claims.sub,
-        &claims.iss,
-        ca.crl_dist_url(),
-    )?;
-    let serial_hex = cert.serial_number().to_bn()?.to_hex_str()?.to_string();
-    let realm = extract_realm_from_issuer(&claims.iss);
-    ledger
-        .record_issued(
-            claims.sub.clone(),
-            serial_hex,
-            Some(claims.iss.clone()),
+        &identity,
+        &cert_profile.usage,
+        IssuedCert {
+            serial_hex: match serial {
+                Some(serial) => serial.to_hex_str()?.to_string(),
+                None => String::new(),
+            },
+            subject: claims.sub.clone(),
+            issuer: Some(claims.iss.clone()),
             realm,
-            dto.wazuh_agent_name.clone(),
-        )
-        .await?;
-    let certificate_pem = String::from_utf8(cert.to_pem()?)?;
-    let ca_cert_pem = String::from_utf8(ca_cert.to_pem()?)?;
+            wazuh_agent_name: dto.wazuh_agent_name,
+            profile: Some(cert_profile.name.clone()),
+            device_id: dto.device_id,
+            ..Default::default()
+        },
+        validity,
+        rotation,
+        ca,
+        ledger,
+    )
+    .await
+}
+
+/// A request checked against its signing profile, ready to be signed.
+struct Prepared<'a> {
+    cert_profile: &'a CertProfile,
+    /// The base signing profile with `cert_profile` applied.
+    profile: SigningProfile,
+    csr: X509Req,
+    realm: Option<String>,
+    identity: CertIdentity,
+}
+
+/// Everything [`sign_csr`] checks before touching the ledger: enrollment
+/// rules, request fields, profile selection, key policy and CSR contents.
+fn prepare<'a>(
+    dto: &SignCsrRequest,
+    claims: &Claims,
+    is_admin: bool,
+    profile: &SigningProfile,
+    profiles: &'a CertProfiles,
+) -> AppResult<Prepared<'a>> {
+    profile.enrollment_rules.check(claims, unix_now())?;
+    // Validate wazuh_agent_name if provided — it is client-supplied and later
+    // interpolated into Wazuh API URLs during eviction. Reject characters that
+    // could break URL parsing even though reqwest .query() encodes them, as
+    // defense-in-depth against revocation-evasion via crafted agent names.
+    if let Some(ref name) = dto.wazuh_agent_name {
+        validate_agent_name(name)?;
+    }
+    if let Some(ref device_id) = dto.device_id {
+        validate_device_id(device_id)?;
+    }
+    let cert_profile = profiles.select(dto.profile.as_deref(), claims, is_admin)?;
+    let profile = cert_profile.apply(profile);
+    let csr = parse_and_verify_csr(&dto.csr_pem, &profile.key_policy)?;
+    let realm = extract_realm_from_issuer(&claims.iss);
+    // Render the names before rotating out the caller's current certificates
+    // so a token the template cannot use leaves them untouched.
+    let mut identity = profile.naming.render(&NamingContext {
+        claims,
+        realm: realm.as_deref(),
+        wazuh_agent_name: dto.wazuh_agent_name.as_deref(),
+    })?;
+    cert_profile.admit_requested_dns(&mut identity, &requested_dns_names(&csr)?);
+    let cn = identity.common_name().unwrap_or_default();
+    inspect_csr(&csr, &[&claims.sub, cn], &identity, profile.csr_extensions)?;
+    Ok(Prepared {
+        cert_profile,
+        profile,
+        csr,
+        realm,
+        identity,
+    })
+}
+
+/// Reject a request [`sign_csr`] would reject, without signing it or touching
+/// the ledger.
+pub(crate) fn check_request(
+    dto: &SignCsrRequest,
+    claims: &Claims,
+    is_admin: bool,
+    profile: &SigningProfile,
+    profiles: &CertProfiles,
+) -> AppResult<()> {
+    prepare(dto, claims, is_admin, profile, profiles).map(|_| ())
+}
+
+/// Sign `csr` with the names in `identity` and the key usages in `usage`,
+/// and record it in the ledger under `cert.subject`, within the quota of
+/// `rotation` when given.
+///
+/// The certificate is signed by the active CA with `cert.serial_hex` as its
+/// serial, or a random one when empty; `cert.serial_hex`,
+/// `cert.not_after_unix` and `cert.issuer_key_id` are filled in from it.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn issue_certificate(
+    csr: &X509Req,
+    identity: &CertIdentity,
+    usage: &CertUsage,
+    mut cert: IssuedCert,
+    (not_before, not_after): (u64, u64),
+    rotation: Option<Rotation>,
+    ca: &CaProvider,
+    ledger: &Ledger,
+) -> AppResult<SignedCertResponse> {
+    let active = ca.active().await?;
+    let serial = match cert.serial_hex.as_str() {
+        "" => new_serial()?,
+        hex => BigNum::from_hex_str(hex)?,
+    };
+    let signed = sign_csr_with_ca(
+        csr,
+        &active.cert,
+        active.signer.as_ref(),
+        &serial,
+        identity,
+        usage,
+        ca.crl_dist_url(&active).as_deref(),
+        ca.delta_crl_url(&active).as_deref(),
+        ca.ocsp_url(),
+        (not_before, not_after),
+    )?;
+    cert.serial_hex = signed.serial_number().to_bn()?.to_hex_str()?.to_string();
+    cert.not_after_unix = Some(not_after);
+    cert.issuer_key_id = Some(active.key_id.clone());
+    match rotation {
+        Some(rotation) => ledger.record_issued_within(cert, rotation).await?,
+        None => ledger.record_issued(cert).await?,
+    }
+    let certificate_pem = String::from_utf8(signed.to_pem()?)?;
+    let mut ca_cert_pem = String::new();
+    let mut full_chain_pem = certificate_pem.clone();
+    for ca_cert in active.chain.iter() {
+        let pem = String::from_utf8(ca_cert.to_pem()?)?;
+        // Self-signed roots belong in trust stores, not in the served chain.
+        if ca_cert.subject_name().to_der()? != ca_cert.issuer_name().to_der()? {
+            full_chain_pem.push_str(&pem);
+        }
+        ca_cert_pem.push_str(&pem);
+    }
 
     Ok(SignedCertResponse {
         certificate_pem,
         ca_cert_pem,
+        full_chain_pem: Some(full_chain_pem),
+        not_after_unix: Some(not_after),
     })
 }
 
+/// Whether every active certificate `subject` of `issuer` holds under
+/// `cert_profile` that a request for `device_id` would replace expires inside
+/// the renewal window, letting the caller re-enroll without `overwrite`.
+#[allow(clippy::too_many_arguments)]
+async fn renewal_due(
+    ledger: &Ledger,
+    profile: &SigningProfile,
+    issuer: &str,
+    subject: &str,
+    cert_profile: &str,
+    device_id: Option<&str>,
+    now: u64,
+) -> AppResult<bool> {
+    if profile.renewal_window_days == 0 {
+        return Ok(false);
+    }
+    let active: Vec<_> = ledger
+        .find_issued_to(issuer, subject)
+        .await?
+        .into_iter()
+        .filter(|e| {
+            !e.revoked && e.profile_name() == cert_profile && e.device_id.as_deref() == device_id
+        })
+        .collect();
+    Ok(!active.is_empty()
+        && active.iter().all(|e| {
+            e.not_after_unix
+                .is_some_and(|na| profile.in_renewal_window(na, now))
+        }))
+}
+
 /// Sign the CSR with the CA to create a certificate, enforcing EKU/KU/SKI and subject
+#[allow(clippy::too_many_arguments)]
 fn sign_csr_with_ca(
     csr: &X509Req,
     ca_cert: &X509Ref,
-    ca_key: &PKey<openssl::pkey::Private>,
-    subject_cn: &str,
-    issuer: &str,
+    signer: &dyn CaSigner,
+    serial: &BigNumRef,
+    identity: &CertIdentity,
+    usage: &CertUsage,
     crl_dist_url: Option<&str>,
+    delta_crl_url: Option<&str>,
+    ocsp_url: Option<&str>,
+    (not_before, not_after): (u64, u64),
 ) -> AppResult<X509> {
     let mut builder = X509::builder()?;
     builder.set_version(2)?;
-    let is_rsa = set_subject_and_pubkey(&mut builder, csr, ca_cert, subject_cn)?;
-    set_serial_number(&mut builder)?;
-    set_validity_1y(&mut builder)?;
+    let key_kind = set_subject_and_pubkey(&mut builder, csr, ca_cert, &identity.subject)?;
+    set_serial_number(&mut builder, serial)?;
+    set_validity(&mut builder, not_before, not_after)?;
     append_core_extensions(&mut builder, ca_cert)?;
     append_crl_dp(&mut builder, ca_cert, crl_dist_url)?;
-    append_key_usage(&mut builder, is_rsa)?;
-    append_client_eku(&mut builder)?;
-    append_san_cn_and_identity_uri(&mut builder, ca_cert, subject_cn, issuer, subject_cn)?;
-    sign_builder(&mut builder, ca_key)?;
-    Ok(builder.build())
+    append_freshest_crl(&mut builder, ca_cert, delta_crl_url)?;
+    append_aia_ocsp(&mut builder, ca_cert, ocsp_url)?;
+    append_key_usage(&mut builder, key_kind, usage.key_usage.as_deref())?;
+    append_eku(&mut builder, &usage.extended)?;
+    append_san(&mut builder, ca_cert, &identity.san)?;
+    sign_certificate(builder, signer, ca_cert)
 }
 
 /// Validate that a Wazuh agent name contains only safe characters.
@@ -141,7 +383,7 @@ fn sign_csr_with_ca(
 /// hyphens, underscores, dots, and spaces — covering typical agent naming
 /// conventions. This is defense-in-depth alongside reqwest's `.query()`
 /// URL-encoding.
-fn validate_agent_name(name: &str) -> AppResult<()> {
+pub(crate) fn validate_agent_name(name: &str) -> AppResult<()> {
     if name.is_empty() {
         return Err(AppError::ValidationError(
             "wazuh_agent_name must not be empty".into(),
@@ -163,9 +405,49 @@ fn validate_agent_name(name: &str) -> AppResult<()> {
     Ok(())
 }
 
+/// Validate a client-supplied device id: 1-64 characters of ASCII letters,
+/// digits, `.`, `_` and `-`.
+pub(crate) fn validate_device_id(device_id: &str) -> AppResult<()> {
+    if device_id.is_empty() || device_id.len() > 64 {
+        return Err(AppError::ValidationError(
+            "device_id must be 1 to 64 characters".into(),
+        ));
+    }
+    if !device_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(AppError::ValidationError(format!(
+            "device_id contains invalid characters: {device_id}"
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{extract_realm_from_issuer, validate_agent_name};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::x509::{X509NameBuilder, X509Req, X509ReqBuilder};
+
+    use super::{
+        extract_realm_from_issuer, sign_csr_with_ca, validate_agent_name, validate_device_id,
+    };
+    use crate::handlers::test_support::{csr_pem, make_ca};
+    use crate::models::cert_profile::CertUsage;
+    use crate::models::key_policy::{KeyPolicy, parse_key_policy};
+    use crate::models::naming_template::{CertIdentity, SanEntry};
+    use crate::shared::certs::{new_serial, parse_and_verify_csr};
+
+    use crate::shared::ca_signer::FileSigner;
+
+    fn identity() -> CertIdentity {
+        CertIdentity {
+            subject: vec![(Nid::COMMONNAME, "agent".into())],
+            san: vec![SanEntry::Dns("agent".into())],
+        }
+    }
 
     #[test]
     fn extracts_realm_when_realms_segment_exists() {
@@ -215,4 +497,101 @@ mod tests {
         let name = "a".repeat(129);
         assert!(validate_agent_name(&name).is_err());
     }
+
+    #[test]
+    fn device_ids_are_short_and_url_safe() {
+        assert!(validate_device_id("laptop-01").is_ok());
+        assert!(validate_device_id("ws_2.example").is_ok());
+        assert!(validate_device_id("").is_err());
+        assert!(validate_device_id(&"d".repeat(65)).is_err());
+        assert!(validate_device_id("my laptop").is_err());
+        assert!(validate_device_id("dev/1").is_err());
+    }
+
+    #[test]
+    fn ocsp_url_is_embedded_as_authority_information_access() {
+        let (ca, ca_key) = make_ca("ca", None);
+        let csr = X509Req::from_pem(csr_pem("agent").as_bytes()).expect("csr");
+        let sign = |ocsp_url| {
+            let cert = sign_csr_with_ca(
+                &csr,
+                &ca,
+                &FileSigner::new(ca_key.clone()),
+                &new_serial().expect("serial"),
+                &identity(),
+                &CertUsage::default(),
+                None,
+                None,
+                ocsp_url,
+                (1_700_000_000, 1_800_000_000),
+            )
+            .expect("sign");
+            String::from_utf8(cert.to_text().expect("text")).expect("utf8")
+        };
+        assert!(
+            sign(Some("https://ocsp.example/ocsp"))
+                .contains("OCSP - URI:https://ocsp.example/ocsp")
+        );
+        assert!(!sign(None).contains("Authority Information Access"));
+    }
+
+    #[test]
+    fn delta_crl_url_is_embedded_as_freshest_crl() {
+        let (ca, ca_key) = make_ca("ca", None);
+        let csr = X509Req::from_pem(csr_pem("agent").as_bytes()).expect("csr");
+        let cert = sign_csr_with_ca(
+            &csr,
+            &ca,
+            &FileSigner::new(ca_key),
+            &new_serial().expect("serial"),
+            &identity(),
+            &CertUsage::default(),
+            Some("https://crl.example/crl/issuing.crl"),
+            Some("https://crl.example/crl/delta/issuing.crl"),
+            None,
+            (1_700_000_000, 1_800_000_000),
+        )
+        .expect("sign");
+        let text = String::from_utf8(cert.to_text().expect("text")).expect("utf8");
+        let freshest = text.split("Freshest CRL").nth(1).expect("freshest crl");
+        assert!(freshest.contains("URI:https://crl.example/crl/delta/issuing.crl"));
+    }
+
+    #[test]
+    fn ed25519_csrs_get_signature_only_key_usage() {
+        let key = PKey::generate_ed25519().expect("ed25519 key");
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", "agent").expect("cn");
+        let mut req = X509ReqBuilder::new().expect("req");
+        req.set_subject_name(&name.build()).expect("subject");
+        req.set_pubkey(&key).expect("pubkey");
+        req.sign(&key, MessageDigest::null()).expect("sign");
+        let pem = String::from_utf8(req.build().to_pem().expect("pem")).expect("utf8");
+
+        assert!(parse_and_verify_csr(&pem, &KeyPolicy::default()).is_err());
+        let policy = parse_key_policy("ed25519").expect("policy");
+        let csr = parse_and_verify_csr(&pem, &policy).expect("csr");
+
+        let (ca, ca_key) = make_ca("ca", None);
+        let cert = sign_csr_with_ca(
+            &csr,
+            &ca,
+            &FileSigner::new(ca_key),
+            &new_serial().expect("serial"),
+            &identity(),
+            &CertUsage::default(),
+            None,
+            None,
+            None,
+            (1_700_000_000, 1_800_000_000),
+        )
+        .expect("sign");
+        let text = String::from_utf8(cert.to_text().expect("text")).expect("utf8");
+        let usage = text
+            .split("X509v3 Key Usage: critical")
+            .nth(1)
+            .expect("key usage");
+        let usage = usage.lines().nth(1).expect("usage line").trim();
+        assert_eq!(usage, "Digital Signature");
+    }
 }
@@ -1,14 +1,29 @@
+use std::ffi::{c_int, c_long};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use foreign_types::ForeignTypeRef;
-use openssl::pkey::PKey;
-use openssl::x509::X509Ref;
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl::asn1::{Asn1Object, Asn1OctetString};
+use openssl::bn::BigNum;
+use openssl::x509::{X509Extension, X509Ref};
 use openssl_sys as ffi;
 use tracing::{debug, info};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
+
+use crate::shared::ca_signer::{self, CaSigner};
 
 use super::RevocationEntry;
 
+unsafe extern "C" {
+    fn ASN1_ENUMERATED_new() -> *mut ffi::ASN1_ENUMERATED;
+    fn ASN1_ENUMERATED_set(a: *mut ffi::ASN1_ENUMERATED, v: c_long) -> c_int;
+    // `t` is a `time_t`, 64 bits on every target the server builds for.
+    fn ASN1_GENERALIZEDTIME_set(
+        s: *mut ffi::ASN1_GENERALIZEDTIME,
+        t: i64,
+    ) -> *mut ffi::ASN1_GENERALIZEDTIME;
+}
+
 pub(crate) unsafe fn create_crl() -> AppResult<*mut ffi::X509_CRL> {
     unsafe {
         debug!("Creating new X509_CRL");
@@ -106,7 +121,7 @@ pub(crate) unsafe fn set_times_now_and_next(crl: *mut ffi::X509_CRL) -> AppResul
 
 pub(crate) unsafe fn add_revocations(
     crl: *mut ffi::X509_CRL,
-    entries_snapshot: Vec<RevocationEntry>,
+    entries_snapshot: &[RevocationEntry],
 ) -> AppResult<()> {
     unsafe {
         info!("Adding {} revocation entries", entries_snapshot.len());
@@ -151,6 +166,14 @@ pub(crate) unsafe fn add_revocations(
                     func: "X509_REVOKED_set_revocationDate",
                 });
             }
+            let reason = e.reason.as_deref().and_then(RevocationReason::from_ledger);
+            // RFC 5280 §5.3.1: omit reasonCode rather than use unspecified.
+            if let Some(reason) = reason.filter(|r| *r != RevocationReason::Unspecified) {
+                add_reason_code(rev, reason)?;
+            }
+            if let Some(at) = e.invalidity_date_unix {
+                add_invalidity_date(rev, at)?;
+            }
             if ffi::X509_CRL_add0_revoked(crl, rev) != 1 {
                 return Err(AppError::CrlFfi {
                     func: "X509_CRL_add0_revoked",
@@ -162,9 +185,132 @@ pub(crate) unsafe fn add_revocations(
     }
 }
 
+unsafe fn add_reason_code(rev: *mut ffi::X509_REVOKED, reason: RevocationReason) -> AppResult<()> {
+    unsafe {
+        let code = ASN1_ENUMERATED_new();
+        if code.is_null() {
+            return Err(AppError::CrlFfi {
+                func: "ASN1_ENUMERATED_new",
+            });
+        }
+        let ok = ASN1_ENUMERATED_set(code, reason.code() as c_long) == 1
+            && ffi::X509_REVOKED_add1_ext_i2d(rev, ffi::NID_crl_reason, code as *mut _, 0, 0) == 1;
+        ffi::ASN1_ENUMERATED_free(code);
+        if !ok {
+            return Err(AppError::CrlFfi {
+                func: "X509_REVOKED_add1_ext_i2d",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Add the invalidityDate extension (RFC 5280 §5.3.2), which is always a
+/// GeneralizedTime.
+unsafe fn add_invalidity_date(rev: *mut ffi::X509_REVOKED, at: u64) -> AppResult<()> {
+    unsafe {
+        let time = invalidity_date(at)?;
+        let ok =
+            ffi::X509_REVOKED_add1_ext_i2d(rev, ffi::NID_invalidity_date, time as *mut _, 0, 0)
+                == 1;
+        ffi::ASN1_GENERALIZEDTIME_free(time);
+        if !ok {
+            return Err(AppError::CrlFfi {
+                func: "X509_REVOKED_add1_ext_i2d",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A new GeneralizedTime for `at`, to be freed by the caller.
+pub(crate) unsafe fn invalidity_date(at: u64) -> AppResult<*mut ffi::ASN1_GENERALIZEDTIME> {
+    unsafe {
+        let time = ASN1_GENERALIZEDTIME_set(std::ptr::null_mut(), at as i64);
+        if time.is_null() {
+            return Err(AppError::CrlFfi {
+                func: "ASN1_GENERALIZEDTIME_set",
+            });
+        }
+        Ok(time)
+    }
+}
+
+/// Add the cRLNumber and, when the CA has a subject key identifier, the
+/// authorityKeyIdentifier extension (RFC 5280 §5.2.1, §5.2.3).
+pub(crate) unsafe fn set_number_and_authority_key_id(
+    crl: *mut ffi::X509_CRL,
+    ca_cert: &X509Ref,
+    number: u64,
+) -> AppResult<()> {
+    unsafe {
+        debug!("Setting cRLNumber={}", number);
+        let number = BigNum::from_slice(&number.to_be_bytes())?.to_asn1_integer()?;
+        if ffi::X509_CRL_add1_ext_i2d(crl, ffi::NID_crl_number, number.as_ptr() as *mut _, 0, 0)
+            != 1
+        {
+            return Err(AppError::CrlFfi {
+                func: "X509_CRL_add1_ext_i2d",
+            });
+        }
+        let Some(ski) = ca_cert.subject_key_id() else {
+            debug!("CA has no subject key identifier; CRL is left without AKI");
+            return Ok(());
+        };
+        // AuthorityKeyIdentifier ::= SEQUENCE { keyIdentifier [0] IMPLICIT OCTET STRING }
+        let ski = ski.as_slice();
+        let mut der = vec![0x30, ski.len() as u8 + 2, 0x80, ski.len() as u8];
+        der.extend_from_slice(ski);
+        let oid = Asn1Object::from_str("2.5.29.35")?;
+        let value = Asn1OctetString::new_from_bytes(&der)?;
+        let ext = X509Extension::new_from_der(&oid, false, &value)?;
+        if ffi::X509_CRL_add_ext(crl, ext.as_ptr(), -1) != 1 {
+            return Err(AppError::CrlFfi {
+                func: "X509_CRL_add_ext",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Mark the CRL as a delta against the complete CRL numbered `base_number`
+/// (critical Delta CRL Indicator, RFC 5280 §5.2.4).
+pub(crate) unsafe fn set_delta_crl_indicator(
+    crl: *mut ffi::X509_CRL,
+    base_number: u64,
+) -> AppResult<()> {
+    unsafe {
+        debug!("Setting deltaCRLIndicator={}", base_number);
+        let base = BigNum::from_slice(&base_number.to_be_bytes())?.to_asn1_integer()?;
+        if ffi::X509_CRL_add1_ext_i2d(crl, ffi::NID_delta_crl, base.as_ptr() as *mut _, 1, 0) != 1 {
+            return Err(AppError::CrlFfi {
+                func: "X509_CRL_add1_ext_i2d",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Point a complete CRL at its delta CRL (Freshest CRL, RFC 5280 §5.2.6).
+pub(crate) unsafe fn set_freshest_crl(crl: *mut ffi::X509_CRL, url: &str) -> AppResult<()> {
+    unsafe {
+        debug!("Setting freshestCRL={}", url);
+        // Same syntax as crlDistributionPoints, which has no typed builder.
+        #[allow(deprecated)]
+        let ext = X509Extension::new(None, None, "freshestCRL", &format!("URI:{}", url))?;
+        if ffi::X509_CRL_add_ext(crl, ext.as_ptr(), -1) != 1 {
+            return Err(AppError::CrlFfi {
+                func: "X509_CRL_add_ext",
+            });
+        }
+        Ok(())
+    }
+}
+
 pub(crate) unsafe fn sort_and_sign(
     crl: *mut ffi::X509_CRL,
-    ca_key: &PKey<openssl::pkey::Private>,
+    signer: &dyn CaSigner,
+    ca_cert: &X509Ref,
 ) -> AppResult<()> {
     unsafe {
         debug!("Sorting CRL entries");
@@ -174,12 +320,7 @@ pub(crate) unsafe fn sort_and_sign(
             });
         }
         info!("Signing CRL with SHA-256");
-        let md = ffi::EVP_sha256();
-        if ffi::X509_CRL_sign(crl, ca_key.as_ptr(), md) == 0 {
-            return Err(AppError::CrlFfi {
-                func: "X509_CRL_sign",
-            });
-        }
+        ca_signer::sign_crl(crl, signer, ca_cert)?;
         debug!("CRL signed successfully");
         Ok(())
     }
@@ -1,8 +1,9 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use openssl::pkey::PKey;
-use openssl::x509::X509;
+use openssl::x509::{CrlNumber, X509, X509Crl};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sqlx::PgPool;
@@ -11,19 +12,53 @@ use tokio::sync::{mpsc, oneshot, watch};
 use tracing::{debug, info};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 
+use crate::models::ca_config::{CaProvider, IssuingCa};
+use crate::shared::ledger::Ledger;
+
 /// ETag string paired with an optional cached CRL body.
 /// `None` means no valid CRL is loaded (cold start or failed rebuild).
 type CrlWatchValue = (String, Option<Arc<Vec<u8>>>);
 
-mod ffi;
+pub(crate) mod ffi;
 mod postgres;
 mod worker;
 
+/// Which of an issuer's CRLs: the complete CRL or the delta against its
+/// current base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrlKind {
+    Complete,
+    Delta,
+}
+
+/// Delta CRL publication (RFC 5280 §5.2.4).
+///
+/// Every rebuild then also signs a delta CRL listing the revocations since
+/// the base: the complete CRL current when the base was last moved. The base
+/// moves every `base_interval`, so clients refresh the complete CRL about
+/// that often and otherwise only fetch the small delta.
+#[derive(Debug, Clone)]
+pub struct DeltaCrlConfig {
+    /// Delta CRL location advertised as Freshest CRL; `{issuer}` is
+    /// replaced by the CA's key id.
+    pub url: String,
+    pub base_interval: Duration,
+}
+
+impl DeltaCrlConfig {
+    fn url_for(&self, ca: &IssuingCa) -> String {
+        self.url.replace("{issuer}", &ca.key_id)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RevocationEntry {
     pub serial_hex: String,
     pub reason: Option<String>,
     pub revoked_at_unix: u64,
+    /// Published as the entry's invalidityDate extension.
+    #[serde(default)]
+    pub invalidity_date_unix: Option<u64>,
 }
 
 /// Compute a SHA-256 ETag from arbitrary bytes.
@@ -47,27 +82,98 @@ fn generate_replica_id() -> String {
     buf.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-/// Where the CRL artifact is persisted.
+/// Where CRL artifacts are persisted.
 ///
 /// - [`CrlBackend::File`]: local-dev / bootstrap fallback (writes DER to disk).
-/// - [`CrlBackend::Postgres`]: shared `crl_cache` table + `NOTIFY crl_changed`
-///   so multiple replicas serve a consistent CRL.
+///   Each CA's CRL is written next to the configured path as
+///   `<stem>-<key id>.crl`, whether it is active or retiring, and the
+///   configured path holds a copy of the active CA's.
+/// - [`CrlBackend::Postgres`]: shared `crl_cache` table (one row per issuer)
+///   + `NOTIFY crl_changed` so multiple replicas serve consistent CRLs.
 #[derive(Clone)]
 pub enum CrlBackend {
     File(PathBuf),
     Postgres(PgPool),
 }
 
+/// File holding the CRL of `ca` for a [`CrlBackend::File`] rooted at `base`,
+/// keyed by the CA's key id so it stays put when the CA stops being active.
+/// Delta CRLs sit next to the complete one with a `-delta` suffix.
+fn crl_file_path(base: &Path, ca: &IssuingCa, kind: CrlKind) -> PathBuf {
+    let stem = format!("{}-{}", file_stem(base), ca.key_id);
+    match kind {
+        CrlKind::Complete => base.with_file_name(format!("{}.crl", stem)),
+        CrlKind::Delta => base.with_file_name(format!("{}-delta.crl", stem)),
+    }
+}
+
+/// The copy of the active CA's CRL at the configured path (`<stem>-delta.crl`
+/// for the delta CRL). Before CRLs were keyed by key id the active CA's CRL
+/// lived only here.
+fn active_alias_path(base: &Path, kind: CrlKind) -> PathBuf {
+    match kind {
+        CrlKind::Complete => base.to_path_buf(),
+        CrlKind::Delta => base.with_file_name(format!("{}-delta.crl", file_stem(base))),
+    }
+}
+
+fn file_stem(base: &Path) -> String {
+    base.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "issuing".into())
+}
+
+/// The CRL of `ca` in the file at `path`; `None` when the file is missing or
+/// holds another CA's CRL.
+async fn read_crl_file(path: &Path, ca: &IssuingCa) -> AppResult<Option<Vec<u8>>> {
+    match fs::read(path).await {
+        Ok(bytes) if signed_by(&bytes, &ca.cert) => Ok(Some(bytes)),
+        Ok(_) => {
+            debug!("CRL file {} is not signed by this CA", path.display());
+            Ok(None)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+type ChannelMap = Arc<Mutex<HashMap<String, watch::Sender<CrlWatchValue>>>>;
+
+/// Watch channel per issuer and [`CrlKind`], keyed by uppercase CA key id.
+///
+/// Channels are created on first use and start empty; the first reader
+/// seeds them from the backend.
+#[derive(Clone, Default)]
+struct CrlChannels {
+    complete: ChannelMap,
+    delta: ChannelMap,
+}
+
+impl CrlChannels {
+    fn sender(&self, key_id: &str, kind: CrlKind) -> watch::Sender<CrlWatchValue> {
+        let map = match kind {
+            CrlKind::Complete => &self.complete,
+            CrlKind::Delta => &self.delta,
+        };
+        let mut map = map.lock().unwrap_or_else(|e| e.into_inner());
+        map.entry(key_id.to_uppercase())
+            .or_insert_with(|| watch::channel((String::new(), None)).0)
+            .clone()
+    }
+}
+
 #[derive(Clone)]
 pub struct CrlState {
     backend: CrlBackend,
     tx: mpsc::Sender<worker::Command>,
-    rebuild_notify: watch::Sender<CrlWatchValue>,
+    channels: CrlChannels,
+    deltas: bool,
 }
 
 impl CrlState {
+    /// Delta CRLs are published next to the complete ones when `delta` is set.
     #[tracing::instrument(skip(backend))]
-    pub async fn new(backend: CrlBackend) -> AppResult<Self> {
+    pub async fn new(backend: CrlBackend, delta: Option<DeltaCrlConfig>) -> AppResult<Self> {
         match &backend {
             CrlBackend::File(path) => {
                 info!("Initialized CrlState (file) with path: {}", path.display())
@@ -76,92 +182,103 @@ impl CrlState {
         }
         let (tx, rx) = mpsc::channel::<worker::Command>(32);
 
-        let (initial_etag, initial_body) = Self::compute_initial(&backend).await?;
-        let (rebuild_tx, _) = watch::channel((initial_etag, initial_body));
+        let channels = CrlChannels::default();
         let replica_id = generate_replica_id();
-        worker::spawn_crl_worker(backend.clone(), replica_id.clone(), rx, rebuild_tx.clone());
+        let deltas = delta.is_some();
+        worker::spawn_crl_worker(
+            backend.clone(),
+            delta,
+            replica_id.clone(),
+            rx,
+            channels.clone(),
+        );
 
         if let CrlBackend::Postgres(pool) = &backend {
-            postgres::spawn_crl_listener(pool.clone(), replica_id, rebuild_tx.clone());
+            postgres::spawn_crl_listener(pool.clone(), replica_id, channels.clone());
         }
 
         Ok(Self {
             backend,
             tx,
-            rebuild_notify: rebuild_tx,
+            channels,
+            deltas,
         })
     }
 
-    /// Read the current CRL bytes from the backend.
+    /// Whether delta CRLs are published.
+    pub fn deltas_enabled(&self) -> bool {
+        self.deltas
+    }
+
+    /// Read the current CRL of `ca` from the backend.
     ///
-    /// Returns `Ok(Vec::new())` when no CRL is available (file missing or no
-    /// cache row) so callers can trigger an on-demand rebuild.
-    #[tracing::instrument(skip(self))]
-    pub async fn read_crl(&self) -> AppResult<Vec<u8>> {
-        match &self.backend {
-            CrlBackend::File(path) => {
+    /// Returns `Ok(Vec::new())` when no CRL is available (file missing, no
+    /// cache row, or a file left behind by another CA) so callers can
+    /// trigger an on-demand rebuild. A CRL found here seeds the issuer's
+    /// watch channel if nothing is cached yet.
+    #[tracing::instrument(skip(self, ca), fields(issuer = %ca.key_id))]
+    pub async fn read_crl(&self, ca: &IssuingCa, kind: CrlKind) -> AppResult<Vec<u8>> {
+        let bytes = match &self.backend {
+            CrlBackend::File(base) => {
+                let path = crl_file_path(base, ca, kind);
                 debug!("Reading CRL file from: {}", path.display());
-                match fs::read(path).await {
-                    Ok(bytes) => {
-                        debug!("Read CRL file ({} bytes)", bytes.len());
-                        Ok(bytes)
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
-                    Err(e) => Err(e.into()),
-                }
+                // A CRL written before files were keyed by key id is only
+                // found at the alias, which holds whichever CA was active.
+                let bytes = match read_crl_file(&path, ca).await? {
+                    Some(bytes) => Some(bytes),
+                    None => read_crl_file(&active_alias_path(base, kind), ca).await?,
+                };
+                bytes.unwrap_or_default()
             }
             CrlBackend::Postgres(pool) => {
                 debug!("Reading CRL from cache");
-                match postgres::load_crl_from_cache(pool).await {
-                    Ok(Some((_, body))) => Ok(body.to_vec()),
-                    Ok(None) => Ok(Vec::new()),
-                    Err(e) => Err(e),
+                match postgres::load_crl_from_cache(pool, &ca.key_id, kind).await? {
+                    Some((_, body)) => body.to_vec(),
+                    None => Vec::new(),
                 }
             }
+        };
+        if !bytes.is_empty() {
+            let etag = compute_etag(&bytes);
+            let body = Arc::new(bytes.clone());
+            self.channels
+                .sender(&ca.key_id, kind)
+                .send_if_modified(|current| {
+                    if current.1.is_some() {
+                        return false;
+                    }
+                    *current = (etag, Some(body));
+                    true
+                });
         }
+        Ok(bytes)
     }
 
-    /// Subscribe to CRL rebuild notifications.
+    /// Subscribe to CRL rebuild notifications for `ca`.
     ///
-    /// Returns a [`watch::Receiver`] that is notified whenever the CRL is
-    /// rebuilt (whether triggered by a long-poll client, a revocation
+    /// Returns a [`watch::Receiver`] that is notified whenever the issuer's
+    /// CRL is rebuilt (whether triggered by a long-poll client, a revocation
     /// request, or a `crl_changed` notification from another replica). The
     /// long-poll handler uses this to hold the connection open until the
     /// ETag changes or a timeout elapses.
-    pub fn subscribe_rebuild(&self) -> watch::Receiver<CrlWatchValue> {
-        self.rebuild_notify.subscribe()
-    }
-
-    async fn compute_initial(backend: &CrlBackend) -> AppResult<CrlWatchValue> {
-        match backend {
-            CrlBackend::File(path) => match fs::read(path).await {
-                Ok(bytes) => Ok((compute_etag(&bytes), Some(Arc::new(bytes)))),
-                // Missing file is a cold start (empty CRL); other I/O errors
-                // are real failures and should surface rather than silently
-                // starting with an empty CRL.
-                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok((String::new(), None)),
-                Err(e) => Err(e.into()),
-            },
-            CrlBackend::Postgres(pool) => match postgres::load_crl_from_cache(pool).await {
-                Ok(Some((etag, body))) => Ok((etag, Some(body))),
-                Ok(None) => Ok((String::new(), None)),
-                Err(e) => Err(e),
-            },
-        }
+    pub fn subscribe_rebuild(
+        &self,
+        ca: &IssuingCa,
+        kind: CrlKind,
+    ) -> watch::Receiver<CrlWatchValue> {
+        self.channels.sender(&ca.key_id, kind).subscribe()
     }
 
-    #[tracing::instrument(skip(self, ca_cert, ca_key, entries_snapshot))]
+    #[tracing::instrument(skip(self, ca, entries_snapshot), fields(issuer = %ca.key_id))]
     pub async fn request_rebuild(
         &self,
-        ca_cert: Arc<X509>,
-        ca_key: Arc<PKey<openssl::pkey::Private>>,
+        ca: Arc<IssuingCa>,
         entries_snapshot: Vec<RevocationEntry>,
     ) -> AppResult<()> {
         let (tx_done, rx_done) = oneshot::channel();
         self.tx
             .send(worker::Command::Rebuild {
-                ca_cert,
-                ca_key,
+                ca,
                 entries_snapshot,
                 respond_to: tx_done,
             })
@@ -173,15 +290,48 @@ impl CrlState {
 
         Ok(())
     }
+
+    /// Rebuild the CRL of `ca` from the ledger's revocations for it.
+    pub async fn rebuild(&self, ca: &Arc<IssuingCa>, ledger: &Ledger) -> AppResult<()> {
+        let revs = ledger.revocations_for_issuer(&ca.key_id).await?;
+        self.request_rebuild(ca.clone(), revs).await
+    }
+
+    /// Rebuild the CRL of every configured CA.
+    pub async fn rebuild_all(&self, ca: &CaProvider, ledger: &Ledger) -> AppResult<()> {
+        for issuer in ca.issuers().await?.iter() {
+            self.rebuild(issuer, ledger).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `der` is a CRL signed by `ca_cert`.
+fn signed_by(der: &[u8], ca_cert: &X509) -> bool {
+    let (Ok(crl), Ok(key)) = (X509Crl::from_der(der), ca_cert.public_key()) else {
+        return false;
+    };
+    crl.verify(&key).unwrap_or(false)
+}
+
+/// The cRLNumber of the CRL `der`, if it has one.
+fn crl_number(der: &[u8]) -> Option<u64> {
+    let crl = X509Crl::from_der(der).ok()?;
+    let (_, number) = crl.extension::<CrlNumber>().ok()??;
+    number.to_bn().ok()?.to_dec_str().ok()?.parse().ok()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::handlers::test_support::temp_dir;
+    use crate::models::ca_config::key_id;
+    use crate::shared::ca_signer::FileSigner;
     use openssl::hash::MessageDigest;
-    use openssl::pkey::{PKey, Private};
+    use openssl::pkey::PKey;
     use openssl::rsa::Rsa;
-    use openssl::x509::{X509, X509NameBuilder};
+    use openssl::x509::extension::SubjectKeyIdentifier;
+    use openssl::x509::{CrlReason, ReasonCode, X509, X509NameBuilder};
 
     /// Connect to a real Postgres for integration tests. Skips when
     /// `TEST_DATABASE_URL` is not set (e.g. plain `cargo test`).
@@ -202,7 +352,7 @@ mod tests {
         Some(pool)
     }
 
-    fn test_ca() -> (X509, PKey<Private>) {
+    fn test_ca(active: bool) -> Arc<IssuingCa> {
         let rsa = Rsa::generate(2048).expect("generate rsa");
         let key = PKey::from_rsa(rsa).expect("pkey from rsa");
         let mut name_builder = X509NameBuilder::new().expect("name builder");
@@ -215,8 +365,355 @@ mod tests {
         builder.set_subject_name(&name).expect("set subject");
         builder.set_issuer_name(&name).expect("set issuer");
         builder.set_pubkey(&key).expect("set pubkey");
+        let ski = SubjectKeyIdentifier::new()
+            .build(&builder.x509v3_context(None, None))
+            .expect("ski");
+        builder.append_extension(ski).expect("append ski");
         builder.sign(&key, MessageDigest::sha256()).expect("sign");
-        (builder.build(), key)
+        let cert = builder.build();
+        Arc::new(IssuingCa {
+            key_id: key_id(&cert).expect("key id"),
+            cert: Arc::new(cert.clone()),
+            signer: Arc::new(FileSigner::new(key)),
+            chain: Arc::new(vec![cert]),
+            active,
+        })
+    }
+
+    #[tokio::test]
+    async fn file_crls_are_kept_per_issuer() {
+        let dir = temp_dir("crl-issuers");
+        let base = dir.join("issuing.crl");
+        let state = CrlState::new(CrlBackend::File(base.clone()), None)
+            .await
+            .expect("crl state");
+        let active = test_ca(true);
+        let retiring = test_ca(false);
+
+        let rx = state.subscribe_rebuild(&retiring, CrlKind::Complete);
+        state
+            .request_rebuild(
+                retiring.clone(),
+                vec![RevocationEntry {
+                    serial_hex: "0A".into(),
+                    reason: None,
+                    revoked_at_unix: 100,
+                    invalidity_date_unix: None,
+                }],
+            )
+            .await
+            .expect("rebuild retiring");
+        let retiring_path = dir.join(format!("issuing-{}.crl", retiring.key_id));
+        let on_disk = fs::read(&retiring_path).await.expect("retiring crl");
+        assert_eq!(rx.borrow().1.as_deref(), Some(&on_disk));
+        assert!(
+            state
+                .subscribe_rebuild(&active, CrlKind::Complete)
+                .borrow()
+                .1
+                .is_none()
+        );
+
+        // A CRL at the active path signed by another CA is not served.
+        fs::write(&base, &on_disk).await.expect("write stale crl");
+        assert!(
+            state
+                .read_crl(&active, CrlKind::Complete)
+                .await
+                .expect("read")
+                .is_empty()
+        );
+        state
+            .request_rebuild(active.clone(), vec![])
+            .await
+            .expect("rebuild active");
+        let active_crl = state
+            .read_crl(&active, CrlKind::Complete)
+            .await
+            .expect("read");
+        assert!(signed_by(&active_crl, &active.cert));
+        let active_path = dir.join(format!("issuing-{}.crl", active.key_id));
+        assert_eq!(
+            fs::read(&active_path).await.expect("active crl"),
+            active_crl
+        );
+        assert_eq!(fs::read(&base).await.expect("alias"), active_crl);
+        assert_eq!(
+            state
+                .read_crl(&retiring, CrlKind::Complete)
+                .await
+                .expect("read retiring"),
+            on_disk
+        );
+
+        let _ = fs::remove_dir_all(dir).await;
+    }
+    #[tokio::test]
+    async fn rollover_keeps_crl_numbers_and_delta_base() {
+        let dir = temp_dir("crl-rollover");
+        let base = dir.join("issuing.crl");
+        let state = CrlState::new(
+            CrlBackend::File(base.clone()),
+            Some(DeltaCrlConfig {
+                url: "http://pki.test/crl/delta/{issuer}.crl".into(),
+                base_interval: Duration::from_secs(3600),
+            }),
+        )
+        .await
+        .expect("crl state");
+        let ca = test_ca(true);
+        for _ in 0..2 {
+            state
+                .request_rebuild(ca.clone(), vec![])
+                .await
+                .expect("rebuild active");
+        }
+
+        // The same CA after a rollover made another one active.
+        let retiring = Arc::new(IssuingCa {
+            key_id: ca.key_id.clone(),
+            cert: ca.cert.clone(),
+            signer: ca.signer.clone(),
+            chain: ca.chain.clone(),
+            active: false,
+        });
+        state
+            .request_rebuild(retiring.clone(), vec![])
+            .await
+            .expect("rebuild retiring");
+        let complete = state
+            .read_crl(&retiring, CrlKind::Complete)
+            .await
+            .expect("read");
+        let delta = state
+            .read_crl(&retiring, CrlKind::Delta)
+            .await
+            .expect("read");
+        assert_eq!(crl_number(&complete), Some(3));
+        assert_eq!(delta_base_of(&delta), Some(1));
+
+        // CRLs written when the active CA's lived only at the configured
+        // path continue from there.
+        let legacy = temp_dir("crl-legacy");
+        fs::write(legacy.join("issuing.crl"), &complete)
+            .await
+            .expect("write legacy crl");
+        let state = CrlState::new(CrlBackend::File(legacy.join("issuing.crl")), None)
+            .await
+            .expect("crl state");
+        assert_eq!(
+            state.read_crl(&ca, CrlKind::Complete).await.expect("read"),
+            complete
+        );
+        state
+            .request_rebuild(ca.clone(), vec![])
+            .await
+            .expect("rebuild");
+        let next = state.read_crl(&ca, CrlKind::Complete).await.expect("read");
+        assert_eq!(crl_number(&next), Some(4));
+
+        let _ = fs::remove_dir_all(dir).await;
+        let _ = fs::remove_dir_all(legacy).await;
+    }
+
+    #[tokio::test]
+    async fn crls_carry_reason_codes_numbers_and_authority_key_id() {
+        let dir = temp_dir("crl-extensions");
+        let state = CrlState::new(CrlBackend::File(dir.join("issuing.crl")), None)
+            .await
+            .expect("crl state");
+        let ca = test_ca(true);
+        let entry = |serial: &str, reason: &str| RevocationEntry {
+            serial_hex: serial.into(),
+            reason: Some(reason.into()),
+            revoked_at_unix: 100,
+            invalidity_date_unix: None,
+        };
+        let compromised = RevocationEntry {
+            invalidity_date_unix: Some(1_700_000_000),
+            ..entry("0A", "keyCompromise: laptop stolen")
+        };
+
+        for expected in 1..=2 {
+            state
+                .request_rebuild(
+                    ca.clone(),
+                    vec![
+                        compromised.clone(),
+                        entry("0B", crate::shared::ledger::ROTATED_REASON),
+                        entry("0C", "test"),
+                        // Free text is not guessed at.
+                        entry("0D", "not compromised, rotating"),
+                    ],
+                )
+                .await
+                .expect("rebuild");
+            let der = state.read_crl(&ca, CrlKind::Complete).await.expect("read");
+            assert_eq!(crl_number(&der), Some(expected));
+        }
+
+        let der = state.read_crl(&ca, CrlKind::Complete).await.expect("read");
+        let crl = X509Crl::from_der(&der).expect("crl");
+        let reasons: Vec<_> = crl
+            .get_revoked()
+            .expect("revoked")
+            .iter()
+            .map(|r| {
+                r.extension::<ReasonCode>()
+                    .expect("reason")
+                    .map(|(_, code)| CrlReason::from_raw(code.get_i64().expect("i64") as _))
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                Some(CrlReason::KEY_COMPROMISE),
+                Some(CrlReason::SUPERSEDED),
+                None,
+                None
+            ]
+        );
+        // invalidityDate (2.5.29.24) of 0A as a GeneralizedTime.
+        let oid = [0x06, 0x03, 0x55, 0x1d, 0x18];
+        assert_eq!(der.windows(oid.len()).filter(|w| *w == oid).count(), 1);
+        assert!(
+            der.windows(17)
+                .any(|w| w == b"\x18\x0f20231114221320Z".as_slice())
+        );
+        let ski = ca.cert.subject_key_id().expect("ski").as_slice();
+        let mut aki = vec![0x30, ski.len() as u8 + 2, 0x80, ski.len() as u8];
+        aki.extend_from_slice(ski);
+        assert!(der.windows(aki.len()).any(|w| w == aki.as_slice()));
+
+        let _ = fs::remove_dir_all(dir).await;
+    }
+
+    /// The base CRL number of a delta CRL, `None` for a complete CRL.
+    fn delta_base_of(der: &[u8]) -> Option<u64> {
+        use foreign_types::ForeignTypeRef;
+        use openssl::asn1::Asn1IntegerRef;
+        let crl = X509Crl::from_der(der).expect("crl");
+        // SAFETY: the returned ASN1_INTEGER is owned here and freed below.
+        unsafe {
+            let base = openssl_sys::X509_CRL_get_ext_d2i(
+                crl.as_ptr(),
+                openssl_sys::NID_delta_crl,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ) as *mut openssl_sys::ASN1_INTEGER;
+            if base.is_null() {
+                return None;
+            }
+            let n = Asn1IntegerRef::from_ptr(base).to_bn().expect("bn");
+            openssl_sys::ASN1_INTEGER_free(base);
+            n.to_dec_str().expect("dec").parse().ok()
+        }
+    }
+
+    #[tokio::test]
+    async fn delta_crls_list_revocations_since_their_base() {
+        let dir = temp_dir("crl-delta");
+        let delta = |base_interval| DeltaCrlConfig {
+            url: "http://pki.test/crl/delta/{issuer}.crl".into(),
+            base_interval,
+        };
+        let state = CrlState::new(
+            CrlBackend::File(dir.join("issuing.crl")),
+            Some(delta(Duration::from_secs(3600))),
+        )
+        .await
+        .expect("crl state");
+        let ca = test_ca(true);
+        let now = crate::shared::certs::unix_now();
+        let entry = |serial: &str, revoked_at_unix| RevocationEntry {
+            serial_hex: serial.into(),
+            reason: None,
+            revoked_at_unix,
+            invalidity_date_unix: None,
+        };
+        let serials = |der: &[u8]| -> Vec<String> {
+            X509Crl::from_der(der)
+                .expect("crl")
+                .get_revoked()
+                .map(|revoked| {
+                    revoked
+                        .iter()
+                        .map(|r| r.serial_number().to_bn().expect("bn").to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut entries = vec![entry("0A", 100)];
+        state
+            .request_rebuild(ca.clone(), entries.clone())
+            .await
+            .expect("rebuild");
+        entries.push(entry("0B", now));
+        state
+            .request_rebuild(ca.clone(), entries.clone())
+            .await
+            .expect("rebuild");
+
+        let complete = state.read_crl(&ca, CrlKind::Complete).await.expect("read");
+        let delta_crl = state.read_crl(&ca, CrlKind::Delta).await.expect("read");
+        assert!(
+            dir.join(format!("issuing-{}-delta.crl", ca.key_id))
+                .exists()
+        );
+        assert!(dir.join("issuing-delta.crl").exists());
+        assert!(signed_by(&delta_crl, &ca.cert));
+        assert_eq!(crl_number(&complete), Some(2));
+        assert_eq!(crl_number(&delta_crl), Some(2));
+        assert_eq!(delta_base_of(&complete), None);
+        assert_eq!(delta_base_of(&delta_crl), Some(1));
+        assert_eq!(serials(&complete), vec!["10", "11"]);
+        assert_eq!(serials(&delta_crl), vec!["11"]);
+        let url = format!("http://pki.test/crl/delta/{}.crl", ca.key_id);
+        assert!(complete.windows(url.len()).any(|w| w == url.as_bytes()));
+        assert!(!delta_crl.windows(url.len()).any(|w| w == url.as_bytes()));
+        assert_eq!(
+            state
+                .subscribe_rebuild(&ca, CrlKind::Delta)
+                .borrow()
+                .1
+                .as_deref(),
+            Some(&delta_crl)
+        );
+
+        // A restart keeps the base the deltas were published against.
+        let state = CrlState::new(
+            CrlBackend::File(dir.join("issuing.crl")),
+            Some(delta(Duration::from_secs(3600))),
+        )
+        .await
+        .expect("crl state");
+        state
+            .request_rebuild(ca.clone(), entries.clone())
+            .await
+            .expect("rebuild");
+        let delta_crl = state.read_crl(&ca, CrlKind::Delta).await.expect("read");
+        assert_eq!(crl_number(&delta_crl), Some(3));
+        assert_eq!(delta_base_of(&delta_crl), Some(1));
+        assert_eq!(serials(&delta_crl), vec!["11"]);
+
+        // Once the base interval has passed, the CRL being built becomes the
+        // new base.
+        let state = CrlState::new(
+            CrlBackend::File(dir.join("issuing.crl")),
+            Some(delta(Duration::ZERO)),
+        )
+        .await
+        .expect("crl state");
+        state
+            .request_rebuild(ca.clone(), entries)
+            .await
+            .expect("rebuild");
+        let delta_crl = state.read_crl(&ca, CrlKind::Delta).await.expect("read");
+        assert_eq!(crl_number(&delta_crl), Some(4));
+        assert_eq!(delta_base_of(&delta_crl), Some(4));
+
+        let _ = fs::remove_dir_all(dir).await;
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -224,32 +721,37 @@ mod tests {
         let Some(pool) = test_pool().await else {
             return;
         };
-        // Reset the single-row cache so generation starts at 1 (the table
-        // persists across test runs).
+        // Start from an empty cache (the table persists across test runs).
         sqlx::query("DELETE FROM crl_cache")
             .execute(&pool)
             .await
             .expect("clear crl_cache");
-        let (ca_cert, ca_key) = test_ca();
+        let ca = test_ca(true);
 
-        let state = CrlState::new(CrlBackend::Postgres(pool.clone()))
+        let delta = DeltaCrlConfig {
+            url: "http://pki.test/crl/delta/{issuer}.crl".into(),
+            base_interval: Duration::from_secs(3600),
+        };
+        let state = CrlState::new(CrlBackend::Postgres(pool.clone()), Some(delta))
             .await
             .expect("crl state");
 
-        let mut rx = state.subscribe_rebuild();
+        let mut rx = state.subscribe_rebuild(&ca, CrlKind::Complete);
         let entries = vec![RevocationEntry {
             serial_hex: "ABC123".to_string(),
             reason: Some("test".to_string()),
             revoked_at_unix: 100,
+            invalidity_date_unix: None,
         }];
         state
-            .request_rebuild(Arc::new(ca_cert), Arc::new(ca_key), entries)
+            .request_rebuild(ca.clone(), entries)
             .await
             .expect("rebuild should succeed");
 
         // The crl_cache table should now hold the signed DER + etag + generation.
         let row: Option<(Vec<u8>, String, i64)> =
-            sqlx::query_as("SELECT der, etag, generation FROM crl_cache WHERE id = 1")
+            sqlx::query_as("SELECT der, etag, generation FROM crl_cache WHERE issuer_key_id = $1")
+                .bind(&ca.key_id)
                 .fetch_optional(&pool)
                 .await
                 .expect("query crl_cache");
@@ -266,15 +768,48 @@ mod tests {
         }
 
         // A second rebuild bumps the generation counter.
-        let (ca_cert, ca_key) = test_ca();
         state
-            .request_rebuild(Arc::new(ca_cert), Arc::new(ca_key), vec![])
+            .request_rebuild(ca.clone(), vec![])
             .await
             .expect("second rebuild");
-        let gen2: i64 = sqlx::query_scalar("SELECT generation FROM crl_cache WHERE id = 1")
-            .fetch_one(&pool)
-            .await
-            .expect("query generation");
+        let gen2: i64 =
+            sqlx::query_scalar("SELECT generation FROM crl_cache WHERE issuer_key_id = $1")
+                .bind(&ca.key_id)
+                .fetch_one(&pool)
+                .await
+                .expect("query generation");
         assert_eq!(gen2, 2, "generation should increment on each rebuild");
+
+        // The delta CRL and its base travel with the complete CRL.
+        let (delta_der, base_number): (Vec<u8>, i64) = sqlx::query_as(
+            "SELECT delta_der, delta_base_number FROM crl_cache WHERE issuer_key_id = $1",
+        )
+        .bind(&ca.key_id)
+        .fetch_one(&pool)
+        .await
+        .expect("query delta");
+        assert_eq!(base_number, 1);
+        assert_eq!(crl_number(&delta_der), Some(2));
+        assert_eq!(delta_base_of(&delta_der), Some(1));
+
+        // The generation is the cRLNumber; a replica that published a higher
+        // one first is never overwritten by a lower one.
+        sqlx::query("UPDATE crl_cache SET generation = 10 WHERE issuer_key_id = $1")
+            .bind(&ca.key_id)
+            .execute(&pool)
+            .await
+            .expect("bump generation");
+        state
+            .request_rebuild(ca.clone(), vec![])
+            .await
+            .expect("third rebuild");
+        let (der, generation): (Vec<u8>, i64) =
+            sqlx::query_as("SELECT der, generation FROM crl_cache WHERE issuer_key_id = $1")
+                .bind(&ca.key_id)
+                .fetch_one(&pool)
+                .await
+                .expect("query crl_cache");
+        assert_eq!(generation, 11);
+        assert_eq!(crl_number(&der), Some(11));
     }
 }
@@ -2,35 +2,61 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use sqlx::PgPool;
-use tokio::sync::watch;
 use tracing::{debug, error};
 
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
-use super::CrlWatchValue;
+use super::{CrlChannels, CrlKind};
 
-/// Load the latest CRL (DER + etag) from the shared `crl_cache` table.
+/// Load the latest CRL (DER + etag) of one issuer from the shared
+/// `crl_cache` table.
 pub(super) async fn load_crl_from_cache(
     pool: &PgPool,
+    issuer_key_id: &str,
+    kind: CrlKind,
 ) -> AppResult<Option<(String, Arc<Vec<u8>>)>> {
-    let row: Option<(Vec<u8>, String)> =
-        sqlx::query_as("SELECT der, etag FROM crl_cache WHERE id = 1")
-            .fetch_optional(pool)
-            .await?;
+    let query = match kind {
+        CrlKind::Complete => "SELECT der, etag FROM crl_cache WHERE issuer_key_id = $1",
+        CrlKind::Delta => {
+            "SELECT delta_der, delta_etag FROM crl_cache
+             WHERE issuer_key_id = $1 AND delta_der IS NOT NULL"
+        }
+    };
+    let row: Option<(Vec<u8>, String)> = sqlx::query_as(query)
+        .bind(issuer_key_id)
+        .fetch_optional(pool)
+        .await?;
     Ok(row.map(|(der, etag)| (etag, Arc::new(der))))
 }
 
+/// Reload the cached CRLs of every issuer into their watch channels.
+async fn reload_all(pool: &PgPool, channels: &CrlChannels) -> AppResult<()> {
+    type Row = (String, Vec<u8>, String, Option<Vec<u8>>, Option<String>);
+    let rows: Vec<Row> =
+        sqlx::query_as("SELECT issuer_key_id, der, etag, delta_der, delta_etag FROM crl_cache")
+            .fetch_all(pool)
+            .await?;
+    for (key_id, der, etag, delta_der, delta_etag) in rows {
+        debug!("crl listener re-synced issuer {} (etag={})", key_id, etag);
+        channels
+            .sender(&key_id, CrlKind::Complete)
+            .send_replace((etag, Some(Arc::new(der))));
+        if let (Some(der), Some(etag)) = (delta_der, delta_etag) {
+            channels
+                .sender(&key_id, CrlKind::Delta)
+                .send_replace((etag, Some(Arc::new(der))));
+        }
+    }
+    Ok(())
+}
+
 /// Background task that listens for `crl_changed` notifications and refreshes
 /// this replica's local cache so long-poll clients get the new CRL promptly.
 ///
-/// `replica_id` is this replica's identity; notifications carrying it are this
-/// replica's own rebuilds (already reflected in the local watch channel), so
-/// they are skipped to avoid a redundant cache reload.
-pub(super) fn spawn_crl_listener(
-    pool: PgPool,
-    replica_id: String,
-    rebuild_notify: watch::Sender<CrlWatchValue>,
-) {
+/// Payloads are `<replica id> <issuer key id>`. Notifications carrying this
+/// replica's id are its own rebuilds (already reflected in the local watch
+/// channel), so they are skipped to avoid a redundant cache reload.
+pub(super) fn spawn_crl_listener(pool: PgPool, replica_id: String, channels: CrlChannels) {
     tokio::spawn(async move {
         // Exponential backoff (capped) for connect/listen retries so a
         // degraded pool isn't hammered with connection attempts.
@@ -57,31 +83,31 @@ pub(super) fn spawn_crl_listener(
             backoff = Duration::from_secs(1);
             // Re-sync on (re)connect: notifications committed while the
             // listener was disconnected are missed (NOTIFY is best-effort),
-            // so reload the latest CRL from the cache to avoid serving a
+            // so reload the latest CRLs from the cache to avoid serving a
             // stale in-memory CRL.
-            match load_crl_from_cache(&pool).await {
-                Ok(Some((etag, body))) => {
-                    debug!("crl listener re-synced from cache (etag={})", etag);
-                    rebuild_notify.send_replace((etag, Some(body)));
-                }
-                Ok(None) => {}
-                Err(e) => error!("failed to reload CRL from cache on reconnect: {}", e),
+            if let Err(e) = reload_all(&pool, &channels).await {
+                error!("failed to reload CRLs from cache on reconnect: {}", e);
             }
             while let Ok(notification) = listener.recv().await {
-                if notification.payload() == replica_id {
+                let Some((sender, key_id)) = notification.payload().split_once(' ') else {
+                    debug!("ignoring malformed crl_changed payload");
+                    continue;
+                };
+                if sender == replica_id {
                     // Our own rebuild — the worker already updated the watch.
                     continue;
                 }
-                debug!(
-                    "crl_changed notification received: {:?}",
-                    notification.payload()
-                );
-                match load_crl_from_cache(&pool).await {
-                    Ok(Some((etag, body))) => {
-                        rebuild_notify.send_replace((etag, Some(body)));
+                debug!("crl_changed notification received for issuer {}", key_id);
+                for kind in [CrlKind::Complete, CrlKind::Delta] {
+                    match load_crl_from_cache(&pool, key_id, kind).await {
+                        Ok(Some((etag, body))) => {
+                            channels
+                                .sender(key_id, kind)
+                                .send_replace((etag, Some(body)));
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("failed to reload CRL from cache: {}", e),
                     }
-                    Ok(None) => {}
-                    Err(e) => error!("failed to reload CRL from cache: {}", e),
                 }
             }
         }
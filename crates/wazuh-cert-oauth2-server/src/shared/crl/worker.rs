@@ -1,51 +1,87 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use openssl::pkey::{PKey, Private};
-use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
 use tokio::fs;
-use tokio::sync::{mpsc, oneshot, watch};
-use tracing::{debug, error, info};
-use wazuh_cert_oauth2_model::models::errors::AppResult;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use crate::models::ca_config::IssuingCa;
+use crate::shared::certs::unix_now;
 
 use super::CrlBackend;
-use super::CrlWatchValue;
+use super::CrlChannels;
+use super::CrlKind;
+use super::DeltaCrlConfig;
 use super::RevocationEntry;
 use super::compute_etag;
 use super::ffi;
 
+/// Rebuilds of one issuer that may lose the cRLNumber race in a row.
+const MAX_PERSIST_ATTEMPTS: u32 = 5;
+
+/// Seconds before a new base that delta CRLs still cover, so revocations
+/// recorded while the base was being built are not missed.
+const DELTA_BASE_OVERLAP_SECS: u64 = 300;
+
 pub(super) enum Command {
     Rebuild {
-        ca_cert: Arc<X509>,
-        ca_key: Arc<PKey<Private>>,
+        ca: Arc<IssuingCa>,
         entries_snapshot: Vec<RevocationEntry>,
         respond_to: oneshot::Sender<AppResult<()>>,
     },
 }
 
+/// The complete CRL that delta CRLs are relative to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct DeltaBase {
+    number: u64,
+    /// Revocations at or after this time are listed in the deltas.
+    since_unix: u64,
+}
+
+/// Where the next CRL of an issuer continues from.
+struct Cursor {
+    number: u64,
+    base: Option<DeltaBase>,
+}
+
+struct Worker {
+    backend: CrlBackend,
+    delta: Option<DeltaCrlConfig>,
+    replica_id: String,
+    channels: CrlChannels,
+    /// Delta bases per issuer for the file backend, as last written to the
+    /// `<stem>-delta.base` file beside the delta CRL; Postgres keeps them in
+    /// `crl_cache` so replicas agree.
+    bases: HashMap<String, DeltaBase>,
+}
+
 pub(super) fn spawn_crl_worker(
     backend: CrlBackend,
+    delta: Option<DeltaCrlConfig>,
     replica_id: String,
     mut rx: mpsc::Receiver<Command>,
-    rebuild_notify: watch::Sender<CrlWatchValue>,
+    channels: CrlChannels,
 ) {
+    let mut worker = Worker {
+        backend,
+        delta,
+        replica_id,
+        channels,
+        bases: HashMap::new(),
+    };
     tokio::spawn(async move {
         while let Some(cmd) = rx.recv().await {
             match cmd {
                 Command::Rebuild {
-                    ca_cert,
-                    ca_key,
+                    ca,
                     entries_snapshot,
                     respond_to,
                 } => {
-                    let res = apply_rebuild(
-                        &backend,
-                        &replica_id,
-                        &ca_cert,
-                        &ca_key,
-                        entries_snapshot,
-                        &rebuild_notify,
-                    )
-                    .await;
+                    let res = worker.apply_rebuild(&ca, entries_snapshot).await;
                     let _ = respond_to.send(res);
                 }
             }
@@ -53,81 +89,314 @@ pub(super) fn spawn_crl_worker(
     });
 }
 
-async fn apply_rebuild(
-    backend: &CrlBackend,
-    replica_id: &str,
-    ca_cert: &X509,
-    ca_key: &PKey<Private>,
-    entries_snapshot: Vec<RevocationEntry>,
-    rebuild_notify: &watch::Sender<CrlWatchValue>,
-) -> AppResult<()> {
-    info!(
-        "Rebuilding CRL with {} revocation entries",
-        entries_snapshot.len()
-    );
-    let started = std::time::Instant::now();
-    let bytes: Vec<u8> = unsafe {
-        let crl = ffi::create_crl()?;
-        ffi::set_version_and_issuer(crl, ca_cert.as_ref())?;
-        ffi::set_times_now_and_next(crl)?;
-        ffi::add_revocations(crl, entries_snapshot)?;
-        ffi::sort_and_sign(crl, ca_key)?;
-        ffi::encode_der_and_free(crl)?
-    };
-
-    persist(backend, replica_id, &bytes).await?;
+impl Worker {
+    async fn apply_rebuild(
+        &mut self,
+        ca: &IssuingCa,
+        entries_snapshot: Vec<RevocationEntry>,
+    ) -> AppResult<()> {
+        info!(
+            "Rebuilding CRL of issuer {} with {} revocation entries",
+            ca.key_id,
+            entries_snapshot.len()
+        );
+        let started = std::time::Instant::now();
+        // Replicas race on the cRLNumber; a lost race rebuilds with a fresh one.
+        let mut attempts = 0;
+        let (complete, delta) = loop {
+            attempts += 1;
+            let cursor = self.load_cursor(ca).await?;
+            let number = cursor.number;
+            let freshest = self.delta.as_ref().map(|d| d.url_for(ca));
+            let complete = sign_crl(ca, number, &entries_snapshot, None, freshest.as_deref())?;
+            let delta = match self.delta_base(ca, &cursor) {
+                Some(base) => {
+                    let changed: Vec<_> = entries_snapshot
+                        .iter()
+                        .filter(|e| e.revoked_at_unix >= base.since_unix)
+                        .cloned()
+                        .collect();
+                    debug!(
+                        "Delta CRL {} against base {} lists {} entries",
+                        number,
+                        base.number,
+                        changed.len()
+                    );
+                    Some((
+                        sign_crl(ca, number, &changed, Some(base.number), None)?,
+                        base,
+                    ))
+                }
+                None => None,
+            };
+            if self.persist(ca, number, &complete, delta.as_ref()).await? {
+                break (complete, delta);
+            }
+            if attempts >= MAX_PERSIST_ATTEMPTS {
+                return Err(AppError::UpstreamError(format!(
+                    "CRL number {} of issuer {} was superseded {} times",
+                    number, ca.key_id, attempts
+                )));
+            }
+            debug!("CRL number {} already taken; rebuilding", number);
+        };
 
-    let etag = compute_etag(&bytes);
-    info!("CRL updated (took {:?}, etag={})", started.elapsed(), etag);
+        let etag = compute_etag(&complete);
+        info!("CRL updated (took {:?}, etag={})", started.elapsed(), etag);
+        self.channels
+            .sender(&ca.key_id, CrlKind::Complete)
+            .send_replace((etag, Some(Arc::new(complete))));
+        if let Some((delta, base)) = delta {
+            self.bases.insert(ca.key_id.clone(), base);
+            self.channels
+                .sender(&ca.key_id, CrlKind::Delta)
+                .send_replace((compute_etag(&delta), Some(Arc::new(delta))));
+        }
 
-    rebuild_notify.send_replace((etag, Some(Arc::new(bytes))));
+        Ok(())
+    }
 
-    Ok(())
-}
+    /// The cRLNumber for the next CRL of `ca` (one past the persisted one)
+    /// and the persisted delta base, if any.
+    async fn load_cursor(&self, ca: &IssuingCa) -> AppResult<Cursor> {
+        match &self.backend {
+            CrlBackend::File(base) => {
+                let own =
+                    super::read_crl_file(&super::crl_file_path(base, ca, CrlKind::Complete), ca)
+                        .await?;
+                // CRLs written before files were keyed by key id continue
+                // from the active alias, along with its delta base.
+                let alias =
+                    super::read_crl_file(&super::active_alias_path(base, CrlKind::Complete), ca)
+                        .await?;
+                let current = own
+                    .iter()
+                    .chain(alias.iter())
+                    .filter_map(|bytes| super::crl_number(bytes))
+                    .max();
+                let base = match self.bases.get(&ca.key_id) {
+                    Some(known) => Some(*known),
+                    None => match read_delta_base(&delta_base_path(base, ca)).await {
+                        Some(saved) => Some(saved),
+                        None if alias.is_some() => {
+                            read_delta_base(
+                                &super::active_alias_path(base, CrlKind::Delta)
+                                    .with_extension("base"),
+                            )
+                            .await
+                        }
+                        None => None,
+                    },
+                };
+                Ok(Cursor {
+                    number: current.unwrap_or(0) + 1,
+                    base,
+                })
+            }
+            CrlBackend::Postgres(pool) => {
+                let row: Option<(i64, Option<i64>, Option<i64>)> = sqlx::query_as(
+                    "SELECT generation, delta_base_number, delta_base_unix
+                     FROM crl_cache WHERE issuer_key_id = $1",
+                )
+                .bind(&ca.key_id)
+                .fetch_optional(pool)
+                .await?;
+                Ok(match row {
+                    Some((generation, number, since)) => Cursor {
+                        number: generation as u64 + 1,
+                        base: number.zip(since).map(|(number, since)| DeltaBase {
+                            number: number as u64,
+                            since_unix: since as u64,
+                        }),
+                    },
+                    None => Cursor {
+                        number: 1,
+                        base: None,
+                    },
+                })
+            }
+        }
+    }
 
-/// Persist the signed CRL to the configured backend.
-async fn persist(backend: &CrlBackend, replica_id: &str, bytes: &[u8]) -> AppResult<()> {
-    match backend {
-        CrlBackend::File(path) => {
-            let tmp = path.with_extension("crl.tmp");
-            debug!(
-                "Writing CRL ({} bytes) to temporary file: {}",
-                bytes.len(),
-                tmp.display()
-            );
-            fs::write(&tmp, bytes).await?;
-            fs::rename(tmp, path).await?;
-            info!("CRL written to {}", path.display());
+    /// The base for the next delta CRL: the persisted one until it is
+    /// `base_interval` old, then the complete CRL being built. `None` when
+    /// delta CRLs are disabled.
+    fn delta_base(&self, ca: &IssuingCa, cursor: &Cursor) -> Option<DeltaBase> {
+        let config = self.delta.as_ref()?;
+        let now = unix_now();
+        match cursor.base {
+            Some(base)
+                if base.number < cursor.number
+                    && now < base.since_unix + config.base_interval.as_secs() =>
+            {
+                Some(base)
+            }
+            _ => {
+                info!(
+                    "Moving delta CRL base of issuer {} to CRL {}",
+                    ca.key_id, cursor.number
+                );
+                Some(DeltaBase {
+                    number: cursor.number,
+                    since_unix: now.saturating_sub(DELTA_BASE_OVERLAP_SECS),
+                })
+            }
         }
-        CrlBackend::Postgres(pool) => {
-            let etag = compute_etag(bytes);
-            // A single statement is already atomic under Postgres' implicit
-            // per-statement transaction, so no explicit tx is needed.
-            sqlx::query(
-                "INSERT INTO crl_cache (id, der, etag, generation) VALUES (1, $1, $2, 1)
-                 ON CONFLICT (id) DO UPDATE SET
-                   der = EXCLUDED.der,
-                   etag = EXCLUDED.etag,
-                   generation = crl_cache.generation + 1,
-                   updated_at = now()",
-            )
-            .bind(bytes)
-            .bind(&etag)
-            .execute(pool)
-            .await?;
-            // Notify other replicas to refresh their local cache, carrying
-            // this replica's id so the listener can skip its own redundant
-            // reload. If the NOTIFY fails, other replicas keep serving a
-            // stale CRL until their listener reconnects — log it.
-            if let Err(e) = sqlx::query("SELECT pg_notify('crl_changed', $1)")
-                .bind(replica_id)
+    }
+
+    /// Persist the signed CRLs of `ca` to the configured backend.
+    ///
+    /// Returns `false` when the shared cache already holds a CRL numbered
+    /// `number` or higher, i.e. another replica won the race.
+    async fn persist(
+        &self,
+        ca: &IssuingCa,
+        number: u64,
+        complete: &[u8],
+        delta: Option<&(Vec<u8>, DeltaBase)>,
+    ) -> AppResult<bool> {
+        match &self.backend {
+            CrlBackend::File(base) => {
+                write_atomically(&super::crl_file_path(base, ca, CrlKind::Complete), complete)
+                    .await?;
+                if ca.active {
+                    write_atomically(&super::active_alias_path(base, CrlKind::Complete), complete)
+                        .await?;
+                }
+                if let Some((delta, delta_base)) = delta {
+                    write_atomically(&super::crl_file_path(base, ca, CrlKind::Delta), delta)
+                        .await?;
+                    if ca.active {
+                        write_atomically(&super::active_alias_path(base, CrlKind::Delta), delta)
+                            .await?;
+                    }
+                    // Kept so a restart keeps publishing deltas against the
+                    // same base instead of silently picking a new one.
+                    write_atomically(&delta_base_path(base, ca), &serde_json::to_vec(delta_base)?)
+                        .await?;
+                }
+            }
+            CrlBackend::Postgres(pool) => {
+                let etag = compute_etag(complete);
+                let (delta_der, delta_etag, base) = match delta {
+                    Some((der, base)) => {
+                        (Some(der.as_slice()), Some(compute_etag(der)), Some(base))
+                    }
+                    None => (None, None, None),
+                };
+                // A single statement is already atomic under Postgres' implicit
+                // per-statement transaction, so no explicit tx is needed. The
+                // generation doubles as the cRLNumber and must only grow.
+                let stored = sqlx::query(
+                    "INSERT INTO crl_cache (issuer_key_id, der, etag, generation,
+                       delta_der, delta_etag, delta_base_number, delta_base_unix)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                     ON CONFLICT (issuer_key_id) DO UPDATE SET
+                       der = EXCLUDED.der,
+                       etag = EXCLUDED.etag,
+                       generation = EXCLUDED.generation,
+                       delta_der = EXCLUDED.delta_der,
+                       delta_etag = EXCLUDED.delta_etag,
+                       delta_base_number = EXCLUDED.delta_base_number,
+                       delta_base_unix = EXCLUDED.delta_base_unix,
+                       updated_at = now()
+                     WHERE crl_cache.generation < EXCLUDED.generation",
+                )
+                .bind(&ca.key_id)
+                .bind(complete)
+                .bind(&etag)
+                .bind(number as i64)
+                .bind(delta_der)
+                .bind(delta_etag)
+                .bind(base.map(|b| b.number as i64))
+                .bind(base.map(|b| b.since_unix as i64))
                 .execute(pool)
-                .await
-            {
-                error!("failed to send crl_changed notification: {}", e);
+                .await?
+                .rows_affected()
+                    > 0;
+                if !stored {
+                    return Ok(false);
+                }
+                // Notify other replicas to refresh their local cache, carrying
+                // this replica's id so the listener can skip its own redundant
+                // reload, and the issuer whose CRL changed. If the NOTIFY
+                // fails, other replicas keep serving a stale CRL until their
+                // listener reconnects — log it.
+                if let Err(e) = sqlx::query("SELECT pg_notify('crl_changed', $1)")
+                    .bind(format!("{} {}", self.replica_id, ca.key_id))
+                    .execute(pool)
+                    .await
+                {
+                    error!("failed to send crl_changed notification: {}", e);
+                }
+                info!(
+                    "CRL of issuer {} persisted to crl_cache (etag={})",
+                    ca.key_id, etag
+                );
             }
-            info!("CRL persisted to crl_cache (etag={})", etag);
         }
+        Ok(true)
+    }
+}
+
+/// Sign a CRL of `ca` numbered `number`; a delta CRL when `delta_base` is
+/// set, otherwise a complete CRL pointing at `freshest` for its deltas.
+fn sign_crl(
+    ca: &IssuingCa,
+    number: u64,
+    entries: &[RevocationEntry],
+    delta_base: Option<u64>,
+    freshest: Option<&str>,
+) -> AppResult<Vec<u8>> {
+    unsafe {
+        let crl = ffi::create_crl()?;
+        ffi::set_version_and_issuer(crl, ca.cert.as_ref())?;
+        ffi::set_times_now_and_next(crl)?;
+        ffi::set_number_and_authority_key_id(crl, ca.cert.as_ref(), number)?;
+        if let Some(base) = delta_base {
+            ffi::set_delta_crl_indicator(crl, base)?;
+        }
+        if let Some(url) = freshest {
+            ffi::set_freshest_crl(crl, url)?;
+        }
+        ffi::add_revocations(crl, entries)?;
+        ffi::sort_and_sign(crl, ca.signer.as_ref(), ca.cert.as_ref())?;
+        ffi::encode_der_and_free(crl)
     }
+}
+
+/// Where the file backend keeps the delta base of `ca`.
+fn delta_base_path(base: &Path, ca: &IssuingCa) -> PathBuf {
+    super::crl_file_path(base, ca, CrlKind::Delta).with_extension("base")
+}
+
+/// The delta base saved at `path`; `None` when there is none or it cannot be
+/// read, in which case the next CRL becomes the base.
+async fn read_delta_base(path: &Path) -> Option<DeltaBase> {
+    let bytes = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Cannot read delta CRL base {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    serde_json::from_slice(&bytes)
+        .inspect_err(|e| warn!("Ignoring invalid delta CRL base {}: {}", path.display(), e))
+        .ok()
+}
+
+async fn write_atomically(path: &Path, bytes: &[u8]) -> AppResult<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    debug!(
+        "Writing CRL ({} bytes) to temporary file: {}",
+        bytes.len(),
+        tmp.display()
+    );
+    fs::write(&tmp, bytes).await?;
+    fs::rename(tmp, path).await?;
+    info!("CRL written to {}", path.display());
     Ok(())
 }
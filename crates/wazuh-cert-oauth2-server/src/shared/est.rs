@@ -0,0 +1,127 @@
+//! EST (RFC 7030) encodings: base64 PKCS#10 requests in, base64 certs-only
+//! PKCS#7 (RFC 5652 degenerate SignedData) out.
+
+use std::io::Cursor;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl::error::ErrorStack;
+use openssl::pkcs7::Pkcs7;
+use openssl::x509::{X509Ref, X509Req};
+use openssl_sys as ffi;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+/// Path the EST routes are mounted under.
+pub const EST_BASE: &str = "/.well-known/est";
+
+/// Line length of the base64 bodies (RFC 2045 allows up to 76).
+const LINE_LEN: usize = 64;
+
+/// Decode the body of `simpleenroll`/`simplereenroll` into a PEM CSR.
+///
+/// The body is the base64 DER of a PKCS#10 request; line breaks and other
+/// whitespace are ignored.
+pub fn decode_csr(body: &str) -> AppResult<String> {
+    let compact: String = body.split_whitespace().collect();
+    let invalid =
+        || AppError::ValidationError("EST body is not a base64-encoded PKCS#10 request".into());
+    let der = B64.decode(compact).map_err(|_| invalid())?;
+    let csr = X509Req::from_der(&der).map_err(|_| invalid())?;
+    Ok(String::from_utf8(csr.to_pem()?)?)
+}
+
+/// DER of a certs-only PKCS#7 carrying `certs` in order.
+pub fn certs_only<'a>(certs: impl IntoIterator<Item = &'a X509Ref>) -> AppResult<Vec<u8>> {
+    unsafe {
+        let p7 = ffi::PKCS7_new();
+        if p7.is_null() {
+            return Err(ErrorStack::get().into());
+        }
+        // Owned from here on so every error path frees it.
+        let p7 = Pkcs7::from_ptr(p7);
+        if ffi::PKCS7_set_type(p7.as_ptr(), ffi::NID_pkcs7_signed) != 1
+            || ffi::PKCS7_content_new(p7.as_ptr(), ffi::NID_pkcs7_data) != 1
+        {
+            return Err(ErrorStack::get().into());
+        }
+        for cert in certs {
+            // Takes its own reference to the certificate.
+            if ffi::PKCS7_add_certificate(p7.as_ptr(), cert.as_ptr()) != 1 {
+                return Err(ErrorStack::get().into());
+            }
+        }
+        Ok(p7.to_der()?)
+    }
+}
+
+/// A certs-only PKCS#7 response body, base64-encoded per RFC 7030 §4.1.3.
+pub struct EstCerts(pub Vec<u8>);
+
+impl<'r> Responder<'r, 'static> for EstCerts {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let encoded = B64.encode(self.0);
+        let body = encoded
+            .as_bytes()
+            .chunks(LINE_LEN)
+            .map(|line| format!("{}\r\n", String::from_utf8_lossy(line)))
+            .collect::<String>();
+        Response::build()
+            .status(Status::Ok)
+            .header(
+                ContentType::new("application", "pkcs7-mime")
+                    .with_params(("smime-type", "certs-only")),
+            )
+            .raw_header("Content-Transfer-Encoding", "base64")
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{certs_only, decode_csr};
+    use crate::handlers::test_support::{csr_pem, make_ca};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as B64;
+    use openssl::pkcs7::Pkcs7;
+    use openssl::x509::X509Req;
+
+    #[test]
+    fn certs_only_round_trips_in_order() {
+        let (root, root_key) = make_ca("est-root", None);
+        let (issuing, _) = make_ca("est-issuing", Some((&root, &root_key)));
+        let der = certs_only([issuing.as_ref(), root.as_ref()]).unwrap();
+
+        let p7 = Pkcs7::from_der(&der).unwrap();
+        let signed = p7.signed().expect("SignedData");
+        let certs = signed.certificates().expect("certificates");
+        assert_eq!(certs.len(), 2);
+        assert_eq!(
+            certs.get(0).unwrap().to_der().unwrap(),
+            issuing.to_der().unwrap()
+        );
+        assert_eq!(
+            certs.get(1).unwrap().to_der().unwrap(),
+            root.to_der().unwrap()
+        );
+    }
+
+    #[test]
+    fn decodes_wrapped_base64_csr() {
+        let pem = csr_pem("est-device");
+        let der = X509Req::from_pem(pem.as_bytes()).unwrap().to_der().unwrap();
+        let encoded = B64.encode(der);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(64)
+            .map(|line| format!("{}\r\n", String::from_utf8_lossy(line)))
+            .collect::<String>();
+        assert_eq!(decode_csr(&wrapped).unwrap(), pem);
+        assert!(decode_csr("not base64!").is_err());
+        assert!(decode_csr(&B64.encode(b"not a csr")).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use jsonwebtoken::errors::ErrorKind;
+use serde_json::{Map, Value};
+use tracing::debug;
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+use wazuh_cert_oauth2_model::services::jwks::TokenRequirements;
+
+use crate::shared::certs::unix_now;
+
+/// Most introspection results kept at once; beyond this new ones are not
+/// cached until older ones expire.
+const MAX_CACHED: usize = 10_000;
+
+/// How long a token the issuer reported inactive, or that failed the checks,
+/// is rejected without asking again.
+const REJECTED_TTL_SECS: u64 = 60;
+
+/// How long the endpoint is left alone after a call to it failed.
+const FAILURE_BACKOFF_SECS: u64 = 5;
+
+/// Validates opaque tokens by asking their issuer about them (RFC 7662).
+///
+/// The endpoint is called with the server's client credentials. Results are
+/// cached by the token's SHA-256: active tokens until their `exp`, rejected
+/// ones for a minute, so a client reusing its token costs one call per token
+/// rather than per request. After a failed call the endpoint is not called
+/// for a few seconds, so an unreachable issuer is not hammered.
+pub struct Introspector {
+    http: HttpClient,
+    client_id: String,
+    client_secret: String,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    /// Claims of active tokens, or `None` for rejected ones, with when the
+    /// result expires.
+    results: HashMap<[u8; 32], (Option<Claims>, u64)>,
+    /// Until when the endpoint is not called after a failure.
+    failing_until: u64,
+}
+
+impl Introspector {
+    pub fn new(http: HttpClient, client_id: String, client_secret: String) -> Self {
+        Self {
+            http,
+            client_id,
+            client_secret,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Claims of `token` according to the introspection `endpoint`, checked
+    /// against `requirements` like a JWT would be.
+    pub async fn introspect(
+        &self,
+        endpoint: &str,
+        token: &str,
+        requirements: &TokenRequirements,
+    ) -> AppResult<Claims> {
+        let key = openssl::sha::sha256(token.as_bytes());
+        let now = unix_now();
+        {
+            let cache = self.cache.lock().expect("cache lock");
+            match cache.results.get(&key) {
+                Some((Some(claims), until)) if *until > now => {
+                    debug!(sub = %claims.sub, "introspection result served from cache");
+                    return Ok(claims.clone());
+                }
+                Some((None, until)) if *until > now => {
+                    debug!("token rejected from cache");
+                    return Err(AppError::TokenInactive);
+                }
+                _ => {}
+            }
+            if cache.failing_until > now {
+                return Err(AppError::UpstreamError(
+                    "token introspection failed recently; retry shortly".into(),
+                ));
+            }
+        }
+
+        let response = match self.call(endpoint, token).await {
+            Ok(response) => response,
+            Err(e) => {
+                self.cache.lock().expect("cache lock").failing_until = now + FAILURE_BACKOFF_SECS;
+                return Err(e);
+            }
+        };
+        let result = claims_from_response(response, requirements, now);
+        let cached = match &result {
+            Ok(claims) => (Some(claims.clone()), claims.exp as u64),
+            Err(_) => (None, now + REJECTED_TTL_SECS),
+        };
+        if cached.1 > now {
+            let mut cache = self.cache.lock().expect("cache lock");
+            if cache.results.len() >= MAX_CACHED {
+                cache.results.retain(|_, (_, until)| *until > now);
+            }
+            if cache.results.len() < MAX_CACHED {
+                cache.results.insert(key, cached);
+            }
+        }
+        result
+    }
+
+    async fn call(&self, endpoint: &str, token: &str) -> AppResult<Map<String, Value>> {
+        Ok(self
+            .http
+            .client()
+            .post(endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("token", token), ("token_type_hint", "access_token")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// Map an introspection response to [`Claims`].
+///
+/// A missing `iss` is the issuer's own, a missing `sub` falls back to
+/// `client_id` as for client-credentials tokens, and a missing `exp` makes
+/// the result expire at once so it is never cached.
+fn claims_from_response(
+    mut response: Map<String, Value>,
+    requirements: &TokenRequirements,
+    now: u64,
+) -> AppResult<Claims> {
+    if response.remove("active") != Some(Value::Bool(true)) {
+        return Err(AppError::TokenInactive);
+    }
+    if let Some(expected) = &requirements.issuer {
+        match response.get("iss").and_then(Value::as_str) {
+            Some(iss) if iss != expected => {
+                return Err(AppError::JwtUntrustedIssuer(iss.to_string()));
+            }
+            Some(_) => {}
+            None => {
+                response.insert("iss".into(), Value::String(expected.clone()));
+            }
+        }
+    }
+    if !response.contains_key("sub") {
+        let client_id = response
+            .get("client_id")
+            .cloned()
+            .ok_or_else(|| AppError::ValidationError("token names no subject".into()))?;
+        response.insert("sub".into(), client_id);
+    }
+    match response.get("exp").and_then(Value::as_u64) {
+        Some(exp) if exp + requirements.leeway_secs.unwrap_or(60) < now => {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::ExpiredSignature).into());
+        }
+        Some(_) => {}
+        None => {
+            response.insert("exp".into(), Value::from(now));
+        }
+    }
+    if let Some(audiences) = &requirements.audiences {
+        let aud: Vec<&str> = match response.get("aud") {
+            Some(Value::String(aud)) => vec![aud.as_str()],
+            Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !aud.iter().any(|a| audiences.iter().any(|want| want == a)) {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidAudience).into());
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(response))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use wazuh_cert_oauth2_model::models::errors::AppError;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+    use wazuh_cert_oauth2_model::services::jwks::TokenRequirements;
+
+    use super::{Introspector, claims_from_response};
+
+    /// An introspection endpoint answering every call with `status` and
+    /// `body`, counting the calls.
+    async fn endpoint(status: &'static str, body: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let url = format!("http://{}/introspect", listener.local_addr().expect("addr"));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                counted.fetch_add(1, Ordering::SeqCst);
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, calls)
+    }
+
+    fn introspector() -> Introspector {
+        Introspector::new(
+            HttpClient::new_with_defaults().expect("client"),
+            "wazuh-server".into(),
+            "secret".into(),
+        )
+    }
+
+    #[tokio::test]
+    async fn inactive_tokens_are_cached() {
+        let (url, calls) = endpoint("200 OK", r#"{"active":false}"#).await;
+        let introspector = introspector();
+        let requirements = TokenRequirements::default();
+        for _ in 0..3 {
+            assert!(matches!(
+                introspector
+                    .introspect(&url, "revoked", &requirements)
+                    .await,
+                Err(AppError::TokenInactive)
+            ));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn a_failing_endpoint_is_left_alone_for_a_while() {
+        let (url, calls) = endpoint("503 Service Unavailable", "").await;
+        let introspector = introspector();
+        let requirements = TokenRequirements::default();
+        for token in ["a", "b", "c"] {
+            assert!(
+                introspector
+                    .introspect(&url, token, &requirements)
+                    .await
+                    .is_err()
+            );
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    fn response(value: Value) -> serde_json::Map<String, Value> {
+        value.as_object().expect("object").clone()
+    }
+
+    #[test]
+    fn responses_are_checked_like_tokens() {
+        let requirements = TokenRequirements {
+            audiences: Some(vec!["wazuh".into()]),
+            issuer: Some("https://gw.example".into()),
+            leeway_secs: Some(10),
+        };
+        let check = |value: Value| claims_from_response(response(value), &requirements, 1_000);
+
+        assert!(matches!(
+            check(json!({"active": false, "sub": "s"})),
+            Err(AppError::TokenInactive)
+        ));
+        assert!(matches!(
+            check(json!({"active": true, "sub": "s", "aud": "wazuh", "iss": "https://other"})),
+            Err(AppError::JwtUntrustedIssuer(_))
+        ));
+        assert!(check(json!({"active": true, "sub": "s", "aud": "other"})).is_err());
+        assert!(check(json!({"active": true, "sub": "s", "aud": "wazuh", "exp": 900})).is_err());
+        assert!(check(json!({"active": true, "aud": "wazuh"})).is_err());
+
+        let claims = check(json!({
+            "active": true,
+            "client_id": "backup-bot",
+            "aud": ["account", "wazuh"],
+            "exp": 995,
+            "scope": "certs",
+        }))
+        .expect("active token");
+        assert_eq!(claims.sub, "backup-bot");
+        assert_eq!(claims.iss, "https://gw.example");
+        assert_eq!(claims.exp, 995);
+        assert_eq!(claims.extra["scope"], "certs");
+
+        let claims = check(json!({"active": true, "sub": "s", "aud": "wazuh"})).expect("no exp");
+        assert_eq!(claims.exp, 1_000, "without exp the result is not cached");
+    }
+}
@@ -0,0 +1,138 @@
+// ACME accounts, EAB keys and orders for the CSV-backed store, kept as a
+// JSON document next to the ledger file. Nonces only live in memory: they
+// expire within the hour, and after a restart clients fetch a fresh one.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::{AcmeAccount, AcmeAuthz, AcmeOrder, AcmeOrderStatus, EabKey};
+
+/// Upper bound on outstanding nonces; the oldest are forgotten first.
+const MAX_NONCES: usize = 10_000;
+
+/// `ledger.csv` keeps its ACME state in `ledger.acme.json`.
+pub(super) fn acme_path(ledger_path: &Path) -> PathBuf {
+    ledger_path.with_extension("acme.json")
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct AcmeDocument {
+    #[serde(default)]
+    pub eab_keys: Vec<EabKey>,
+    #[serde(default)]
+    pub accounts: Vec<AcmeAccount>,
+    #[serde(default)]
+    pub orders: Vec<AcmeOrder>,
+    #[serde(default)]
+    pub authzs: Vec<AcmeAuthz>,
+}
+
+impl AcmeDocument {
+    /// Drop what expired at `now`; returns whether anything did.
+    pub fn purge_expired(&mut self, now: u64) -> bool {
+        let len = |d: &Self| d.eab_keys.len() + d.orders.len() + d.authzs.len();
+        let before = len(self);
+        self.eab_keys.retain(|key| key.expires_unix > now);
+        self.orders.retain(|order| order.expires_unix > now);
+        self.authzs.retain(|authz| authz.expires_unix > now);
+        len(self) != before
+    }
+
+    pub fn record_account(&mut self, account: AcmeAccount) -> AppResult<()> {
+        if self
+            .accounts
+            .iter()
+            .any(|a| a.id == account.id || a.thumbprint == account.thumbprint)
+        {
+            return Err(AppError::Conflict(format!(
+                "ACME account {} or its key is already registered",
+                account.id
+            )));
+        }
+        self.accounts.push(account);
+        Ok(())
+    }
+
+    pub fn update_account(&mut self, account: AcmeAccount) -> AppResult<()> {
+        let current = self
+            .accounts
+            .iter_mut()
+            .find(|a| a.id == account.id)
+            .ok_or_else(|| AppError::NotFound(format!("ACME account {}", account.id)))?;
+        current.deactivated = account.deactivated;
+        current.contact = account.contact;
+        current.binding = account.binding;
+        current.bound_until = account.bound_until;
+        Ok(())
+    }
+
+    /// Apply a status change, see
+    /// [`LedgerStore::update_acme_order`](super::LedgerStore::update_acme_order).
+    pub fn update_order(&mut self, order: AcmeOrder, from: AcmeOrderStatus) -> AppResult<()> {
+        let current = self
+            .orders
+            .iter_mut()
+            .find(|o| o.id == order.id)
+            .ok_or_else(|| AppError::NotFound(format!("ACME order {}", order.id)))?;
+        if current.status != from {
+            return Err(AppError::Conflict(format!(
+                "ACME order {} is already {}",
+                order.id,
+                current.status.as_str()
+            )));
+        }
+        current.status = order.status;
+        current.certificate = order.certificate;
+        Ok(())
+    }
+}
+
+pub(super) async fn load_acme(path: &Path) -> AppResult<AcmeDocument> {
+    match fs::read(path).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AcmeDocument::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(super) async fn persist_acme(path: &Path, document: &AcmeDocument) -> AppResult<()> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(document)?).await?;
+    fs::rename(tmp, path).await?;
+    Ok(())
+}
+
+/// Outstanding nonces with their expiry, oldest first.
+#[derive(Default)]
+pub(super) struct Nonces {
+    expiry: HashMap<String, u64>,
+    order: VecDeque<String>,
+}
+
+impl Nonces {
+    pub fn insert(&mut self, nonce: String, expires_unix: u64) {
+        self.expiry.insert(nonce.clone(), expires_unix);
+        self.order.push_back(nonce);
+        while self.order.len() > MAX_NONCES {
+            if let Some(old) = self.order.pop_front() {
+                self.expiry.remove(&old);
+            }
+        }
+    }
+
+    pub fn take(&mut self, nonce: &str, now: u64) -> bool {
+        self.expiry
+            .remove(nonce)
+            .is_some_and(|expires_unix| expires_unix > now)
+    }
+
+    pub fn purge_expired(&mut self, now: u64) {
+        self.expiry.retain(|_, expires_unix| *expires_unix > now);
+        let expiry = &self.expiry;
+        self.order.retain(|nonce| expiry.contains_key(nonce));
+    }
+}
@@ -1,26 +1,25 @@
 // Commands for the ledger worker loop
 
+use super::{IssuedCert, Rotation};
 use wazuh_cert_oauth2_model::models::errors::AppResult;
 
 pub(super) enum Command {
     RecordIssued {
-        subject: String,
-        serial_hex: String,
+        cert: IssuedCert,
+        /// Quota to check before recording, see `record_issued_within`.
+        rotation: Option<Box<Rotation>>,
         issued_at_unix: u64,
-        issuer: Option<String>,
-        realm: Option<String>,
-        wazuh_agent_name: Option<String>,
         respond_to: tokio::sync::oneshot::Sender<AppResult<()>>,
     },
     MarkRevoked {
         serial_hex: String,
         reason: Option<String>,
+        invalidity_date_unix: Option<u64>,
         revoked_at_unix: u64,
-        respond_to: tokio::sync::oneshot::Sender<AppResult<()>>,
+        respond_to: tokio::sync::oneshot::Sender<AppResult<bool>>,
     },
     CheckAndRevokeActive {
-        subject: String,
-        overwrite: bool,
+        rotation: Rotation,
         revoked_at_unix: u64,
         respond_to: tokio::sync::oneshot::Sender<AppResult<Option<Vec<String>>>>,
     },
@@ -9,7 +9,7 @@ use wazuh_cert_oauth2_model::models::errors::AppResult;
 pub async fn persist_csv(path: &PathBuf, inner: &Arc<RwLock<Vec<LedgerEntry>>>) -> AppResult<()> {
     let data = inner.read().await.clone();
     let mut out = String::new();
-    out.push_str("subject,serial_hex,issued_at_unix,revoked,revoked_at_unix,reason,issuer,realm,wazuh_agent_name\n");
+    out.push_str("subject,serial_hex,issued_at_unix,revoked,revoked_at_unix,reason,issuer,realm,wazuh_agent_name,not_after_unix,issuer_key_id,profile,device_id,invalidity_date_unix\n");
     for e in data.iter() {
         let subject = escape_csv_field(&e.subject);
         let serial = escape_csv_field(&e.serial_hex);
@@ -24,9 +24,30 @@ pub async fn persist_csv(path: &PathBuf, inner: &Arc<RwLock<Vec<LedgerEntry>>>)
         let realm = escape_csv_field(realm);
         let agent_name = e.wazuh_agent_name.as_deref().unwrap_or("");
         let agent_name = escape_csv_field(agent_name);
+        let not_after = e.not_after_unix.map(|v| v.to_string()).unwrap_or_default();
+        let issuer_key_id = escape_csv_field(e.issuer_key_id.as_deref().unwrap_or(""));
+        let profile = escape_csv_field(e.profile.as_deref().unwrap_or(""));
+        let device_id = escape_csv_field(e.device_id.as_deref().unwrap_or(""));
+        let invalidity_date = e
+            .invalidity_date_unix
+            .map(|v| v.to_string())
+            .unwrap_or_default();
         out.push_str(&format!(
-            "{},{},{},{},{},{},{},{},{}\n",
-            subject, serial, issued, revoked, revoked_at, reason, issuer, realm, agent_name
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            subject,
+            serial,
+            issued,
+            revoked,
+            revoked_at,
+            reason,
+            issuer,
+            realm,
+            agent_name,
+            not_after,
+            issuer_key_id,
+            profile,
+            device_id,
+            invalidity_date
         ));
     }
 
@@ -82,6 +103,26 @@ pub fn parse_csv(s: &str) -> AppResult<Vec<LedgerEntry>> {
         } else {
             None
         };
+        let not_after_unix = fields
+            .get(9)
+            .filter(|v| !v.is_empty())
+            .and_then(|v| v.parse::<u64>().ok());
+        let issuer_key_id = fields
+            .get(10)
+            .map(|v| unescape_csv_field(v))
+            .filter(|v| !v.is_empty());
+        let profile = fields
+            .get(11)
+            .map(|v| unescape_csv_field(v))
+            .filter(|v| !v.is_empty());
+        let device_id = fields
+            .get(12)
+            .map(|v| unescape_csv_field(v))
+            .filter(|v| !v.is_empty());
+        let invalidity_date_unix = fields
+            .get(13)
+            .filter(|v| !v.is_empty())
+            .and_then(|v| v.parse::<u64>().ok());
         out.push(LedgerEntry {
             subject,
             serial_hex,
@@ -92,6 +133,11 @@ pub fn parse_csv(s: &str) -> AppResult<Vec<LedgerEntry>> {
             issuer,
             realm,
             wazuh_agent_name,
+            not_after_unix,
+            issuer_key_id,
+            profile,
+            device_id,
+            invalidity_date_unix,
         });
     }
     Ok(out)
@@ -136,6 +182,10 @@ mod tests {
         assert_eq!(row.issuer, None);
         assert_eq!(row.realm, None);
         assert_eq!(row.wazuh_agent_name, None);
+        assert_eq!(row.not_after_unix, None);
+        assert_eq!(row.issuer_key_id, None);
+        assert_eq!(row.profile, None);
+        assert_eq!(row.profile_name(), "agent");
     }
 
     #[test]
@@ -174,6 +224,11 @@ mod tests {
                 issuer: Some("https://issuer/realms/main".to_string()),
                 realm: Some("main".to_string()),
                 wazuh_agent_name: Some("DevOps-SRE-main".to_string()),
+                not_after_unix: Some(31_536_111),
+                issuer_key_id: Some("A1B2C3".to_string()),
+                profile: Some("manager".to_string()),
+                device_id: Some("laptop".to_string()),
+                invalidity_date_unix: None,
             },
             LedgerEntry {
                 subject: "user-b".to_string(),
@@ -185,6 +240,11 @@ mod tests {
                 issuer: None,
                 realm: None,
                 wazuh_agent_name: None,
+                not_after_unix: None,
+                issuer_key_id: None,
+                profile: None,
+                device_id: None,
+                invalidity_date_unix: Some(300),
             },
         ];
 
@@ -198,8 +258,18 @@ mod tests {
         assert_eq!(parsed.len(), entries.len());
         assert_eq!(parsed[0].subject, entries[0].subject);
         assert_eq!(parsed[0].issuer, entries[0].issuer);
+        assert_eq!(parsed[0].not_after_unix, entries[0].not_after_unix);
+        assert_eq!(parsed[1].not_after_unix, None);
+        assert_eq!(parsed[0].issuer_key_id, entries[0].issuer_key_id);
+        assert_eq!(parsed[1].issuer_key_id, None);
+        assert_eq!(parsed[0].profile.as_deref(), Some("manager"));
+        assert_eq!(parsed[1].profile, None);
+        assert_eq!(parsed[0].device_id.as_deref(), Some("laptop"));
+        assert_eq!(parsed[1].device_id, None);
         assert_eq!(parsed[1].revoked, entries[1].revoked);
         assert_eq!(parsed[1].reason, entries[1].reason);
+        assert_eq!(parsed[0].invalidity_date_unix, None);
+        assert_eq!(parsed[1].invalidity_date_unix, Some(300));
 
         let _ = fs::remove_dir_all(parent).await;
     }
@@ -2,57 +2,100 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::{RwLock, mpsc, oneshot};
+use tokio::sync::{Mutex, RwLock, mpsc, oneshot};
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 
+use super::IssuedCert;
 use super::LedgerEntry;
+use super::LedgerPage;
+use super::LedgerQuery;
 use super::LedgerStore;
+use super::PendingEnrollment;
+use super::PolicyDecision;
+use super::Rotation;
+use super::acme;
+use super::enrollments;
+use super::policy_decisions;
 use super::worker;
+use super::{AcmeAccount, AcmeAuthz, AcmeOrder, AcmeOrderStatus, EabKey};
 
 /// CSV-backed ledger store.
 ///
 /// Kept for local-dev, tests, and as an emergency fallback when no database
 /// is configured. Uses the original in-memory `Vec` + single background
-/// writer + full-file rewrite on every mutation.
+/// writer + full-file rewrite on every mutation. Enrollments awaiting
+/// approval and ACME state live in JSON files beside the CSV, rewritten under
+/// their lock.
 pub struct CsvLedgerStore {
     inner: Arc<RwLock<Vec<LedgerEntry>>>,
     tx: mpsc::Sender<worker::Command>,
+    enrollments: RwLock<Vec<PendingEnrollment>>,
+    enrollments_path: PathBuf,
+    policy_decisions_path: PathBuf,
+    acme: RwLock<acme::AcmeDocument>,
+    acme_path: PathBuf,
+    acme_nonces: Mutex<acme::Nonces>,
 }
 
 impl CsvLedgerStore {
     #[tracing::instrument(skip(path))]
     pub async fn new(path: PathBuf) -> AppResult<Self> {
         let entries = worker::load_entries(&path).await?;
+        let enrollments_path = enrollments::enrollments_path(&path);
+        let pending = enrollments::load_enrollments(&enrollments_path).await?;
+        let acme_path = acme::acme_path(&path);
+        let acme_document = acme::load_acme(&acme_path).await?;
 
         let inner = Arc::new(RwLock::new(entries));
         let (tx, rx) = mpsc::channel::<worker::Command>(100);
         worker::spawn_ledger_worker(inner.clone(), path.clone(), rx);
 
-        Ok(Self { inner, tx })
+        Ok(Self {
+            inner,
+            tx,
+            enrollments: RwLock::new(pending),
+            enrollments_path,
+            policy_decisions_path: policy_decisions::policy_decisions_path(&path),
+            acme: RwLock::new(acme_document),
+            acme_path,
+            acme_nonces: Mutex::default(),
+        })
     }
 }
 
 #[async_trait]
 impl LedgerStore for CsvLedgerStore {
     #[tracing::instrument(skip(self))]
-    async fn record_issued(
+    async fn record_issued(&self, cert: IssuedCert, issued_at_unix: u64) -> AppResult<()> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(worker::Command::RecordIssued {
+                cert,
+                rotation: None,
+                issued_at_unix,
+                respond_to: tx,
+            })
+            .await
+            .map_err(|e| AppError::UpstreamError(format!("ledger writer dropped: {}", e)))?;
+        rx.await
+            .map_err(|e| AppError::UpstreamError(format!("ledger writer closed: {}", e)))??;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn record_issued_within(
         &self,
-        subject: String,
-        serial_hex: String,
+        cert: IssuedCert,
+        rotation: Rotation,
         issued_at_unix: u64,
-        issuer: Option<String>,
-        realm: Option<String>,
-        wazuh_agent_name: Option<String>,
     ) -> AppResult<()> {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(worker::Command::RecordIssued {
-                subject,
-                serial_hex,
+                cert,
+                rotation: Some(Box::new(rotation)),
                 issued_at_unix,
-                issuer,
-                realm,
-                wazuh_agent_name,
                 respond_to: tx,
             })
             .await
@@ -67,35 +110,34 @@ impl LedgerStore for CsvLedgerStore {
         &self,
         serial_hex: String,
         reason: Option<String>,
+        invalidity_date_unix: Option<u64>,
         revoked_at_unix: u64,
-    ) -> AppResult<()> {
+    ) -> AppResult<bool> {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(worker::Command::MarkRevoked {
                 serial_hex,
                 reason,
+                invalidity_date_unix,
                 revoked_at_unix,
                 respond_to: tx,
             })
             .await
             .map_err(|e| AppError::UpstreamError(format!("ledger writer dropped: {}", e)))?;
         rx.await
-            .map_err(|e| AppError::UpstreamError(format!("ledger writer closed: {}", e)))??;
-        Ok(())
+            .map_err(|e| AppError::UpstreamError(format!("ledger writer closed: {}", e)))?
     }
 
     #[tracing::instrument(skip(self))]
     async fn check_and_revoke_active(
         &self,
-        subject: String,
-        overwrite: bool,
+        rotation: Rotation,
         revoked_at_unix: u64,
     ) -> AppResult<Option<Vec<String>>> {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(worker::Command::CheckAndRevokeActive {
-                subject,
-                overwrite,
+                rotation,
                 revoked_at_unix,
                 respond_to: tx,
             })
@@ -117,6 +159,17 @@ impl LedgerStore for CsvLedgerStore {
             .collect())
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn find_by_serial(&self, serial_hex: &str) -> AppResult<Option<LedgerEntry>> {
+        Ok(self
+            .inner
+            .read()
+            .await
+            .iter()
+            .find(|e| e.serial_hex.eq_ignore_ascii_case(serial_hex))
+            .cloned())
+    }
+
     #[tracing::instrument(skip(self))]
     async fn find_active(&self) -> AppResult<Vec<LedgerEntry>> {
         Ok(self
@@ -145,4 +198,230 @@ impl LedgerStore for CsvLedgerStore {
     async fn find_all(&self) -> AppResult<Vec<LedgerEntry>> {
         Ok(self.inner.read().await.clone())
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn search(&self, query: &LedgerQuery) -> AppResult<LedgerPage> {
+        let guard = self.inner.read().await;
+        let mut rows: Vec<&LedgerEntry> = guard.iter().filter(|e| query.matches(e)).collect();
+        // Only the page (plus one) needs sorting, not every match.
+        let wanted = query.limit as usize + 1;
+        let order = |a: &&LedgerEntry, b: &&LedgerEntry| query.sort.compare(a, b);
+        if rows.len() > wanted {
+            rows.select_nth_unstable_by(wanted, order);
+            rows.truncate(wanted);
+        }
+        rows.sort_by(order);
+        Ok(LedgerPage::from_rows(
+            rows.into_iter().cloned().collect(),
+            query,
+        ))
+    }
+
+    #[tracing::instrument(skip(self, enrollment))]
+    async fn record_enrollment(&self, enrollment: PendingEnrollment) -> AppResult<()> {
+        let mut guard = self.enrollments.write().await;
+        guard.push(enrollment);
+        enrollments::persist_enrollments(&self.enrollments_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollment(&self, id: &str) -> AppResult<Option<PendingEnrollment>> {
+        Ok(self
+            .enrollments
+            .read()
+            .await
+            .iter()
+            .find(|e| e.request.id == id)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollments(
+        &self,
+        status: Option<EnrollmentStatus>,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        Ok(self
+            .enrollments
+            .read()
+            .await
+            .iter()
+            .filter(|e| status.is_none_or(|s| e.request.status == s))
+            .cloned()
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollments_by_subject(
+        &self,
+        subject: &str,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        Ok(self
+            .enrollments
+            .read()
+            .await
+            .iter()
+            .filter(|e| e.request.subject == subject)
+            .cloned()
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self, request))]
+    async fn update_enrollment(
+        &self,
+        request: EnrollmentRequest,
+        from: EnrollmentStatus,
+    ) -> AppResult<()> {
+        let mut guard = self.enrollments.write().await;
+        enrollments::apply_update(&mut guard, request, from)?;
+        enrollments::persist_enrollments(&self.enrollments_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self, decision))]
+    async fn record_policy_decision(&self, decision: PolicyDecision) -> AppResult<()> {
+        policy_decisions::append_decision(&self.policy_decisions_path, &decision).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_policy_decisions(&self, subject: &str) -> AppResult<Vec<PolicyDecision>> {
+        policy_decisions::load_decisions(&self.policy_decisions_path, subject).await
+    }
+
+    async fn record_acme_nonce(&self, nonce: String, expires_unix: u64) -> AppResult<()> {
+        self.acme_nonces.lock().await.insert(nonce, expires_unix);
+        Ok(())
+    }
+
+    async fn take_acme_nonce(&self, nonce: &str, now: u64) -> AppResult<bool> {
+        Ok(self.acme_nonces.lock().await.take(nonce, now))
+    }
+
+    #[tracing::instrument(skip(self, key), fields(kid = %key.kid))]
+    async fn record_acme_eab_key(&self, key: EabKey) -> AppResult<()> {
+        let mut guard = self.acme.write().await;
+        guard.eab_keys.push(key);
+        acme::persist_acme(&self.acme_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_eab_key(&self, kid: &str) -> AppResult<Option<EabKey>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .eab_keys
+            .iter()
+            .find(|k| k.kid == kid)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn take_acme_eab_key(&self, kid: &str, now: u64) -> AppResult<Option<EabKey>> {
+        let mut guard = self.acme.write().await;
+        let Some(index) = guard.eab_keys.iter().position(|k| k.kid == kid) else {
+            return Ok(None);
+        };
+        let key = guard.eab_keys.remove(index);
+        acme::persist_acme(&self.acme_path, &guard).await?;
+        Ok(Some(key).filter(|key| key.expires_unix > now))
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    async fn record_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        let mut guard = self.acme.write().await;
+        guard.record_account(account)?;
+        acme::persist_acme(&self.acme_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_account(&self, id: &str) -> AppResult<Option<AcmeAccount>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .accounts
+            .iter()
+            .find(|a| a.id == id)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_account_by_key(&self, thumbprint: &str) -> AppResult<Option<AcmeAccount>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .accounts
+            .iter()
+            .find(|a| a.thumbprint == thumbprint)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    async fn update_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        let mut guard = self.acme.write().await;
+        guard.update_account(account)?;
+        acme::persist_acme(&self.acme_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self, order, authzs), fields(order = %order.id))]
+    async fn record_acme_order(&self, order: AcmeOrder, authzs: Vec<AcmeAuthz>) -> AppResult<()> {
+        let mut guard = self.acme.write().await;
+        guard.orders.push(order);
+        guard.authzs.extend(authzs);
+        acme::persist_acme(&self.acme_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_order(&self, id: &str) -> AppResult<Option<AcmeOrder>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .orders
+            .iter()
+            .find(|o| o.id == id)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_orders(&self, account_id: &str, now: u64) -> AppResult<Vec<AcmeOrder>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .orders
+            .iter()
+            .filter(|o| o.account_id == account_id && o.expires_unix > now)
+            .cloned()
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self, order), fields(order = %order.id))]
+    async fn update_acme_order(&self, order: AcmeOrder, from: AcmeOrderStatus) -> AppResult<()> {
+        let mut guard = self.acme.write().await;
+        guard.update_order(order, from)?;
+        acme::persist_acme(&self.acme_path, &guard).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_authz(&self, id: &str) -> AppResult<Option<AcmeAuthz>> {
+        Ok(self
+            .acme
+            .read()
+            .await
+            .authzs
+            .iter()
+            .find(|a| a.id == id)
+            .cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn purge_acme(&self, now: u64) -> AppResult<()> {
+        self.acme_nonces.lock().await.purge_expired(now);
+        let mut guard = self.acme.write().await;
+        if guard.purge_expired(now) {
+            acme::persist_acme(&self.acme_path, &guard).await?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,61 @@
+// Enrollments awaiting approval for the CSV-backed store, kept as a JSON
+// document next to the ledger file.
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::PendingEnrollment;
+
+/// `ledger.csv` keeps its enrollments in `ledger.enrollments.json`.
+pub(super) fn enrollments_path(ledger_path: &Path) -> PathBuf {
+    ledger_path.with_extension("enrollments.json")
+}
+
+pub(super) async fn load_enrollments(path: &Path) -> AppResult<Vec<PendingEnrollment>> {
+    match fs::read(path).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(super) async fn persist_enrollments(
+    path: &Path,
+    enrollments: &[PendingEnrollment],
+) -> AppResult<()> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(enrollments)?).await?;
+    fs::rename(tmp, path).await?;
+    Ok(())
+}
+
+/// Apply a decision to the matching entry, see
+/// [`LedgerStore::update_enrollment`](super::LedgerStore::update_enrollment).
+pub(super) fn apply_update(
+    enrollments: &mut [PendingEnrollment],
+    request: EnrollmentRequest,
+    from: EnrollmentStatus,
+) -> AppResult<()> {
+    let entry = enrollments
+        .iter_mut()
+        .find(|e| e.request.id == request.id)
+        .ok_or_else(|| AppError::NotFound(format!("enrollment request {}", request.id)))?;
+    if entry.request.status != from {
+        return Err(AppError::Conflict(format!(
+            "enrollment request {} is already {}",
+            request.id,
+            entry.request.status.as_str()
+        )));
+    }
+    let current = &mut entry.request;
+    current.status = request.status;
+    current.decided_at_unix = request.decided_at_unix;
+    current.decided_by = request.decided_by;
+    current.reason = request.reason;
+    current.serial_hex = request.serial_hex;
+    current.certificate = request.certificate;
+    Ok(())
+}
@@ -3,17 +3,155 @@ use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
-use wazuh_cert_oauth2_model::models::errors::AppResult;
+use serde::{Deserialize, Serialize};
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 pub use wazuh_cert_oauth2_model::models::ledger_entry::LedgerEntry;
 
+use crate::shared::acme::{
+    Account as AcmeAccount, Authz as AcmeAuthz, EabKey, Order as AcmeOrder,
+    OrderStatus as AcmeOrderStatus,
+};
+
+mod acme;
 mod commands;
 pub(crate) mod csv;
 mod csv_store;
 pub(crate) mod csv_utils;
+mod enrollments;
 mod loader;
+mod policy_decisions;
 mod postgres;
+mod query;
 mod worker;
 
+pub use query::{
+    DEFAULT_SEARCH_LIMIT, LedgerCursor, LedgerPage, LedgerQuery, LedgerSort, MAX_SEARCH_LIMIT,
+};
+
+/// Ledger reason of certificates revoked because a new one replaced them.
+pub const ROTATED_REASON: &str = "superseded: auto-rotate (one cert per user)";
+
+/// Metadata recorded for a newly issued certificate.
+#[derive(Debug, Clone, Default)]
+pub struct IssuedCert {
+    pub subject: String,
+    pub serial_hex: String,
+    pub issuer: Option<String>,
+    pub realm: Option<String>,
+    pub wazuh_agent_name: Option<String>,
+    pub not_after_unix: Option<u64>,
+    /// Key identifier of the issuing CA.
+    pub issuer_key_id: Option<String>,
+    /// Signing profile the certificate was issued under.
+    pub profile: Option<String>,
+    pub device_id: Option<String>,
+}
+
+/// Which active certificates a new issuance replaces, and how many the
+/// subject may hold.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    pub subject: String,
+    /// Token issuer of the subject; certificates of the same subject under
+    /// another issuer belong to a different caller and are left alone.
+    pub issuer: Option<String>,
+    /// Signing profile; certificates of other profiles are left alone.
+    pub profile: String,
+    /// Device of the new certificate. Only active certificates of the same
+    /// device are replaced; those recorded without a device count as one
+    /// device of their own, replaced only by requests without a device.
+    pub device_id: Option<String>,
+    /// Active certificates the subject may hold under the profile, the new
+    /// one included.
+    pub quota: u32,
+    pub overwrite: bool,
+}
+
+impl Rotation {
+    /// Whether `entry` is an active certificate under the rotation's subject,
+    /// issuer and profile.
+    fn covers(&self, entry: &LedgerEntry) -> bool {
+        !entry.revoked
+            && entry.subject == self.subject
+            && entry.issuer == self.issuer
+            && entry.profile_name() == self.profile
+    }
+
+    /// Whether the new certificate replaces a covered entry with this device.
+    fn replaces(&self, device_id: Option<&str>) -> bool {
+        device_id == self.device_id.as_deref()
+    }
+
+    /// Check a rotation that replaces `replaced` active certificates and
+    /// keeps `kept` of other devices.
+    fn check(&self, replaced: usize, kept: usize) -> AppResult<()> {
+        if kept + 1 > self.quota as usize {
+            return Err(AppError::Conflict(format!(
+                "Active certificate quota reached ({} devices). Revoke the certificate of another device first.",
+                self.quota
+            )));
+        }
+        if replaced > 0 && !self.overwrite {
+            return Err(AppError::Conflict(
+                "User already has an active certificate. Use the --overwrite flag to re-enroll and replace it.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check, as the new certificate is recorded, that no concurrent
+    /// issuance took its place since the rotation ran: nothing it replaces is
+    /// active again and the other devices still leave room.
+    fn check_insert(&self, replaced: usize, kept: usize) -> AppResult<()> {
+        if replaced > 0 {
+            return Err(AppError::Conflict(
+                "Another certificate was issued for this device meanwhile. Retry the request."
+                    .to_string(),
+            ));
+        }
+        self.check(0, kept)
+    }
+}
+
+/// An enrollment held for admin approval, with what signing it later needs:
+/// the CSR and the claims of the token that requested it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEnrollment {
+    pub request: EnrollmentRequest,
+    pub csr_pem: String,
+    pub claims: Claims,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// One decision of the external policy hook, kept as an audit trail.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyDecision {
+    pub subject: String,
+    pub decided_at_unix: u64,
+    /// `allow`, `deny`, or `error_allow` / `error_deny` when the endpoint
+    /// failed and the hook failed open or closed.
+    pub outcome: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub wazuh_agent_name: Option<String>,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    /// Lifetime the endpoint asked for.
+    #[serde(default)]
+    pub validity_days: Option<u32>,
+    /// SANs the endpoint added, as `dns:`, `email:` or `uri:` entries.
+    #[serde(default)]
+    pub extra_sans: Vec<String>,
+}
+
 /// Storage backend for the issuance ledger.
 ///
 /// The public [`Ledger`] API is backend-agnostic; the CSV implementation is
@@ -21,40 +159,103 @@ mod worker;
 /// system of record for multi-replica deployments.
 #[async_trait]
 pub trait LedgerStore: Send + Sync {
-    async fn record_issued(
+    async fn record_issued(&self, cert: IssuedCert, issued_at_unix: u64) -> AppResult<()>;
+
+    /// Record `cert` if the subject still has room for it under `rotation`,
+    /// checking and inserting atomically so concurrent requests cannot both
+    /// take the last slot. Returns [`AppError::Conflict`] otherwise.
+    async fn record_issued_within(
         &self,
-        subject: String,
-        serial_hex: String,
+        cert: IssuedCert,
+        rotation: Rotation,
         issued_at_unix: u64,
-        issuer: Option<String>,
-        realm: Option<String>,
-        wazuh_agent_name: Option<String>,
     ) -> AppResult<()>;
 
+    /// Revoke `serial_hex`, recording a revoked stub for an unknown serial.
+    /// Returns `true` only when an active entry was revoked, checking and
+    /// updating atomically so concurrent callers cannot both see it active.
     async fn mark_revoked(
         &self,
         serial_hex: String,
         reason: Option<String>,
+        invalidity_date_unix: Option<u64>,
         revoked_at_unix: u64,
-    ) -> AppResult<()>;
+    ) -> AppResult<bool>;
 
-    /// Revoke all active certs for a subject (auto-rotate).
+    /// Revoke the active certs the new one replaces (auto-rotate), see
+    /// [`Rotation`]. Legacy entries without a profile count as
+    /// [`DEFAULT_CERT_PROFILE`](wazuh_cert_oauth2_model::models::ledger_entry::DEFAULT_CERT_PROFILE).
     ///
-    /// Returns `None` when the subject has no active cert, `Some(names)` when
-    /// active certs were revoked (names = the Wazuh agent names that were
-    /// active, for eviction notification). When `overwrite` is false and an
-    /// active cert exists, returns [`AppError::Conflict`].
+    /// Returns `None` when nothing is replaced, `Some(names)` when active
+    /// certs were revoked (names = the Wazuh agent names that were active,
+    /// for eviction notification). Returns [`AppError::Conflict`] when the
+    /// subject's other devices already fill the quota, or when `overwrite` is
+    /// false and a cert would be replaced.
     async fn check_and_revoke_active(
         &self,
-        subject: String,
-        overwrite: bool,
+        rotation: Rotation,
         revoked_at_unix: u64,
     ) -> AppResult<Option<Vec<String>>>;
 
     async fn find_by_subject(&self, subject: &str) -> AppResult<Vec<LedgerEntry>>;
+    /// Entry for `serial_hex`, compared case-insensitively.
+    async fn find_by_serial(&self, serial_hex: &str) -> AppResult<Option<LedgerEntry>>;
     async fn find_active(&self) -> AppResult<Vec<LedgerEntry>>;
     async fn find_revoked(&self) -> AppResult<Vec<LedgerEntry>>;
     async fn find_all(&self) -> AppResult<Vec<LedgerEntry>>;
+    /// One page of the entries matching `query`, in its sort order.
+    async fn search(&self, query: &LedgerQuery) -> AppResult<LedgerPage>;
+
+    async fn record_enrollment(&self, enrollment: PendingEnrollment) -> AppResult<()>;
+    async fn find_enrollment(&self, id: &str) -> AppResult<Option<PendingEnrollment>>;
+    /// Enrollments in `status` (all when `None`), oldest first.
+    async fn find_enrollments(
+        &self,
+        status: Option<EnrollmentStatus>,
+    ) -> AppResult<Vec<PendingEnrollment>>;
+    async fn find_enrollments_by_subject(&self, subject: &str)
+    -> AppResult<Vec<PendingEnrollment>>;
+    /// Replace the decision fields of enrollment `request.id` (status, decider,
+    /// reason, serial and certificate) if it is still in `from`. Returns
+    /// [`AppError::NotFound`] for an unknown id and [`AppError::Conflict`]
+    /// when another decision got there first.
+    async fn update_enrollment(
+        &self,
+        request: EnrollmentRequest,
+        from: EnrollmentStatus,
+    ) -> AppResult<()>;
+
+    async fn record_policy_decision(&self, decision: PolicyDecision) -> AppResult<()>;
+    /// Policy decisions about `subject`, oldest first.
+    async fn find_policy_decisions(&self, subject: &str) -> AppResult<Vec<PolicyDecision>>;
+
+    async fn record_acme_nonce(&self, nonce: String, expires_unix: u64) -> AppResult<()>;
+    /// Consume `nonce`. Returns `true` only when it was issued and neither
+    /// used nor expired at `now`, checking and removing atomically.
+    async fn take_acme_nonce(&self, nonce: &str, now: u64) -> AppResult<bool>;
+    async fn record_acme_eab_key(&self, key: EabKey) -> AppResult<()>;
+    async fn find_acme_eab_key(&self, kid: &str) -> AppResult<Option<EabKey>>;
+    /// Remove and return EAB key `kid` unless it expired at `now`, so
+    /// concurrent registrations cannot both consume it.
+    async fn take_acme_eab_key(&self, kid: &str, now: u64) -> AppResult<Option<EabKey>>;
+    /// Returns [`AppError::Conflict`] when an account holds the same key.
+    async fn record_acme_account(&self, account: AcmeAccount) -> AppResult<()>;
+    async fn find_acme_account(&self, id: &str) -> AppResult<Option<AcmeAccount>>;
+    /// Account registered for the key with `thumbprint`.
+    async fn find_acme_account_by_key(&self, thumbprint: &str) -> AppResult<Option<AcmeAccount>>;
+    /// Replace the status, contacts and binding of account `account.id`.
+    async fn update_acme_account(&self, account: AcmeAccount) -> AppResult<()>;
+    async fn record_acme_order(&self, order: AcmeOrder, authzs: Vec<AcmeAuthz>) -> AppResult<()>;
+    async fn find_acme_order(&self, id: &str) -> AppResult<Option<AcmeOrder>>;
+    /// Orders of `account_id` that have not expired at `now`.
+    async fn find_acme_orders(&self, account_id: &str, now: u64) -> AppResult<Vec<AcmeOrder>>;
+    /// Replace the status and certificate of order `order.id` if it is still
+    /// in `from`. Returns [`AppError::NotFound`] for an unknown id and
+    /// [`AppError::Conflict`] when another request changed it first.
+    async fn update_acme_order(&self, order: AcmeOrder, from: AcmeOrderStatus) -> AppResult<()>;
+    async fn find_acme_authz(&self, id: &str) -> AppResult<Option<AcmeAuthz>>;
+    /// Forget nonces, EAB keys, orders and authorizations expired at `now`.
+    async fn purge_acme(&self, now: u64) -> AppResult<()>;
 }
 
 /// Selects which ledger backend to use.
@@ -88,30 +289,37 @@ impl Ledger {
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn record_issued(
+    pub async fn record_issued(&self, cert: IssuedCert) -> AppResult<()> {
+        self.store.record_issued(cert, Self::now()).await
+    }
+
+    /// Record `cert` unless the quota of `rotation` filled up since
+    /// [`Ledger::check_and_revoke_active`] made room for it.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_issued_within(
         &self,
-        subject: String,
-        serial_hex: String,
-        issuer: Option<String>,
-        realm: Option<String>,
-        wazuh_agent_name: Option<String>,
+        cert: IssuedCert,
+        rotation: Rotation,
     ) -> AppResult<()> {
         self.store
-            .record_issued(
-                subject,
-                serial_hex,
-                Self::now(),
-                issuer,
-                realm,
-                wazuh_agent_name,
-            )
+            .record_issued_within(cert, rotation, Self::now())
             .await
     }
 
+    /// Revoke `serial_hex` now. `invalidity_date_unix` is when its key was
+    /// known or suspected to be compromised, if the revoker knows.
+    ///
+    /// Returns whether this call revoked an active certificate, so callers
+    /// can tell it apart from one already revoked or unknown.
     #[tracing::instrument(skip(self))]
-    pub async fn mark_revoked(&self, serial_hex: String, reason: Option<String>) -> AppResult<()> {
+    pub async fn mark_revoked(
+        &self,
+        serial_hex: String,
+        reason: Option<String>,
+        invalidity_date_unix: Option<u64>,
+    ) -> AppResult<bool> {
         self.store
-            .mark_revoked(serial_hex, reason, Self::now())
+            .mark_revoked(serial_hex, reason, invalidity_date_unix, Self::now())
             .await
     }
 
@@ -120,14 +328,27 @@ impl Ledger {
         self.store.find_by_subject(subject).await
     }
 
+    /// Entries issued to `subject` of the token issuer `issuer`; the same
+    /// subject of another issuer is a different caller.
+    #[tracing::instrument(skip(self))]
+    pub async fn find_issued_to(&self, issuer: &str, subject: &str) -> AppResult<Vec<LedgerEntry>> {
+        let mut entries = self.store.find_by_subject(subject).await?;
+        entries.retain(|e| e.issued_to(issuer, subject));
+        Ok(entries)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_by_serial(&self, serial_hex: &str) -> AppResult<Option<LedgerEntry>> {
+        self.store.find_by_serial(serial_hex).await
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn check_and_revoke_active(
         &self,
-        subject: String,
-        overwrite: bool,
+        rotation: Rotation,
     ) -> AppResult<Option<Vec<String>>> {
         self.store
-            .check_and_revoke_active(subject, overwrite, Self::now())
+            .check_and_revoke_active(rotation, Self::now())
             .await
     }
 
@@ -146,6 +367,140 @@ impl Ledger {
         self.store.find_all().await
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn search(&self, query: &LedgerQuery) -> AppResult<LedgerPage> {
+        self.store.search(query).await
+    }
+
+    #[tracing::instrument(skip(self, enrollment), fields(id = %enrollment.request.id))]
+    pub async fn record_enrollment(&self, enrollment: PendingEnrollment) -> AppResult<()> {
+        self.store.record_enrollment(enrollment).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_enrollment(&self, id: &str) -> AppResult<Option<PendingEnrollment>> {
+        self.store.find_enrollment(id).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_enrollments(
+        &self,
+        status: Option<EnrollmentStatus>,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        self.store.find_enrollments(status).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_enrollments_by_subject(
+        &self,
+        subject: &str,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        self.store.find_enrollments_by_subject(subject).await
+    }
+
+    #[tracing::instrument(skip(self, request), fields(id = %request.id, to = request.status.as_str()))]
+    pub async fn update_enrollment(
+        &self,
+        request: EnrollmentRequest,
+        from: EnrollmentStatus,
+    ) -> AppResult<()> {
+        self.store.update_enrollment(request, from).await
+    }
+
+    #[tracing::instrument(skip(self, decision), fields(sub = %decision.subject, outcome = %decision.outcome))]
+    pub async fn record_policy_decision(&self, decision: PolicyDecision) -> AppResult<()> {
+        self.store.record_policy_decision(decision).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_policy_decisions(&self, subject: &str) -> AppResult<Vec<PolicyDecision>> {
+        self.store.find_policy_decisions(subject).await
+    }
+
+    pub async fn record_acme_nonce(&self, nonce: String, expires_unix: u64) -> AppResult<()> {
+        self.store.record_acme_nonce(nonce, expires_unix).await
+    }
+
+    pub async fn take_acme_nonce(&self, nonce: &str) -> AppResult<bool> {
+        self.store.take_acme_nonce(nonce, Self::now()).await
+    }
+
+    #[tracing::instrument(skip(self, key), fields(kid = %key.kid))]
+    pub async fn record_acme_eab_key(&self, key: EabKey) -> AppResult<()> {
+        self.store.record_acme_eab_key(key).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_eab_key(&self, kid: &str) -> AppResult<Option<EabKey>> {
+        self.store.find_acme_eab_key(kid).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn take_acme_eab_key(&self, kid: &str) -> AppResult<Option<EabKey>> {
+        self.store.take_acme_eab_key(kid, Self::now()).await
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    pub async fn record_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        self.store.record_acme_account(account).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_account(&self, id: &str) -> AppResult<Option<AcmeAccount>> {
+        self.store.find_acme_account(id).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_account_by_key(
+        &self,
+        thumbprint: &str,
+    ) -> AppResult<Option<AcmeAccount>> {
+        self.store.find_acme_account_by_key(thumbprint).await
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    pub async fn update_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        self.store.update_acme_account(account).await
+    }
+
+    #[tracing::instrument(skip(self, order, authzs), fields(order = %order.id))]
+    pub async fn record_acme_order(
+        &self,
+        order: AcmeOrder,
+        authzs: Vec<AcmeAuthz>,
+    ) -> AppResult<()> {
+        self.store.record_acme_order(order, authzs).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_order(&self, id: &str) -> AppResult<Option<AcmeOrder>> {
+        self.store.find_acme_order(id).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_orders(&self, account_id: &str) -> AppResult<Vec<AcmeOrder>> {
+        self.store.find_acme_orders(account_id, Self::now()).await
+    }
+
+    #[tracing::instrument(skip(self, order), fields(order = %order.id, to = order.status.as_str()))]
+    pub async fn update_acme_order(
+        &self,
+        order: AcmeOrder,
+        from: AcmeOrderStatus,
+    ) -> AppResult<()> {
+        self.store.update_acme_order(order, from).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_acme_authz(&self, id: &str) -> AppResult<Option<AcmeAuthz>> {
+        self.store.find_acme_authz(id).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn purge_acme(&self) -> AppResult<()> {
+        self.store.purge_acme(Self::now()).await
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn revoked_as_revocations(
         &self,
@@ -155,22 +510,64 @@ impl Ledger {
             .find_revoked()
             .await?
             .into_iter()
-            .map(|e| crate::shared::crl::RevocationEntry {
-                serial_hex: e.serial_hex,
-                reason: e.reason,
-                revoked_at_unix: e.revoked_at_unix.unwrap_or_default(),
+            .map(to_revocation)
+            .collect())
+    }
+
+    /// Revocations belonging in the CRL of the CA with `issuer_key_id`.
+    ///
+    /// Untagged entries (legacy rows, revoke-stubs) are listed by every
+    /// issuer: serials are random, so they cannot collide with another CA's.
+    #[tracing::instrument(skip(self))]
+    pub async fn revocations_for_issuer(
+        &self,
+        issuer_key_id: &str,
+    ) -> AppResult<Vec<crate::shared::crl::RevocationEntry>> {
+        Ok(self
+            .store
+            .find_revoked()
+            .await?
+            .into_iter()
+            .filter(|e| {
+                e.issuer_key_id
+                    .as_deref()
+                    .is_none_or(|k| k.eq_ignore_ascii_case(issuer_key_id))
             })
+            .map(to_revocation)
             .collect())
     }
 }
 
+fn to_revocation(e: LedgerEntry) -> crate::shared::crl::RevocationEntry {
+    crate::shared::crl::RevocationEntry {
+        serial_hex: e.serial_hex,
+        reason: e.reason,
+        revoked_at_unix: e.revoked_at_unix.unwrap_or_default(),
+        invalidity_date_unix: e.invalidity_date_unix,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::IssuedCert;
     use super::Ledger;
     use super::LedgerBackend;
+    use super::LedgerStore;
+    use super::PendingEnrollment;
+    use super::ROTATED_REASON;
+    use super::Rotation;
+    use super::csv_store::CsvLedgerStore;
+    use super::{AcmeAccount, AcmeAuthz, AcmeOrder, AcmeOrderStatus, EabKey};
+    use super::{LedgerCursor, LedgerQuery, LedgerSort};
+    use crate::shared::acme::{Binding, Identifier};
     use std::path::PathBuf;
     use std::time::{SystemTime, UNIX_EPOCH};
     use tokio::fs;
+    use wazuh_cert_oauth2_model::models::claims::Claims;
+    use wazuh_cert_oauth2_model::models::enrollment_request::{
+        EnrollmentRequest, EnrollmentStatus,
+    };
+    use wazuh_cert_oauth2_model::models::errors::AppError;
 
     fn unique_ledger_path() -> PathBuf {
         let nanos = SystemTime::now()
@@ -182,6 +579,23 @@ mod tests {
             .join("ledger.csv")
     }
 
+    /// A rotation under the `agent` profile, shared with the store tests.
+    pub(super) fn rotation(
+        subject: &str,
+        device_id: Option<&str>,
+        quota: u32,
+        overwrite: bool,
+    ) -> Rotation {
+        Rotation {
+            subject: subject.to_string(),
+            issuer: None,
+            profile: "agent".to_string(),
+            device_id: device_id.map(str::to_string),
+            quota,
+            overwrite,
+        }
+    }
+
     async fn csv_ledger(path: PathBuf) -> Ledger {
         let parent = path.parent().expect("path should have parent");
         fs::create_dir_all(parent)
@@ -199,13 +613,17 @@ mod tests {
 
         let ledger = csv_ledger(path.clone()).await;
         ledger
-            .record_issued(
-                "subject-a".to_string(),
-                "ABCD01".to_string(),
-                Some("https://issuer/realms/dev".to_string()),
-                Some("dev".to_string()),
-                None,
-            )
+            .record_issued(IssuedCert {
+                subject: "subject-a".to_string(),
+                serial_hex: "ABCD01".to_string(),
+                issuer: Some("https://issuer/realms/dev".to_string()),
+                realm: Some("dev".to_string()),
+                wazuh_agent_name: None,
+                not_after_unix: Some(2_000_000_000),
+                issuer_key_id: Some("A1B2C3".to_string()),
+                profile: Some("agent".to_string()),
+                device_id: Some("laptop-01".to_string()),
+            })
             .await
             .expect("record_issued should succeed");
 
@@ -216,11 +634,33 @@ mod tests {
         assert_eq!(by_subject.len(), 1);
         assert_eq!(by_subject[0].serial_hex, "ABCD01");
         assert!(!by_subject[0].revoked);
-
-        ledger
-            .mark_revoked("ABCD01".to_string(), Some("manual".to_string()))
+        assert_eq!(by_subject[0].not_after_unix, Some(2_000_000_000));
+        let by_serial = ledger
+            .find_by_serial("abcd01")
             .await
-            .expect("mark_revoked should succeed");
+            .expect("find_by_serial should succeed");
+        assert_eq!(by_serial.map(|e| e.subject).as_deref(), Some("subject-a"));
+        assert!(
+            ledger
+                .find_by_serial("ABCD02")
+                .await
+                .expect("find_by_serial should succeed")
+                .is_none()
+        );
+
+        assert!(
+            ledger
+                .mark_revoked("ABCD01".to_string(), Some("manual".to_string()), None)
+                .await
+                .expect("mark_revoked should succeed")
+        );
+        // Only the first revocation of an active entry reports it.
+        assert!(
+            !ledger
+                .mark_revoked("ABCD01".to_string(), Some("again".to_string()), None)
+                .await
+                .expect("mark_revoked should succeed")
+        );
 
         let revocations = ledger
             .revoked_as_revocations()
@@ -230,6 +670,21 @@ mod tests {
         assert_eq!(revocations[0].serial_hex, "ABCD01");
         assert_eq!(revocations[0].reason.as_deref(), Some("manual"));
         assert!(revocations[0].revoked_at_unix > 0);
+        assert_eq!(
+            ledger
+                .revocations_for_issuer("a1b2c3")
+                .await
+                .expect("revocations_for_issuer")
+                .len(),
+            1
+        );
+        assert!(
+            ledger
+                .revocations_for_issuer("FFFF")
+                .await
+                .expect("revocations_for_issuer")
+                .is_empty()
+        );
 
         let _ = fs::remove_dir_all(parent).await;
     }
@@ -241,7 +696,11 @@ mod tests {
 
         let ledger = csv_ledger(path.clone()).await;
         ledger
-            .mark_revoked("UNKNOWN01".to_string(), Some("preemptive".to_string()))
+            .mark_revoked(
+                "UNKNOWN01".to_string(),
+                Some("preemptive".to_string()),
+                None,
+            )
             .await
             .expect("mark_revoked should succeed");
 
@@ -252,6 +711,15 @@ mod tests {
         assert_eq!(revocations.len(), 1);
         assert_eq!(revocations[0].serial_hex, "UNKNOWN01");
         assert_eq!(revocations[0].reason.as_deref(), Some("preemptive"));
+        // Untagged stubs are listed by every issuer.
+        assert_eq!(
+            ledger
+                .revocations_for_issuer("FFFF")
+                .await
+                .expect("revocations_for_issuer")
+                .len(),
+            1
+        );
 
         let _ = fs::remove_dir_all(parent).await;
     }
@@ -263,19 +731,22 @@ mod tests {
 
         let ledger = csv_ledger(path.clone()).await;
         ledger
-            .record_issued(
-                "user-a".to_string(),
-                "CERT01".to_string(),
-                Some("https://issuer/realms/dev".to_string()),
-                Some("dev".to_string()),
-                None,
-            )
+            .record_issued(IssuedCert {
+                subject: "user-a".to_string(),
+                serial_hex: "CERT01".to_string(),
+                issuer: Some("https://issuer/realms/dev".to_string()),
+                realm: Some("dev".to_string()),
+                ..Default::default()
+            })
             .await
             .expect("record_issued should succeed");
 
         // overwrite=true — Some(names) means a cert was revoked; names empty because no agent name stored
         let revoked_names = ledger
-            .check_and_revoke_active("user-a".to_string(), true)
+            .check_and_revoke_active(Rotation {
+                issuer: Some("https://issuer/realms/dev".to_string()),
+                ..rotation("user-a", None, 1, true)
+            })
             .await
             .expect("check_and_revoke_active should succeed");
         assert!(
@@ -302,10 +773,7 @@ mod tests {
             .expect("revoked_as_revocations should succeed");
         assert_eq!(revocations.len(), 1);
         assert_eq!(revocations[0].serial_hex, "CERT01");
-        assert_eq!(
-            revocations[0].reason.as_deref(),
-            Some("auto-rotate (one cert per user)")
-        );
+        assert_eq!(revocations[0].reason.as_deref(), Some(ROTATED_REASON));
 
         let _ = fs::remove_dir_all(parent).await;
     }
@@ -319,7 +787,7 @@ mod tests {
 
         // No certs at all — should return None, not error
         let revoked_names = ledger
-            .check_and_revoke_active("user-b".to_string(), true)
+            .check_and_revoke_active(rotation("user-b", None, 1, true))
             .await
             .expect("check_and_revoke_active should succeed even with no certs");
         assert!(
@@ -329,4 +797,544 @@ mod tests {
 
         let _ = fs::remove_dir_all(parent).await;
     }
+
+    #[tokio::test]
+    async fn rotation_leaves_the_same_subject_of_another_issuer_alone() {
+        let path = unique_ledger_path();
+        let parent = path.parent().expect("path should have parent");
+
+        let ledger = csv_ledger(path.clone()).await;
+        ledger
+            .record_issued(IssuedCert {
+                subject: "user-e".to_string(),
+                serial_hex: "ISS01".to_string(),
+                issuer: Some("https://issuer/realms/dev".to_string()),
+                ..Default::default()
+            })
+            .await
+            .expect("record_issued should succeed");
+
+        let partner = Rotation {
+            issuer: Some("https://issuer/realms/partners".to_string()),
+            ..rotation("user-e", None, 1, false)
+        };
+        let revoked_names = ledger
+            .check_and_revoke_active(partner)
+            .await
+            .expect("another issuer's certificate neither counts nor conflicts");
+        assert!(revoked_names.is_none());
+        let active = ledger.find_active().await.expect("find_active");
+        assert_eq!(active.len(), 1);
+
+        let own = ledger
+            .find_issued_to("https://issuer/realms/dev", "user-e")
+            .await
+            .expect("find_issued_to");
+        assert_eq!(own.len(), 1);
+        let foreign = ledger
+            .find_issued_to("https://issuer/realms/partners", "user-e")
+            .await
+            .expect("find_issued_to");
+        assert!(foreign.is_empty());
+
+        let _ = fs::remove_dir_all(parent).await;
+    }
+
+    #[tokio::test]
+    async fn check_and_revoke_active_enforces_device_quota() {
+        let path = unique_ledger_path();
+        let parent = path.parent().expect("path should have parent");
+
+        let ledger = csv_ledger(path.clone()).await;
+        for (serial, device) in [("DEV01", Some("laptop")), ("DEV02", None)] {
+            ledger
+                .record_issued(IssuedCert {
+                    subject: "user-c".to_string(),
+                    serial_hex: serial.to_string(),
+                    device_id: device.map(str::to_string),
+                    ..Default::default()
+                })
+                .await
+                .expect("record_issued should succeed");
+        }
+
+        // The device-less cert counts as a device: a third one is over quota.
+        let err = ledger
+            .check_and_revoke_active(rotation("user-c", Some("workstation"), 2, true))
+            .await
+            .expect_err("quota of two is full");
+        assert!(err.to_string().contains("quota"), "{err}");
+
+        // A request without a device replaces only the device-less cert.
+        let revoked = ledger
+            .check_and_revoke_active(rotation("user-c", None, 2, true))
+            .await
+            .expect("within quota");
+        assert_eq!(revoked, Some(Vec::new()));
+        let active = ledger.find_active().await.expect("find_active");
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].serial_hex, "DEV01");
+
+        let err = ledger
+            .check_and_revoke_active(rotation("user-c", Some("workstation"), 1, true))
+            .await
+            .expect_err("quota of one is full");
+        assert!(err.to_string().contains("quota"), "{err}");
+
+        let _ = fs::remove_dir_all(parent).await;
+    }
+
+    #[tokio::test]
+    async fn record_issued_within_rechecks_the_quota() {
+        let path = unique_ledger_path();
+        let parent = path.parent().expect("parent").to_path_buf();
+        let ledger = csv_ledger(path).await;
+        let cert = |serial: &str, device: &str| IssuedCert {
+            subject: "user-d".to_string(),
+            serial_hex: serial.to_string(),
+            profile: Some("agent".to_string()),
+            device_id: Some(device.to_string()),
+            ..Default::default()
+        };
+
+        // Both requests passed the rotation before either was recorded.
+        for device in ["laptop", "tablet"] {
+            assert_eq!(
+                ledger
+                    .check_and_revoke_active(rotation("user-d", Some(device), 1, false))
+                    .await
+                    .expect("room for one"),
+                None
+            );
+        }
+        ledger
+            .record_issued_within(
+                cert("D01", "laptop"),
+                rotation("user-d", Some("laptop"), 1, false),
+            )
+            .await
+            .expect("first takes the slot");
+        let err = ledger
+            .record_issued_within(
+                cert("D02", "tablet"),
+                rotation("user-d", Some("tablet"), 1, false),
+            )
+            .await
+            .expect_err("quota of one is full");
+        assert!(err.to_string().contains("quota"), "{err}");
+        let err = ledger
+            .record_issued_within(
+                cert("D03", "laptop"),
+                rotation("user-d", Some("laptop"), 2, false),
+            )
+            .await
+            .expect_err("laptop already has one");
+        assert!(err.to_string().contains("meanwhile"), "{err}");
+        let active = ledger.find_active().await.expect("find_active");
+        assert_eq!(active.len(), 1);
+
+        let _ = fs::remove_dir_all(parent).await;
+    }
+
+    #[tokio::test]
+    async fn search_pages_through_filtered_entries() {
+        let path = unique_ledger_path();
+        let ledger = csv_ledger(path.clone()).await;
+        for (serial, realm, agent) in [
+            ("0A", "lab", "lab-01"),
+            ("0B", "lab", "lab-02"),
+            ("0C", "office", "office-01"),
+            ("0D", "lab", "lab-03"),
+            ("0E", "lab", "lab-10"),
+        ] {
+            ledger
+                .record_issued(IssuedCert {
+                    subject: format!("user-{serial}"),
+                    serial_hex: serial.to_string(),
+                    realm: Some(realm.to_string()),
+                    wazuh_agent_name: Some(agent.to_string()),
+                    ..Default::default()
+                })
+                .await
+                .expect("record_issued should succeed");
+        }
+        ledger
+            .mark_revoked("0B".to_string(), Some("compromised".to_string()), None)
+            .await
+            .expect("mark_revoked should succeed");
+
+        let mut query = LedgerQuery {
+            realm: Some("lab".to_string()),
+            sort: LedgerSort::SerialAsc,
+            limit: 3,
+            ..Default::default()
+        };
+        let first = ledger.search(&query).await.expect("search");
+        let cursor = first.next_cursor.expect("a second page");
+        query.after = Some(LedgerCursor::decode(&cursor, query.sort).expect("cursor"));
+        let second = ledger.search(&query).await.expect("search");
+        assert!(second.next_cursor.is_none());
+        let serials: Vec<_> = first
+            .entries
+            .iter()
+            .chain(&second.entries)
+            .map(|e| e.serial_hex.as_str())
+            .collect();
+        assert_eq!(serials, ["0A", "0B", "0D", "0E"]);
+
+        let only = |query: LedgerQuery| {
+            let ledger = ledger.clone();
+            async move {
+                let page = ledger.search(&query).await.expect("search");
+                page.entries
+                    .into_iter()
+                    .map(|e| e.serial_hex)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let revoked = only(LedgerQuery {
+            agent_name_prefix: Some("lab-0".to_string()),
+            revoked: Some(true),
+            reason: Some("compromised".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(revoked, ["0B"]);
+        let by_serial = only(LedgerQuery {
+            serial_hex: Some("0e".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(by_serial, ["0E"]);
+        let newest_serials = only(LedgerQuery {
+            sort: LedgerSort::SerialDesc,
+            limit: 2,
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(newest_serials, ["0E", "0D"]);
+
+        let _ = fs::remove_dir_all(path.parent().expect("parent")).await;
+    }
+
+    #[tokio::test]
+    async fn enrollments_survive_a_restart_and_decide_once() {
+        let path = unique_ledger_path();
+        let parent = path.parent().expect("path should have parent");
+
+        let ledger = csv_ledger(path.clone()).await;
+        let request = EnrollmentRequest {
+            id: "req-1".to_string(),
+            subject: "user-d".to_string(),
+            status: EnrollmentStatus::Pending,
+            created_at_unix: 100,
+            realm: Some("partners".to_string()),
+            profile: None,
+            device_id: None,
+            wazuh_agent_name: None,
+            decided_at_unix: None,
+            decided_by: None,
+            reason: None,
+            serial_hex: None,
+            certificate: None,
+        };
+        ledger
+            .record_enrollment(PendingEnrollment {
+                request: request.clone(),
+                csr_pem: "CSR".to_string(),
+                claims: Claims {
+                    sub: "user-d".to_string(),
+                    name: None,
+                    iss: "https://issuer/realms/partners".to_string(),
+                    exp: 0,
+                    preferred_username: None,
+                    email: None,
+                    realm_access: None,
+                    ..Default::default()
+                },
+                overwrite: false,
+            })
+            .await
+            .expect("record_enrollment should succeed");
+
+        let rejected = EnrollmentRequest {
+            status: EnrollmentStatus::Rejected,
+            decided_by: Some("admin".to_string()),
+            ..request.clone()
+        };
+        ledger
+            .update_enrollment(rejected.clone(), EnrollmentStatus::Pending)
+            .await
+            .expect("first decision wins");
+        let err = ledger
+            .update_enrollment(rejected, EnrollmentStatus::Pending)
+            .await
+            .expect_err("second decision conflicts");
+        assert!(err.to_string().contains("already rejected"), "{err}");
+
+        let reopened = csv_ledger(path.clone()).await;
+        let stored = reopened
+            .find_enrollment("req-1")
+            .await
+            .expect("find_enrollment should succeed")
+            .expect("enrollment should be persisted");
+        assert_eq!(stored.request.status, EnrollmentStatus::Rejected);
+        assert_eq!(stored.csr_pem, "CSR");
+        assert!(
+            reopened
+                .find_enrollments(Some(EnrollmentStatus::Pending))
+                .await
+                .expect("find_enrollments should succeed")
+                .is_empty()
+        );
+
+        let _ = fs::remove_dir_all(parent).await;
+    }
+
+    /// An EAB key, an account and an order with one authorization, all
+    /// named after `id` and expiring at `expires_unix`.
+    fn acme_records(id: &str, expires_unix: u64) -> (EabKey, AcmeAccount, AcmeOrder, AcmeAuthz) {
+        let binding = Binding {
+            claims: Claims {
+                sub: format!("{id}-sub"),
+                iss: "https://issuer/realms/dev".to_string(),
+                ..Default::default()
+            },
+            profile: None,
+            device_id: Some("laptop".to_string()),
+            wazuh_agent_name: None,
+        };
+        let identifier = Identifier {
+            kind: "dns".to_string(),
+            value: format!("{id}.example.com"),
+        };
+        let key = EabKey {
+            kid: format!("{id}-kid"),
+            hmac_key: vec![7; 32],
+            binding: binding.clone(),
+            expires_unix,
+            bound_until: expires_unix,
+        };
+        let account = AcmeAccount {
+            id: format!("{id}-account"),
+            thumbprint: format!("{id}-thumb"),
+            jwk: serde_json::json!({ "kty": "EC", "crv": "P-256", "x": id }),
+            deactivated: false,
+            contact: vec![format!("mailto:{id}@example.com")],
+            binding,
+            bound_until: expires_unix,
+        };
+        let authz = AcmeAuthz {
+            id: format!("{id}-authz"),
+            account_id: account.id.clone(),
+            identifier: identifier.clone(),
+            expires_unix,
+        };
+        let order = AcmeOrder {
+            id: format!("{id}-order"),
+            account_id: account.id.clone(),
+            status: AcmeOrderStatus::Ready,
+            expires_unix,
+            identifiers: vec![identifier],
+            authz_ids: vec![authz.id.clone()],
+            certificate: None,
+        };
+        (key, account, order, authz)
+    }
+
+    /// Round-trip ACME records through `store`: nonces and EAB keys are
+    /// single-use, a key registers one account, and only one request moves
+    /// an order out of `ready`. Shared with the Postgres store tests.
+    pub(super) async fn exercise_acme_store(store: &dyn LedgerStore, id: &str) {
+        let now = Ledger::now();
+        let (key, account, order, authz) = acme_records(id, now + 600);
+
+        let nonce = format!("{id}-nonce");
+        store
+            .record_acme_nonce(nonce.clone(), now + 600)
+            .await
+            .expect("record nonce");
+        store
+            .record_acme_nonce(format!("{id}-stale"), now)
+            .await
+            .expect("record nonce");
+        assert!(store.take_acme_nonce(&nonce, now).await.expect("take"));
+        assert!(!store.take_acme_nonce(&nonce, now).await.expect("take"));
+        assert!(
+            !store
+                .take_acme_nonce(&format!("{id}-stale"), now)
+                .await
+                .expect("take")
+        );
+
+        store
+            .record_acme_eab_key(key.clone())
+            .await
+            .expect("record EAB key");
+        let found = store.find_acme_eab_key(&key.kid).await.expect("find");
+        assert_eq!(found.map(|k| k.hmac_key), Some(key.hmac_key.clone()));
+        let taken = store.take_acme_eab_key(&key.kid, now).await.expect("take");
+        assert_eq!(
+            taken.map(|k| k.binding.claims.sub),
+            Some(key.binding.claims.sub.clone())
+        );
+        assert!(
+            store
+                .take_acme_eab_key(&key.kid, now)
+                .await
+                .expect("take")
+                .is_none()
+        );
+
+        store
+            .record_acme_account(account.clone())
+            .await
+            .expect("record account");
+        let same_key = AcmeAccount {
+            id: format!("{id}-other"),
+            ..account.clone()
+        };
+        assert!(matches!(
+            store.record_acme_account(same_key).await,
+            Err(AppError::Conflict(_))
+        ));
+        let mut updated = store
+            .find_acme_account_by_key(&account.thumbprint)
+            .await
+            .expect("find by key")
+            .expect("account of the key");
+        assert_eq!(updated.id, account.id);
+        assert_eq!(updated.jwk, account.jwk);
+        updated.deactivated = true;
+        updated.contact = Vec::new();
+        store
+            .update_acme_account(updated)
+            .await
+            .expect("update account");
+        let found = store
+            .find_acme_account(&account.id)
+            .await
+            .expect("find")
+            .expect("account");
+        assert!(found.deactivated && found.contact.is_empty());
+        assert_eq!(found.binding.device_id.as_deref(), Some("laptop"));
+
+        store
+            .record_acme_order(order.clone(), vec![authz.clone()])
+            .await
+            .expect("record order");
+        let orders = store
+            .find_acme_orders(&account.id, now)
+            .await
+            .expect("find orders");
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].identifiers, order.identifiers);
+        let found = store.find_acme_authz(&authz.id).await.expect("find authz");
+        assert_eq!(found.map(|a| a.identifier), Some(authz.identifier));
+
+        let processing = AcmeOrder {
+            status: AcmeOrderStatus::Processing,
+            ..order.clone()
+        };
+        store
+            .update_acme_order(processing.clone(), AcmeOrderStatus::Ready)
+            .await
+            .expect("claim the order");
+        assert!(matches!(
+            store
+                .update_acme_order(processing, AcmeOrderStatus::Ready)
+                .await,
+            Err(AppError::Conflict(_))
+        ));
+        let valid = AcmeOrder {
+            status: AcmeOrderStatus::Valid,
+            certificate: Some("chain".to_string()),
+            ..order.clone()
+        };
+        store
+            .update_acme_order(valid, AcmeOrderStatus::Processing)
+            .await
+            .expect("complete the order");
+        let found = store
+            .find_acme_order(&order.id)
+            .await
+            .expect("find order")
+            .expect("order");
+        assert_eq!(found.status, AcmeOrderStatus::Valid);
+        assert_eq!(found.certificate.as_deref(), Some("chain"));
+
+        store.purge_acme(now + 600).await.expect("purge");
+        assert!(
+            store
+                .find_acme_order(&order.id)
+                .await
+                .expect("find order")
+                .is_none()
+        );
+        assert!(
+            store
+                .find_acme_authz(&authz.id)
+                .await
+                .expect("find authz")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn csv_acme_state_survives_a_restart() {
+        let path = unique_ledger_path();
+        let parent = path.parent().expect("path should have parent");
+        fs::create_dir_all(parent).await.expect("temp dir");
+
+        let store = CsvLedgerStore::new(path.clone()).await.expect("store");
+        exercise_acme_store(&store, "csv").await;
+        let (key, account, order, authz) = acme_records("kept", Ledger::now() + 600);
+        let account_id = account.id.clone();
+        store.record_acme_eab_key(key).await.expect("record key");
+        store
+            .record_acme_account(account)
+            .await
+            .expect("record account");
+        store
+            .record_acme_order(order.clone(), vec![authz])
+            .await
+            .expect("record order");
+        store
+            .record_acme_nonce("kept-nonce".to_string(), Ledger::now() + 600)
+            .await
+            .expect("record nonce");
+        drop(store);
+
+        let store = CsvLedgerStore::new(path.clone()).await.expect("store");
+        assert!(
+            store
+                .find_acme_account(&account_id)
+                .await
+                .expect("find")
+                .is_some()
+        );
+        assert!(
+            store
+                .find_acme_eab_key("kept-kid")
+                .await
+                .expect("find")
+                .is_some()
+        );
+        assert!(
+            store
+                .find_acme_order(&order.id)
+                .await
+                .expect("find")
+                .is_some()
+        );
+        // Nonces live in memory only.
+        assert!(
+            !store
+                .take_acme_nonce("kept-nonce", Ledger::now())
+                .await
+                .expect("take")
+        );
+
+        let _ = fs::remove_dir_all(parent).await;
+    }
 }
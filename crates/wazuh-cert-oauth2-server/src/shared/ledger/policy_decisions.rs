@@ -0,0 +1,43 @@
+// Policy hook decisions for the CSV-backed store, appended as JSON lines
+// next to the ledger file.
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use wazuh_cert_oauth2_model::models::errors::AppResult;
+
+use super::PolicyDecision;
+
+/// `ledger.csv` keeps its policy decisions in `ledger.policy.jsonl`.
+pub(super) fn policy_decisions_path(ledger_path: &Path) -> PathBuf {
+    ledger_path.with_extension("policy.jsonl")
+}
+
+pub(super) async fn append_decision(path: &Path, decision: &PolicyDecision) -> AppResult<()> {
+    let mut line = serde_json::to_vec(decision)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    Ok(())
+}
+
+pub(super) async fn load_decisions(path: &Path, subject: &str) -> AppResult<Vec<PolicyDecision>> {
+    let text = match fs::read_to_string(path).await {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut decisions = Vec::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let decision: PolicyDecision = serde_json::from_str(line)?;
+        if decision.subject == subject {
+            decisions.push(decision);
+        }
+    }
+    Ok(decisions)
+}
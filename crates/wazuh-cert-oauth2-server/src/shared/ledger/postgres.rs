@@ -1,17 +1,28 @@
 use async_trait::async_trait;
 use sqlx::PgPool;
 use sqlx::Row;
+use wazuh_cert_oauth2_model::models::enrollment_request::{EnrollmentRequest, EnrollmentStatus};
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::ledger_entry::DEFAULT_CERT_PROFILE;
 
+use super::IssuedCert;
 use super::LedgerEntry;
+use super::LedgerPage;
+use super::LedgerQuery;
+use super::LedgerSort;
 use super::LedgerStore;
+use super::PendingEnrollment;
+use super::PolicyDecision;
+use super::ROTATED_REASON;
+use super::Rotation;
+use super::{AcmeAccount, AcmeAuthz, AcmeOrder, AcmeOrderStatus, EabKey};
 
 /// PostgreSQL-backed ledger store (system of record for multi-replica).
 ///
 /// Writes go through a single transaction that appends to the audit log
 /// (`ledger_event`) and materializes current state (`ledger_entry`).
-/// `check_and_revoke_active` uses `SELECT ... FOR UPDATE` so auto-rotate is
-/// atomic across replicas.
+/// `check_and_revoke_active` and `record_issued_within` take a per-subject
+/// advisory lock so auto-rotate and the device quota hold across replicas.
 pub struct PostgresLedgerStore {
     pool: PgPool,
 }
@@ -22,6 +33,44 @@ impl PostgresLedgerStore {
     }
 }
 
+/// Ledger search in one sort order: `$1`..`$12` are the optional filters,
+/// `$13`/`$14` the cursor's issue time and serial, and `$15` the row limit.
+/// The agent-name prefix is matched as the `COLLATE "C"` range `$4`..`$5`
+/// so it can use `idx_entry_agent_name`.
+macro_rules! search_sql {
+    ($after:literal, $order:literal) => {
+        concat!(
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
+             FROM ledger_entry
+             WHERE ($1::text IS NULL OR realm = $1)
+               AND ($2::text IS NULL OR issuer = $2)
+               AND ($3::text IS NULL OR wazuh_agent_name = $3)
+               AND ($4::text IS NULL OR (wazuh_agent_name COLLATE \"C\" >= $4
+                    AND wazuh_agent_name COLLATE \"C\" < $5
+                    AND starts_with(wazuh_agent_name, $4)))
+               AND ($6::text IS NULL OR serial_hex = $6)
+               AND ($7::text IS NULL OR reason = $7 OR starts_with(reason, $7 || ':'))
+               AND ($8::boolean IS NULL OR revoked = $8)
+               AND ($9::bigint IS NULL OR issued_at_unix >= $9)
+               AND ($10::bigint IS NULL OR issued_at_unix < $10)
+               AND ($11::bigint IS NULL OR revoked_at_unix >= $11)
+               AND ($12::bigint IS NULL OR revoked_at_unix < $12)
+               AND ($14::text IS NULL OR ",
+            $after,
+            ")
+             ORDER BY ",
+            $order,
+            " LIMIT $15"
+        )
+    };
+}
+
+/// Bound above every string starting with `prefix` in byte order;
+/// `starts_with` keeps the match exact.
+fn prefix_upper_bound(prefix: &str) -> String {
+    format!("{prefix}\u{10FFFF}")
+}
+
 fn normalize_serial(serial: &str) -> String {
     serial.to_uppercase()
 }
@@ -39,62 +88,205 @@ fn map_row(row: &sqlx::postgres::PgRow) -> LedgerEntry {
         issuer: row.get("issuer"),
         realm: row.get("realm"),
         wazuh_agent_name: row.get("wazuh_agent_name"),
+        not_after_unix: row
+            .get::<Option<i64>, _>("not_after_unix")
+            .map(|v| v as u64),
+        issuer_key_id: row.get("issuer_key_id"),
+        profile: row.get("profile"),
+        device_id: row.get("device_id"),
+        invalidity_date_unix: row
+            .get::<Option<i64>, _>("invalidity_date_unix")
+            .map(|v| v as u64),
     }
 }
 
+fn map_enrollment(row: &sqlx::postgres::PgRow) -> AppResult<PendingEnrollment> {
+    let status: String = row.get("status");
+    let status = EnrollmentStatus::parse(&status)
+        .ok_or_else(|| AppError::Serialization(format!("unknown enrollment status '{status}'")))?;
+    let certificate = row
+        .get::<Option<String>, _>("certificate")
+        .map(|json| serde_json::from_str(&json))
+        .transpose()?;
+    Ok(PendingEnrollment {
+        request: EnrollmentRequest {
+            id: row.get("id"),
+            subject: row.get("subject"),
+            status,
+            created_at_unix: row.get::<i64, _>("created_at_unix") as u64,
+            realm: row.get("realm"),
+            profile: row.get("profile"),
+            device_id: row.get("device_id"),
+            wazuh_agent_name: row.get("wazuh_agent_name"),
+            decided_at_unix: row
+                .get::<Option<i64>, _>("decided_at_unix")
+                .map(|v| v as u64),
+            decided_by: row.get("decided_by"),
+            reason: row.get("reason"),
+            serial_hex: row.get("serial_hex"),
+            certificate,
+        },
+        csr_pem: row.get("csr_pem"),
+        claims: serde_json::from_str(&row.get::<String, _>("claims"))?,
+        overwrite: row.get("overwrite"),
+    })
+}
+
+fn map_acme_eab_key(row: &sqlx::postgres::PgRow) -> AppResult<EabKey> {
+    Ok(EabKey {
+        kid: row.get("kid"),
+        hmac_key: row.get("hmac_key"),
+        binding: serde_json::from_str(&row.get::<String, _>("binding"))?,
+        expires_unix: row.get::<i64, _>("expires_unix") as u64,
+        bound_until: row.get::<i64, _>("bound_until_unix") as u64,
+    })
+}
+
+fn map_acme_account(row: &sqlx::postgres::PgRow) -> AppResult<AcmeAccount> {
+    Ok(AcmeAccount {
+        id: row.get("id"),
+        thumbprint: row.get("thumbprint"),
+        jwk: serde_json::from_str(&row.get::<String, _>("jwk"))?,
+        deactivated: row.get("deactivated"),
+        contact: row.get("contact"),
+        binding: serde_json::from_str(&row.get::<String, _>("binding"))?,
+        bound_until: row.get::<i64, _>("bound_until_unix") as u64,
+    })
+}
+
+fn map_acme_order(row: &sqlx::postgres::PgRow) -> AppResult<AcmeOrder> {
+    let status: String = row.get("status");
+    let status = AcmeOrderStatus::parse(&status)
+        .ok_or_else(|| AppError::Serialization(format!("unknown ACME order status '{status}'")))?;
+    Ok(AcmeOrder {
+        id: row.get("id"),
+        account_id: row.get("account_id"),
+        status,
+        expires_unix: row.get::<i64, _>("expires_unix") as u64,
+        identifiers: serde_json::from_str(&row.get::<String, _>("identifiers"))?,
+        authz_ids: serde_json::from_str(&row.get::<String, _>("authz_ids"))?,
+        certificate: row.get("certificate"),
+    })
+}
+
+/// Append the `ISSUED` event for `cert` and make it the current entry of its
+/// serial.
+async fn insert_issued(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    cert: &IssuedCert,
+    issued_at_unix: u64,
+) -> AppResult<()> {
+    let serial = normalize_serial(&cert.serial_hex);
+    let not_after = cert.not_after_unix.map(|v| v as i64);
+    sqlx::query(
+        "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id)
+         VALUES ('ISSUED', $1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&cert.subject)
+    .bind(&serial)
+    .bind(issued_at_unix as i64)
+    .bind(&cert.issuer)
+    .bind(&cert.realm)
+    .bind(&cert.wazuh_agent_name)
+    .bind(not_after)
+    .bind(&cert.issuer_key_id)
+    .bind(&cert.profile)
+    .bind(&cert.device_id)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id)
+         VALUES ($1, $2, $3, FALSE, $4, $5, $6, $7, $8, $9, $10)
+         ON CONFLICT (serial_hex) DO UPDATE SET
+           subject = EXCLUDED.subject,
+           issued_at_unix = EXCLUDED.issued_at_unix,
+           revoked = FALSE,
+           revoked_at_unix = NULL,
+           reason = NULL,
+           invalidity_date_unix = NULL,
+           issuer = EXCLUDED.issuer,
+           realm = EXCLUDED.realm,
+           wazuh_agent_name = EXCLUDED.wazuh_agent_name,
+           not_after_unix = EXCLUDED.not_after_unix,
+           issuer_key_id = EXCLUDED.issuer_key_id,
+           profile = EXCLUDED.profile,
+           device_id = EXCLUDED.device_id,
+           updated_at = now()",
+    )
+    .bind(&serial)
+    .bind(&cert.subject)
+    .bind(issued_at_unix as i64)
+    .bind(&cert.issuer)
+    .bind(&cert.realm)
+    .bind(&cert.wazuh_agent_name)
+    .bind(not_after)
+    .bind(&cert.issuer_key_id)
+    .bind(&cert.profile)
+    .bind(&cert.device_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Serialize the transaction with every other one rotating or recording
+/// certificates of `subject`, so quota checks cannot interleave.
+async fn lock_subject(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    subject: &str,
+) -> AppResult<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(subject)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Active certificates of the rotation's subject, issuer and profile, as
+/// `(serial, agent name, device)`.
+async fn covered_rows(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    rotation: &Rotation,
+) -> AppResult<Vec<(String, Option<String>, Option<String>)>> {
+    Ok(sqlx::query_as(
+        "SELECT serial_hex, wazuh_agent_name, device_id FROM ledger_entry
+         WHERE subject = $1 AND revoked = FALSE AND COALESCE(profile, $3) = $2
+           AND issuer IS NOT DISTINCT FROM $4
+         FOR UPDATE",
+    )
+    .bind(&rotation.subject)
+    .bind(&rotation.profile)
+    .bind(DEFAULT_CERT_PROFILE)
+    .bind(&rotation.issuer)
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
 #[async_trait]
 impl LedgerStore for PostgresLedgerStore {
     #[tracing::instrument(skip(self))]
-    async fn record_issued(
+    async fn record_issued(&self, cert: IssuedCert, issued_at_unix: u64) -> AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_issued(&mut tx, &cert, issued_at_unix).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn record_issued_within(
         &self,
-        subject: String,
-        serial_hex: String,
+        cert: IssuedCert,
+        rotation: Rotation,
         issued_at_unix: u64,
-        issuer: Option<String>,
-        realm: Option<String>,
-        wazuh_agent_name: Option<String>,
     ) -> AppResult<()> {
-        let serial = normalize_serial(&serial_hex);
         let mut tx = self.pool.begin().await?;
-
-        sqlx::query(
-            "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, issuer, realm, wazuh_agent_name)
-             VALUES ('ISSUED', $1, $2, $3, $4, $5, $6)",
-        )
-        .bind(&subject)
-        .bind(&serial)
-        .bind(issued_at_unix as i64)
-        .bind(&issuer)
-        .bind(&realm)
-        .bind(&wazuh_agent_name)
-        .execute(&mut *tx)
-        .await
-        ?;
-
-        sqlx::query(
-            "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, issuer, realm, wazuh_agent_name)
-             VALUES ($1, $2, $3, FALSE, $4, $5, $6)
-             ON CONFLICT (serial_hex) DO UPDATE SET
-               subject = EXCLUDED.subject,
-               issued_at_unix = EXCLUDED.issued_at_unix,
-               revoked = FALSE,
-               revoked_at_unix = NULL,
-               reason = NULL,
-               issuer = EXCLUDED.issuer,
-               realm = EXCLUDED.realm,
-               wazuh_agent_name = EXCLUDED.wazuh_agent_name,
-               updated_at = now()",
-        )
-        .bind(&serial)
-        .bind(&subject)
-        .bind(issued_at_unix as i64)
-        .bind(&issuer)
-        .bind(&realm)
-        .bind(&wazuh_agent_name)
-        .execute(&mut *tx)
-        .await
-        ?;
-
+        lock_subject(&mut tx, &rotation.subject).await?;
+        let (replaced, kept): (Vec<_>, Vec<_>) = covered_rows(&mut tx, &rotation)
+            .await?
+            .into_iter()
+            .partition(|(_, _, device_id)| rotation.replaces(device_id.as_deref()));
+        rotation.check_insert(replaced.len(), kept.len())?;
+        insert_issued(&mut tx, &cert, issued_at_unix).await?;
         tx.commit().await?;
         Ok(())
     }
@@ -104,9 +296,11 @@ impl LedgerStore for PostgresLedgerStore {
         &self,
         serial_hex: String,
         reason: Option<String>,
+        invalidity_date_unix: Option<u64>,
         revoked_at_unix: u64,
-    ) -> AppResult<()> {
+    ) -> AppResult<bool> {
         let serial = normalize_serial(&serial_hex);
+        let invalidity_date = invalidity_date_unix.map(|v| v as i64);
         let mut tx = self.pool.begin().await?;
 
         let existing: Option<(bool,)> =
@@ -119,55 +313,59 @@ impl LedgerStore for PostgresLedgerStore {
             Some((true,)) => {
                 // Already revoked — no-op (matches CSV behaviour).
                 tx.commit().await?;
-                Ok(())
+                Ok(false)
             }
             Some((false,)) => {
                 sqlx::query(
-                    "UPDATE ledger_entry SET revoked = TRUE, revoked_at_unix = $2, reason = $3, updated_at = now()
+                    "UPDATE ledger_entry SET revoked = TRUE, revoked_at_unix = $2, reason = $3, invalidity_date_unix = $4, updated_at = now()
                      WHERE serial_hex = $1",
                 )
                 .bind(&serial)
                 .bind(revoked_at_unix as i64)
                 .bind(&reason)
+                .bind(invalidity_date)
                 .execute(&mut *tx)
                 .await
                 ?;
                 sqlx::query(
-                    "INSERT INTO ledger_event (event_type, serial_hex, revoked_at_unix, reason)
-                     VALUES ('REVOKED', $1, $2, $3)",
+                    "INSERT INTO ledger_event (event_type, serial_hex, revoked_at_unix, reason, invalidity_date_unix)
+                     VALUES ('REVOKED', $1, $2, $3, $4)",
                 )
                 .bind(&serial)
                 .bind(revoked_at_unix as i64)
                 .bind(&reason)
+                .bind(invalidity_date)
                 .execute(&mut *tx)
                 .await?;
                 tx.commit().await?;
-                Ok(())
+                Ok(true)
             }
             None => {
                 // Revoke of an unknown serial — insert a revoked stub.
                 sqlx::query(
-                    "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, revoked_at_unix, reason)
-                     VALUES ($1, '', 0, TRUE, $2, $3)",
+                    "INSERT INTO ledger_entry (serial_hex, subject, issued_at_unix, revoked, revoked_at_unix, reason, invalidity_date_unix)
+                     VALUES ($1, '', 0, TRUE, $2, $3, $4)",
                 )
                 .bind(&serial)
                 .bind(revoked_at_unix as i64)
                 .bind(&reason)
+                .bind(invalidity_date)
                 .execute(&mut *tx)
                 .await
                 ?;
                 sqlx::query(
-                    "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, revoked_at_unix, reason)
-                     VALUES ('STUB_REVOKED', '', $1, 0, $2, $3)",
+                    "INSERT INTO ledger_event (event_type, subject, serial_hex, issued_at_unix, revoked_at_unix, reason, invalidity_date_unix)
+                     VALUES ('STUB_REVOKED', '', $1, 0, $2, $3, $4)",
                 )
                 .bind(&serial)
                 .bind(revoked_at_unix as i64)
                 .bind(&reason)
+                .bind(invalidity_date)
                 .execute(&mut *tx)
                 .await
                 ?;
                 tx.commit().await?;
-                Ok(())
+                Ok(false)
             }
         }
     }
@@ -175,35 +373,24 @@ impl LedgerStore for PostgresLedgerStore {
     #[tracing::instrument(skip(self))]
     async fn check_and_revoke_active(
         &self,
-        subject: String,
-        overwrite: bool,
+        rotation: Rotation,
         revoked_at_unix: u64,
     ) -> AppResult<Option<Vec<String>>> {
         let mut tx = self.pool.begin().await?;
+        lock_subject(&mut tx, &rotation.subject).await?;
 
-        let rows: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT serial_hex, wazuh_agent_name FROM ledger_entry
-             WHERE subject = $1 AND revoked = FALSE
-             FOR UPDATE",
-        )
-        .bind(&subject)
-        .fetch_all(&mut *tx)
-        .await?;
-
+        let (rows, kept): (Vec<_>, Vec<_>) = covered_rows(&mut tx, &rotation)
+            .await?
+            .into_iter()
+            .partition(|(_, _, device_id)| rotation.replaces(device_id.as_deref()));
+        rotation.check(rows.len(), kept.len())?;
         if rows.is_empty() {
             tx.commit().await?;
             return Ok(None);
         }
 
-        if !overwrite {
-            return Err(AppError::Conflict(
-                "User already has an active certificate. Use the --overwrite flag to re-enroll and replace it."
-                    .to_string(),
-            ));
-        }
-
         let mut old_agent_names = Vec::new();
-        for (serial, agent_name) in &rows {
+        for (serial, agent_name, _) in &rows {
             if let Some(name) = agent_name {
                 old_agent_names.push(name.clone());
             }
@@ -213,7 +400,7 @@ impl LedgerStore for PostgresLedgerStore {
             )
             .bind(serial)
             .bind(revoked_at_unix as i64)
-            .bind("auto-rotate (one cert per user)")
+            .bind(ROTATED_REASON)
             .execute(&mut *tx)
             .await
             ?;
@@ -221,10 +408,10 @@ impl LedgerStore for PostgresLedgerStore {
                 "INSERT INTO ledger_event (event_type, subject, serial_hex, revoked_at_unix, reason)
                  VALUES ('REVOKED', $1, $2, $3, $4)",
             )
-            .bind(&subject)
+            .bind(&rotation.subject)
             .bind(serial)
             .bind(revoked_at_unix as i64)
-            .bind("auto-rotate (one cert per user)")
+            .bind(ROTATED_REASON)
             .execute(&mut *tx)
             .await
             ?;
@@ -237,7 +424,7 @@ impl LedgerStore for PostgresLedgerStore {
     #[tracing::instrument(skip(self))]
     async fn find_by_subject(&self, subject: &str) -> AppResult<Vec<LedgerEntry>> {
         let rows = sqlx::query(
-            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
              FROM ledger_entry WHERE subject = $1 ORDER BY issued_at_unix",
         )
         .bind(subject)
@@ -246,10 +433,22 @@ impl LedgerStore for PostgresLedgerStore {
         Ok(rows.iter().map(map_row).collect())
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn find_by_serial(&self, serial_hex: &str) -> AppResult<Option<LedgerEntry>> {
+        let row = sqlx::query(
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
+             FROM ledger_entry WHERE serial_hex = $1",
+        )
+        .bind(normalize_serial(serial_hex))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(map_row))
+    }
+
     #[tracing::instrument(skip(self))]
     async fn find_active(&self) -> AppResult<Vec<LedgerEntry>> {
         let rows = sqlx::query(
-            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
              FROM ledger_entry WHERE revoked = FALSE ORDER BY issued_at_unix",
         )
         .fetch_all(&self.pool)
@@ -260,7 +459,7 @@ impl LedgerStore for PostgresLedgerStore {
     #[tracing::instrument(skip(self))]
     async fn find_revoked(&self) -> AppResult<Vec<LedgerEntry>> {
         let rows = sqlx::query(
-            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
              FROM ledger_entry WHERE revoked = TRUE ORDER BY issued_at_unix",
         )
         .fetch_all(&self.pool)
@@ -271,19 +470,485 @@ impl LedgerStore for PostgresLedgerStore {
     #[tracing::instrument(skip(self))]
     async fn find_all(&self) -> AppResult<Vec<LedgerEntry>> {
         let rows = sqlx::query(
-            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name
+            "SELECT subject, serial_hex, issued_at_unix, revoked, revoked_at_unix, reason, issuer, realm, wazuh_agent_name, not_after_unix, issuer_key_id, profile, device_id, invalidity_date_unix
              FROM ledger_entry ORDER BY issued_at_unix",
         )
         .fetch_all(&self.pool)
         .await?;
         Ok(rows.iter().map(map_row).collect())
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn search(&self, query: &LedgerQuery) -> AppResult<LedgerPage> {
+        let sql = match query.sort {
+            LedgerSort::IssuedAsc => search_sql!(
+                "(issued_at_unix, serial_hex) > ($13, $14)",
+                "issued_at_unix, serial_hex"
+            ),
+            LedgerSort::IssuedDesc => search_sql!(
+                "(issued_at_unix, serial_hex) < ($13, $14)",
+                "issued_at_unix DESC, serial_hex DESC"
+            ),
+            LedgerSort::SerialAsc => search_sql!("serial_hex > $14", "serial_hex"),
+            LedgerSort::SerialDesc => search_sql!("serial_hex < $14", "serial_hex DESC"),
+        };
+        let rows = sqlx::query(sql)
+            .bind(&query.realm)
+            .bind(&query.issuer)
+            .bind(&query.agent_name)
+            .bind(&query.agent_name_prefix)
+            .bind(query.agent_name_prefix.as_deref().map(prefix_upper_bound))
+            .bind(query.serial_hex.as_deref().map(normalize_serial))
+            .bind(&query.reason)
+            .bind(query.revoked)
+            .bind(query.issued_after.map(|v| v as i64))
+            .bind(query.issued_before.map(|v| v as i64))
+            .bind(query.revoked_after.map(|v| v as i64))
+            .bind(query.revoked_before.map(|v| v as i64))
+            .bind(query.after.as_ref().map(|c| c.issued_at_unix as i64))
+            .bind(query.after.as_ref().map(|c| c.serial_hex.clone()))
+            .bind(query.limit as i64 + 1)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(LedgerPage::from_rows(
+            rows.iter().map(map_row).collect(),
+            query,
+        ))
+    }
+
+    #[tracing::instrument(skip(self, enrollment))]
+    async fn record_enrollment(&self, enrollment: PendingEnrollment) -> AppResult<()> {
+        let PendingEnrollment {
+            request,
+            csr_pem,
+            claims,
+            overwrite,
+        } = enrollment;
+        sqlx::query(
+            "INSERT INTO enrollment_request (id, subject, status, created_at_unix, realm, profile, device_id, wazuh_agent_name, csr_pem, claims, overwrite)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(&request.id)
+        .bind(&request.subject)
+        .bind(request.status.as_str())
+        .bind(request.created_at_unix as i64)
+        .bind(&request.realm)
+        .bind(&request.profile)
+        .bind(&request.device_id)
+        .bind(&request.wazuh_agent_name)
+        .bind(&csr_pem)
+        .bind(serde_json::to_string(&claims)?)
+        .bind(overwrite)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollment(&self, id: &str) -> AppResult<Option<PendingEnrollment>> {
+        let row = sqlx::query(
+            "SELECT id, subject, status, created_at_unix, realm, profile, device_id, wazuh_agent_name, decided_at_unix, decided_by, reason, serial_hex, certificate, csr_pem, claims, overwrite
+             FROM enrollment_request WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(map_enrollment).transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollments(
+        &self,
+        status: Option<EnrollmentStatus>,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        let rows = sqlx::query(
+            "SELECT id, subject, status, created_at_unix, realm, profile, device_id, wazuh_agent_name, decided_at_unix, decided_by, reason, serial_hex, certificate, csr_pem, claims, overwrite
+             FROM enrollment_request WHERE $1::TEXT IS NULL OR status = $1 ORDER BY created_at_unix",
+        )
+        .bind(status.map(EnrollmentStatus::as_str))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(map_enrollment).collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_enrollments_by_subject(
+        &self,
+        subject: &str,
+    ) -> AppResult<Vec<PendingEnrollment>> {
+        let rows = sqlx::query(
+            "SELECT id, subject, status, created_at_unix, realm, profile, device_id, wazuh_agent_name, decided_at_unix, decided_by, reason, serial_hex, certificate, csr_pem, claims, overwrite
+             FROM enrollment_request WHERE subject = $1 ORDER BY created_at_unix",
+        )
+        .bind(subject)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(map_enrollment).collect()
+    }
+
+    #[tracing::instrument(skip(self, request))]
+    async fn update_enrollment(
+        &self,
+        request: EnrollmentRequest,
+        from: EnrollmentStatus,
+    ) -> AppResult<()> {
+        let certificate = request
+            .certificate
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let updated = sqlx::query(
+            "UPDATE enrollment_request SET status = $3, decided_at_unix = $4, decided_by = $5,
+               reason = $6, serial_hex = $7, certificate = $8, updated_at = now()
+             WHERE id = $1 AND status = $2",
+        )
+        .bind(&request.id)
+        .bind(from.as_str())
+        .bind(request.status.as_str())
+        .bind(request.decided_at_unix.map(|v| v as i64))
+        .bind(&request.decided_by)
+        .bind(&request.reason)
+        .bind(&request.serial_hex)
+        .bind(certificate)
+        .execute(&self.pool)
+        .await?;
+        if updated.rows_affected() == 1 {
+            return Ok(());
+        }
+        let current: Option<(String,)> =
+            sqlx::query_as("SELECT status FROM enrollment_request WHERE id = $1")
+                .bind(&request.id)
+                .fetch_optional(&self.pool)
+                .await?;
+        match current {
+            Some((status,)) => Err(AppError::Conflict(format!(
+                "enrollment request {} is already {status}",
+                request.id
+            ))),
+            None => Err(AppError::NotFound(format!(
+                "enrollment request {}",
+                request.id
+            ))),
+        }
+    }
+
+    #[tracing::instrument(skip(self, decision))]
+    async fn record_policy_decision(&self, decision: PolicyDecision) -> AppResult<()> {
+        sqlx::query(
+            "INSERT INTO policy_decision (subject, decided_at_unix, outcome, reason, profile, device_id, wazuh_agent_name, client_ip, validity_days, extra_sans)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(&decision.subject)
+        .bind(decision.decided_at_unix as i64)
+        .bind(&decision.outcome)
+        .bind(&decision.reason)
+        .bind(&decision.profile)
+        .bind(&decision.device_id)
+        .bind(&decision.wazuh_agent_name)
+        .bind(&decision.client_ip)
+        .bind(decision.validity_days.map(|v| v as i32))
+        .bind(&decision.extra_sans)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_policy_decisions(&self, subject: &str) -> AppResult<Vec<PolicyDecision>> {
+        let rows = sqlx::query(
+            "SELECT subject, decided_at_unix, outcome, reason, profile, device_id, wazuh_agent_name, client_ip, validity_days, extra_sans
+             FROM policy_decision WHERE subject = $1 ORDER BY decided_at_unix, id",
+        )
+        .bind(subject)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| PolicyDecision {
+                subject: row.get("subject"),
+                decided_at_unix: row.get::<i64, _>("decided_at_unix") as u64,
+                outcome: row.get("outcome"),
+                reason: row.get("reason"),
+                profile: row.get("profile"),
+                device_id: row.get("device_id"),
+                wazuh_agent_name: row.get("wazuh_agent_name"),
+                client_ip: row.get("client_ip"),
+                validity_days: row.get::<Option<i32>, _>("validity_days").map(|v| v as u32),
+                extra_sans: row.get("extra_sans"),
+            })
+            .collect())
+    }
+
+    async fn record_acme_nonce(&self, nonce: String, expires_unix: u64) -> AppResult<()> {
+        sqlx::query("INSERT INTO acme_nonce (nonce, expires_unix) VALUES ($1, $2)")
+            .bind(nonce)
+            .bind(expires_unix as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_acme_nonce(&self, nonce: &str, now: u64) -> AppResult<bool> {
+        let taken = sqlx::query("DELETE FROM acme_nonce WHERE nonce = $1 AND expires_unix > $2")
+            .bind(nonce)
+            .bind(now as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(taken.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(skip(self, key), fields(kid = %key.kid))]
+    async fn record_acme_eab_key(&self, key: EabKey) -> AppResult<()> {
+        sqlx::query(
+            "INSERT INTO acme_eab_key (kid, hmac_key, binding, expires_unix, bound_until_unix)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&key.kid)
+        .bind(&key.hmac_key)
+        .bind(serde_json::to_string(&key.binding)?)
+        .bind(key.expires_unix as i64)
+        .bind(key.bound_until as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_eab_key(&self, kid: &str) -> AppResult<Option<EabKey>> {
+        let row = sqlx::query(
+            "SELECT kid, hmac_key, binding, expires_unix, bound_until_unix
+             FROM acme_eab_key WHERE kid = $1",
+        )
+        .bind(kid)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(map_acme_eab_key).transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn take_acme_eab_key(&self, kid: &str, now: u64) -> AppResult<Option<EabKey>> {
+        let row = sqlx::query(
+            "DELETE FROM acme_eab_key WHERE kid = $1
+             RETURNING kid, hmac_key, binding, expires_unix, bound_until_unix",
+        )
+        .bind(kid)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row
+            .as_ref()
+            .map(map_acme_eab_key)
+            .transpose()?
+            .filter(|key| key.expires_unix > now))
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    async fn record_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        let inserted = sqlx::query(
+            "INSERT INTO acme_account (id, thumbprint, jwk, deactivated, contact, binding, bound_until_unix)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&account.id)
+        .bind(&account.thumbprint)
+        .bind(account.jwk.to_string())
+        .bind(account.deactivated)
+        .bind(&account.contact)
+        .bind(serde_json::to_string(&account.binding)?)
+        .bind(account.bound_until as i64)
+        .execute(&self.pool)
+        .await?;
+        if inserted.rows_affected() == 1 {
+            Ok(())
+        } else {
+            Err(AppError::Conflict(format!(
+                "ACME account {} or its key is already registered",
+                account.id
+            )))
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_account(&self, id: &str) -> AppResult<Option<AcmeAccount>> {
+        let row = sqlx::query(
+            "SELECT id, thumbprint, jwk, deactivated, contact, binding, bound_until_unix
+             FROM acme_account WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(map_acme_account).transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_account_by_key(&self, thumbprint: &str) -> AppResult<Option<AcmeAccount>> {
+        let row = sqlx::query(
+            "SELECT id, thumbprint, jwk, deactivated, contact, binding, bound_until_unix
+             FROM acme_account WHERE thumbprint = $1",
+        )
+        .bind(thumbprint)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(map_acme_account).transpose()
+    }
+
+    #[tracing::instrument(skip(self, account), fields(account = %account.id))]
+    async fn update_acme_account(&self, account: AcmeAccount) -> AppResult<()> {
+        let updated = sqlx::query(
+            "UPDATE acme_account SET deactivated = $2, contact = $3, binding = $4,
+               bound_until_unix = $5, updated_at = now()
+             WHERE id = $1",
+        )
+        .bind(&account.id)
+        .bind(account.deactivated)
+        .bind(&account.contact)
+        .bind(serde_json::to_string(&account.binding)?)
+        .bind(account.bound_until as i64)
+        .execute(&self.pool)
+        .await?;
+        if updated.rows_affected() == 1 {
+            Ok(())
+        } else {
+            Err(AppError::NotFound(format!("ACME account {}", account.id)))
+        }
+    }
+
+    #[tracing::instrument(skip(self, order, authzs), fields(order = %order.id))]
+    async fn record_acme_order(&self, order: AcmeOrder, authzs: Vec<AcmeAuthz>) -> AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO acme_order (id, account_id, status, expires_unix, identifiers, authz_ids, certificate)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&order.id)
+        .bind(&order.account_id)
+        .bind(order.status.as_str())
+        .bind(order.expires_unix as i64)
+        .bind(serde_json::to_string(&order.identifiers)?)
+        .bind(serde_json::to_string(&order.authz_ids)?)
+        .bind(&order.certificate)
+        .execute(&mut *tx)
+        .await?;
+        for authz in &authzs {
+            sqlx::query(
+                "INSERT INTO acme_authz (id, account_id, identifier, expires_unix)
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&authz.id)
+            .bind(&authz.account_id)
+            .bind(serde_json::to_string(&authz.identifier)?)
+            .bind(authz.expires_unix as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_order(&self, id: &str) -> AppResult<Option<AcmeOrder>> {
+        let row = sqlx::query(
+            "SELECT id, account_id, status, expires_unix, identifiers, authz_ids, certificate
+             FROM acme_order WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(map_acme_order).transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_orders(&self, account_id: &str, now: u64) -> AppResult<Vec<AcmeOrder>> {
+        let rows = sqlx::query(
+            "SELECT id, account_id, status, expires_unix, identifiers, authz_ids, certificate
+             FROM acme_order WHERE account_id = $1 AND expires_unix > $2 ORDER BY expires_unix, id",
+        )
+        .bind(account_id)
+        .bind(now as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(map_acme_order).collect()
+    }
+
+    #[tracing::instrument(skip(self, order), fields(order = %order.id))]
+    async fn update_acme_order(&self, order: AcmeOrder, from: AcmeOrderStatus) -> AppResult<()> {
+        let updated = sqlx::query(
+            "UPDATE acme_order SET status = $3, certificate = $4, updated_at = now()
+             WHERE id = $1 AND status = $2",
+        )
+        .bind(&order.id)
+        .bind(from.as_str())
+        .bind(order.status.as_str())
+        .bind(&order.certificate)
+        .execute(&self.pool)
+        .await?;
+        if updated.rows_affected() == 1 {
+            return Ok(());
+        }
+        let current: Option<(String,)> =
+            sqlx::query_as("SELECT status FROM acme_order WHERE id = $1")
+                .bind(&order.id)
+                .fetch_optional(&self.pool)
+                .await?;
+        match current {
+            Some((status,)) => Err(AppError::Conflict(format!(
+                "ACME order {} is already {status}",
+                order.id
+            ))),
+            None => Err(AppError::NotFound(format!("ACME order {}", order.id))),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_acme_authz(&self, id: &str) -> AppResult<Option<AcmeAuthz>> {
+        let row = sqlx::query(
+            "SELECT id, account_id, identifier, expires_unix FROM acme_authz WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| {
+            Ok(AcmeAuthz {
+                id: row.get("id"),
+                account_id: row.get("account_id"),
+                identifier: serde_json::from_str(&row.get::<String, _>("identifier"))?,
+                expires_unix: row.get::<i64, _>("expires_unix") as u64,
+            })
+        })
+        .transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn purge_acme(&self, now: u64) -> AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for statement in [
+            "DELETE FROM acme_nonce WHERE expires_unix <= $1",
+            "DELETE FROM acme_eab_key WHERE expires_unix <= $1",
+            "DELETE FROM acme_authz WHERE expires_unix <= $1",
+            "DELETE FROM acme_order WHERE expires_unix <= $1",
+        ] {
+            sqlx::query(statement)
+                .bind(now as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::PostgresLedgerStore;
-    use crate::shared::ledger::LedgerStore;
+    use crate::shared::ledger::tests::{exercise_acme_store, rotation};
+    use crate::shared::ledger::{
+        IssuedCert, LedgerCursor, LedgerPage, LedgerQuery, LedgerSort, LedgerStore,
+        PendingEnrollment, PolicyDecision, Rotation,
+    };
+    use wazuh_cert_oauth2_model::models::claims::Claims;
+    use wazuh_cert_oauth2_model::models::enrollment_request::{
+        EnrollmentRequest, EnrollmentStatus,
+    };
+    use wazuh_cert_oauth2_model::models::signed_cert_response::SignedCertResponse;
 
     /// Connect to a real Postgres for integration tests. Skips when
     /// `TEST_DATABASE_URL` is not set (e.g. plain `cargo test`).
@@ -313,6 +978,14 @@ mod tests {
         format!("{prefix}-{}-{nanos}", std::process::id())
     }
 
+    #[tokio::test]
+    async fn postgres_keeps_acme_state() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        exercise_acme_store(&store, &unique_subject("pg-acme")).await;
+    }
+
     #[tokio::test]
     async fn postgres_records_and_revokes_entries() {
         let Some(store) = test_store().await else {
@@ -322,12 +995,18 @@ mod tests {
 
         store
             .record_issued(
-                subject.clone(),
-                "ABCD01".to_string(),
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "ABCD01".to_string(),
+                    issuer: Some("https://issuer/realms/dev".to_string()),
+                    realm: Some("dev".to_string()),
+                    wazuh_agent_name: None,
+                    not_after_unix: Some(31_536_100),
+                    issuer_key_id: Some("A1B2C3".to_string()),
+                    profile: Some("manager".to_string()),
+                    device_id: Some("laptop-01".to_string()),
+                },
                 100,
-                Some("https://issuer/realms/dev".to_string()),
-                Some("dev".to_string()),
-                None,
             )
             .await
             .expect("record_issued should succeed");
@@ -339,11 +1018,22 @@ mod tests {
         assert_eq!(by_subject.len(), 1);
         assert_eq!(by_subject[0].serial_hex, "ABCD01");
         assert!(!by_subject[0].revoked);
+        assert_eq!(by_subject[0].not_after_unix, Some(31_536_100));
+        assert_eq!(by_subject[0].issuer_key_id.as_deref(), Some("A1B2C3"));
+        assert_eq!(by_subject[0].profile.as_deref(), Some("manager"));
 
-        store
-            .mark_revoked("ABCD01".to_string(), Some("manual".to_string()), 200)
-            .await
-            .expect("mark_revoked should succeed");
+        assert!(
+            store
+                .mark_revoked("ABCD01".to_string(), Some("manual".to_string()), None, 200)
+                .await
+                .expect("mark_revoked should succeed")
+        );
+        assert!(
+            !store
+                .mark_revoked("ABCD01".to_string(), Some("again".to_string()), None, 250)
+                .await
+                .expect("mark_revoked should succeed")
+        );
 
         let revoked = store.find_revoked().await.expect("find_revoked");
         let entry = revoked
@@ -361,7 +1051,12 @@ mod tests {
         };
 
         store
-            .mark_revoked("UNKNOWN01".to_string(), Some("preemptive".to_string()), 300)
+            .mark_revoked(
+                "UNKNOWN01".to_string(),
+                Some("preemptive".to_string()),
+                None,
+                300,
+            )
             .await
             .expect("mark_revoked should succeed");
 
@@ -384,18 +1079,31 @@ mod tests {
 
         store
             .record_issued(
-                subject.clone(),
-                "CERT01".to_string(),
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "CERT01".to_string(),
+                    wazuh_agent_name: Some("agent-1".to_string()),
+                    ..Default::default()
+                },
+                100,
+            )
+            .await
+            .expect("record_issued");
+        store
+            .record_issued(
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "CERT01M".to_string(),
+                    profile: Some("manager".to_string()),
+                    ..Default::default()
+                },
                 100,
-                None,
-                None,
-                Some("agent-1".to_string()),
             )
             .await
             .expect("record_issued");
 
         let names = store
-            .check_and_revoke_active(subject.clone(), true, 400)
+            .check_and_revoke_active(rotation(&subject, None, 1, true), 400)
             .await
             .expect("check_and_revoke_active");
         assert_eq!(names, Some(vec!["agent-1".to_string()]));
@@ -405,13 +1113,15 @@ mod tests {
             .await
             .expect("find_by_subject");
         assert!(
-            active.iter().all(|e| e.revoked),
-            "no active certs for subject after auto-rotate"
+            active
+                .iter()
+                .all(|e| e.revoked == (e.profile_name() == "agent")),
+            "only the agent profile is rotated; legacy rows count as agent"
         );
 
         // Second call with no active certs returns None.
         let again = store
-            .check_and_revoke_active(subject.clone(), true, 500)
+            .check_and_revoke_active(rotation(&subject, None, 1, true), 500)
             .await
             .expect("second call");
         assert_eq!(again, None);
@@ -425,12 +1135,19 @@ mod tests {
         let subject = unique_subject("pg-conflict");
 
         store
-            .record_issued(subject.clone(), "CERT02".to_string(), 100, None, None, None)
+            .record_issued(
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "CERT02".to_string(),
+                    ..Default::default()
+                },
+                100,
+            )
             .await
             .expect("record_issued");
 
         let res = store
-            .check_and_revoke_active(subject.clone(), false, 400)
+            .check_and_revoke_active(rotation(&subject, None, 1, false), 400)
             .await;
         assert!(
             res.is_err(),
@@ -438,6 +1155,139 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn postgres_rotation_is_scoped_to_the_token_issuer() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let subject = unique_subject("pg-issuer");
+
+        store
+            .record_issued(
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "CERT03".to_string(),
+                    issuer: Some("https://issuer/realms/dev".to_string()),
+                    ..Default::default()
+                },
+                100,
+            )
+            .await
+            .expect("record_issued");
+
+        let partner = Rotation {
+            issuer: Some("https://issuer/realms/partners".to_string()),
+            ..rotation(&subject, None, 1, false)
+        };
+        let names = store
+            .check_and_revoke_active(partner, 400)
+            .await
+            .expect("another issuer's certificate neither counts nor conflicts");
+        assert_eq!(names, None);
+
+        let own = Rotation {
+            issuer: Some("https://issuer/realms/dev".to_string()),
+            ..rotation(&subject, None, 1, true)
+        };
+        let names = store
+            .check_and_revoke_active(own, 400)
+            .await
+            .expect("check_and_revoke_active");
+        assert_eq!(names, Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn postgres_rotation_is_per_device_within_quota() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let subject = unique_subject("pg-quota");
+        for (serial, device) in [("QUOTA01", "laptop"), ("QUOTA02", "workstation")] {
+            store
+                .record_issued(
+                    IssuedCert {
+                        subject: subject.clone(),
+                        serial_hex: serial.to_string(),
+                        device_id: Some(device.to_string()),
+                        ..Default::default()
+                    },
+                    100,
+                )
+                .await
+                .expect("record_issued");
+        }
+
+        // A third device exceeds a quota of two, even with overwrite.
+        assert!(
+            store
+                .check_and_revoke_active(rotation(&subject, Some("tablet"), 2, true), 400)
+                .await
+                .is_err()
+        );
+        // Re-enrolling the laptop replaces only its certificate.
+        let names = store
+            .check_and_revoke_active(rotation(&subject, Some("laptop"), 2, true), 400)
+            .await
+            .expect("rotate laptop");
+        assert_eq!(names, Some(Vec::new()));
+        let entries = store.find_by_subject(&subject).await.expect("find");
+        let revoked: Vec<_> = entries
+            .iter()
+            .filter(|e| e.revoked)
+            .map(|e| e.serial_hex.as_str())
+            .collect();
+        assert_eq!(revoked, ["QUOTA01"]);
+        // With room left, a new device is enrolled without rotating anything.
+        assert_eq!(
+            store
+                .check_and_revoke_active(rotation(&subject, Some("tablet"), 2, false), 500)
+                .await
+                .expect("new device"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn postgres_concurrent_issuance_respects_the_quota() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let store = std::sync::Arc::new(store);
+        let subject = unique_subject("pg-race");
+        let tasks: Vec<_> = ["laptop", "tablet", "phone"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let store = store.clone();
+                let subject = subject.clone();
+                tokio::spawn(async move {
+                    store
+                        .record_issued_within(
+                            IssuedCert {
+                                subject: subject.clone(),
+                                serial_hex: format!("{subject}-{i}"),
+                                profile: Some("agent".to_string()),
+                                device_id: Some(device.to_string()),
+                                ..Default::default()
+                            },
+                            rotation(&subject, Some(device), 2, false),
+                            100,
+                        )
+                        .await
+                })
+            })
+            .collect();
+        let mut recorded = 0;
+        for task in tasks {
+            if task.await.expect("join").is_ok() {
+                recorded += 1;
+            }
+        }
+        assert_eq!(recorded, 2);
+        let entries = store.find_by_subject(&subject).await.expect("find");
+        assert_eq!(entries.iter().filter(|e| !e.revoked).count(), 2);
+    }
+
     #[tokio::test]
     async fn postgres_serial_is_normalized_to_uppercase() {
         let Some(store) = test_store().await else {
@@ -446,7 +1296,14 @@ mod tests {
         let subject = unique_subject("pg-case");
 
         store
-            .record_issued(subject.clone(), "abcd02".to_string(), 100, None, None, None)
+            .record_issued(
+                IssuedCert {
+                    subject: subject.clone(),
+                    serial_hex: "abcd02".to_string(),
+                    ..Default::default()
+                },
+                100,
+            )
             .await
             .expect("record_issued");
 
@@ -456,5 +1313,227 @@ mod tests {
             .expect("find_by_subject");
         assert_eq!(by_subject.len(), 1);
         assert_eq!(by_subject[0].serial_hex, "ABCD02");
+        let by_serial = store
+            .find_by_serial("abcd02")
+            .await
+            .expect("find_by_serial");
+        assert_eq!(by_serial.map(|e| e.subject), Some(subject));
+    }
+
+    #[tokio::test]
+    async fn postgres_enrollment_is_decided_once() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let subject = unique_subject("pg-enroll");
+        let request = EnrollmentRequest {
+            id: unique_subject("req"),
+            subject: subject.clone(),
+            status: EnrollmentStatus::Pending,
+            created_at_unix: 100,
+            realm: None,
+            profile: Some("agent".to_string()),
+            device_id: Some("laptop".to_string()),
+            wazuh_agent_name: None,
+            decided_at_unix: None,
+            decided_by: None,
+            reason: None,
+            serial_hex: None,
+            certificate: None,
+        };
+        store
+            .record_enrollment(PendingEnrollment {
+                request: request.clone(),
+                csr_pem: "CSR".to_string(),
+                claims: Claims {
+                    sub: subject.clone(),
+                    name: None,
+                    iss: "https://issuer/realms/dev".to_string(),
+                    exp: 0,
+                    preferred_username: None,
+                    email: None,
+                    realm_access: None,
+                    ..Default::default()
+                },
+                overwrite: true,
+            })
+            .await
+            .expect("record_enrollment");
+
+        let approved = EnrollmentRequest {
+            status: EnrollmentStatus::Approved,
+            decided_by: Some("admin".to_string()),
+            serial_hex: Some("ABCD".to_string()),
+            certificate: Some(SignedCertResponse {
+                certificate_pem: "CERT".to_string(),
+                ca_cert_pem: "CA".to_string(),
+                full_chain_pem: None,
+                not_after_unix: Some(200),
+            }),
+            ..request.clone()
+        };
+        store
+            .update_enrollment(approved.clone(), EnrollmentStatus::Pending)
+            .await
+            .expect("approve");
+        assert!(
+            store
+                .update_enrollment(approved, EnrollmentStatus::Pending)
+                .await
+                .is_err()
+        );
+
+        let stored = store
+            .find_enrollments_by_subject(&subject)
+            .await
+            .expect("find");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].request.status, EnrollmentStatus::Approved);
+        assert!(stored[0].overwrite);
+        assert_eq!(
+            stored[0]
+                .request
+                .certificate
+                .as_ref()
+                .map(|c| c.certificate_pem.as_str()),
+            Some("CERT")
+        );
+        let mut missing = request;
+        missing.id = unique_subject("missing");
+        assert!(
+            store
+                .update_enrollment(missing, EnrollmentStatus::Pending)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn postgres_policy_decisions_round_trip() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let subject = unique_subject("pg-policy");
+        for (at, outcome) in [(2, "deny"), (1, "allow")] {
+            store
+                .record_policy_decision(PolicyDecision {
+                    subject: subject.clone(),
+                    decided_at_unix: at,
+                    outcome: outcome.to_string(),
+                    reason: None,
+                    profile: Some("agent".to_string()),
+                    device_id: None,
+                    wazuh_agent_name: None,
+                    client_ip: Some("10.0.0.1".to_string()),
+                    validity_days: Some(7),
+                    extra_sans: vec!["dns:asset-42.example".to_string()],
+                })
+                .await
+                .expect("record_policy_decision");
+        }
+        let stored = store
+            .find_policy_decisions(&subject)
+            .await
+            .expect("find_policy_decisions");
+        let outcomes: Vec<_> = stored.iter().map(|d| d.outcome.as_str()).collect();
+        assert_eq!(outcomes, ["allow", "deny"]);
+        assert_eq!(stored[0].extra_sans, ["dns:asset-42.example"]);
+        assert_eq!(stored[0].validity_days, Some(7));
+    }
+
+    #[tokio::test]
+    async fn postgres_search_pages_and_filters() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let realm = unique_subject("pg-search");
+        for (serial, issued_at, agent) in [
+            ("a1", 100, "lab-01"),
+            ("a2", 200, "lab-02"),
+            ("a3", 200, "office-01"),
+            ("a4", 300, "lab-10"),
+        ] {
+            store
+                .record_issued(
+                    IssuedCert {
+                        subject: format!("{realm}-{serial}"),
+                        serial_hex: format!("{realm}-{serial}"),
+                        realm: Some(realm.clone()),
+                        wazuh_agent_name: Some(agent.to_string()),
+                        ..Default::default()
+                    },
+                    issued_at,
+                )
+                .await
+                .expect("record_issued");
+        }
+        store
+            .mark_revoked(
+                format!("{realm}-a2"),
+                Some("keyCompromise: laptop stolen".to_string()),
+                None,
+                250,
+            )
+            .await
+            .expect("mark_revoked");
+        let suffixes = |page: &LedgerPage| -> Vec<String> {
+            page.entries
+                .iter()
+                .map(|e| {
+                    e.serial_hex
+                        .rsplit('-')
+                        .next()
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect()
+        };
+
+        // Newest first, two per page, ties broken by serial.
+        let mut query = LedgerQuery {
+            realm: Some(realm.clone()),
+            sort: LedgerSort::IssuedDesc,
+            limit: 2,
+            ..Default::default()
+        };
+        let first = store.search(&query).await.expect("search");
+        assert_eq!(suffixes(&first), ["A4", "A3"]);
+        let cursor = first.next_cursor.as_deref().expect("a second page");
+        query.after = Some(LedgerCursor::decode(cursor, query.sort).expect("cursor"));
+        let second = store.search(&query).await.expect("search");
+        assert_eq!(suffixes(&second), ["A2", "A1"]);
+        assert!(second.next_cursor.is_none());
+
+        let filtered = store
+            .search(&LedgerQuery {
+                realm: Some(realm.clone()),
+                agent_name_prefix: Some("lab-0".to_string()),
+                issued_after: Some(150),
+                issued_before: Some(300),
+                ..Default::default()
+            })
+            .await
+            .expect("search");
+        assert_eq!(suffixes(&filtered), ["A2"]);
+        let revoked = store
+            .search(&LedgerQuery {
+                realm: Some(realm.clone()),
+                revoked_after: Some(250),
+                revoked_before: Some(251),
+                reason: Some("keyCompromise".to_string()),
+                sort: LedgerSort::SerialAsc,
+                ..Default::default()
+            })
+            .await
+            .expect("search");
+        assert_eq!(suffixes(&revoked), ["A2"]);
+        let by_serial = store
+            .search(&LedgerQuery {
+                serial_hex: Some(format!("{realm}-a4")),
+                ..Default::default()
+            })
+            .await
+            .expect("search");
+        assert_eq!(suffixes(&by_serial), ["A4"]);
     }
 }
@@ -0,0 +1,281 @@
+// Filtered, paginated ledger searches shared by both stores.
+
+use std::cmp::Ordering;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Serialize;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+
+use super::LedgerEntry;
+
+/// Page size when the caller names none.
+pub const DEFAULT_SEARCH_LIMIT: u32 = 100;
+/// Largest page a search returns.
+pub const MAX_SEARCH_LIMIT: u32 = 1000;
+
+/// Order of search results. Entries issued in the same second are ordered by
+/// serial, so every order is total and pages never overlap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LedgerSort {
+    #[default]
+    IssuedAsc,
+    IssuedDesc,
+    SerialAsc,
+    SerialDesc,
+}
+
+impl LedgerSort {
+    /// `issued_at`, `serial`, or either prefixed with `-` for descending.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "issued_at" => Some(Self::IssuedAsc),
+            "-issued_at" => Some(Self::IssuedDesc),
+            "serial" => Some(Self::SerialAsc),
+            "-serial" => Some(Self::SerialDesc),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::IssuedAsc => "issued_at",
+            Self::IssuedDesc => "-issued_at",
+            Self::SerialAsc => "serial",
+            Self::SerialDesc => "-serial",
+        }
+    }
+
+    /// How `a` and `b` are ordered in results.
+    pub fn compare(self, a: &LedgerEntry, b: &LedgerEntry) -> Ordering {
+        let (a_serial, b_serial) = (a.serial_hex.to_uppercase(), b.serial_hex.to_uppercase());
+        match self {
+            Self::IssuedAsc => (a.issued_at_unix, a_serial).cmp(&(b.issued_at_unix, b_serial)),
+            Self::IssuedDesc => (b.issued_at_unix, b_serial).cmp(&(a.issued_at_unix, a_serial)),
+            Self::SerialAsc => a_serial.cmp(&b_serial),
+            Self::SerialDesc => b_serial.cmp(&a_serial),
+        }
+    }
+}
+
+/// The last entry of a page, which the next page starts after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerCursor {
+    pub issued_at_unix: u64,
+    /// Upper-case, as stored in Postgres.
+    pub serial_hex: String,
+}
+
+impl LedgerCursor {
+    fn of(entry: &LedgerEntry) -> Self {
+        Self {
+            issued_at_unix: entry.issued_at_unix,
+            serial_hex: entry.serial_hex.to_uppercase(),
+        }
+    }
+
+    /// Opaque form handed to clients; it only continues a search in `sort`.
+    pub fn encode(&self, sort: LedgerSort) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}:{}",
+            sort.as_str(),
+            self.issued_at_unix,
+            self.serial_hex
+        ))
+    }
+
+    pub fn decode(cursor: &str, sort: LedgerSort) -> AppResult<Self> {
+        let invalid = || AppError::ValidationError("invalid cursor".into());
+        let raw = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let mut parts = raw.splitn(3, ':');
+        let (Some(cursor_sort), Some(issued), Some(serial)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        if cursor_sort != sort.as_str() {
+            return Err(AppError::ValidationError(format!(
+                "cursor continues a search sorted by '{cursor_sort}', not '{}'",
+                sort.as_str()
+            )));
+        }
+        Ok(Self {
+            issued_at_unix: issued.parse().map_err(|_| invalid())?,
+            serial_hex: serial.to_string(),
+        })
+    }
+}
+
+/// A ledger search. `None` filters match every entry; `*_after` bounds are
+/// inclusive and `*_before` bounds exclusive, in unix seconds.
+#[derive(Debug, Clone)]
+pub struct LedgerQuery {
+    pub realm: Option<String>,
+    pub issuer: Option<String>,
+    pub agent_name: Option<String>,
+    pub agent_name_prefix: Option<String>,
+    pub serial_hex: Option<String>,
+    /// A reason code such as `keyCompromise`, matching both the bare code and
+    /// `<code>: <text>`; a free-text reason must match exactly.
+    pub reason: Option<String>,
+    pub revoked: Option<bool>,
+    pub issued_after: Option<u64>,
+    pub issued_before: Option<u64>,
+    pub revoked_after: Option<u64>,
+    pub revoked_before: Option<u64>,
+    pub sort: LedgerSort,
+    pub after: Option<LedgerCursor>,
+    pub limit: u32,
+}
+
+impl Default for LedgerQuery {
+    fn default() -> Self {
+        Self {
+            realm: None,
+            issuer: None,
+            agent_name: None,
+            agent_name_prefix: None,
+            serial_hex: None,
+            reason: None,
+            revoked: None,
+            issued_after: None,
+            issued_before: None,
+            revoked_after: None,
+            revoked_before: None,
+            sort: LedgerSort::default(),
+            after: None,
+            limit: DEFAULT_SEARCH_LIMIT,
+        }
+    }
+}
+
+impl LedgerQuery {
+    /// Whether `entry` passes the filters and comes after the cursor.
+    pub fn matches(&self, entry: &LedgerEntry) -> bool {
+        fn eq(filter: &Option<String>, value: &Option<String>) -> bool {
+            filter.is_none() || filter == value
+        }
+        fn within(value: Option<u64>, after: Option<u64>, before: Option<u64>) -> bool {
+            after.is_none_or(|a| value.is_some_and(|v| v >= a))
+                && before.is_none_or(|b| value.is_some_and(|v| v < b))
+        }
+        eq(&self.realm, &entry.realm)
+            && eq(&self.issuer, &entry.issuer)
+            && eq(&self.agent_name, &entry.wazuh_agent_name)
+            && self.reason.as_ref().is_none_or(|filter| {
+                entry.reason.as_ref().is_some_and(|reason| {
+                    reason == filter
+                        || reason
+                            .strip_prefix(filter.as_str())
+                            .is_some_and(|rest| rest.starts_with(':'))
+                })
+            })
+            && self.agent_name_prefix.as_ref().is_none_or(|prefix| {
+                entry
+                    .wazuh_agent_name
+                    .as_ref()
+                    .is_some_and(|name| name.starts_with(prefix.as_str()))
+            })
+            && self
+                .serial_hex
+                .as_ref()
+                .is_none_or(|serial| serial.eq_ignore_ascii_case(&entry.serial_hex))
+            && self.revoked.is_none_or(|revoked| revoked == entry.revoked)
+            && within(
+                Some(entry.issued_at_unix),
+                self.issued_after,
+                self.issued_before,
+            )
+            && within(
+                entry.revoked_at_unix,
+                self.revoked_after,
+                self.revoked_before,
+            )
+            && self.after.as_ref().is_none_or(|cursor| {
+                let last = LedgerEntry {
+                    issued_at_unix: cursor.issued_at_unix,
+                    serial_hex: cursor.serial_hex.clone(),
+                    ..Default::default()
+                };
+                self.sort.compare(entry, &last) == Ordering::Greater
+            })
+    }
+}
+
+/// One page of search results.
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerPage {
+    pub entries: Vec<LedgerEntry>,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl LedgerPage {
+    /// Page of `query` from up to `limit + 1` matching entries in order; the
+    /// extra entry only tells that another page follows.
+    pub fn from_rows(mut rows: Vec<LedgerEntry>, query: &LedgerQuery) -> Self {
+        let more = rows.len() > query.limit as usize;
+        rows.truncate(query.limit as usize);
+        let next_cursor = rows
+            .last()
+            .filter(|_| more)
+            .map(|last| LedgerCursor::of(last).encode(query.sort));
+        Self {
+            entries: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LedgerCursor, LedgerEntry, LedgerQuery, LedgerSort};
+
+    fn entry(serial: &str, issued: u64, agent: &str) -> LedgerEntry {
+        LedgerEntry {
+            subject: "s".into(),
+            serial_hex: serial.into(),
+            issued_at_unix: issued,
+            realm: Some("staff".into()),
+            wazuh_agent_name: Some(agent.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filters_and_cursor_select_entries() {
+        let cursor = LedgerCursor {
+            issued_at_unix: 10,
+            serial_hex: "0B".into(),
+        };
+        let encoded = cursor.encode(LedgerSort::IssuedAsc);
+        assert_eq!(
+            LedgerCursor::decode(&encoded, LedgerSort::IssuedAsc).expect("decode"),
+            cursor
+        );
+        assert!(LedgerCursor::decode(&encoded, LedgerSort::SerialDesc).is_err());
+        assert!(LedgerCursor::decode("not a cursor", LedgerSort::IssuedAsc).is_err());
+
+        let query = LedgerQuery {
+            realm: Some("staff".into()),
+            agent_name_prefix: Some("lab-".into()),
+            issued_before: Some(20),
+            after: Some(cursor),
+            ..Default::default()
+        };
+        // Same second as the cursor, but a later serial.
+        assert!(query.matches(&entry("0c", 10, "lab-1")));
+        assert!(!query.matches(&entry("0A", 10, "lab-1")));
+        assert!(!query.matches(&entry("0C", 20, "lab-1")));
+        assert!(!query.matches(&entry("0C", 15, "office-1")));
+        assert!(
+            !LedgerQuery {
+                revoked_after: Some(0),
+                ..Default::default()
+            }
+            .matches(&entry("01", 1, "lab-1")),
+            "never-revoked entries have no revocation time in range"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use super::IssuedCert;
 use super::LedgerEntry;
+use super::ROTATED_REASON;
+use super::Rotation;
 use super::csv::persist_csv;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -25,80 +28,75 @@ async fn ledger_worker(
     while let Some(cmd) = rx.recv().await {
         match cmd {
             Command::RecordIssued {
-                subject,
-                serial_hex,
+                cert,
+                rotation,
                 issued_at_unix,
-                issuer,
-                realm,
-                wazuh_agent_name,
                 respond_to,
             } => {
-                let res = apply_record_issued(
-                    &inner,
-                    &path,
-                    subject,
-                    serial_hex,
-                    issued_at_unix,
-                    issuer,
-                    realm,
-                    wazuh_agent_name,
-                )
-                .await;
+                let res =
+                    apply_record_issued(&inner, &path, cert, rotation.as_deref(), issued_at_unix)
+                        .await;
                 let _ = respond_to.send(res);
             }
             Command::MarkRevoked {
                 serial_hex,
                 reason,
+                invalidity_date_unix,
                 revoked_at_unix,
                 respond_to,
             } => {
-                let res =
-                    apply_mark_revoked(&inner, &path, serial_hex, reason, revoked_at_unix).await;
+                let res = apply_mark_revoked(
+                    &inner,
+                    &path,
+                    serial_hex,
+                    reason,
+                    invalidity_date_unix,
+                    revoked_at_unix,
+                )
+                .await;
                 let _ = respond_to.send(res);
             }
             Command::CheckAndRevokeActive {
-                subject,
-                overwrite,
+                rotation,
                 revoked_at_unix,
                 respond_to,
             } => {
-                let res: AppResult<Option<Vec<String>>> = apply_check_and_revoke_active(
-                    &inner,
-                    &path,
-                    &subject,
-                    overwrite,
-                    revoked_at_unix,
-                )
-                .await;
+                let res: AppResult<Option<Vec<String>>> =
+                    apply_check_and_revoke_active(&inner, &path, &rotation, revoked_at_unix).await;
                 let _ = respond_to.send(res);
             }
         }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 async fn apply_record_issued(
     inner: &Arc<RwLock<Vec<LedgerEntry>>>,
     path: &PathBuf,
-    subject: String,
-    serial_hex: String,
+    cert: IssuedCert,
+    rotation: Option<&Rotation>,
     issued_at_unix: u64,
-    issuer: Option<String>,
-    realm: Option<String>,
-    wazuh_agent_name: Option<String>,
 ) -> AppResult<()> {
     {
         let mut guard = inner.write().await;
+        if let Some(rotation) = rotation {
+            let (replaced, kept) = count_covered(&guard, rotation);
+            rotation.check_insert(replaced, kept)?;
+        }
         guard.push(LedgerEntry {
-            subject,
-            serial_hex,
+            subject: cert.subject,
+            serial_hex: cert.serial_hex,
             issued_at_unix,
             revoked: false,
             revoked_at_unix: None,
             reason: None,
-            issuer,
-            realm,
-            wazuh_agent_name,
+            issuer: cert.issuer,
+            realm: cert.realm,
+            wazuh_agent_name: cert.wazuh_agent_name,
+            not_after_unix: cert.not_after_unix,
+            issuer_key_id: cert.issuer_key_id,
+            profile: cert.profile,
+            device_id: cert.device_id,
+            invalidity_date_unix: None,
         });
     }
     persist_csv(path, inner).await
@@ -109,20 +107,24 @@ async fn apply_mark_revoked(
     path: &PathBuf,
     serial_hex: String,
     reason: Option<String>,
+    invalidity_date_unix: Option<u64>,
     revoked_at_unix: u64,
-) -> AppResult<()> {
-    {
+) -> AppResult<bool> {
+    let revoked = {
         let mut guard = inner.write().await;
         if let Some(entry) = guard
             .iter_mut()
             .rev()
             .find(|e| e.serial_hex.eq_ignore_ascii_case(&serial_hex))
         {
-            if !entry.revoked {
-                entry.revoked = true;
-                entry.revoked_at_unix = Some(revoked_at_unix);
-                entry.reason = reason.clone();
+            if entry.revoked {
+                return Ok(false);
             }
+            entry.revoked = true;
+            entry.revoked_at_unix = Some(revoked_at_unix);
+            entry.reason = reason.clone();
+            entry.invalidity_date_unix = invalidity_date_unix;
+            true
         } else {
             guard.push(LedgerEntry {
                 subject: String::new(),
@@ -131,45 +133,37 @@ async fn apply_mark_revoked(
                 revoked: true,
                 revoked_at_unix: Some(revoked_at_unix),
                 reason: reason.clone(),
-                issuer: None,
-                realm: None,
-                wazuh_agent_name: None,
+                invalidity_date_unix,
+                ..Default::default()
             });
+            false
         }
-    }
-    persist_csv(path, inner).await
+    };
+    persist_csv(path, inner).await?;
+    Ok(revoked)
 }
 
 async fn apply_check_and_revoke_active(
     inner: &Arc<RwLock<Vec<LedgerEntry>>>,
     path: &PathBuf,
-    subject: &str,
-    overwrite: bool,
+    rotation: &Rotation,
     revoked_at_unix: u64,
 ) -> AppResult<Option<Vec<String>>> {
-    use wazuh_cert_oauth2_model::models::errors::AppError;
-
     let mut guard = inner.write().await;
 
-    let has_active = guard.iter().any(|e| e.subject == subject && !e.revoked);
-    if !has_active {
+    let replaced =
+        |e: &LedgerEntry| rotation.covers(e) && rotation.replaces(e.device_id.as_deref());
+    let (replaced_count, kept_count) = count_covered(&guard, rotation);
+    rotation.check(replaced_count, kept_count)?;
+    if replaced_count == 0 {
         return Ok(None);
     }
 
-    if !overwrite {
-        return Err(AppError::Conflict(
-            "User already has an active certificate. Use the --overwrite flag to re-enroll and replace it.".to_string(),
-        ));
-    }
-
     let mut old_agent_names = Vec::new();
-    for entry in guard
-        .iter_mut()
-        .filter(|e| e.subject == subject && !e.revoked)
-    {
+    for entry in guard.iter_mut().filter(|e| replaced(e)) {
         entry.revoked = true;
         entry.revoked_at_unix = Some(revoked_at_unix);
-        entry.reason = Some("auto-rotate (one cert per user)".to_string());
+        entry.reason = Some(ROTATED_REASON.to_string());
         if let Some(ref name) = entry.wazuh_agent_name {
             old_agent_names.push(name.clone());
         }
@@ -179,3 +173,17 @@ async fn apply_check_and_revoke_active(
     persist_csv(path, inner).await?;
     Ok(Some(old_agent_names))
 }
+
+/// Active certificates under `rotation` that it replaces, and those it keeps.
+fn count_covered(entries: &[LedgerEntry], rotation: &Rotation) -> (usize, usize) {
+    entries
+        .iter()
+        .filter(|e| rotation.covers(e))
+        .fold((0, 0), |(replaced, kept), e| {
+            if rotation.replaces(e.device_id.as_deref()) {
+                (replaced + 1, kept)
+            } else {
+                (replaced, kept + 1)
+            }
+        })
+}
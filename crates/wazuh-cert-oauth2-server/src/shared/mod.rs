@@ -1,5 +1,11 @@
+pub mod acme;
+pub mod ca_signer;
 pub mod certs;
 pub mod crl;
+pub mod est;
+pub mod introspection;
 pub mod ledger;
+pub mod ocsp;
 pub mod opts;
+pub mod policy_hook;
 pub mod webhook_notifier;
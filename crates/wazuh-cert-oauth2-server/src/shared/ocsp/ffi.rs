@@ -0,0 +1,223 @@
+use std::ffi::{c_int, c_void};
+
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl::asn1::{Asn1IntegerRef, Asn1ObjectRef, Asn1Time};
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{
+    OcspBasicResponse, OcspBasicResponseRef, OcspCertId, OcspCertIdRef, OcspCertStatus,
+    OcspRequestRef,
+};
+use openssl::x509::X509Ref;
+use openssl_sys as ffi;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
+
+use crate::shared::crl::ffi::invalidity_date as invalidity_date_time;
+
+unsafe extern "C" {
+    fn OCSP_request_onereq_count(req: *mut ffi::OCSP_REQUEST) -> c_int;
+    fn OCSP_request_onereq_get0(req: *mut ffi::OCSP_REQUEST, i: c_int) -> *mut ffi::OCSP_ONEREQ;
+    fn OCSP_onereq_get0_id(one: *mut ffi::OCSP_ONEREQ) -> *mut ffi::OCSP_CERTID;
+    fn OCSP_id_get0_info(
+        name_hash: *mut *mut ffi::ASN1_OCTET_STRING,
+        md: *mut *mut ffi::ASN1_OBJECT,
+        key_hash: *mut *mut ffi::ASN1_OCTET_STRING,
+        serial: *mut *mut ffi::ASN1_INTEGER,
+        cid: *mut ffi::OCSP_CERTID,
+    ) -> c_int;
+    fn OCSP_CERTID_dup(id: *const ffi::OCSP_CERTID) -> *mut ffi::OCSP_CERTID;
+    fn OCSP_id_issuer_cmp(a: *const ffi::OCSP_CERTID, b: *const ffi::OCSP_CERTID) -> c_int;
+    fn OCSP_basic_add1_status(
+        rsp: *mut ffi::OCSP_BASICRESP,
+        cid: *mut ffi::OCSP_CERTID,
+        status: c_int,
+        reason: c_int,
+        revtime: *mut ffi::ASN1_TIME,
+        thisupd: *mut ffi::ASN1_TIME,
+        nextupd: *mut ffi::ASN1_TIME,
+    ) -> *mut c_void;
+    fn OCSP_SINGLERESP_add1_ext_i2d(
+        x: *mut c_void,
+        nid: c_int,
+        value: *mut c_void,
+        crit: c_int,
+        flags: std::ffi::c_ulong,
+    ) -> c_int;
+    fn OCSP_copy_nonce(resp: *mut ffi::OCSP_BASICRESP, req: *mut ffi::OCSP_REQUEST) -> c_int;
+}
+
+/// One CertID of a request.
+pub(super) struct RequestedId {
+    pub id: OcspCertId,
+    pub serial_hex: String,
+    /// Hash algorithm of the issuer name/key hashes, if OpenSSL knows it.
+    pub digest: Option<MessageDigest>,
+}
+
+/// The CertIDs of `req`, in request order.
+pub(super) fn requested_ids(req: &OcspRequestRef) -> AppResult<Vec<RequestedId>> {
+    // SAFETY: the ONEREQs and CertIDs are owned by `req`, which outlives the
+    // loop; each CertID is duplicated before it is kept.
+    unsafe {
+        let count = OCSP_request_onereq_count(req.as_ptr());
+        let mut ids = Vec::with_capacity(count.max(0) as usize);
+        for i in 0..count {
+            let cid = OCSP_onereq_get0_id(OCSP_request_onereq_get0(req.as_ptr(), i));
+            if cid.is_null() {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_onereq_get0_id",
+                });
+            }
+            let mut md: *mut ffi::ASN1_OBJECT = std::ptr::null_mut();
+            let mut serial: *mut ffi::ASN1_INTEGER = std::ptr::null_mut();
+            if OCSP_id_get0_info(
+                std::ptr::null_mut(),
+                &mut md,
+                std::ptr::null_mut(),
+                &mut serial,
+                cid,
+            ) != 1
+                || md.is_null()
+                || serial.is_null()
+            {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_id_get0_info",
+                });
+            }
+            let serial_hex = Asn1IntegerRef::from_ptr(serial)
+                .to_bn()?
+                .to_hex_str()?
+                .to_string();
+            let digest = MessageDigest::from_nid(Asn1ObjectRef::from_ptr(md).nid());
+            let dup = OCSP_CERTID_dup(cid);
+            if dup.is_null() {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_CERTID_dup",
+                });
+            }
+            ids.push(RequestedId {
+                id: OcspCertId::from_ptr(dup),
+                serial_hex,
+                digest,
+            });
+        }
+        Ok(ids)
+    }
+}
+
+/// Whether `id` names `ca_cert` as the issuer.
+pub(super) fn issued_by(id: &RequestedId, ca_cert: &X509Ref) -> AppResult<bool> {
+    let Some(digest) = id.digest else {
+        return Ok(false);
+    };
+    // SAFETY: OCSP_cert_to_id only reads the CA certificate; the returned
+    // CertID is owned by the wrapper and freed on drop.
+    unsafe {
+        let ca_id = ffi::OCSP_cert_to_id(digest.as_ptr(), std::ptr::null(), ca_cert.as_ptr());
+        if ca_id.is_null() {
+            return Err(AppError::CrlFfi {
+                func: "OCSP_cert_to_id",
+            });
+        }
+        let ca_id = OcspCertId::from_ptr(ca_id);
+        Ok(OCSP_id_issuer_cmp(ca_id.as_ptr(), id.id.as_ptr()) == 0)
+    }
+}
+
+pub(super) fn new_basic_response() -> AppResult<OcspBasicResponse> {
+    // SAFETY: a fresh object, handed straight to its owning wrapper.
+    unsafe {
+        let bs = ffi::OCSP_BASICRESP_new();
+        if bs.is_null() {
+            return Err(AppError::CrlFfi {
+                func: "OCSP_BASICRESP_new",
+            });
+        }
+        Ok(OcspBasicResponse::from_ptr(bs))
+    }
+}
+
+/// Append a SingleResponse for `id`; `revoked_at`, `reason` and
+/// `invalidity_date` are only used with [`OcspCertStatus::REVOKED`].
+#[allow(clippy::too_many_arguments)]
+pub(super) fn add_status(
+    bs: &OcspBasicResponseRef,
+    id: &OcspCertIdRef,
+    status: OcspCertStatus,
+    revoked_at: u64,
+    reason: Option<RevocationReason>,
+    invalidity_date: Option<u64>,
+    this_update: u64,
+    next_update: u64,
+) -> AppResult<()> {
+    let this_update = Asn1Time::from_unix(this_update as _)?;
+    let next_update = Asn1Time::from_unix(next_update as _)?;
+    let revoked_at = Asn1Time::from_unix(revoked_at as _)?;
+    let revtime = if status == OcspCertStatus::REVOKED {
+        revoked_at.as_ptr()
+    } else {
+        std::ptr::null_mut()
+    };
+    // SAFETY: OCSP_basic_add1_status copies the CertID and every time it is
+    // given; nothing passed in is retained. The SingleResponse it returns is
+    // owned by `bs`, and the invalidityDate is encoded into a copy.
+    unsafe {
+        let single = OCSP_basic_add1_status(
+            bs.as_ptr(),
+            id.as_ptr(),
+            status.as_raw(),
+            // Like the CRL, leave out revocationReason rather than send
+            // unspecified.
+            reason
+                .filter(|r| *r != RevocationReason::Unspecified)
+                .map_or(-1, |r| r.code()),
+            revtime,
+            this_update.as_ptr(),
+            next_update.as_ptr(),
+        );
+        if single.is_null() {
+            return Err(AppError::CrlFfi {
+                func: "OCSP_basic_add1_status",
+            });
+        }
+        // RFC 6960 §4.4.5: CRL entry extensions may be sent as
+        // singleExtensions.
+        if let Some(at) = invalidity_date.filter(|_| status == OcspCertStatus::REVOKED) {
+            let time = invalidity_date_time(at)?;
+            let ok = OCSP_SINGLERESP_add1_ext_i2d(
+                single,
+                ffi::NID_invalidity_date,
+                time as *mut c_void,
+                0,
+                0,
+            ) == 1;
+            ffi::ASN1_GENERALIZEDTIME_free(time);
+            if !ok {
+                return Err(AppError::CrlFfi {
+                    func: "OCSP_SINGLERESP_add1_ext_i2d",
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Echo the request nonce into `bs`. Returns whether the request had one.
+pub(super) fn copy_nonce(bs: &OcspBasicResponseRef, req: &OcspRequestRef) -> AppResult<bool> {
+    // SAFETY: both objects are valid; the nonce extension is copied.
+    match unsafe { OCSP_copy_nonce(bs.as_ptr(), req.as_ptr()) } {
+        1 => Ok(true),
+        2 => Ok(false),
+        _ => Err(AppError::CrlFfi {
+            func: "OCSP_copy_nonce",
+        }),
+    }
+}
+
+/// Whether `cert` carries an extended key usage including OCSPSigning.
+pub(super) fn has_ocsp_signing_eku(cert: &X509Ref) -> bool {
+    // SAFETY: read-only access; OpenSSL caches the decoded extensions.
+    let xku = unsafe { ffi::X509_get_extended_key_usage(cert.as_ptr()) };
+    // UINT32_MAX means "no EKU extension", which does not authorize OCSP.
+    xku != u32::MAX && xku & ffi::XKU_OCSP_SIGN != 0
+}
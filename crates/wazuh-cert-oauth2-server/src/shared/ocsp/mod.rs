@@ -0,0 +1,507 @@
+//! OCSP responder (RFC 6960) backed by the ledger.
+//!
+//! Status comes straight from [`Ledger::find_by_serial`]: ledger entries are
+//! `good` until revoked, serials the ledger has never seen are `unknown`.
+//! Responses are signed by the issuing CA, or by a delegated OCSP signing
+//! certificate issued by it, and identify the responder by name.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use openssl::ocsp::{OcspCertStatus, OcspRequest, OcspResponse, OcspResponseStatus};
+use openssl::x509::X509;
+use tokio::fs::read;
+use tracing::{debug, error, warn};
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
+
+use crate::models::ca_config::{CaProvider, IssuingCa};
+use crate::shared::ca_signer::{CaSigner, load_signer, sign_ocsp};
+use crate::shared::certs::unix_now;
+use crate::shared::ledger::Ledger;
+
+mod ffi;
+
+/// Upper bound on CertIDs answered in one request.
+const MAX_REQUESTED_IDS: usize = 16;
+
+/// Builds signed OCSP responses.
+pub struct OcspResponder {
+    delegate: Option<OcspDelegate>,
+    validity: Duration,
+}
+
+/// A delegated responder: a certificate with the OCSPSigning EKU issued by
+/// one of the configured CAs, and its key.
+pub struct OcspDelegate {
+    cert: X509,
+    signer: Arc<dyn CaSigner>,
+}
+
+/// A DER `OCSPResponse` with what HTTP caches need to know about it.
+pub struct OcspReply {
+    pub der: Vec<u8>,
+    /// Unix time of `thisUpdate`.
+    pub this_update: u64,
+    /// Seconds the response may be cached; `None` for responses that must
+    /// not be (nonced or unsuccessful).
+    pub max_age: Option<u64>,
+}
+
+impl OcspDelegate {
+    /// Load the delegated signing certificate and its key spec (see
+    /// [`load_signer`]).
+    pub async fn load(cert_path: &str, key_spec: &str) -> AppResult<Self> {
+        let cert = X509::from_pem(&read(cert_path).await?)?;
+        if !ffi::has_ocsp_signing_eku(&cert) {
+            return Err(AppError::ValidationError(
+                "OCSP signer certificate lacks the OCSPSigning extended key usage".into(),
+            ));
+        }
+        let signer = load_signer(key_spec).await?;
+        if let Some(key) = signer.public_key()
+            && !cert.public_key()?.public_eq(&key)
+        {
+            return Err(AppError::ValidationError(
+                "OCSP signer key does not match its certificate".into(),
+            ));
+        }
+        Ok(Self { cert, signer })
+    }
+
+    fn issued_by(&self, ca: &IssuingCa) -> bool {
+        ca.cert
+            .public_key()
+            .and_then(|key| self.cert.verify(&key))
+            .unwrap_or(false)
+    }
+}
+
+impl OcspResponder {
+    /// Responder whose responses are valid (and cacheable) for `validity`.
+    pub fn new(validity: Duration) -> Self {
+        Self {
+            delegate: None,
+            validity,
+        }
+    }
+
+    /// Sign with `delegate` for the CA that issued it.
+    pub fn with_delegate(mut self, delegate: Option<OcspDelegate>) -> Self {
+        self.delegate = delegate;
+        self
+    }
+
+    /// Answer a DER `OCSPRequest`. Failures are reported in-band as an
+    /// unsuccessful `OCSPResponse`, as RFC 6960 requires.
+    pub async fn respond(&self, request_der: &[u8], ca: &CaProvider, ledger: &Ledger) -> OcspReply {
+        match self.try_respond(request_der, ca, ledger).await {
+            Ok(reply) => reply,
+            Err(status) => OcspReply {
+                // OCSPResponse ::= SEQUENCE { responseStatus ENUMERATED }
+                der: vec![0x30, 0x03, 0x0A, 0x01, status.as_raw() as u8],
+                this_update: unix_now(),
+                max_age: None,
+            },
+        }
+    }
+
+    async fn try_respond(
+        &self,
+        request_der: &[u8],
+        ca: &CaProvider,
+        ledger: &Ledger,
+    ) -> Result<OcspReply, OcspResponseStatus> {
+        let malformed = |e: AppError| {
+            debug!("malformed OCSP request: {}", e);
+            OcspResponseStatus::MALFORMED_REQUEST
+        };
+        let req = OcspRequest::from_der(request_der).map_err(|e| malformed(e.into()))?;
+        let ids = ffi::requested_ids(&req).map_err(malformed)?;
+        if ids.is_empty() || ids.len() > MAX_REQUESTED_IDS {
+            debug!("OCSP request with {} CertIDs rejected", ids.len());
+            return Err(OcspResponseStatus::MALFORMED_REQUEST);
+        }
+
+        // One signer per response, so every CertID must name the same CA.
+        let issuers = ca.issuers().await.map_err(internal)?;
+        let mut issuer = None;
+        for candidate in issuers.iter() {
+            if ffi::issued_by(&ids[0], &candidate.cert).map_err(internal)? {
+                issuer = Some(candidate.clone());
+                break;
+            }
+        }
+        let issuer = issuer.ok_or_else(|| {
+            warn!("OCSP request for a CA this responder does not serve");
+            OcspResponseStatus::UNAUTHORIZED
+        })?;
+        for id in &ids[1..] {
+            if !ffi::issued_by(id, &issuer.cert).map_err(internal)? {
+                warn!("OCSP request mixes CertIDs of different issuers");
+                return Err(OcspResponseStatus::UNAUTHORIZED);
+            }
+        }
+
+        let this_update = unix_now();
+        let next_update = this_update + self.validity.as_secs();
+        let bs = ffi::new_basic_response().map_err(internal)?;
+        for id in &ids {
+            let entry = ledger
+                .find_by_serial(&id.serial_hex)
+                .await
+                .map_err(internal)?
+                .filter(|e| {
+                    e.issuer_key_id
+                        .as_deref()
+                        .is_none_or(|k| k.eq_ignore_ascii_case(&issuer.key_id))
+                });
+            let (status, revoked_at, reason, invalidity_date) = match entry {
+                Some(e) if e.revoked => (
+                    OcspCertStatus::REVOKED,
+                    e.revoked_at_unix.unwrap_or_default(),
+                    e.reason.as_deref().and_then(RevocationReason::from_ledger),
+                    e.invalidity_date_unix,
+                ),
+                Some(_) => (OcspCertStatus::GOOD, 0, None, None),
+                None => (OcspCertStatus::UNKNOWN, 0, None, None),
+            };
+            debug!(
+                "OCSP status serial={} status={:?}",
+                id.serial_hex,
+                status.as_raw()
+            );
+            ffi::add_status(
+                &bs,
+                &id.id,
+                status,
+                revoked_at,
+                reason,
+                invalidity_date,
+                this_update,
+                next_update,
+            )
+            .map_err(internal)?;
+        }
+        let nonced = ffi::copy_nonce(&bs, &req).map_err(internal)?;
+
+        match self.delegate.as_ref().filter(|d| d.issued_by(&issuer)) {
+            Some(d) => sign_ocsp(&bs, d.signer.as_ref(), &d.cert, true),
+            None => sign_ocsp(&bs, issuer.signer.as_ref(), &issuer.cert, false),
+        }
+        .map_err(internal)?;
+        let der = OcspResponse::create(OcspResponseStatus::SUCCESSFUL, Some(&bs))
+            .and_then(|r| r.to_der())
+            .map_err(|e| internal(e.into()))?;
+
+        Ok(OcspReply {
+            der,
+            this_update,
+            max_age: (!nonced).then_some(self.validity.as_secs()),
+        })
+    }
+}
+
+fn internal(e: AppError) -> OcspResponseStatus {
+    error!("OCSP response failed: {}", e);
+    OcspResponseStatus::INTERNAL_ERROR
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_int, c_uchar};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use foreign_types::ForeignTypeRef;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::ocsp::{
+        OcspCertId, OcspCertStatus, OcspFlag, OcspRequest, OcspResponse, OcspResponseStatus,
+        OcspRevokedStatus,
+    };
+    use openssl::pkey::{PKey, Private};
+    use openssl::stack::Stack;
+    use openssl::x509::extension::ExtendedKeyUsage;
+    use openssl::x509::store::X509StoreBuilder;
+    use openssl::x509::{X509, X509NameBuilder};
+    use openssl_sys as ffi;
+
+    use super::{OcspDelegate, OcspResponder};
+    use crate::handlers::test_support::{make_ca, temp_dir};
+    use crate::models::ca_config::CaProvider;
+    use crate::shared::certs::set_validity;
+    use crate::shared::ledger::{IssuedCert, Ledger, LedgerBackend};
+
+    unsafe extern "C" {
+        fn OCSP_request_add1_nonce(
+            req: *mut ffi::OCSP_REQUEST,
+            val: *mut c_uchar,
+            len: c_int,
+        ) -> c_int;
+        fn OCSP_check_nonce(req: *mut ffi::OCSP_REQUEST, bs: *mut ffi::OCSP_BASICRESP) -> c_int;
+    }
+
+    struct Fixture {
+        dir: PathBuf,
+        ca: X509,
+        ca_key: PKey<Private>,
+        provider: CaProvider,
+        ledger: Ledger,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn fixture() -> Fixture {
+        let dir = temp_dir("ocsp-test");
+        let (ca, ca_key) = make_ca("ocsp-ca", None);
+        let cert_path = dir.join("ca.pem");
+        let key_path = dir.join("ca.key");
+        std::fs::write(&cert_path, ca.to_pem().expect("pem")).expect("write cert");
+        std::fs::write(&key_path, ca_key.private_key_to_pem_pkcs8().expect("key"))
+            .expect("write key");
+        let provider = CaProvider::new(
+            cert_path.display().to_string(),
+            key_path.display().to_string(),
+            Duration::from_secs(60),
+            None,
+        );
+        let ledger = Ledger::new(LedgerBackend::Csv(dir.join("ledger.csv")))
+            .await
+            .expect("ledger");
+        Fixture {
+            dir,
+            ca,
+            ca_key,
+            provider,
+            ledger,
+        }
+    }
+
+    /// Certificate with `serial_hex` issued by `ca`, optionally for OCSP signing.
+    fn issue(
+        ca: &X509,
+        ca_key: &PKey<Private>,
+        serial_hex: &str,
+        ocsp_signing: bool,
+    ) -> (X509, PKey<Private>) {
+        let (_, key) = make_ca("leaf", None);
+        let mut name = X509NameBuilder::new().expect("name");
+        name.append_entry_by_text("CN", serial_hex).expect("cn");
+        let mut builder = X509::builder().expect("builder");
+        builder.set_version(2).expect("version");
+        let serial = BigNum::from_hex_str(serial_hex)
+            .and_then(|bn| bn.to_asn1_integer())
+            .expect("serial");
+        builder.set_serial_number(&serial).expect("serial");
+        builder.set_subject_name(&name.build()).expect("subject");
+        builder.set_issuer_name(ca.subject_name()).expect("issuer");
+        builder.set_pubkey(&key).expect("pubkey");
+        set_validity(&mut builder, 1_700_000_000, 4_000_000_000).expect("validity");
+        if ocsp_signing {
+            let eku = ExtendedKeyUsage::new()
+                .other("OCSPSigning")
+                .build()
+                .expect("eku");
+            builder.append_extension(eku).expect("eku");
+        }
+        builder.sign(ca_key, MessageDigest::sha256()).expect("sign");
+        (builder.build(), key)
+    }
+
+    fn request(ca: &X509, certs: &[&X509], nonce: bool) -> OcspRequest {
+        let mut req = OcspRequest::new().expect("request");
+        for cert in certs {
+            let id = OcspCertId::from_cert(MessageDigest::sha1(), cert, ca).expect("cert id");
+            req.add_id(id).expect("add id");
+        }
+        if nonce {
+            // SAFETY: a random nonce of the default length is generated.
+            let ok = unsafe { OCSP_request_add1_nonce(req.as_ptr(), std::ptr::null_mut(), -1) };
+            assert_eq!(ok, 1);
+        }
+        req
+    }
+
+    fn status_of(der: &[u8], ca: &X509, subject: &X509) -> OcspCertStatus {
+        let res = OcspResponse::from_der(der).expect("response der");
+        assert_eq!(res.status(), OcspResponseStatus::SUCCESSFUL);
+        let basic = res.basic().expect("basic response");
+        let mut store = X509StoreBuilder::new().expect("store");
+        store.add_cert(ca.clone()).expect("add ca");
+        let store = store.build();
+        // Clients look the CA-signed responder up among the certs they know.
+        let mut certs = Stack::new().expect("stack");
+        certs.push(ca.clone()).expect("push");
+        basic
+            .verify(&certs, &store, OcspFlag::empty())
+            .expect("response must verify against the CA");
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), subject, ca).expect("id");
+        basic.find_status(&id).expect("status present").status
+    }
+
+    #[tokio::test]
+    async fn statuses_come_from_the_ledger() {
+        let f = fixture().await;
+        let (good, _) = issue(&f.ca, &f.ca_key, "0A01", false);
+        let (revoked, _) = issue(&f.ca, &f.ca_key, "0A02", false);
+        let (unknown, _) = issue(&f.ca, &f.ca_key, "0A03", false);
+        for serial in ["0A01", "0A02"] {
+            f.ledger
+                .record_issued(IssuedCert {
+                    subject: format!("subject-{serial}"),
+                    serial_hex: serial.to_string(),
+                    ..Default::default()
+                })
+                .await
+                .expect("record");
+        }
+        f.ledger
+            .mark_revoked(
+                "0A02".into(),
+                Some("keyCompromise: laptop stolen".into()),
+                Some(1_700_000_000),
+            )
+            .await
+            .expect("revoke");
+
+        let responder = OcspResponder::new(Duration::from_secs(600));
+        let req = request(&f.ca, &[&good, &revoked, &unknown], false);
+        let reply = responder
+            .respond(&req.to_der().expect("der"), &f.provider, &f.ledger)
+            .await;
+        assert_eq!(reply.max_age, Some(600));
+        assert_eq!(status_of(&reply.der, &f.ca, &good), OcspCertStatus::GOOD);
+        assert_eq!(
+            status_of(&reply.der, &f.ca, &revoked),
+            OcspCertStatus::REVOKED
+        );
+        assert_eq!(
+            status_of(&reply.der, &f.ca, &unknown),
+            OcspCertStatus::UNKNOWN
+        );
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), &revoked, &f.ca).expect("id");
+        let basic = OcspResponse::from_der(&reply.der)
+            .expect("der")
+            .basic()
+            .expect("basic");
+        assert_eq!(
+            basic.find_status(&id).expect("status").reason,
+            OcspRevokedStatus::KEY_COMPROMISE
+        );
+        // invalidityDate (2.5.29.24) as a singleExtension.
+        let oid = [0x06, 0x03, 0x55, 0x1d, 0x18];
+        assert!(reply.der.windows(oid.len()).any(|w| w == oid));
+        assert!(
+            reply
+                .der
+                .windows(17)
+                .any(|w| w == b"\x18\x0f20231114221320Z".as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn nonces_are_echoed_and_disable_caching() {
+        let f = fixture().await;
+        let (cert, _) = issue(&f.ca, &f.ca_key, "0B01", false);
+        let req = request(&f.ca, &[&cert], true);
+        let reply = OcspResponder::new(Duration::from_secs(600))
+            .respond(&req.to_der().expect("der"), &f.provider, &f.ledger)
+            .await;
+        assert_eq!(reply.max_age, None);
+        let basic = OcspResponse::from_der(&reply.der)
+            .expect("der")
+            .basic()
+            .expect("basic");
+        // SAFETY: both objects are valid for the duration of the call.
+        assert_eq!(
+            unsafe { OCSP_check_nonce(req.as_ptr(), basic.as_ptr()) },
+            1,
+            "response must carry the request nonce"
+        );
+    }
+
+    #[tokio::test]
+    async fn delegated_signer_answers_for_its_issuer() {
+        let f = fixture().await;
+        let (cert, _) = issue(&f.ca, &f.ca_key, "0C01", false);
+        let (delegate, delegate_key) = issue(&f.ca, &f.ca_key, "0C02", true);
+        let cert_path = f.dir.join("ocsp.pem");
+        let key_path = f.dir.join("ocsp.key");
+        std::fs::write(&cert_path, delegate.to_pem().expect("pem")).expect("write cert");
+        std::fs::write(
+            &key_path,
+            delegate_key.private_key_to_pem_pkcs8().expect("key"),
+        )
+        .expect("write key");
+        let delegate_spec = OcspDelegate::load(
+            &cert_path.display().to_string(),
+            &key_path.display().to_string(),
+        )
+        .await
+        .expect("delegate should load");
+
+        let req = request(&f.ca, &[&cert], false);
+        let reply = OcspResponder::new(Duration::from_secs(600))
+            .with_delegate(Some(delegate_spec))
+            .respond(&req.to_der().expect("der"), &f.provider, &f.ledger)
+            .await;
+        assert_eq!(status_of(&reply.der, &f.ca, &cert), OcspCertStatus::UNKNOWN);
+        let basic = OcspResponse::from_der(&reply.der)
+            .expect("der")
+            .basic()
+            .expect("basic");
+        // Only the delegate as signer candidate: the response names it.
+        let mut store = X509StoreBuilder::new().expect("store");
+        store.add_cert(f.ca.clone()).expect("add ca");
+        let mut certs = Stack::new().expect("stack");
+        certs.push(delegate.clone()).expect("push");
+        basic
+            .verify(&certs, &store.build(), OcspFlag::NO_INTERN)
+            .expect("delegate signature verifies");
+    }
+
+    #[tokio::test]
+    async fn delegate_without_ocsp_signing_eku_is_rejected() {
+        let f = fixture().await;
+        let (cert, key) = issue(&f.ca, &f.ca_key, "0D01", false);
+        let cert_path = f.dir.join("not-ocsp.pem");
+        let key_path = f.dir.join("not-ocsp.key");
+        std::fs::write(&cert_path, cert.to_pem().expect("pem")).expect("write cert");
+        std::fs::write(&key_path, key.private_key_to_pem_pkcs8().expect("key")).expect("write key");
+        assert!(
+            OcspDelegate::load(
+                &cert_path.display().to_string(),
+                &key_path.display().to_string()
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn foreign_issuers_and_garbage_are_refused() {
+        let f = fixture().await;
+        let (other, other_key) = make_ca("other-ca", None);
+        let (cert, _) = issue(&other, &other_key, "0E01", false);
+        let responder = OcspResponder::new(Duration::from_secs(600));
+
+        let req = request(&other, &[&cert], false);
+        let reply = responder
+            .respond(&req.to_der().expect("der"), &f.provider, &f.ledger)
+            .await;
+        let res = OcspResponse::from_der(&reply.der).expect("der");
+        assert_eq!(res.status(), OcspResponseStatus::UNAUTHORIZED);
+        assert_eq!(reply.max_age, None);
+
+        let reply = responder
+            .respond(b"not an ocsp request", &f.provider, &f.ledger)
+            .await;
+        let res = OcspResponse::from_der(&reply.der).expect("der");
+        assert_eq!(res.status(), OcspResponseStatus::MALFORMED_REQUEST);
+    }
+}
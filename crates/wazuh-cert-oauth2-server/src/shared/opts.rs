@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
 
+use crate::models::naming_template::DEFAULT_SAN_TEMPLATE;
+
 #[derive(Parser, Debug)]
 #[command(
     name = "wazuh-cert-oauth2-server",
@@ -11,6 +13,8 @@ pub struct Opt {
     pub command: Command,
 }
 
+// Parsed once at startup; the size of the serve options does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Run the OAuth2 certificate server
@@ -25,30 +29,103 @@ pub enum Command {
 
 #[derive(Parser, Debug)]
 pub struct ServeOpt {
-    #[arg(long, env = "OAUTH_ISSUER", required = true, short = 'i')]
-    pub oauth_issuer: String,
+    /// Trusted OIDC issuer, validated against the tokens' `iss` claim.
+    /// Optional when `OIDC_ISSUERS_PATH` lists the issuers.
+    #[arg(
+        long,
+        env = "OAUTH_ISSUER",
+        required_unless_present = "oidc_issuers_path",
+        short = 'i'
+    )]
+    pub oauth_issuer: Option<String>,
 
     #[arg(long, env = "KC_AUDIENCES")]
     pub kc_audiences: Option<String>,
 
+    /// JSON file listing further trusted issuers, each with its own
+    /// audiences, admin roles and clock leeway.
+    #[arg(long, env = "OIDC_ISSUERS_PATH")]
+    pub oidc_issuers_path: Option<String>,
+
+    /// Clock skew tolerated on token `exp` and `nbf`, in seconds.
+    #[arg(long, env = "OIDC_LEEWAY_SECS", default_value_t = 60)]
+    pub oidc_leeway_secs: u64,
+
+    /// Validate `OAUTH_ISSUER`'s tokens at its introspection endpoint
+    /// instead of against its JWKS, for opaque tokens.
+    #[arg(long, env = "OAUTH_INTROSPECTION", default_value_t = false, action = clap::ArgAction::Set)]
+    pub oauth_introspection: bool,
+
+    /// Client credentials the server presents to introspection endpoints.
+    #[arg(long, env = "INTROSPECTION_CLIENT_ID")]
+    pub introspection_client_id: Option<String>,
+
+    #[arg(long, env = "INTROSPECTION_CLIENT_SECRET")]
+    pub introspection_client_secret: Option<String>,
+
     #[arg(long, env = "ROOT_CA_PATH", required = true, short = 'c')]
     pub root_ca_path: String,
 
     #[arg(long, env = "ROOT_CA_KEY_PATH", required = true, short = 'k')]
     pub root_ca_key_path: String,
 
+    /// CAs being rolled over: comma-separated `<cert_path>=<key_spec>` pairs.
+    /// They stop issuing but keep serving their own CRL.
+    #[arg(long, env = "RETIRING_CAS", default_value = "")]
+    pub retiring_cas: String,
+
     #[arg(long, env = "DISCOVERY_TTL_SECS", default_value_t = 3600)]
     pub discovery_ttl_secs: u64,
 
     #[arg(long, env = "JWKS_TTL_SECS", default_value_t = 300)]
     pub jwks_ttl_secs: u64,
 
+    /// Shortest time between two fetches of an issuer's discovery document
+    /// or JWKS, also when a token names a key the cached JWKS lacks.
+    #[arg(long, env = "JWKS_REFRESH_MIN_INTERVAL_SECS", default_value_t = 10)]
+    pub jwks_refresh_min_interval_secs: u64,
+
+    /// How long past its TTL a cached discovery document or JWKS is still
+    /// used while the issuer cannot be reached.
+    #[arg(long, env = "JWKS_MAX_STALE_SECS", default_value_t = 3600)]
+    pub jwks_max_stale_secs: u64,
+
     #[arg(long, env = "CA_CACHE_TTL_SECS", default_value_t = 300)]
     pub ca_cache_ttl_secs: u64,
 
+    /// CRL distribution point embedded in issued certificates; `{issuer}` is
+    /// replaced by the issuing CA's key id.
     #[arg(long, env = "CRL_DIST_URL")]
     pub crl_dist_url: Option<String>,
 
+    /// Delta CRL location, advertised as Freshest CRL in issued certificates
+    /// and complete CRLs; `{issuer}` is replaced by the issuing CA's key id.
+    /// Setting it enables delta CRLs, served at `/crl/delta/<key id>.crl`.
+    #[arg(long, env = "DELTA_CRL_URL")]
+    pub delta_crl_url: Option<String>,
+
+    /// Seconds a complete CRL stays the base of the delta CRLs after it.
+    #[arg(long, env = "DELTA_CRL_BASE_INTERVAL_SECS", default_value_t = 86400)]
+    pub delta_crl_base_interval_secs: u64,
+
+    /// OCSP responder URL embedded (as Authority Information Access) in
+    /// issued certificates, e.g. `https://certs.example.com/ocsp`.
+    #[arg(long, env = "OCSP_URL")]
+    pub ocsp_url: Option<String>,
+
+    /// Delegated OCSP signing certificate (must carry the OCSPSigning EKU
+    /// and be issued by the CA it answers for). Without it the CA signs.
+    #[arg(long, env = "OCSP_SIGNER_CERT_PATH", requires = "ocsp_signer_key_path")]
+    pub ocsp_signer_cert_path: Option<String>,
+
+    /// Key of the delegated OCSP signer; same forms as `ROOT_CA_KEY_PATH`.
+    #[arg(long, env = "OCSP_SIGNER_KEY_PATH", requires = "ocsp_signer_cert_path")]
+    pub ocsp_signer_key_path: Option<String>,
+
+    /// Seconds an OCSP response stays valid (`nextUpdate`) and cacheable.
+    #[arg(long, env = "OCSP_VALIDITY_SECS", default_value_t = 3600)]
+    pub ocsp_validity_secs: u64,
+
     #[arg(long, env = "CRL_PATH", default_value = "/data/issuing.crl")]
     pub crl_path: String,
 
@@ -68,4 +145,159 @@ pub struct ServeOpt {
     /// Bearer token used to authenticate eviction requests to the webhook.
     #[arg(long, env = "WEBHOOK_BEARER_TOKEN")]
     pub webhook_bearer_token: Option<String>,
+
+    /// Comma-separated realm roles granting admin access to the ledger,
+    /// revocation and unrestricted enrollment endpoints.
+    #[arg(long, env = "ADMIN_ROLES", default_value = "wazuh_admin")]
+    pub admin_roles: String,
+
+    /// Comma-separated JSON paths of the token's roles, e.g.
+    /// `realm_access.roles,resource_access.wazuh.roles`.
+    #[arg(long, env = "CLAIM_ROLES", default_value = "realm_access.roles")]
+    pub claim_roles: String,
+
+    /// Comma-separated JSON paths of the token's groups, matched like roles.
+    #[arg(long, env = "CLAIM_GROUPS", default_value = "")]
+    pub claim_groups: String,
+
+    /// Comma-separated JSON paths of the display name; the first present wins.
+    #[arg(long, env = "CLAIM_NAME", default_value = "name,preferred_username")]
+    pub claim_name: String,
+
+    /// Comma-separated JSON paths of the email; the first present wins.
+    #[arg(long, env = "CLAIM_EMAIL", default_value = "email")]
+    pub claim_email: String,
+
+    /// Let non-admin callers read and revoke the certificates issued to
+    /// their own subject.
+    #[arg(long, env = "SELF_SERVICE", default_value_t = true, action = clap::ArgAction::Set)]
+    pub self_service: bool,
+
+    /// Comma-separated `acr` values proving multi-factor authentication.
+    #[arg(long, env = "ENROLL_ACR_VALUES", default_value = "")]
+    pub enroll_acr_values: String,
+
+    /// Comma-separated `amr` methods proving multi-factor authentication,
+    /// e.g. `otp,hwk,mfa`.
+    #[arg(long, env = "ENROLL_AMR_VALUES", default_value = "")]
+    pub enroll_amr_values: String,
+
+    /// Only enroll tokens with `email_verified: true`.
+    #[arg(long, env = "ENROLL_REQUIRE_EMAIL_VERIFIED", default_value_t = false, action = clap::ArgAction::Set)]
+    pub enroll_require_email_verified: bool,
+
+    /// Only enroll tokens whose `auth_time` is at most this many seconds old.
+    #[arg(long, env = "ENROLL_MAX_AUTH_AGE_SECS")]
+    pub enroll_max_auth_age_secs: Option<u64>,
+
+    /// Comma-separated clients (`azp`) whose tokens may enroll; empty allows any.
+    #[arg(long, env = "ENROLL_ALLOWED_CLIENTS", default_value = "")]
+    pub enroll_allowed_clients: String,
+
+    /// Comma-separated roles exempt from the enrollment rules, e.g. the
+    /// service accounts renewing agents.
+    #[arg(long, env = "ENROLL_EXEMPT_ROLES", default_value = "")]
+    pub enroll_exempt_roles: String,
+
+    /// Comma-separated realms whose enrollments wait for admin approval.
+    #[arg(long, env = "APPROVAL_REALMS", default_value = "")]
+    pub approval_realms: String,
+
+    /// Comma-separated realm roles whose enrollments wait for admin approval.
+    #[arg(long, env = "APPROVAL_ROLES", default_value = "")]
+    pub approval_roles: String,
+
+    /// Policy endpoint consulted before each certificate is signed. Unset
+    /// disables the hook.
+    #[arg(long, env = "POLICY_HOOK_URL")]
+    pub policy_hook_url: Option<String>,
+
+    /// Bearer token sent to the policy endpoint.
+    #[arg(long, env = "POLICY_HOOK_BEARER_TOKEN")]
+    pub policy_hook_bearer_token: Option<String>,
+
+    /// How long to wait for the policy endpoint, in milliseconds.
+    #[arg(long, env = "POLICY_HOOK_TIMEOUT_MS", default_value_t = 2000)]
+    pub policy_hook_timeout_ms: u64,
+
+    /// Sign anyway when the policy endpoint fails instead of refusing.
+    #[arg(long, env = "POLICY_HOOK_FAIL_OPEN", default_value_t = false, action = clap::ArgAction::Set)]
+    pub policy_hook_fail_open: bool,
+
+    /// Default lifetime of issued certificates, in days.
+    #[arg(long, env = "CERT_VALIDITY_DAYS", default_value_t = 365)]
+    pub cert_validity_days: u32,
+
+    /// Upper bound on certificate lifetime, applied after overrides.
+    #[arg(long, env = "CERT_MAX_VALIDITY_DAYS")]
+    pub cert_max_validity_days: Option<u32>,
+
+    /// Comma-separated lifetime overrides, e.g. `role:contractor=30,realm:dev=90`.
+    /// The shortest matching override wins.
+    #[arg(long, env = "CERT_VALIDITY_OVERRIDES", default_value = "")]
+    pub cert_validity_overrides: String,
+
+    /// Seconds `notBefore` is backdated to tolerate client clock skew.
+    #[arg(long, env = "CERT_BACKDATE_SECS", default_value_t = 300)]
+    pub cert_backdate_secs: u64,
+
+    /// Days before expiry during which a subject may re-enroll without
+    /// `--overwrite`. 0 disables the window.
+    #[arg(long, env = "CERT_RENEWAL_WINDOW_DAYS", default_value_t = 0)]
+    pub cert_renewal_window_days: u32,
+
+    /// Public key algorithms accepted in CSRs, e.g. `rsa:2048-4096,p256,p384,ed25519`.
+    #[arg(long, env = "KEY_POLICY", default_value = "rsa:2048,p256")]
+    pub key_policy: String,
+
+    /// Subject DN of issued certificates, e.g. `O=Example,OU={realm},CN={preferred_username}`.
+    #[arg(long, env = "CERT_SUBJECT_TEMPLATE", default_value = "CN={sub}")]
+    pub cert_subject_template: String,
+
+    /// SANs of issued certificates, e.g. `dns:{wazuh_agent_name},email:{email},uri:{identity_uri}`.
+    #[arg(
+        long,
+        env = "CERT_SAN_TEMPLATE",
+        default_value = DEFAULT_SAN_TEMPLATE
+    )]
+    pub cert_san_template: String,
+
+    /// Leave out `dns:` SANs whose value is not a DNS name (e.g. a `sub` of
+    /// `auth0|abc`) instead of refusing the request.
+    #[arg(long, env = "CERT_SAN_SKIP_INVALID_DNS", default_value_t = true, action = clap::ArgAction::Set)]
+    pub cert_san_skip_invalid_dns: bool,
+
+    /// CSR extension requests the certificate would not honour: `strip` ignores
+    /// them with a warning, `reject` fails the request.
+    #[arg(long, env = "CSR_EXTENSIONS", default_value = "strip")]
+    pub csr_extensions: String,
+
+    /// Active certificates a subject may hold per signing profile, one per
+    /// device (`device_id` in the request).
+    #[arg(long, env = "CERT_MAX_DEVICES", default_value_t = 1)]
+    pub cert_max_devices: u32,
+
+    /// Comma-separated device quotas for realm roles, e.g. `role:engineer=2`.
+    /// The largest matching quota wins.
+    #[arg(long, env = "CERT_DEVICE_QUOTAS", default_value = "")]
+    pub cert_device_quotas: String,
+
+    /// Externally visible origin of this server, e.g.
+    /// `https://cert.example.com`; enables the ACME endpoints under `/acme`.
+    #[arg(long, env = "ACME_BASE_URL")]
+    pub acme_base_url: Option<String>,
+
+    /// Seconds an unused ACME External Account Binding key stays valid.
+    #[arg(long, env = "ACME_EAB_TTL_SECS", default_value_t = 86400)]
+    pub acme_eab_ttl_secs: u64,
+
+    /// Seconds an ACME account stays bound to the caller that minted its EAB
+    /// key; afterwards it registers again with a fresh key.
+    #[arg(long, env = "ACME_BINDING_TTL_SECS", default_value_t = 86400)]
+    pub acme_binding_ttl_secs: u64,
+
+    /// JSON file of named signing profiles (e.g. manager or dashboard server
+    /// certificates) requests may select besides the built-in `agent` profile.
+    #[arg(long, env = "CERT_PROFILES_PATH")]
+    pub cert_profiles_path: Option<String>,
 }
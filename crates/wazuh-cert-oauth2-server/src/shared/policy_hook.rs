@@ -0,0 +1,347 @@
+use std::time::Duration;
+
+use openssl::x509::X509ReqRef;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use wazuh_cert_oauth2_model::models::claims::Claims;
+use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
+use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+use crate::handlers::middle::ClientInfo;
+use crate::models::naming_template::SanEntry;
+use crate::shared::certs::{KeyKind, requested_dns_names, unix_now};
+use crate::shared::ledger::{Ledger, PolicyDecision};
+
+/// What a request looked like to the policy endpoint, without the CSR itself.
+#[derive(Debug, Serialize)]
+pub struct CsrSummary {
+    pub common_name: Option<String>,
+    /// `rsa`, `ec` or `ed25519`.
+    pub key_type: &'static str,
+    pub key_bits: u32,
+    pub dns_names: Vec<String>,
+    /// Hex SHA-256 of the DER SubjectPublicKeyInfo.
+    pub public_key_sha256: String,
+}
+
+impl CsrSummary {
+    pub fn of(csr: &X509ReqRef) -> AppResult<Self> {
+        let key = csr.public_key()?;
+        let key_type = match KeyKind::of(&key)? {
+            KeyKind::Rsa => "rsa",
+            KeyKind::Ec => "ec",
+            KeyKind::Ed25519 => "ed25519",
+        };
+        let common_name = csr
+            .subject_name()
+            .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+            .next()
+            .and_then(|e| e.data().to_string().ok());
+        let digest = openssl::sha::sha256(&key.public_key_to_der()?);
+        Ok(Self {
+            common_name,
+            key_type,
+            key_bits: key.bits(),
+            dns_names: requested_dns_names(csr)?,
+            public_key_sha256: digest.iter().map(|b| format!("{b:02x}")).collect(),
+        })
+    }
+}
+
+/// Body POSTed to the policy endpoint before a certificate is signed.
+#[derive(Debug, Serialize)]
+pub struct PolicyRequest<'a> {
+    pub claims: &'a Claims,
+    pub is_admin: bool,
+    pub profile: &'a str,
+    pub wazuh_agent_name: Option<&'a str>,
+    pub device_id: Option<&'a str>,
+    pub csr: CsrSummary,
+    pub client: &'a ClientInfo,
+}
+
+/// Answer of the policy endpoint.
+#[derive(Debug, Default, Deserialize)]
+struct PolicyResponse {
+    allow: bool,
+    #[serde(default)]
+    reason: Option<String>,
+    /// Lifetime replacing the profile's; `CERT_MAX_VALIDITY_DAYS` and the
+    /// CA's own expiry still apply.
+    #[serde(default)]
+    validity_days: Option<u32>,
+    /// `dns:`, `email:` or `uri:` entries added to the certificate's SANs.
+    #[serde(default)]
+    extra_sans: Vec<String>,
+}
+
+/// Adjustments an allowing decision makes to the certificate.
+#[derive(Debug, Default)]
+pub struct PolicyOverrides {
+    pub validity_days: Option<u32>,
+    pub extra_sans: Vec<SanEntry>,
+}
+
+/// How a decision was reached, as recorded in the audit trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyOutcome {
+    Allowed,
+    Denied,
+    /// The endpoint failed and the hook fails open.
+    FailedOpen,
+    /// The endpoint failed and the hook fails closed.
+    FailedClosed,
+}
+
+impl PolicyOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allowed => "allow",
+            Self::Denied => "deny",
+            Self::FailedOpen => "error_allow",
+            Self::FailedClosed => "error_deny",
+        }
+    }
+}
+
+/// Optional callout to an external policy decision point, consulted by
+/// [`sign_csr`](crate::shared::certs::sign_csr) before anything is signed or
+/// rotated out.
+///
+/// The endpoint answers `{"allow": bool, "reason"?, "validity_days"?,
+/// "extra_sans"?}`. A timeout, a non-success status or an unreadable answer
+/// allows the request when `fail_open` is set and refuses it otherwise.
+/// Every decision is recorded in the ledger.
+#[derive(Clone)]
+pub struct PolicyHook {
+    http: HttpClient,
+    url: String,
+    bearer_token: Option<String>,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+impl PolicyHook {
+    pub fn new(
+        http: HttpClient,
+        url: String,
+        bearer_token: Option<String>,
+        timeout: Duration,
+        fail_open: bool,
+    ) -> Self {
+        Self {
+            http,
+            url,
+            bearer_token,
+            timeout,
+            fail_open,
+        }
+    }
+
+    /// Ask the endpoint about `request` and record the decision.
+    ///
+    /// Returns the overrides to apply, `Forbidden` when denied, and
+    /// `UpstreamError` when the endpoint failed and the hook fails closed.
+    pub async fn decide(
+        &self,
+        request: &PolicyRequest<'_>,
+        ledger: &Ledger,
+    ) -> AppResult<PolicyOverrides> {
+        let (outcome, reason, overrides) = match self.call(request).await {
+            Ok((true, reason, overrides)) => (PolicyOutcome::Allowed, reason, overrides),
+            Ok((false, reason, _)) => (PolicyOutcome::Denied, reason, PolicyOverrides::default()),
+            Err(e) => {
+                warn!(sub = %request.claims.sub, "policy endpoint failed: {}", e);
+                let outcome = if self.fail_open {
+                    PolicyOutcome::FailedOpen
+                } else {
+                    PolicyOutcome::FailedClosed
+                };
+                (outcome, Some(e.to_string()), PolicyOverrides::default())
+            }
+        };
+        info!(
+            sub = %request.claims.sub,
+            outcome = outcome.as_str(),
+            reason = reason.as_deref().unwrap_or(""),
+            "policy decision"
+        );
+        ledger
+            .record_policy_decision(PolicyDecision {
+                subject: request.claims.sub.clone(),
+                decided_at_unix: unix_now(),
+                outcome: outcome.as_str().to_string(),
+                reason: reason.clone(),
+                profile: Some(request.profile.to_string()),
+                device_id: request.device_id.map(str::to_string),
+                wazuh_agent_name: request.wazuh_agent_name.map(str::to_string),
+                client_ip: request.client.ip.clone(),
+                validity_days: overrides.validity_days,
+                extra_sans: overrides
+                    .extra_sans
+                    .iter()
+                    .map(SanEntry::to_string)
+                    .collect(),
+            })
+            .await?;
+        match outcome {
+            PolicyOutcome::Allowed | PolicyOutcome::FailedOpen => Ok(overrides),
+            PolicyOutcome::Denied => Err(AppError::Forbidden(format!(
+                "issuance denied by policy: {}",
+                reason.as_deref().unwrap_or("no reason given")
+            ))),
+            PolicyOutcome::FailedClosed => Err(AppError::UpstreamError(
+                "policy endpoint unavailable".into(),
+            )),
+        }
+    }
+
+    async fn call(
+        &self,
+        request: &PolicyRequest<'_>,
+    ) -> AppResult<(bool, Option<String>, PolicyOverrides)> {
+        let mut builder = self
+            .http
+            .client()
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(request);
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let answer: PolicyResponse = builder.send().await?.error_for_status()?.json().await?;
+        // A zero lifetime would issue a certificate that is already expired.
+        if answer.validity_days == Some(0) {
+            return Err(AppError::UpstreamError(
+                "policy endpoint answered validity_days 0".into(),
+            ));
+        }
+        let overrides = PolicyOverrides {
+            validity_days: answer.validity_days,
+            extra_sans: answer
+                .extra_sans
+                .iter()
+                .map(|s| SanEntry::parse(s))
+                .collect::<AppResult<_>>()?,
+        };
+        Ok((answer.allow, answer.reason, overrides))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wazuh_cert_oauth2_model::models::claims::Claims;
+    use wazuh_cert_oauth2_model::models::errors::AppError;
+    use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+
+    use super::{CsrSummary, PolicyHook, PolicyRequest};
+    use crate::handlers::middle::ClientInfo;
+    use crate::handlers::test_support::{csr_pem, policy_endpoint, temp_dir};
+    use crate::models::naming_template::SanEntry;
+    use crate::shared::ledger::{Ledger, LedgerBackend};
+
+    fn hook(url: String, fail_open: bool) -> PolicyHook {
+        PolicyHook::new(
+            HttpClient::new_with_defaults().expect("http"),
+            url,
+            None,
+            Duration::from_millis(200),
+            fail_open,
+        )
+    }
+
+    async fn ledger() -> Ledger {
+        let dir = temp_dir("policy-test");
+        Ledger::new(LedgerBackend::Csv(dir.join("ledger.csv")))
+            .await
+            .expect("ledger should initialize")
+    }
+
+    async fn ask(hook: &PolicyHook, ledger: &Ledger) -> Result<super::PolicyOverrides, AppError> {
+        let claims = Claims {
+            sub: "user-a".to_string(),
+            name: None,
+            iss: "https://issuer/realms/main".to_string(),
+            exp: 0,
+            preferred_username: None,
+            email: None,
+            realm_access: None,
+            ..Default::default()
+        };
+        let csr = openssl::x509::X509Req::from_pem(csr_pem("user-a").as_bytes()).expect("csr");
+        let request = PolicyRequest {
+            claims: &claims,
+            is_admin: false,
+            profile: "agent",
+            wazuh_agent_name: None,
+            device_id: Some("laptop"),
+            csr: CsrSummary::of(&csr).expect("summary"),
+            client: &ClientInfo::default(),
+        };
+        hook.decide(&request, ledger).await
+    }
+
+    #[tokio::test]
+    async fn decisions_apply_overrides_deny_and_fail_as_configured() {
+        let ledger = ledger().await;
+        let url = policy_endpoint(Some(
+            r#"{"allow":true,"validity_days":7,"extra_sans":["dns:asset-42.example"]}"#,
+        ))
+        .await;
+        let overrides = ask(&hook(url, false), &ledger).await.expect("allowed");
+        assert_eq!(overrides.validity_days, Some(7));
+        assert_eq!(
+            overrides.extra_sans,
+            vec![SanEntry::Dns("asset-42.example".into())]
+        );
+
+        let url = policy_endpoint(Some(r#"{"allow":false,"reason":"offboarded"}"#)).await;
+        let err = ask(&hook(url, true), &ledger).await.expect_err("denied");
+        assert!(matches!(err, AppError::Forbidden(ref m) if m.contains("offboarded")));
+
+        let url = policy_endpoint(None).await;
+        let err = ask(&hook(url, false), &ledger)
+            .await
+            .expect_err("fails closed");
+        assert!(matches!(err, AppError::UpstreamError(_)));
+        let url = policy_endpoint(None).await;
+        assert!(ask(&hook(url, true), &ledger).await.is_ok());
+
+        let outcomes: Vec<_> = ledger
+            .find_policy_decisions("user-a")
+            .await
+            .expect("decisions")
+            .into_iter()
+            .map(|d| d.outcome)
+            .collect();
+        assert_eq!(outcomes, ["allow", "deny", "error_deny", "error_allow"]);
+    }
+
+    #[tokio::test]
+    async fn zero_validity_follows_the_failure_policy() {
+        let ledger = ledger().await;
+        let answer = Some(r#"{"allow":true,"validity_days":0}"#);
+        let err = ask(&hook(policy_endpoint(answer).await, false), &ledger)
+            .await
+            .expect_err("fails closed");
+        assert!(matches!(err, AppError::UpstreamError(_)));
+        let overrides = ask(&hook(policy_endpoint(answer).await, true), &ledger)
+            .await
+            .expect("fails open");
+        assert_eq!(overrides.validity_days, None);
+
+        let decisions = ledger
+            .find_policy_decisions("user-a")
+            .await
+            .expect("decisions");
+        assert_eq!(decisions[0].outcome, "error_deny");
+        assert!(
+            decisions[0]
+                .reason
+                .as_deref()
+                .is_some_and(|r| r.contains("validity_days 0"))
+        );
+    }
+}
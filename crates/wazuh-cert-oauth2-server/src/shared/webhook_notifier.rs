@@ -1,6 +1,7 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use tracing::warn;
+use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentRequest;
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
 
 /// Minimal eviction payload sent to the webhook's internal endpoint.
@@ -12,7 +13,8 @@ struct EvictRequest {
     triggered_at_unix: u64,
 }
 
-/// Fires eviction requests to the webhook after an auto-rotate override.
+/// Fires eviction requests to the webhook after an auto-rotate override, and
+/// announces enrollments awaiting approval.
 /// All fields are optional so the server starts fine without webhook config.
 #[derive(Clone)]
 pub struct WebhookNotifier {
@@ -56,20 +58,7 @@ impl WebhookNotifier {
                 triggered_at_unix,
             };
 
-            let result = match &self.bearer_token {
-                Some(token) => {
-                    self.http
-                        .client()
-                        .post(&url)
-                        .bearer_auth(token)
-                        .json(&req)
-                        .send()
-                        .await
-                }
-                None => self.http.client().post(&url).json(&req).send().await,
-            };
-
-            match result {
+            match self.post(&url).json(&req).send().await {
                 Ok(resp) if resp.status().is_success() => {}
                 Ok(resp) => {
                     warn!(
@@ -84,4 +73,34 @@ impl WebhookNotifier {
             }
         }
     }
+
+    /// Fire-and-forget: tell the webhook that `request` awaits approval so it
+    /// can open a ticket for the admins.
+    pub async fn notify_pending(&self, request: &EnrollmentRequest) {
+        let url = format!(
+            "{}/api/internal/pending-enrollment",
+            self.base_url.trim_end_matches('/')
+        );
+        match self.post(&url).json(request).send().await {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => {
+                warn!(
+                    id = %request.id,
+                    status = %resp.status(),
+                    "Webhook pending-enrollment notification returned non-success"
+                );
+            }
+            Err(e) => {
+                warn!(id = %request.id, "Failed to notify webhook of pending enrollment: {}", e);
+            }
+        }
+    }
+
+    fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        let builder = self.http.client().post(url);
+        match &self.bearer_token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
 }
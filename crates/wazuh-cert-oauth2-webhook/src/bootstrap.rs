@@ -6,6 +6,7 @@ use wazuh_cert_oauth2_model::services::http_client::HttpClient;
 use crate::handlers::enrollment::get_enrollment_report;
 use crate::handlers::evict::internal_evict;
 use crate::handlers::health::health;
+use crate::handlers::pending_enrollment::internal_pending_enrollment;
 use crate::handlers::webhook::send_webhook;
 use crate::opts::Opt;
 use crate::state::{ProxyState, spawn_spool_processor};
@@ -69,7 +70,12 @@ pub async fn launch_rocket(state: ProxyState) -> AppResult<()> {
         .mount("/", routes![health])
         .mount(
             "/api",
-            routes![send_webhook, get_enrollment_report, internal_evict],
+            routes![
+                send_webhook,
+                get_enrollment_report,
+                internal_evict,
+                internal_pending_enrollment
+            ],
         )
         .launch()
         .await
@@ -2,5 +2,6 @@ pub mod auth;
 pub mod enrollment;
 pub mod evict;
 pub mod health;
+pub mod pending_enrollment;
 pub mod webhook;
 pub mod webhook_util;
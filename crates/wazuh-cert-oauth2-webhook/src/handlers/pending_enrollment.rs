@@ -0,0 +1,41 @@
+use crate::handlers::auth::WebhookAuth;
+use crate::handlers::webhook_util::prepare_pending_enrollment_issue;
+use crate::state::ProxyState;
+use crate::state::spool::GitHubTicket;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{State, post};
+use tracing::{error, info, warn};
+use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentRequest;
+
+/// Internal endpoint for the cert server to announce an enrollment held for
+/// admin approval; opens a GitHub ticket linking to the pending request.
+#[post(
+    "/internal/pending-enrollment",
+    format = "application/json",
+    data = "<req>"
+)]
+#[tracing::instrument(skip(_auth, state, req), fields(id = %req.id, subject = %req.subject))]
+pub async fn internal_pending_enrollment(
+    _auth: WebhookAuth,
+    state: &State<ProxyState>,
+    req: Json<EnrollmentRequest>,
+) -> Result<Status, Status> {
+    let req = req.into_inner();
+    info!("Received pending enrollment notification");
+    let (title, body) = prepare_pending_enrollment_issue(&req, &state.server_base_url);
+    let ticket = GitHubTicket { title, body };
+
+    if let Err(e) = state.forward_github_ticket_with_retry(ticket.clone()).await {
+        warn!(
+            "initial GitHub ticket creation failed; spooling for retry: {}",
+            e
+        );
+        if let Err(se) = state.queue_github_ticket(ticket).await {
+            error!("CRITICAL: failed to spool GitHub ticket: {}", se);
+            return Err(Status::InternalServerError);
+        }
+    }
+
+    Ok(Status::Accepted)
+}
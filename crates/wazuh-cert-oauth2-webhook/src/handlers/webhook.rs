@@ -1,5 +1,5 @@
 use crate::handlers::auth::WebhookAuth;
-use crate::handlers::webhook_util::{extract_user_id, prepare_github_issue};
+use crate::handlers::webhook_util::{extract_user_id, prepare_github_issue, revocation_reason_for};
 use crate::models::WebhookRequest;
 use crate::state::ProxyState;
 use crate::state::core::EventAction;
@@ -97,6 +97,8 @@ async fn handle_revoke(state: &State<ProxyState>, p: WebhookRequest) -> Result<S
         serial_hex: None,
         subject: Some(subject.clone()),
         reason: reason.clone(),
+        reason_code: Some(revocation_reason_for(&p)),
+        invalidity_date_unix: None,
     };
     match state.forward_revoke_with_retry(req.clone()).await {
         Ok(()) => {}
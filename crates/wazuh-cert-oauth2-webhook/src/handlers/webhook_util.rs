@@ -1,4 +1,16 @@
 use crate::models::{SimpleUserRepresentation, WebhookRequest};
+use wazuh_cert_oauth2_model::models::enrollment_request::EnrollmentRequest;
+use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
+
+/// CRL reason for a revoke-eligible event: a deleted user has left the
+/// organization, a disabled one no longer needs its certificates.
+pub(super) fn revocation_reason_for(p: &WebhookRequest) -> RevocationReason {
+    if p.event_type.eq_ignore_ascii_case("user-delete") {
+        RevocationReason::AffiliationChanged
+    } else {
+        RevocationReason::CessationOfOperation
+    }
+}
 
 pub(super) fn extract_user_id(p: &WebhookRequest) -> Option<String> {
     if let Ok(SimpleUserRepresentation { id: Some(id), .. }) = &p.get_simple_user_representation() {
@@ -55,11 +67,48 @@ pub fn prepare_github_issue(p: &WebhookRequest) -> (String, String) {
     (title, body)
 }
 
+pub fn prepare_pending_enrollment_issue(
+    req: &EnrollmentRequest,
+    server_base_url: &str,
+) -> (String, String) {
+    let optional = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+    let link = format!(
+        "{}/api/enrollments/{}",
+        server_base_url.trim_end_matches('/'),
+        req.id
+    );
+
+    let title = format!("Enrollment awaiting approval: {}", req.subject);
+    let body = format!(
+        "A certificate enrollment is waiting for an admin's decision.\n\
+        ---\n\n\
+         - **Subject**: {}\n\
+         - **Realm**: {}\n\
+         - **Profile**: {}\n\
+         - **Device**: {}\n\
+         - **Agent name**: {}\n\
+         - **Request**: {}\n\n\
+         Approve with `POST {link}/approve` or reject with `POST {link}/reject`.",
+        req.subject,
+        optional(&req.realm),
+        optional(&req.profile),
+        optional(&req.device_id),
+        optional(&req.wazuh_agent_name),
+        link,
+    );
+
+    (title, body)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::extract_user_id;
+    use super::{extract_user_id, prepare_pending_enrollment_issue, revocation_reason_for};
     use crate::models::WebhookRequest;
     use std::collections::HashMap;
+    use wazuh_cert_oauth2_model::models::enrollment_request::{
+        EnrollmentRequest, EnrollmentStatus,
+    };
+    use wazuh_cert_oauth2_model::models::revocation_reason::RevocationReason;
 
     fn request_with(resource_path: Option<&str>, representation: Option<&str>) -> WebhookRequest {
         WebhookRequest {
@@ -92,9 +141,47 @@ mod tests {
         assert_eq!(extract_user_id(&req).as_deref(), Some("resource-id"));
     }
 
+    #[test]
+    fn deleted_and_disabled_users_get_crl_reasons() {
+        let mut req = request_with(Some("users/abc"), None);
+        assert_eq!(
+            revocation_reason_for(&req),
+            RevocationReason::CessationOfOperation
+        );
+        req.event_type = "USER-DELETE".to_string();
+        assert_eq!(
+            revocation_reason_for(&req),
+            RevocationReason::AffiliationChanged
+        );
+    }
+
     #[test]
     fn returns_none_when_no_source_contains_user_id() {
         let req = request_with(Some("admin/realms/a/groups/abc"), None);
         assert_eq!(extract_user_id(&req), None);
     }
+
+    #[test]
+    fn pending_enrollment_issue_links_to_the_request() {
+        let req = EnrollmentRequest {
+            id: "ab12".to_string(),
+            subject: "user-a".to_string(),
+            status: EnrollmentStatus::Pending,
+            created_at_unix: 0,
+            realm: Some("partners".to_string()),
+            profile: None,
+            device_id: Some("laptop".to_string()),
+            wazuh_agent_name: None,
+            decided_at_unix: None,
+            decided_by: None,
+            reason: None,
+            serial_hex: None,
+            certificate: None,
+        };
+        let (title, body) = prepare_pending_enrollment_issue(&req, "https://certs.example/");
+        assert_eq!(title, "Enrollment awaiting approval: user-a");
+        assert!(body.contains("https://certs.example/api/enrollments/ab12/approve"));
+        assert!(body.contains("- **Realm**: partners"));
+        assert!(body.contains("- **Profile**: -"));
+    }
 }
@@ -4,6 +4,7 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 use wazuh_cert_oauth2_model::models::errors::{AppError, AppResult};
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+use wazuh_cert_oauth2_model::services::refresh_cache::{RefreshPolicy, RefreshingCache};
 
 use super::{ProxyState, WazuhApiClient, oauth, utils};
 
@@ -72,7 +73,7 @@ impl ProxyState {
         Ok(Self {
             server_base_url,
             spool_dir,
-            http,
+            http: http.clone(),
             retry_attempts,
             retry_base,
             retry_max,
@@ -91,6 +92,10 @@ impl ProxyState {
             github_repo_name,
             keycloak_admin_base_url,
             token_cache: Arc::new(RwLock::new(None)),
+            discovery: RefreshingCache::new(
+                RefreshPolicy::with_ttl(Duration::from_secs(3600)),
+                http.clone(),
+            ),
             wazuh_api,
         })
     }
@@ -268,7 +268,7 @@ pub enum EventAction {
 
 #[cfg(test)]
 mod tests {
-    use super::{EventAction, ProxyState};
+    use super::{EventAction, ProxyState, oauth};
     use crate::models::WebhookRequest;
     use std::collections::HashMap;
     use std::path::PathBuf;
@@ -428,4 +428,25 @@ mod tests {
         let action = state.is_allowed_event("user-update", &req);
         assert_eq!(action, EventAction::Revoke);
     }
+
+    #[tokio::test]
+    async fn cached_oauth_token_is_used_while_the_issuer_is_unreachable() {
+        let mut state = build_state(None, None, None, None);
+        state.oauth = oauth::build_oauth(
+            Some("http://127.0.0.1:9".to_string()),
+            Some("client".to_string()),
+            Some("secret".to_string()),
+            None,
+            None,
+        );
+        oauth::cache_token(
+            &state.token_cache,
+            "cached".to_string(),
+            Some(Duration::from_secs(300)),
+        )
+        .await;
+
+        let token = state.acquire_token().await.expect("cached token");
+        assert_eq!(token.as_deref(), Some("cached"));
+    }
 }
@@ -3,7 +3,9 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use tokio::sync::RwLock;
+use wazuh_cert_oauth2_model::models::document::DiscoveryDocument;
 use wazuh_cert_oauth2_model::services::http_client::HttpClient;
+use wazuh_cert_oauth2_model::services::refresh_cache::RefreshingCache;
 
 pub(crate) mod audit;
 mod builder;
@@ -45,6 +47,9 @@ pub struct ProxyState {
     pub(crate) github_repo_name: Option<String>,
 
     pub(crate) token_cache: Arc<RwLock<Option<oauth::CachedToken>>>,
+    /// Discovery document of `oauth`'s issuer, refetched in the background
+    /// and kept while the issuer is briefly unreachable.
+    pub(crate) discovery: RefreshingCache<DiscoveryDocument>,
 
     /// Wazuh manager API client; `None` when eviction is not configured.
     pub(crate) wazuh_api: Option<WazuhApiClient>,
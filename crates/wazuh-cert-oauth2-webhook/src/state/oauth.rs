@@ -2,7 +2,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use super::ProxyState;
-use oauth2::basic::BasicClient;
+use oauth2::basic::{BasicClient, BasicTokenResponse};
 use oauth2::{AuthType, AuthUrl, ClientId, ClientSecret, Scope, TokenResponse, TokenUrl};
 use tokio::sync::RwLock;
 use wazuh_cert_oauth2_model::models::document::DiscoveryDocument;
@@ -47,21 +47,39 @@ pub(crate) async fn acquire_oauth_token(state: &ProxyState) -> AppResult<Option<
         Some(c) => c.clone(),
         None => return Ok(None),
     };
-    let disc_url = format!(
-        "{}/.well-known/openid-configuration",
-        cfg.issuer.trim_end_matches('/')
-    );
-    let doc: DiscoveryDocument = state.http.fetch_json(&disc_url).await?;
-
     if let Some(cached) = state.token_cache.read().await.clone()
         && Instant::now() < cached.exp
     {
         return Ok(Some(cached.token));
     }
 
+    let disc_url = format!(
+        "{}/.well-known/openid-configuration",
+        cfg.issuer.trim_end_matches('/')
+    );
+    let doc = state.discovery.get(&disc_url).await?;
+    let token = match request_token(&cfg, &doc, state).await {
+        Ok(token) => token,
+        Err(e) => {
+            // The endpoints may have moved; have the next attempt use a
+            // freshly fetched document.
+            let _ = state.discovery.refresh(&disc_url).await;
+            return Err(e);
+        }
+    };
+    let access = token.access_token().secret().to_string();
+    cache_token(&state.token_cache, access.clone(), token.expires_in()).await;
+    Ok(Some(access))
+}
+
+async fn request_token(
+    cfg: &OAuthConfig,
+    doc: &DiscoveryDocument,
+    state: &ProxyState,
+) -> AppResult<BasicTokenResponse> {
     let mut basic_client = BasicClient::new(ClientId::new(cfg.client_id.clone()))
-        .set_auth_uri(AuthUrl::new(doc.authorization_endpoint)?)
-        .set_token_uri_option(Some(TokenUrl::new(doc.token_endpoint)?));
+        .set_auth_uri(AuthUrl::new(doc.authorization_endpoint.clone())?)
+        .set_token_uri_option(Some(TokenUrl::new(doc.token_endpoint.clone())?));
     basic_client = basic_client.set_client_secret(ClientSecret::new(cfg.client_secret.clone()));
     let client = basic_client.set_auth_type(AuthType::BasicAuth);
 
@@ -73,10 +91,7 @@ pub(crate) async fn acquire_oauth_token(state: &ProxyState) -> AppResult<Option<
         req = req.add_extra_param("audience", aud.clone());
     }
 
-    let token = req.request_async(state.http.client()).await?;
-    let access = token.access_token().secret().to_string();
-    cache_token(&state.token_cache, access.clone(), token.expires_in()).await;
-    Ok(Some(access))
+    Ok(req.request_async(state.http.client()).await?)
 }
 
 pub(crate) async fn cache_token(
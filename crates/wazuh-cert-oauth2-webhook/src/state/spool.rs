@@ -375,6 +375,8 @@ mod tests {
                 serial_hex: None,
                 subject: Some("user-1".to_string()),
                 reason: Some("reason".to_string()),
+                reason_code: None,
+                invalidity_date_unix: None,
             },
         )
         .await